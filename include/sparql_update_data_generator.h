@@ -0,0 +1,44 @@
+#ifndef SPARQL_UPDATE_DATA_GENERATOR_H
+#define SPARQL_UPDATE_DATA_GENERATOR_H
+
+/*
+ * C interface of sparql-update-data-generator, built with `cargo build --release --features ffi`.
+ *
+ * Functions returning int return 0 on success and -1 on failure,
+ * in which case sudg_last_error() describes the error.
+ */
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SudgState SudgState;
+
+/* Error message of the last failed call on this thread or NULL, valid until the next failing call */
+const char *sudg_last_error(void);
+
+/* Compress `dataset` into `<dataset>.compressed_nt`, state_in may be NULL to start with an empty state */
+int sudg_compress_file(const char *dataset, const char *state_in, const char *state_out, bool dedup, bool parse);
+
+/* Load a compressor state, returns NULL on failure. Release with sudg_free_state */
+SudgState *sudg_load_state(const char *path);
+
+void sudg_free_state(SudgState *state);
+
+/* Generate randomized queries according to the query specs (command line syntax, e.g. "i100x1k") */
+int sudg_generate_queries(const SudgState *state,
+                          const char *dataset,
+                          const char *query_out,
+                          const char *prepare_out,
+                          const char *const *specs,
+                          size_t n_specs,
+                          bool allow_duplicates);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
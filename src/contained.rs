@@ -0,0 +1,58 @@
+//! Summary of how much of every checked dataset is contained in the main dataset, so that checking
+//! thousands of datasets stays readable
+
+use crate::{
+    overlap::{csv_path, fraction},
+    util::{commit_buffered, AtomicFile},
+};
+use std::{
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Number of equally wide containment buckets of the histogram
+pub const N_BUCKETS: usize = 10;
+
+pub struct ContainedDataset {
+    pub path: PathBuf,
+    pub n_triples: usize,
+    pub n_contained: usize,
+}
+
+impl ContainedDataset {
+    pub fn fraction(&self) -> f64 {
+        fraction(self.n_contained, self.n_triples)
+    }
+}
+
+/// Number of datasets per containment bucket, `[0%, 10%)` up to `[90%, 100%]`
+pub fn histogram(datasets: &[ContainedDataset]) -> [usize; N_BUCKETS] {
+    let mut buckets = [0; N_BUCKETS];
+
+    for dataset in datasets {
+        let bucket = (dataset.fraction() * N_BUCKETS as f64) as usize;
+        buckets[bucket.min(N_BUCKETS - 1)] += 1;
+    }
+
+    buckets
+}
+
+/// Writes one row per dataset with its number of triples, its contained triples and their fraction
+pub fn write_csv<P: AsRef<Path>>(path: P, datasets: &[ContainedDataset]) -> io::Result<()> {
+    let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+
+    writeln!(writer, "dataset,n_triples,n_contained,contained")?;
+
+    for dataset in datasets {
+        writeln!(
+            writer,
+            "{},{},{},{:.6}",
+            csv_path(&dataset.path),
+            dataset.n_triples,
+            dataset.n_contained,
+            dataset.fraction()
+        )?;
+    }
+
+    commit_buffered(writer)
+}
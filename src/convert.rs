@@ -0,0 +1,378 @@
+//! Conversion of generated query files into other formats, so that a workload can be repackaged without
+//! generating it again. Reads query files with one `INSERT DATA` or `DELETE DATA` query per line, as `generate` and
+//! `replicate` write them without a template.
+
+use crate::{
+    rdf::ntriples_tokenizer::{skip_whitespace, term_end},
+    sparql::QueryType,
+    util::{commit_buffered, AtomicFile, GzipWriter},
+};
+use clap::ArgEnum;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// Format a query file is converted into
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConvertFormat {
+    /// one file per query in the output directory, `<ix>.rq` and `warmup-<ix>.rq` for warm-up queries
+    PerFile,
+    /// one JSON object per line and query with its type, graph and triples
+    Jsonl,
+    /// the triples of all queries as N-Triples, the query boundaries are lost
+    NTriples,
+}
+
+/// A query of a query file, its triples are the `<s> <p> <o>` terms without terminator
+#[derive(Serialize)]
+pub struct ParsedQuery<'q> {
+    pub query_ix: usize,
+    pub query_type: QueryType,
+    pub graph: Option<&'q str>,
+    pub warmup: bool,
+    pub triples: Vec<&'q str>,
+}
+
+/// Cursor over a query line, tolerating any spaces and tabs between its tokens
+struct QueryTokens<'q> {
+    line: &'q str,
+    pos: usize,
+}
+
+impl<'q> QueryTokens<'q> {
+    fn peek(&mut self) -> Option<u8> {
+        self.pos = skip_whitespace(self.line.as_bytes(), self.pos);
+        self.line.as_bytes().get(self.pos).copied()
+    }
+
+    /// Consumes `keyword` if it is next, ignoring its case
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.peek();
+        let found = self
+            .line
+            .get(self.pos..self.pos + keyword.len())
+            .is_some_and(|next| next.eq_ignore_ascii_case(keyword));
+
+        if found {
+            self.pos += keyword.len();
+        }
+
+        found
+    }
+
+    fn expect(&mut self, punctuation: u8) -> Result<(), String> {
+        match self.peek() {
+            Some(b) if b == punctuation => {
+                self.pos += 1;
+                Ok(())
+            },
+            Some(b) => Err(format!(
+                "expected '{}' but found '{}' at column {}",
+                punctuation as char,
+                b as char,
+                self.pos + 1
+            )),
+            None => Err(format!("expected '{}' but the query ended", punctuation as char)),
+        }
+    }
+
+    fn term(&mut self) -> Result<&'q str, String> {
+        self.peek();
+        let start = self.pos;
+        self.pos = term_end(self.line.as_bytes(), start)?;
+        Ok(&self.line[start..self.pos])
+    }
+
+    /// The triples of a `DATA` block up to its closing brace, the `.` after the last one is optional
+    fn triples(&mut self) -> Result<Vec<&'q str>, String> {
+        let mut triples = Vec::new();
+
+        while self.peek() != Some(b'}') {
+            let start = self.pos;
+            for _ in 0..3 {
+                self.term()?;
+            }
+            triples.push(&self.line[start..self.pos]);
+
+            if self.peek() != Some(b'}') {
+                self.expect(b'.')?;
+            }
+        }
+
+        self.pos += 1;
+        Ok(triples)
+    }
+}
+
+/// Parses a query line as written without a template. Keywords may be in any case and tokens separated by any
+/// spaces and tabs, so that hand-edited files can be read, but the terms must be in N-Triples syntax
+pub fn parse_query(line: &str, query_ix: usize, warmup: bool) -> Result<ParsedQuery<'_>, String> {
+    let mut tokens = QueryTokens { line, pos: 0 };
+
+    let query_type = if tokens.keyword("INSERT") {
+        QueryType::InsertData
+    } else if tokens.keyword("DELETE") {
+        QueryType::DeleteData
+    } else {
+        return Err("expected an INSERT DATA or DELETE DATA query".to_owned());
+    };
+
+    if !tokens.keyword("DATA") {
+        return Err("expected DATA after the operation".to_owned());
+    }
+
+    tokens.expect(b'{')?;
+
+    let (graph, triples) = if tokens.keyword("GRAPH") {
+        let graph = tokens.term()?;
+        let graph = graph
+            .strip_prefix('<')
+            .and_then(|graph| graph.strip_suffix('>'))
+            .ok_or_else(|| format!("expected the IRI of the graph but found {graph}"))?;
+
+        tokens.expect(b'{')?;
+        let triples = tokens.triples()?;
+        tokens.expect(b'}')?;
+        (Some(graph), triples)
+    } else {
+        (None, tokens.triples()?)
+    };
+
+    match tokens.peek() {
+        None => Ok(ParsedQuery { query_ix, query_type, graph, warmup, triples }),
+        Some(_) => Err(format!("unexpected content at column {}", tokens.pos + 1)),
+    }
+}
+
+/// Number of queries and triples of a converted query file
+pub struct ConvertCounts {
+    pub n_queries: usize,
+    pub n_triples: usize,
+}
+
+/// Calls `on_query` with every query of the query file at `query_file` and its line without terminator.
+/// Comment lines other than the warm-up markers are skipped.
+pub fn read_queries(
+    query_file: &Path,
+    mut on_query: impl FnMut(&str, ParsedQuery<'_>) -> io::Result<()>,
+) -> io::Result<()> {
+    let reader = BufReader::new(File::open(query_file)?);
+
+    let mut n_queries = 0;
+    let mut warmup = false;
+
+    for (line_ix, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+
+        match line {
+            b"# warm-up start" => warmup = true,
+            b"# warm-up end" => warmup = false,
+            _ => (),
+        }
+
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{query_file:?}:{}: {reason}", line_ix + 1),
+            )
+        };
+
+        let line = std::str::from_utf8(line).map_err(|e| invalid(format!("query is not valid UTF-8: {e}")))?;
+        let query = parse_query(line, n_queries, warmup).map_err(invalid)?;
+
+        on_query(line, query)?;
+        n_queries += 1;
+    }
+
+    Ok(())
+}
+
+/// An output file of a conversion, gzip compressed or not
+enum ConvertWriter {
+    Plain(BufWriter<AtomicFile>),
+    Gzip(BufWriter<GzipWriter>),
+}
+
+impl ConvertWriter {
+    fn create(path: &Path, gzip: bool) -> io::Result<Self> {
+        let out = AtomicFile::create(path, false)?;
+
+        Ok(if gzip {
+            ConvertWriter::Gzip(BufWriter::new(GzipWriter::new(out)))
+        } else {
+            ConvertWriter::Plain(BufWriter::new(out))
+        })
+    }
+
+    fn commit(self) -> io::Result<()> {
+        match self {
+            ConvertWriter::Plain(writer) => commit_buffered(writer),
+            ConvertWriter::Gzip(writer) => writer.into_inner().map_err(io::IntoInnerError::into_error)?.finish(),
+        }
+    }
+}
+
+impl Write for ConvertWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ConvertWriter::Plain(writer) => writer.write(buf),
+            ConvertWriter::Gzip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ConvertWriter::Plain(writer) => writer.flush(),
+            ConvertWriter::Gzip(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Converts the query file at `query_file` into `format` at `out`, a directory for [`ConvertFormat::PerFile`].
+/// With `gzip` the output files are gzip compressed, the per-file queries are named `<ix>.rq.gz`.
+/// Comment lines other than the warm-up markers are dropped.
+pub fn convert_query_file(
+    query_file: &Path,
+    out: &Path,
+    format: ConvertFormat,
+    gzip: bool,
+) -> io::Result<ConvertCounts> {
+    let mut writer = match format {
+        ConvertFormat::PerFile => {
+            std::fs::create_dir_all(out)?;
+            None
+        },
+        ConvertFormat::Jsonl | ConvertFormat::NTriples => Some(ConvertWriter::create(out, gzip)?),
+    };
+    let extension = if gzip { "rq.gz" } else { "rq" };
+
+    let mut counts = ConvertCounts { n_queries: 0, n_triples: 0 };
+
+    read_queries(query_file, |line, query| {
+        match (&mut writer, format) {
+            (Some(writer), ConvertFormat::NTriples) => {
+                for triple in &query.triples {
+                    writeln!(writer, "{triple} .")?;
+                }
+            },
+            (Some(writer), _) => {
+                serde_json::to_writer(&mut *writer, &query)?;
+                writer.write_all(b"\n")?;
+            },
+            (None, _) => {
+                let file_name = if query.warmup {
+                    format!("warmup-{:06}.{extension}", query.query_ix)
+                } else {
+                    format!("{:06}.{extension}", query.query_ix)
+                };
+
+                let mut file = ConvertWriter::create(&out.join(file_name), gzip)?;
+                writeln!(file, "{line}")?;
+                file.commit()?;
+            },
+        }
+
+        counts.n_queries += 1;
+        counts.n_triples += query.triples.len();
+        Ok(())
+    })?;
+
+    if let Some(writer) = writer {
+        writer.commit()?;
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn decompressed(path: &Path) -> String {
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn parse_hand_edited_queries() {
+        let query = parse_query(
+            "insert  data {\tGRAPH <http://g> {<a> <p> \"x . y\"@en .<b> <p> _:c . <b>  <p>\t<d>}}  ",
+            3,
+            true,
+        )
+        .unwrap();
+        assert!(query.query_type == QueryType::InsertData);
+        assert_eq!((query.query_ix, query.warmup, query.graph), (3, true, Some("http://g")));
+        assert_eq!(query.triples, ["<a> <p> \"x . y\"@en", "<b> <p> _:c", "<b>  <p>\t<d>"]);
+
+        let query = parse_query(
+            "DELETE DATA { <s> <p> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> . }",
+            0,
+            false,
+        )
+        .unwrap();
+        assert!(query.query_type == QueryType::DeleteData);
+        assert_eq!(query.graph, None);
+        assert_eq!(
+            query.triples,
+            ["<s> <p> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer>"]
+        );
+
+        assert!(parse_query("DELETE DATA {}", 0, false).unwrap().triples.is_empty());
+    }
+
+    #[test]
+    fn reject_malformed_queries() {
+        let err = |line| parse_query(line, 0, false).err().unwrap();
+
+        assert!(err("SELECT * {}").contains("INSERT DATA or DELETE DATA"));
+        assert!(err("INSERT { <s> <p> <o> }").contains("expected DATA"));
+        assert!(err("INSERT DATA { <s> <p> <o> <x> }").contains("expected '.'"));
+        assert!(err("INSERT DATA { <s> <p> <o> .").contains("expected a term"));
+        assert!(err("INSERT DATA { GRAPH <g> { <s> <p> <o> }").contains("query ended"));
+        assert!(err("INSERT DATA { <s> <p> <o> } }").contains("unexpected content at column 29"));
+    }
+
+    #[test]
+    fn convert_gzip() {
+        let dir = std::env::temp_dir().join(format!("sudg-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let query_file = dir.join("queries.txt");
+        std::fs::write(
+            &query_file,
+            "# warm-up start\nINSERT DATA { <a> <p> \"x . y\" . }\n# warm-up end\nDELETE DATA { <s> <p> <o> . }\n",
+        )
+        .unwrap();
+
+        let out = dir.join("triples.nt.gz");
+        let counts = convert_query_file(&query_file, &out, ConvertFormat::NTriples, true).unwrap();
+        assert_eq!((counts.n_queries, counts.n_triples), (2, 2));
+        assert_eq!(decompressed(&out), "<a> <p> \"x . y\" .\n<s> <p> <o> .\n");
+
+        let per_file = dir.join("per-file");
+        convert_query_file(&query_file, &per_file, ConvertFormat::PerFile, true).unwrap();
+        assert_eq!(
+            decompressed(&per_file.join("warmup-000000.rq.gz")),
+            "INSERT DATA { <a> <p> \"x . y\" . }\n"
+        );
+        assert_eq!(
+            decompressed(&per_file.join("000001.rq.gz")),
+            "DELETE DATA { <s> <p> <o> . }\n"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
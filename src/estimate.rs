@@ -0,0 +1,71 @@
+//! Estimation of output sizes and generation time from a small random sample of the main dataset,
+//! to size a long generation run before starting it
+
+use crate::{
+    rdf::triple_compressor::{decompressor::RdfTripleDecompressor, CompressedRdfTriples},
+    sparql::{serialize_triple, TermEscaping},
+};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+/// Measurements taken on the sampled triples
+pub struct TripleSample {
+    pub n_sampled: usize,
+    /// Sampled triples with ids missing from the compressor state, they are not part of the averages
+    pub n_missing: usize,
+    /// Average length in bytes of a serialized triple including its terminator
+    pub avg_triple_bytes: f64,
+    /// Average time to look up and serialize a triple
+    pub time_per_triple: Duration,
+}
+
+impl TripleSample {
+    /// Predicted time to look up and serialize `n_triples` triples
+    pub fn predict_time(&self, n_triples: usize) -> Duration {
+        self.time_per_triple.mul_f64(n_triples as f64)
+    }
+}
+
+/// Serializes `n_samples` triples drawn uniformly from `dataset` as they would be written into a query
+pub fn sample_triples(
+    decompressor: &RdfTripleDecompressor,
+    dataset: &CompressedRdfTriples,
+    n_samples: usize,
+    term_escaping: TermEscaping,
+) -> io::Result<TripleSample> {
+    let n_samples = n_samples.min(dataset.len());
+    let ixs = rand::seq::index::sample(&mut rand::thread_rng(), dataset.len(), n_samples);
+
+    let mut buf = Vec::new();
+    let mut n_bytes = 0;
+    let mut n_missing = 0;
+
+    let start = Instant::now();
+
+    for ix in ixs {
+        match decompressor.decompress_rdf_triple(dataset[ix])? {
+            Some(triple) => n_bytes += serialize_triple(&mut buf, triple, b" . ", term_escaping, false).len(),
+            None => n_missing += 1,
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let n_serialized = n_samples - n_missing;
+
+    Ok(TripleSample {
+        n_sampled: n_samples,
+        n_missing,
+        avg_triple_bytes: if n_serialized == 0 {
+            0.0
+        } else {
+            n_bytes as f64 / n_serialized as f64
+        },
+        time_per_triple: if n_serialized == 0 {
+            Duration::ZERO
+        } else {
+            elapsed.div_f64(n_serialized as f64)
+        },
+    })
+}
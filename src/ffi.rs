@@ -0,0 +1,216 @@
+//! C ABI for embedding the generator in other programs, enabled by the `ffi` feature.
+//! See `include/sparql_update_data_generator.h` for the corresponding declarations.
+//!
+//! All functions returning `c_int` return 0 on success and -1 on failure,
+//! in which case `sudg_last_error` returns a description of the error.
+
+use crate::{
+    query_spec::{self, QuerySpecOpt},
+    rdf::{
+        self,
+        triple_compressor::{
+            compressor::RdfTripleCompressor, decompressor::RdfTripleDecompressor, CompressedRdfTriples,
+        },
+    },
+    sparql::{self, OutputFormat, OutputOrder, WriteOpts},
+};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, OsStr},
+    fmt::Display,
+    os::{
+        raw::{c_char, c_int},
+        unix::ffi::OsStrExt,
+    },
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+    ptr,
+};
+
+/// Opaque handle to a loaded compressor state
+pub struct SudgState(RdfTripleDecompressor);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: impl Display) {
+    let msg = CString::new(msg.to_string().replace('\0', "")).expect("nul bytes removed");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
+
+/// Runs `f`, converting errors and panics into the `c_int` error convention
+fn run(f: impl FnOnce() -> Result<(), String>) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            set_last_error(e);
+            -1
+        },
+        Err(_) => {
+            set_last_error("panic during operation");
+            -1
+        },
+    }
+}
+
+unsafe fn path_arg<'a>(name: &str, ptr: *const c_char) -> Result<&'a Path, String> {
+    if ptr.is_null() {
+        return Err(format!("{name} must not be null"));
+    }
+
+    Ok(Path::new(OsStr::from_bytes(CStr::from_ptr(ptr).to_bytes())))
+}
+
+/// Returns the error message of the last failed call on this thread, or null if there was none.
+/// The string is owned by the library and valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn sudg_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
+
+/// Compresses the n-triples file `dataset` into `<dataset>.compressed_nt`, extending the compressor state
+/// `state_in` (may be null to start from an empty state) and writing the resulting state to `state_out`.
+///
+/// # Safety
+/// All non-null pointers must point to valid nul terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn sudg_compress_file(
+    dataset: *const c_char,
+    state_in: *const c_char,
+    state_out: *const c_char,
+    dedup: bool,
+    parse: bool,
+) -> c_int {
+    run(|| {
+        let dataset = path_arg("dataset", dataset)?;
+        let state_out = path_arg("state_out", state_out)?;
+
+        let mut compressor = if state_in.is_null() {
+            RdfTripleCompressor::new()
+        } else {
+            let frozen =
+                RdfTripleDecompressor::load_state(path_arg("state_in", state_in)?).map_err(|e| e.to_string())?;
+            RdfTripleCompressor::from_decompressor(frozen).map_err(|e| e.to_string())?
+        };
+
+        compressor
+            .compress_rdf_triple_file(dataset, dedup, parse)
+            .map_err(|e| e.to_string())?;
+        compressor.save_state(state_out).map_err(|e| e.to_string())
+    })
+}
+
+/// Loads the compressor state at `path`. Returns null on failure.
+/// The returned handle must be released with `sudg_free_state`.
+///
+/// # Safety
+/// `path` must point to a valid nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sudg_load_state(path: *const c_char) -> *mut SudgState {
+    let mut state = ptr::null_mut();
+
+    run(|| {
+        let decompressor = RdfTripleDecompressor::load_state(path_arg("path", path)?).map_err(|e| e.to_string())?;
+        state = Box::into_raw(Box::new(SudgState(decompressor)));
+        Ok(())
+    });
+
+    state
+}
+
+/// Releases a state returned by `sudg_load_state`. Passing null is a no-op.
+///
+/// # Safety
+/// `state` must be null or a handle returned by `sudg_load_state` that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sudg_free_state(state: *mut SudgState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+
+/// Generates randomized queries from the compressed dataset `dataset` according to the `n_specs` query specs in `specs`
+/// (same syntax as on the command line), writing the queries to `query_out` and the preparation queries to `prepare_out`.
+///
+/// # Safety
+/// `state` must be a valid handle returned by `sudg_load_state`, all string pointers must point to valid
+/// nul terminated strings and `specs` must point to an array of at least `n_specs` strings.
+#[no_mangle]
+pub unsafe extern "C" fn sudg_generate_queries(
+    state: *const SudgState,
+    dataset: *const c_char,
+    query_out: *const c_char,
+    prepare_out: *const c_char,
+    specs: *const *const c_char,
+    n_specs: usize,
+    allow_duplicates: bool,
+) -> c_int {
+    run(|| {
+        let decompressor = &state.as_ref().ok_or("state must not be null")?.0;
+        let dataset = path_arg("dataset", dataset)?;
+        let query_out = path_arg("query_out", query_out)?;
+        let prepare_out = path_arg("prepare_out", prepare_out)?;
+
+        if specs.is_null() && n_specs > 0 {
+            return Err("specs must not be null".to_owned());
+        }
+
+        let specs = (0..n_specs)
+            .map(|ix| {
+                let spec = *specs.add(ix);
+
+                if spec.is_null() {
+                    return Err(format!("query spec {ix} must not be null"));
+                }
+
+                CStr::from_ptr(spec)
+                    .to_str()
+                    .map_err(|e| format!("query spec {ix} is not valid UTF-8: {e}"))?
+                    .parse::<QuerySpecOpt>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let dataset_triples = CompressedRdfTriples::load(dataset).map_err(|e| e.to_string())?;
+
+        let query_specs = query_spec::resolve_random_specs(
+            specs,
+            dataset_triples.len(),
+            (!allow_duplicates).then_some(dataset_triples.len()),
+            &mut rand::thread_rng(),
+        )?;
+
+        let res = if allow_duplicates {
+            sparql::generate_queries(
+                query_out,
+                prepare_out,
+                OutputFormat::Query,
+                query_specs,
+                decompressor,
+                rdf::triple_generator::random_triple_generator(&dataset_triples, None, None),
+                OutputOrder::AsSpecified,
+                WriteOpts::default(),
+            )
+        } else {
+            let total_query_triples = query_specs.iter().map(|spec| spec.n_queries * spec.n_triples_per_query).sum();
+
+            sparql::generate_queries(
+                query_out,
+                prepare_out,
+                OutputFormat::Query,
+                query_specs,
+                decompressor,
+                rdf::triple_generator::random_distinct_triple_generator(
+                    &dataset_triples,
+                    None,
+                    total_query_triples,
+                    None,
+                ),
+                OutputOrder::AsSpecified,
+                WriteOpts::default(),
+            )
+        };
+
+        res.map_err(|e| e.to_string())
+    })
+}
@@ -0,0 +1,29 @@
+#![feature(hasher_prefixfree_extras, is_sorted, iter_advance_by)]
+
+pub mod contained;
+pub mod convert;
+pub mod estimate;
+pub mod load;
+pub mod manifest;
+pub mod overlap;
+pub mod pattern;
+pub mod perf;
+pub mod query_spec;
+pub mod query_stats;
+pub mod rdf;
+pub mod remote;
+pub mod roundtrip;
+pub mod sample;
+pub mod selector;
+pub mod sparql;
+pub mod stats;
+pub mod template;
+pub mod util;
+pub mod versions;
+pub mod warnings;
+pub mod workload;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+mod python;
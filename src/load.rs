@@ -0,0 +1,293 @@
+//! Loading of a compressed dataset into a SPARQL endpoint, so that the store of a benchmark can be prepared
+//! without other tools. The dataset is sent in chunks and the loaded triples are recorded in
+//! `<dataset>.load-progress.json` after every chunk, so that an interrupted load can be resumed.
+
+use crate::{
+    rdf::triple_compressor::{chain_hash, decompressor::RdfTripleDecompressor, CompressedRdfTriples, CompressedTriple},
+    sparql::{serialize_triple, TermEscaping},
+    util::{self, commit_buffered, AtomicFile},
+};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// How the triples are sent to the endpoint
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadMethod {
+    /// `INSERT DATA` updates posted to the SPARQL update endpoint
+    Update,
+    /// Graph Store Protocol, the first chunk replaces the graph by `PUT` and the following chunks are added by `POST`
+    GraphStore,
+}
+
+/// Endpoint and graph a dataset is loaded into, the default graph if there is none
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadTarget {
+    pub endpoint: String,
+    pub graph: Option<String>,
+    pub method: LoadMethod,
+}
+
+/// Triples of a dataset loaded so far, recorded after every chunk
+#[derive(Serialize, Deserialize)]
+pub struct LoadProgress {
+    #[serde(flatten)]
+    pub target: LoadTarget,
+    /// Number of triples and [`CompressedRdfTriples::fingerprint`] of the dataset, a changed dataset is not resumed
+    pub n_triples: usize,
+    pub fingerprint: String,
+    pub n_loaded: usize,
+    /// Order dependent [`chain_hash`] of the loaded triples, since resuming skips the first `n_loaded` triples. A
+    /// reordered dataset, e.g. by `sort`, has the same fingerprint but is not resumed
+    pub loaded_hash: String,
+    #[serde(skip)]
+    loaded_hash_state: u64,
+}
+
+impl LoadProgress {
+    /// Progress of loading `triples` into `target` from the start
+    pub fn new(target: LoadTarget, triples: &CompressedRdfTriples) -> Self {
+        LoadProgress {
+            target,
+            n_triples: triples.len(),
+            fingerprint: format!("{:032x}", triples.fingerprint()),
+            n_loaded: 0,
+            loaded_hash: format!("{:016x}", 0),
+            loaded_hash_state: 0,
+        }
+    }
+
+    fn add_loaded(&mut self, triples: &[CompressedTriple]) {
+        self.n_loaded += triples.len();
+        self.loaded_hash_state = chain_hash(self.loaded_hash_state, triples);
+        self.loaded_hash = format!("{:016x}", self.loaded_hash_state);
+    }
+
+    /// Continues the recorded progress `recorded` of an interrupted load, which must have loaded the same dataset
+    /// `triples` in the same order into the same target
+    pub fn resume(&mut self, recorded: &LoadProgress, triples: &[CompressedTriple]) -> Result<(), String> {
+        if recorded.target != self.target {
+            return Err("the endpoint, graph or method differs from the interrupted load".to_owned());
+        }
+
+        if recorded.n_triples != self.n_triples || recorded.fingerprint != self.fingerprint {
+            return Err(format!(
+                "the dataset changed since the interrupted load, it had {} triples with fingerprint {} and has {} with fingerprint {}",
+                recorded.n_triples, recorded.fingerprint, self.n_triples, self.fingerprint
+            ));
+        }
+
+        self.add_loaded(&triples[..recorded.n_loaded.min(triples.len())]);
+        if self.loaded_hash != recorded.loaded_hash {
+            return Err(
+                "the dataset was reordered since the interrupted load, e.g. by sorting it, so the loaded triples are not the first ones any more"
+                    .to_owned(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Path of the progress of loading the dataset at `dataset`
+    pub fn path(dataset: &Path) -> PathBuf {
+        let mut file_name = dataset.file_name().map(OsString::from).unwrap_or_default();
+        file_name.push(".load-progress.json");
+        dataset.with_file_name(file_name)
+    }
+
+    pub fn read(dataset: &Path) -> io::Result<Self> {
+        let path = Self::path(dataset);
+        let file = File::open(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("unable to open the load progress {path:?} to resume from: {e}"),
+            )
+        })?;
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn write(&self, dataset: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(AtomicFile::create(Self::path(dataset), false)?);
+        serde_json::to_writer(&mut writer, self)?;
+        commit_buffered(writer)
+    }
+
+    /// Removes the progress of a completed load, if there is one
+    pub fn remove(dataset: &Path) -> io::Result<()> {
+        match std::fs::remove_file(Self::path(dataset)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn send_chunk(target: &LoadTarget, body: &[u8], first: bool) -> io::Result<()> {
+    let request = match target.method {
+        LoadMethod::Update => ureq::post(&target.endpoint).set("Content-Type", "application/sparql-update"),
+        LoadMethod::GraphStore => {
+            let method = if first { "PUT" } else { "POST" };
+
+            match &target.graph {
+                Some(graph) => ureq::request(method, &target.endpoint).query("graph", graph),
+                None => ureq::request(method, &format!("{}?default", target.endpoint)),
+            }
+            .set("Content-Type", "application/n-triples")
+        },
+    };
+
+    match request.send_bytes(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => Err(io::Error::other(format!(
+            "{} answered {status}: {}",
+            target.endpoint,
+            response.into_string().unwrap_or_default()
+        ))),
+        Err(e) => Err(io::Error::other(format!("unable to reach {}: {e}", target.endpoint))),
+    }
+}
+
+/// Serializes `triples` as body of a single request
+fn chunk_body(
+    decompressor: &RdfTripleDecompressor,
+    target: &LoadTarget,
+    triples: &[CompressedTriple],
+) -> io::Result<Vec<u8>> {
+    let (escaping, terminator): (_, &[u8]) = match target.method {
+        LoadMethod::Update => (TermEscaping::Escape, b" . "),
+        LoadMethod::GraphStore => (TermEscaping::Verbatim, b" .\n"),
+    };
+
+    let mut body = Vec::new();
+
+    if target.method == LoadMethod::Update {
+        body.extend_from_slice(b"INSERT DATA { ");
+
+        if let Some(graph) = &target.graph {
+            body.extend_from_slice(format!("GRAPH <{graph}> {{ ").as_bytes());
+        }
+    }
+
+    let mut buf = Vec::new();
+    for &triple in triples {
+        let triple = decompressor.decompress_known_rdf_triple(triple)?;
+
+        body.extend_from_slice(serialize_triple(&mut buf, triple, terminator, escaping, false));
+    }
+
+    if target.method == LoadMethod::Update {
+        if target.graph.is_some() {
+            body.extend_from_slice(b"} ");
+        }
+
+        body.extend_from_slice(b"}");
+    }
+
+    Ok(body)
+}
+
+/// Loads `triples` of the dataset at `dataset` into the target of `progress` in chunks of `chunk_size` triples,
+/// skipping the ones an earlier run loaded already. `on_chunk` is called with the number of loaded triples after every
+/// chunk. An interrupt finishes the current chunk, the progress is kept so that the load can be resumed.
+pub fn load_dataset(
+    decompressor: &RdfTripleDecompressor,
+    dataset: &Path,
+    triples: &[CompressedTriple],
+    mut progress: LoadProgress,
+    chunk_size: usize,
+    mut on_chunk: impl FnMut(usize),
+) -> io::Result<()> {
+    let target = progress.target.clone();
+
+    for chunk in triples[progress.n_loaded.min(triples.len())..].chunks(chunk_size.max(1)) {
+        if util::is_interrupted() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!(
+                    "interrupted after loading {} triples, continue with --resume",
+                    progress.n_loaded
+                ),
+            ));
+        }
+
+        send_chunk(
+            &target,
+            &chunk_body(decompressor, &target, chunk)?,
+            progress.n_loaded == 0,
+        )?;
+
+        progress.add_loaded(chunk);
+        progress.write(dataset)?;
+        on_chunk(progress.n_loaded);
+    }
+
+    LoadProgress::remove(dataset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> LoadTarget {
+        LoadTarget {
+            endpoint: "http://localhost:8890/sparql".to_owned(),
+            graph: None,
+            method: LoadMethod::Update,
+        }
+    }
+
+    fn progress_after(dataset: &CompressedRdfTriples, n_loaded: usize) -> LoadProgress {
+        let mut progress = LoadProgress::new(target(), dataset);
+        progress.add_loaded(&dataset[..n_loaded]);
+        progress
+    }
+
+    #[test]
+    fn resume_same_dataset() {
+        let dataset = CompressedRdfTriples::from_triples(vec![[1, 2, 3], [4, 5, 6]]);
+        let recorded = progress_after(&dataset, 1);
+
+        let mut progress = LoadProgress::new(target(), &dataset);
+        progress.resume(&recorded, &dataset).unwrap();
+        assert_eq!(progress.n_loaded, 1);
+
+        // continues the hash of the recorded triples
+        progress.add_loaded(&dataset[1..]);
+        assert_eq!(progress.loaded_hash, progress_after(&dataset, 2).loaded_hash);
+    }
+
+    #[test]
+    fn refuse_to_resume_changed_dataset() {
+        let dataset = CompressedRdfTriples::from_triples(vec![[1, 2, 3], [4, 5, 6]]);
+        let recorded = progress_after(&dataset, 1);
+
+        // same length, different triples
+        let changed = CompressedRdfTriples::from_triples(vec![[1, 2, 3], [4, 5, 7]]);
+        assert!(LoadProgress::new(target(), &changed).resume(&recorded, &changed).is_err());
+
+        let longer = CompressedRdfTriples::from_triples(vec![[1, 2, 3], [4, 5, 6], [7, 8, 9]]);
+        assert!(LoadProgress::new(target(), &longer).resume(&recorded, &longer).is_err());
+
+        let other_graph = LoadTarget { graph: Some("http://example.org/g".to_owned()), ..target() };
+        assert!(LoadProgress::new(other_graph, &dataset).resume(&recorded, &dataset).is_err());
+    }
+
+    #[test]
+    fn refuse_to_resume_permuted_dataset() {
+        let dataset = CompressedRdfTriples::from_triples(vec![[4, 5, 6], [1, 2, 3], [7, 8, 9]]);
+        let recorded = progress_after(&dataset, 1);
+
+        // sorted, the same fingerprint but another first triple
+        let sorted = CompressedRdfTriples::from_triples(vec![[1, 2, 3], [4, 5, 6], [7, 8, 9]]);
+        assert_eq!(sorted.fingerprint(), dataset.fingerprint());
+
+        let err = LoadProgress::new(target(), &sorted).resume(&recorded, &sorted).unwrap_err();
+        assert!(err.contains("reordered"), "{err}");
+    }
+}
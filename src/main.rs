@@ -1,86 +1,84 @@
-#![feature(hasher_prefixfree_extras, is_sorted, iter_advance_by)]
+#![feature(is_sorted)]
 
-mod rdf;
-mod sparql;
-mod util;
-
-use crate::sparql::QueryType;
 use clap::{ArgEnum, Parser, Subcommand};
-use memory_mapped::MemoryMapped;
-use rdf::triple_compressor::{
-    compressor::RdfTripleCompressor, decompressor::RdfTripleDecompressor, CompressedRdfTriples,
-    COMPRESSED_TRIPLE_FILE_EXTENSION, UNCOMPRESSED_TRIPLE_FILE_EXTENSION,
+use globset::GlobMatcher;
+use rand::seq::SliceRandom;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use sparql_update_data_generator::{
+    contained::{self, ContainedDataset},
+    convert::{self, ConvertFormat},
+    estimate,
+    load::{self, LoadMethod, LoadProgress, LoadTarget},
+    manifest, overlap,
+    pattern::{PatternMatcher, TriplePattern},
+    perf,
+    query_spec::{self, QuerySpecOpt},
+    query_stats,
+    rdf::{
+        self, iri_normalization,
+        triple_compressor::{
+            check_shard_count,
+            compressor::{
+                compressed_path, CompressedFileStats, CompressionReport, CompressionStats, DatasetMetadata,
+                LiteralFilter, RdfTripleCompressor,
+            },
+            decompressor::RdfTripleDecompressor,
+            save_compressed_triples,
+            term_counts::TermCounts,
+            CompressedRdfTriples, CompressedTriple, ContainmentIndex, ContainmentStrategy,
+            COMPRESSED_TRIPLE_FILE_EXTENSION, HDT_FILE_EXTENSION, UNCOMPRESSED_TRIPLE_FILE_EXTENSION,
+        },
+        triple_generator::{
+            ChangesetPreference, ChangesetTruncation, GeneratorContext, SelectionLog, TripleGeneratorRegistry,
+        },
+    },
+    roundtrip,
+    sample::{self, Stratification},
+    selector::TripleSelector,
+    sparql::{
+        self, LineEnding, MissingTermPolicy, OutputFormat, OutputOrder, ProvenanceFormat, QueryType, TermEscaping,
+        WriteOpts, DEFAULT_WRITE_BUFFER_CAPACITY,
+    },
+    stats,
+    template::QueryTemplate,
+    util::{
+        self, changeset_file_iter, commit_buffered, dataset_iter, fits_in_memory, parse_duration, parse_glob,
+        AtomicFile, GzipWriter, LoadOpts, TraversalOpts, WalkOpts,
+    },
+    versions,
+    warnings::{self, warn, Warning},
+    workload::{ChangesetGeneration, Workload, WorkloadPart},
 };
-use sparql::OutputOrder;
 use std::{
-    collections::HashSet, hash::BuildHasherDefault, io::BufWriter, os::unix::ffi::OsStrExt, path::PathBuf, str::FromStr,
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
-use util::{changeset_file_iter, dataset_iter};
-
-#[derive(Clone, Copy)]
-pub struct QuerySpecOpt {
-    n_queries: usize,
-    n_triples_per_query: QuerySizeOpt,
-    query_type: QueryType,
-}
-
-#[derive(Clone, Copy)]
-pub enum QuerySizeOpt {
-    Percentage(f64),
-    Absolute(usize),
-}
-
-impl FromStr for QuerySpecOpt {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let query_type = &s[0..1];
-
-        let query_type = match query_type {
-            "i" => QueryType::InsertData,
-            "d" => QueryType::DeleteData,
-            _ => return Err("invalid query spec, query type not specified".to_owned()),
-        };
-
-        let (n_queries, n_triples_per_query) = s[1..]
-            .split_once('x')
-            .ok_or_else(|| "invalid query spec, expected delimiter".to_owned())?;
-
-        let n_queries = n_queries
-            .parse()
-            .map_err(|e| format!("invalid query spec, first value is not integer: {e:?}"))?;
-
-        let n_triples_per_query = if n_triples_per_query.ends_with('%') {
-            QuerySizeOpt::Percentage(
-                n_triples_per_query
-                    .trim_end_matches('%')
-                    .parse::<f64>()
-                    .map_err(|e| format!("invalid query spec, triple count specifier is not integer: {e:?}"))?
-                    / 100.0,
-            )
-        } else {
-            QuerySizeOpt::Absolute(
-                n_triples_per_query
-                    .parse()
-                    .map_err(|e| format!("invalid query spec, triple count specifier is not integer: {e:?}"))?,
-            )
-        };
-
-        Ok(QuerySpecOpt { n_queries, n_triples_per_query, query_type })
-    }
-}
-
-impl QuerySizeOpt {
-    pub fn get_absolute(self, n_total_triples: usize) -> usize {
-        match self {
-            QuerySizeOpt::Absolute(n) => n,
-            QuerySizeOpt::Percentage(percent) => (n_total_triples as f64 * percent) as usize,
-        }
-    }
-}
 
 #[derive(Parser)]
 #[clap(author, version, about)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Opts,
+
+    /// Write all warnings encountered during the run to this file as JSON
+    #[clap(long, global(true))]
+    warnings_out: Option<PathBuf>,
+
+    /// Exit with an error if any warnings were encountered
+    #[clap(long, global(true), action)]
+    fail_on_warning: bool,
+
+    /// Limit in bytes on the resident memory. Deduplication, distinct counting and distinct sampling
+    /// check it before growing and count with less memory or abort with an error instead of running out of memory
+    #[clap(long, global(true))]
+    max_memory: Option<u64>,
+}
+
+#[derive(Subcommand)]
 enum Opts {
     /// Compress n-triples datasets
     Compress {
@@ -93,19 +91,79 @@ enum Opts {
         #[clap(short = 'o', long, required_unless_present("previous-compressor-state"))]
         compressor_state_out: Option<PathBuf>,
 
-        /// Operate recursively on directories
-        #[clap(short = 'r', long, action)]
-        recursive: bool,
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
 
         /// Deduplicate the triples to save space. Will use more RAM and time.
         #[clap(short = 'D', long, action)]
         dedup: bool,
 
+        /// Existing compressed datasets whose triples are treated as already seen by dedup,
+        /// so that compressing new data does not reintroduce them. They must have been compressed into
+        /// the previous compressor state, which is required
+        #[clap(long, requires_all = &["dedup", "previous-compressor-state"])]
+        dedup_against: Vec<PathBuf>,
+
         /// Don't run a parser to sanitize the input, instead primitively split and preserve bytes exactly.
         #[clap(short = 'N', long, action)]
         no_parse: bool,
 
-        /// Datasets to compress
+        /// Only keep literals with this language tag or one of its subtags (`en` also keeps `en-GB`), can be repeated.
+        /// Triples with literals of other languages are not compressed, plain and typed literals are kept
+        #[clap(long)]
+        keep_language: Vec<String>,
+
+        /// Only keep typed literals with this datatype IRI, `xsd:` abbreviates the XML Schema namespace, can be repeated.
+        /// Triples with literals of other datatypes are not compressed, plain and language tagged literals are kept
+        #[clap(long)]
+        keep_datatype: Vec<String>,
+
+        /// Normalize IRIs before compressing them, so that differently spelled forms of the same IRI get the same id:
+        /// lowercase scheme and host, uppercase percent-encodings and decoded percent-encoded unreserved characters
+        #[clap(long, action)]
+        normalize_iris: bool,
+
+        /// Write the normalized IRIs with the spellings that were merged into them as JSON to this file
+        #[clap(long, requires = "normalize-iris")]
+        normalization_report: Option<PathBuf>,
+
+        /// Number of distinct terms expected in the resulting state, pre-sizes the dictionary
+        #[clap(long)]
+        expected_terms: Option<usize>,
+
+        /// Write the counts of every compressed file and their total as JSON to this file
+        #[clap(long)]
+        stats_out: Option<PathBuf>,
+
+        /// Write the source, its modification time, the counts, the tool version and the fingerprint of the
+        /// compressor state of every compressed file next to it as `<file>.meta.json`, stats prints it
+        #[clap(long, action)]
+        metadata: bool,
+
+        /// Only write the terms new since previous-compressor-state, as a segment appended to it,
+        /// instead of rewriting the whole state
+        #[clap(
+            long,
+            action,
+            requires = "previous-compressor-state",
+            conflicts_with = "compressor-state-out"
+        )]
+        incremental: bool,
+
+        /// Split the written state into this many shards (a power of two up to 256) keyed by the leading bits of the
+        /// term ids, written as `<state>.shard-<n>`. A shard is only mapped or read once an id of it is looked up,
+        /// so that commands touching few terms of a very large state start quickly
+        #[clap(long, conflicts_with = "incremental")]
+        shards: Option<usize>,
+
+        /// Count how often every term occurs in the compressed triples and store the counts next to the compressor
+        /// state as `<state>.term-counts`, continuing the counts of previous-compressor-state. Without it stale counts are removed
+        #[clap(long, action)]
+        term_counts: bool,
+
+        /// Datasets to compress. HDT files (.hdt) are supported if built with the hdt feature,
+        /// directories are only searched for N-Triples files. http(s):// and s3:// URLs (public objects only)
+        /// are streamed and decompressed if they end in .gz, their compressed datasets are written to the current directory
         datasets: Vec<PathBuf>,
     },
     /// Generate SPARQL DELETE DATA queries from a compressed dataset
@@ -114,9 +172,15 @@ enum Opts {
         #[clap(short = 's', long)]
         compressor_state: PathBuf,
 
-        /// Path to the compressed dataset
-        #[clap(short = 'i', long)]
-        compressed_dataset: PathBuf,
+        /// Path to the compressed dataset. Randomized generation accepts it multiple times to take the triples of every
+        /// query from several datasets sharing the compressor state, the first one is the main dataset
+        #[clap(short = 'i', long, required = true)]
+        compressed_dataset: Vec<PathBuf>,
+
+        /// Weight of every --compressed-dataset in the same order, the share of the triples of every query taken from it.
+        /// All datasets have the same weight if not given
+        #[clap(long)]
+        dataset_weight: Vec<f64>,
 
         /// File to write the query to
         #[clap(short = 'o', long)]
@@ -136,10 +200,118 @@ enum Opts {
         #[clap(short, long, action)]
         append: bool,
 
+        /// Append even if the output files were written with a different format or term escaping
+        #[clap(long, action, requires = "append")]
+        force: bool,
+
+        /// Capacity in bytes of the output buffers
+        #[clap(long, default_value_t = DEFAULT_WRITE_BUFFER_CAPACITY)]
+        write_buffer_size: usize,
+
+        /// Stop generating once the query file has reached this size in bytes, the current query is still finished.
+        /// The cut-off is recorded in the manifest
+        #[clap(long)]
+        max_output_bytes: Option<u64>,
+
+        /// Stop generating once this many triples were written, the current query is still finished.
+        /// The cut-off is recorded in the manifest
+        #[clap(long)]
+        max_total_triples: Option<usize>,
+
+        /// Rhai script defining `fn select(subject, predicate, object)` that returns whether a triple is used,
+        /// terms are passed in N-Triples syntax. Queries can end up smaller than requested.
+        /// Requires the scripting feature
+        #[clap(long)]
+        selector: Option<PathBuf>,
+
+        /// Only use the triples matching this triple pattern, see decompress --pattern for the syntax.
+        /// Can be given multiple times to use the triples matching any of them, queries can end up smaller than requested
+        #[clap(long, value_parser)]
+        pattern: Vec<TriplePattern>,
+
+        /// How to handle triples whose ids are missing from the compressor state.
+        /// For replicate the reported query number is the position of the dataset, or of its part with --max-triples-per-query
+        #[clap(arg_enum, long, default_value_t = MissingTermPolicy::Abort)]
+        on_missing_terms: MissingTermPolicy,
+
+        /// How terms are written into the queries, escaping keeps terms read with --no-parse from breaking them
+        #[clap(arg_enum, long, default_value_t = TermEscaping::Escape)]
+        term_escaping: TermEscaping,
+
+        /// Line terminator of the written queries and N-Triples
+        #[clap(arg_enum, long, default_value_t = LineEnding::Lf)]
+        line_ending: LineEnding,
+
+        /// Write non-ASCII characters of terms as \u escapes, for drivers that only handle ASCII
+        #[clap(long, action)]
+        ascii_escape: bool,
+
+        /// Additionally write the INSERT DATA and DELETE DATA queries to <query-out-stem>.insert.<ext>
+        /// and <query-out-stem>.delete.<ext>
+        #[clap(long, action, conflicts_with = "checkpoint-every")]
+        split_by_type: bool,
+
+        /// File with a template for every SPARQL query, with the placeholders {{triples}}, {{graph}} (IRI in <>,
+        /// empty for the default graph), {{query_id}} and {{operation}} (INSERT DATA or DELETE DATA), on a single line
+        #[clap(long)]
+        template: Option<PathBuf>,
+
+        /// Only load the inputs and print what would be generated, without writing anything
+        #[clap(long, action)]
+        dry_run: bool,
+
+        /// Parse every generated query with a SPARQL parser before declaring the run successful
+        #[clap(long, action)]
+        validate_output: bool,
+
+        /// After generating, report how many insert triples are contained in the input datasets and thus have to be
+        /// removed by the prepare file, and warn about triples inserted by more than one query
+        #[clap(long, action)]
+        check_inserts: bool,
+
+        /// Write the time spent in sampling, containment checks, decompression and writing per batch of queries
+        /// as JSON to this file
+        #[clap(long)]
+        perf_report: Option<PathBuf>,
+
+        /// Number of queries per batch of --perf-report
+        #[clap(long, default_value_t = 100, requires = "perf-report")]
+        perf_batch_size: usize,
+
+        #[clap(flatten)]
+        load_opts: LoadOpts,
+
+        /// Use a sorted copy of unsorted datasets instead of refusing them. The copy is written next to the
+        /// dataset as hidden `.<name>.sorted` file once and reused while it is newer than the dataset
+        #[clap(long, action)]
+        auto_sort: bool,
+
         #[clap(subcommand)]
         g_type: GenerateType,
 
-        /// Query specs of the form <N_QUERIES>x<N_TRIPLE_PER_QUERY>
+        /// Query spec of warm-up queries, written before all other queries between marker comments and
+        /// listed separately in the manifest. Can be given multiple times
+        #[clap(long, value_parser, global(true))]
+        warmup: Vec<QuerySpecOpt>,
+
+        /// Seed of the run. Makes the sizes drawn from ranges, the types assigned by mixed specs,
+        /// the selected triples and randomized output orders reproducible
+        #[clap(long, global(true))]
+        seed: Option<u64>,
+
+        /// Write a checkpoint to <query-out>.checkpoint.json every this many queries, so that an interrupted run can be
+        /// continued with --resume. Picks a random seed if none is given
+        #[clap(long, global(true))]
+        checkpoint_every: Option<usize>,
+
+        /// Continue the interrupted run from the checkpoint of query-out, all other arguments must be the same as before
+        #[clap(long, global(true), action, conflicts_with = "dry-run")]
+        resume: bool,
+
+        /// Query specs of the form <i|d><N_QUERIES>x<N_TRIPLE_PER_QUERY>[#comment].
+        /// Counts accept k/M/G suffixes, sizes may also be a percentage of the dataset (5%) or a range (100-1k).
+        /// m<N_QUERIES>x<N_TRIPLE_PER_QUERY>:<INSERTS>/<DELETES> randomly mixes both types in the given ratio.
+        /// Inserts may contain a share of novel triples, not contained in the dataset, given as ~<PERCENT>% after the size
         #[clap(value_parser, global(true))]
         query_specs: Vec<QuerySpecOpt>,
     },
@@ -155,7 +327,7 @@ enum Opts {
         include_dataset: Option<PathBuf>,
 
         /// Path to dataset that will be used to check if the to be replicated triples are contained.
-        /// If they are not contained they will be pruned from the queries.
+        /// If they are contained they will be pruned from the queries.
         #[clap(short = 'E', long, action)]
         exclude_dataset: Option<PathBuf>,
 
@@ -163,17 +335,107 @@ enum Opts {
         #[clap(short = 'o', long)]
         query_out: PathBuf,
 
-        /// Operate recursively on directories
-        #[clap(short = 'r', long, action)]
-        recursive: bool,
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
 
         /// Append to query-out instead of overwriting it
         #[clap(short, long, action)]
         append: bool,
 
+        /// Append even if the output files were written with a different format or term escaping
+        #[clap(long, action, requires = "append")]
+        force: bool,
+
+        /// Capacity in bytes of the output buffers
+        #[clap(long, default_value_t = DEFAULT_WRITE_BUFFER_CAPACITY)]
+        write_buffer_size: usize,
+
+        /// How to handle triples whose ids are missing from the compressor state.
+        /// For replicate the reported query number is the position of the dataset, or of its part with --max-triples-per-query
+        #[clap(arg_enum, long, default_value_t = MissingTermPolicy::Abort)]
+        on_missing_terms: MissingTermPolicy,
+
+        /// How terms are written into the queries, escaping keeps terms read with --no-parse from breaking them
+        #[clap(arg_enum, long, default_value_t = TermEscaping::Escape)]
+        term_escaping: TermEscaping,
+
+        /// Line terminator of the written queries and N-Triples
+        #[clap(arg_enum, long, default_value_t = LineEnding::Lf)]
+        line_ending: LineEnding,
+
+        /// Write non-ASCII characters of terms as \u escapes, for drivers that only handle ASCII
+        #[clap(long, action)]
+        ascii_escape: bool,
+
         #[clap(arg_enum, long, default_value_t = OutputFormat::Query)]
         output_format: OutputFormat,
 
+        /// Record the source dataset of every triple, requires --output-format n-triples
+        #[clap(arg_enum, long)]
+        provenance: Option<ProvenanceFormat>,
+
+        /// Additionally write the INSERT DATA and DELETE DATA queries to <query-out-stem>.insert.<ext>
+        /// and <query-out-stem>.delete.<ext>, requires --output-format query
+        #[clap(long, action)]
+        split_by_type: bool,
+
+        /// File with a template for every SPARQL query, with the placeholders {{triples}}, {{graph}} (IRI in <>,
+        /// empty for the default graph), {{query_id}} and {{operation}} (INSERT DATA or DELETE DATA), on a single line.
+        /// Requires --output-format query
+        #[clap(long)]
+        template: Option<PathBuf>,
+
+        /// Replicate all datasets as this operation instead of detecting it from the file name
+        #[clap(arg_enum, long)]
+        treat_as: Option<TreatAs>,
+
+        /// Order of the replicated datasets, as-specified keeps the order they are found in.
+        /// The alternating orders require --output-format query
+        #[clap(arg_enum, long, default_value_t = OutputOrder::AsSpecified)]
+        output_order: OutputOrder,
+
+        /// Seed of the randomized output order
+        #[clap(long)]
+        seed: Option<u64>,
+
+        /// Split datasets with more triples into several consecutive queries of the same type with at most this many
+        /// triples, for stores that reject large updates. Requires --output-format query
+        #[clap(long)]
+        max_triples_per_query: Option<usize>,
+
+        /// Merge consecutive datasets of the same type into one query while it has fewer triples, to reduce the
+        /// request overhead of many small changesets. Requires --output-format query
+        #[clap(long)]
+        min_triples_per_query: Option<usize>,
+
+        /// Datasets whose file name matches this glob are replicated as INSERT DATA queries
+        #[clap(long, value_parser = parse_glob, default_value = "*added.compressed_nt")]
+        insert_pattern: GlobMatcher,
+
+        /// Datasets whose file name matches this glob are replicated as DELETE DATA queries
+        #[clap(long, value_parser = parse_glob, default_value = "*removed.compressed_nt")]
+        delete_pattern: GlobMatcher,
+
+        /// Only load the inputs and print what would be generated, without writing anything
+        #[clap(long, action)]
+        dry_run: bool,
+
+        /// Parse every generated query with a SPARQL parser before declaring the run successful
+        #[clap(long, action)]
+        validate_output: bool,
+
+        #[clap(flatten)]
+        load_opts: LoadOpts,
+
+        /// Use a sorted copy of unsorted datasets instead of refusing them. The copy is written next to the
+        /// dataset as hidden `.<name>.sorted` file once and reused while it is newer than the dataset
+        #[clap(long, action)]
+        auto_sort: bool,
+
+        /// How containment in the include and exclude datasets is checked
+        #[clap(arg_enum, long, default_value_t = ContainmentStrategy::Auto)]
+        containment: ContainmentStrategy,
+
         /// The datasets to replicate
         compressed_datasets: Vec<PathBuf>,
     },
@@ -183,27 +445,79 @@ enum Opts {
         #[clap(short = 's', long)]
         compressor_state: PathBuf,
 
-        /// Operate recursively on directories
-        #[clap(short = 'r', long, action)]
-        recursive: bool,
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
+
+        /// Write every dataset into its own `.nt` file in this directory instead of all of them to stdout.
+        /// Datasets found by walking a directory keep their path below it
+        #[clap(long)]
+        out_dir: Option<PathBuf>,
+
+        /// Write gzip compressed `.nt.gz` files
+        #[clap(long, action, requires = "out-dir")]
+        gzip: bool,
+
+        /// Write `.hdt` files instead of N-Triples, they can be compressed again with `--features hdt`
+        #[clap(long, action, requires = "out-dir", conflicts_with = "gzip")]
+        hdt: bool,
+
+        /// Only decompress the triples matching this triple pattern, e.g. `?s <http://xmlns.com/foaf/0.1/name> *`.
+        /// Positions are terms in N-Triples syntax, `?name` or `*` for any term, or `~regex~` searching the term
+        /// in N-Triples syntax. Can be given multiple times to keep the triples matching any of them
+        #[clap(long, value_parser)]
+        pattern: Vec<TriplePattern>,
 
         /// The datasets to replicate
         compressed_datasets: Vec<PathBuf>,
     },
     /// Print stats about compressed datasets (triple count, number of subjects, predicates, objects)
     Stats {
-        /// Operate recursively on directories
-        #[clap(short = 'r', long, action)]
-        recursive: bool,
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
+
+        /// Instead of the stats of each dataset print how the sorted dataset B differs from the sorted dataset A:
+        /// added and removed triples, changes of the distinct term counts and of the triples per predicate
+        #[clap(long, number_of_values = 2, value_names = &["A", "B"], conflicts_with = "compressed-datasets")]
+        compare: Option<Vec<PathBuf>>,
+
+        /// Compressor state to print the predicates of --compare with instead of their ids. Without --compare
+        /// the datasets with metadata are checked to be compressed with it
+        #[clap(short = 's', long)]
+        compressor_state: Option<PathBuf>,
+
+        /// Print this many of the most frequent terms of the compressor state, from the counts written by `compress --term-counts`
+        #[clap(long, requires = "compressor-state", conflicts_with = "compare")]
+        top_terms: Option<usize>,
 
         /// The datasets to analyze
         compressed_datasets: Vec<PathBuf>,
     },
+    /// Compress an N-Triples file with a new compressor state, decompress it again and report the triples that differ
+    VerifyRoundtrip {
+        /// Don't run a parser to sanitize the input, as `compress --no-parse` does
+        #[clap(short = 'N', long, action)]
+        no_parse: bool,
+
+        /// Print at most this many of the lost and of the added triples
+        #[clap(long, default_value_t = 10)]
+        show: usize,
+
+        /// The N-Triples file to check
+        file: PathBuf,
+    },
+    /// Print an order independent fingerprint of compressed datasets, to check datasets on different machines
+    /// for equality without transferring them. Only comparable between datasets compressed with the same compressor state
+    Fingerprint {
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
+
+        /// The datasets to fingerprint
+        compressed_datasets: Vec<PathBuf>,
+    },
     /// Sort compressed datasets so that they can be used as main datasets for query generation or contained
     Sort {
-        /// Operate recursively on directories
-        #[clap(short = 'r', long, action)]
-        recursive: bool,
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
 
         /// The datasets to sort
         compressed_datasets: Vec<PathBuf>,
@@ -214,13 +528,213 @@ enum Opts {
         #[clap(short = 'd', long)]
         main_dataset: PathBuf,
 
-        /// Operate recursively on directories
-        #[clap(short = 'r', long, action)]
-        recursive: bool,
+        #[clap(flatten)]
+        walk_opts: WalkOpts,
+
+        #[clap(flatten)]
+        load_opts: LoadOpts,
+
+        /// Use a sorted copy of unsorted datasets instead of refusing them. The copy is written next to the
+        /// dataset as hidden `.<name>.sorted` file once and reused while it is newer than the dataset
+        #[clap(long, action)]
+        auto_sort: bool,
+
+        /// How containment in the main dataset is checked
+        #[clap(arg_enum, long, default_value_t = ContainmentStrategy::Auto)]
+        containment: ContainmentStrategy,
+
+        /// Only print the summary over all datasets instead of a line per dataset
+        #[clap(long, action)]
+        summary_only: bool,
+
+        /// Write the contained triples of every dataset as CSV to this file
+        #[clap(long)]
+        csv_out: Option<PathBuf>,
 
         /// The datasets to check against the main dataset
         compressed_datasets: Vec<PathBuf>,
     },
+    /// Write the fractions of the triples of every changeset contained in `main_dataset` and in other changesets as CSV
+    Overlap {
+        /// The main dataset to check against
+        #[clap(short = 'd', long)]
+        main_dataset: PathBuf,
+
+        /// Path to the compressed changeset file or directory tree containing the compressed changesets
+        #[clap(short = 'c', long)]
+        compressed_changesets: PathBuf,
+
+        #[clap(flatten)]
+        traversal_opts: TraversalOpts,
+
+        /// File to write the CSV to
+        #[clap(short = 'o', long)]
+        out: PathBuf,
+
+        /// Also write the pairwise overlap between all changesets as a CSV matrix to this file
+        #[clap(long)]
+        pairwise_out: Option<PathBuf>,
+
+        #[clap(flatten)]
+        load_opts: LoadOpts,
+    },
+    /// Apply changesets to a base dataset in order and write every resulting dataset version v0...vn
+    /// together with the delta of each version
+    Versions {
+        /// The dataset of version 0
+        #[clap(short = 'd', long)]
+        base_dataset: PathBuf,
+
+        /// Path to the compressed changeset file or directory tree containing the compressed changesets.
+        /// Files with the same name up to the first `.` form one changeset
+        #[clap(short = 'c', long)]
+        compressed_changesets: PathBuf,
+
+        #[clap(flatten)]
+        traversal_opts: TraversalOpts,
+
+        /// Changeset files whose file name matches this glob contain added triples
+        #[clap(long, value_parser = parse_glob, default_value = "*added.compressed_nt")]
+        insert_pattern: GlobMatcher,
+
+        /// Changeset files whose file name matches this glob contain removed triples
+        #[clap(long, value_parser = parse_glob, default_value = "*removed.compressed_nt")]
+        delete_pattern: GlobMatcher,
+
+        /// Directory to write the versions and versions.csv to
+        #[clap(short = 'o', long)]
+        out_dir: PathBuf,
+    },
+    /// Build the current state of a dataset by applying the added and removed files of a changeset tree in order,
+    /// writing a sorted dataset without duplicates that can be used as main dataset
+    BuildDataset {
+        /// Dataset to apply the changesets to, an empty dataset if not given
+        #[clap(short = 'd', long)]
+        base_dataset: Option<PathBuf>,
+
+        /// Path to the compressed changeset file or directory tree containing the compressed changesets.
+        /// Files with the same name up to the first `.` form one changeset
+        #[clap(short = 'c', long)]
+        compressed_changesets: PathBuf,
+
+        #[clap(flatten)]
+        traversal_opts: TraversalOpts,
+
+        /// Changeset files whose file name matches this glob contain added triples
+        #[clap(long, value_parser = parse_glob, default_value = "*added.compressed_nt")]
+        insert_pattern: GlobMatcher,
+
+        /// Changeset files whose file name matches this glob contain removed triples
+        #[clap(long, value_parser = parse_glob, default_value = "*removed.compressed_nt")]
+        delete_pattern: GlobMatcher,
+
+        /// Output file for the resulting dataset
+        #[clap(short = 'o', long)]
+        out: PathBuf,
+    },
+    /// Load a compressed dataset into a SPARQL endpoint in chunks, to prepare the store of a benchmark
+    Load {
+        /// Path to the associated compressor state
+        #[clap(short = 's', long)]
+        compressor_state: PathBuf,
+
+        /// Path to the compressed dataset
+        #[clap(short = 'i', long)]
+        compressed_dataset: PathBuf,
+
+        /// URL of the SPARQL update endpoint, or of the graph store for --method graph-store
+        #[clap(long)]
+        endpoint: String,
+
+        /// Graph to load the triples into instead of the default graph
+        #[clap(long)]
+        graph: Option<String>,
+
+        #[clap(arg_enum, long, default_value_t = LoadMethod::Update)]
+        method: LoadMethod,
+
+        /// Number of triples sent per request
+        #[clap(long, default_value_t = 10_000)]
+        chunk_size: usize,
+
+        /// Continue an interrupted load from <compressed-dataset>.load-progress.json, skipping the loaded triples
+        #[clap(long, action)]
+        resume: bool,
+    },
+    /// Write a random sample of a compressed dataset as a new sorted dataset
+    Sample {
+        /// Path to the compressed dataset
+        #[clap(short = 'i', long)]
+        compressed_dataset: PathBuf,
+
+        /// Number of triples to sample, all triples are taken if the dataset has fewer
+        #[clap(short = 'n', long)]
+        n_triples: usize,
+
+        /// How the triples are drawn, `predicate` keeps the share of triples of every predicate
+        #[clap(arg_enum, long, default_value_t = Stratification::None)]
+        stratify: Stratification,
+
+        /// Seed of the sample, makes it reproducible
+        #[clap(long)]
+        seed: Option<u64>,
+
+        /// Output file for the sampled dataset
+        #[clap(short = 'o', long)]
+        out: PathBuf,
+    },
+    /// Estimate the output sizes and generation time of query specs by serializing a sample of the main dataset
+    Estimate {
+        /// Path to the associated compressor state
+        #[clap(short = 's', long)]
+        compressor_state: PathBuf,
+
+        /// Path to the compressed dataset
+        #[clap(short = 'i', long)]
+        compressed_dataset: PathBuf,
+
+        #[clap(arg_enum, long, default_value_t = OutputFormat::Query)]
+        prepare_query_format: OutputFormat,
+
+        /// How terms would be written into the queries
+        #[clap(arg_enum, long, default_value_t = TermEscaping::Escape)]
+        term_escaping: TermEscaping,
+
+        /// Number of triples to sample
+        #[clap(long, default_value_t = 10_000)]
+        n_samples: usize,
+
+        #[clap(flatten)]
+        load_opts: LoadOpts,
+
+        /// Query specs as for generate
+        #[clap(value_parser)]
+        query_specs: Vec<QuerySpecOpt>,
+    },
+    /// Convert a generated query file into another format without generating it again.
+    /// Needs one INSERT DATA or DELETE DATA query per line with its terms in N-Triples syntax
+    ConvertQueries {
+        /// Format to convert into
+        #[clap(arg_enum, short = 'f', long)]
+        format: ConvertFormat,
+
+        /// Output file, or output directory for the per-file format
+        #[clap(short = 'o', long)]
+        out: PathBuf,
+
+        /// Gzip compress the output files
+        #[clap(long, action)]
+        gzip: bool,
+
+        /// The query file to convert
+        query_file: PathBuf,
+    },
+    /// Count the queries and triples of a query file.
+    /// Needs one INSERT DATA or DELETE DATA query per line with its terms in N-Triples syntax
+    QueryStats {
+        /// The query file to count
+        query_file: PathBuf,
+    },
 }
 
 #[derive(Subcommand)]
@@ -231,6 +745,20 @@ enum GenerateType {
         /// with common triples
         #[clap(short = 'd', long, action)]
         allow_duplicates: bool,
+
+        /// Compressed triples file recording all triples emitted so far. Triples in it are not selected again
+        /// and the emitted triples are added to it, extending the distinct guarantee across runs
+        #[clap(long, conflicts_with = "allow-duplicates")]
+        used_triples_state: Option<PathBuf>,
+
+        /// Select at least this many triples of every subject in a query (fewer only to fill up a query),
+        /// requires a sorted main dataset
+        #[clap(long)]
+        min_per_subject: Option<usize>,
+
+        /// Select at most this many triples of every subject in a query, requires a sorted main dataset
+        #[clap(long)]
+        max_per_subject: Option<usize>,
     },
 
     /// derives the queries from a set of changesets
@@ -239,12 +767,96 @@ enum GenerateType {
         #[clap(short = 'c', long)]
         compressed_changesets: PathBuf,
 
+        #[clap(flatten)]
+        traversal_opts: TraversalOpts,
+
         /// Query generation type
         #[clap(arg_enum, short = 't', long = "type", default_value_t = GenerateChangesetType::AsIs)]
         generate_type: GenerateChangesetType,
+
+        /// JSON object mapping changeset paths (or trailing parts of them) to weights, changesets with higher
+        /// weights are preferred. Unlisted changesets have weight 1, changesets with weight 0 are never used
+        #[clap(long)]
+        changeset_weights: Option<PathBuf>,
+
+        /// Changeset files whose file name matches this glob contain added triples, only insert queries are generated from them
+        #[clap(long, value_parser = parse_glob, default_value = "*added.compressed_nt")]
+        insert_pattern: GlobMatcher,
+
+        /// Changeset files whose file name matches this glob contain removed triples, only delete queries are generated from them
+        #[clap(long, value_parser = parse_glob, default_value = "*removed.compressed_nt")]
+        delete_pattern: GlobMatcher,
+
+        /// Generate insert and delete queries from all changeset files regardless of their name
+        #[clap(long, action)]
+        mix_added_removed: bool,
+
+        /// For fixed-size generation, start every query at the first changeset instead of where the previous one
+        /// stopped, so that queries may share triples
+        #[clap(long, action)]
+        allow_overlap: bool,
+
+        /// What percentage query sizes (5%) refer to
+        #[clap(arg_enum, long, default_value_t = PercentageBase::Dataset)]
+        percentage_of: PercentageBase,
+
+        /// For fixed-size generation, group the changesets into consecutive time windows of this length (e.g. 6h or 1d)
+        /// and stitch every query from the changesets of a window and the windows following it in chronological order.
+        /// Changeset times are taken from <year>/<month>/<day>/<hour> directories in their path or their modification time
+        #[clap(long, value_parser = parse_duration, conflicts_with = "changeset-weights")]
+        window: Option<Duration>,
+
+        /// For as-is generation, which of the changesets equally close in size to a query to use.
+        /// By default the first one in path order
+        #[clap(arg_enum, long)]
+        prefer: Option<ChangesetPreference>,
+
+        /// For fixed-size generation, which triples a query keeps of the changesets it stitched when the last one has
+        /// more than needed
+        #[clap(arg_enum, long, default_value_t = ChangesetTruncation::End)]
+        truncate: ChangesetTruncation,
+    },
+
+    /// combines randomized and changeset generation in one run, the queries of all parts are ordered together
+    Mix {
+        /// JSON file listing the parts of the workload, every part with its strategy, options and query specs.
+        /// Takes the place of the query specs on the command line
+        #[clap(short = 'w', long)]
+        workload: PathBuf,
+
+        /// How the changeset directories of the changeset parts are traversed
+        #[clap(flatten)]
+        traversal_opts: TraversalOpts,
     },
 }
 
+#[derive(ArgEnum, Clone, Copy)]
+enum PercentageBase {
+    /// the triples of the main dataset
+    Dataset,
+
+    /// the triples of all changesets together
+    TotalChangesets,
+
+    /// the average number of triples of the non-empty changesets
+    AverageChangeset,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum TreatAs {
+    Insert,
+    Delete,
+}
+
+impl TreatAs {
+    fn query_type(self) -> QueryType {
+        match self {
+            TreatAs::Insert => QueryType::InsertData,
+            TreatAs::Delete => QueryType::DeleteData,
+        }
+    }
+}
+
 #[derive(ArgEnum, Clone)]
 enum GenerateChangesetType {
     /// tries to fulfill the requested query sizes as closely as possible
@@ -256,24 +868,252 @@ enum GenerateChangesetType {
     FixedSize,
 }
 
-#[derive(ArgEnum, Clone, PartialEq, Eq)]
-enum OutputFormat {
-    Query,
-    NTriples,
+/// Prints the triple counts of compressing the dataset `name`
+fn print_compression_stats(name: &str, stats: &CompressionStats) {
+    let input_bytes = stats
+        .input_bytes
+        .map_or_else(|| "unknown".to_owned(), |n_bytes| n_bytes.to_string());
+
+    println!(
+        "{name}: input bytes = {input_bytes}, output bytes = {}, triples = {}, new terms = {}, duplicates skipped = {}, \
+         filtered = {}, blank node triples = {}, malformed = {}, filtered literals = {}, normalized IRIs = {}",
+        stats.output_bytes,
+        stats.n_triples,
+        stats.n_new_terms,
+        stats.n_duplicates,
+        stats.n_filtered,
+        stats.n_blank_nodes,
+        stats.n_malformed,
+        stats.n_filtered_literals,
+        stats.n_normalized_iris
+    );
+}
+
+fn print_dataset_metadata(meta: &DatasetMetadata, state_fingerprint: Option<&str>) {
+    let source_mtime = meta
+        .source_mtime
+        .map_or_else(|| "unknown".to_owned(), |mtime| mtime.to_string());
+
+    println!(
+        "  compressed from {:?} (modified at {source_mtime}) by version {}: filtered = {}, malformed = {}, \
+         duplicates skipped = {}, state fingerprint = {}",
+        meta.source,
+        meta.tool_version,
+        meta.stats.n_filtered,
+        meta.stats.n_malformed,
+        meta.stats.n_duplicates,
+        meta.state_fingerprint
+    );
+
+    match state_fingerprint {
+        Some(fingerprint) if fingerprint != meta.state_fingerprint => {
+            println!("  not compressed with the given compressor state, its fingerprint is {fingerprint}")
+        },
+        _ => (),
+    }
+}
+
+/// Checks whether the file at `path` can be read into memory, recording a warning if not
+fn should_load_into_memory(path: &Path) -> std::io::Result<bool> {
+    if fits_in_memory(std::fs::metadata(path)?.len()) {
+        Ok(true)
+    } else {
+        warn(Warning::MemoryMappedFallback { path: path.to_owned() });
+        Ok(false)
+    }
+}
+
+fn load_dataset(path: &Path, into_memory: bool) -> std::io::Result<CompressedRdfTriples> {
+    if into_memory && should_load_into_memory(path)? {
+        CompressedRdfTriples::read_into_memory(path)
+    } else {
+        unsafe { CompressedRdfTriples::load(path) }
+    }
+}
+
+/// Path in `out_dir` that `dataset` is decompressed to, datasets found below one of `roots` keep their path below it
+fn decompressed_path(out_dir: &Path, roots: &[PathBuf], dataset: &Path, extension: &str) -> PathBuf {
+    let relative = roots
+        .iter()
+        .find_map(|root| dataset.strip_prefix(root).ok().filter(|rel| !rel.as_os_str().is_empty()))
+        .unwrap_or_else(|| Path::new(dataset.file_name().unwrap_or_default()));
+
+    out_dir.join(relative).with_extension(extension)
+}
+
+/// Path of the sorted copy `--auto-sort` makes of the dataset at `path`
+fn sorted_copy_path(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".sorted");
+    path.with_file_name(file_name)
+}
+
+/// Replaces the unsorted `dataset` loaded from `path` by its sorted copy if `auto_sort` is set,
+/// the copy is only written if it does not exist or is older than the dataset
+fn auto_sorted(
+    dataset: CompressedRdfTriples,
+    path: &Path,
+    into_memory: bool,
+    auto_sort: bool,
+) -> std::io::Result<CompressedRdfTriples> {
+    if !auto_sort || dataset.is_sorted() {
+        return Ok(dataset);
+    }
+
+    let sorted_path = sorted_copy_path(path);
+    let is_fresh =
+        sorted_path.exists() && std::fs::metadata(&sorted_path)?.modified()? >= std::fs::metadata(path)?.modified()?;
+
+    if !is_fresh {
+        println!("sorting a copy of {path:?} into {sorted_path:?}...");
+        let mut triples = dataset.to_vec();
+        drop(dataset);
+
+        triples.sort_unstable();
+        save_compressed_triples(&sorted_path, &triples)?;
+    }
+
+    println!("using the sorted copy {sorted_path:?} of {path:?}");
+    load_dataset(&sorted_path, into_memory)
+}
+
+/// Loads the dataset at `path` that containment is checked against
+fn load_containment_dataset(path: &Path, into_memory: bool, auto_sort: bool) -> std::io::Result<CompressedRdfTriples> {
+    auto_sorted(load_dataset(path, into_memory)?, path, into_memory, auto_sort)
+}
+
+/// Loads the compressed changesets found at `path`, skipping empty and unreadable ones with a warning
+fn load_changesets(path: &Path, traversal_opts: &TraversalOpts) -> Vec<(PathBuf, CompressedRdfTriples)> {
+    changeset_file_iter(path, traversal_opts, COMPRESSED_TRIPLE_FILE_EXTENSION)
+        .map(Result::unwrap)
+        .filter_map(|path| match unsafe { CompressedRdfTriples::load(&path) } {
+            Ok(triples) if triples.is_empty() => {
+                warn(Warning::SkippedFile { path, reason: "contains no triples".to_owned() });
+                None
+            },
+            Ok(triples) => Some((path, triples)),
+            Err(e) => {
+                warn(Warning::SkippedFile { path, reason: format!("unable to open: {e:?}") });
+                None
+            },
+        })
+        .collect()
+}
+
+/// Prepares the dataset loaded from `path` for containment checks, `what` names it in errors
+fn containment_index<'d>(
+    triples: &'d CompressedRdfTriples,
+    path: &Path,
+    strategy: ContainmentStrategy,
+    what: &str,
+) -> Result<ContainmentIndex<'d>, String> {
+    let index = ContainmentIndex::new(triples, strategy).map_err(|e| format!("{what} {path:?}: {e}"))?;
+
+    if strategy == ContainmentStrategy::Auto && matches!(index, ContainmentIndex::Hashed(_)) {
+        println!("{what} {path:?} is not sorted, checking containment with a hash set of its triples");
+    }
+
+    Ok(index)
+}
+
+/// Reports the insert triples of generate that are contained in one of the `datasets` given with their paths, and warns
+/// about the ones inserted by more than one query since all but the first insert of them find the triple already in
+/// the store. `inserted` holds the triples with the number of the insert query they were generated for
+fn check_insert_triples(
+    mut inserted: Vec<(CompressedTriple, usize)>,
+    datasets: &[(&CompressedRdfTriples, &Path)],
+) -> Result<(), String> {
+    let what = if datasets.len() > 1 {
+        "input datasets"
+    } else {
+        "main dataset"
+    };
+    println!("checking insert triples against the {what}...");
+
+    inserted.sort_unstable();
+    inserted.dedup();
+
+    let n_inserted = inserted.len();
+    inserted.dedup_by_key(|(triple, _)| *triple);
+    let n_repeated = n_inserted - inserted.len();
+
+    let indices = datasets
+        .iter()
+        .map(|&(triples, path)| containment_index(triples, path, ContainmentStrategy::Auto, "dataset"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut checkers: Vec<_> = indices.iter().map(ContainmentIndex::checker).collect();
+    let n_contained = inserted
+        .iter()
+        .filter(|(triple, _)| checkers.iter_mut().any(|checker| checker.contains(triple)))
+        .count();
+
+    println!(
+        "{n_contained} of {} distinct insert triples ({:.2}%) are contained in the {what}, run the prepare queries before the benchmark to remove them",
+        inserted.len(),
+        overlap::fraction(n_contained, inserted.len()) * 100.0
+    );
+
+    if n_repeated > 0 {
+        warn(Warning::RepeatedInserts { n_triples: n_repeated });
+    }
+
+    Ok(())
+}
+
+fn load_state(path: &Path, into_memory: bool) -> std::io::Result<RdfTripleDecompressor> {
+    if into_memory && should_load_into_memory(path)? {
+        RdfTripleDecompressor::read_state_into_memory(path)
+    } else {
+        unsafe { RdfTripleDecompressor::load_state(path) }
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let opts: Opts = Opts::parse();
+    let cli = Cli::parse();
 
+    util::set_max_memory(cli.max_memory);
+    run(cli.command)?;
+
+    let warnings = warnings::collected();
+    warnings::print_summary(&warnings);
+
+    if let Some(warnings_out) = cli.warnings_out {
+        warnings::write_json(warnings_out, &warnings)?;
+    }
+
+    if cli.fail_on_warning && !warnings.is_empty() {
+        return Err(format!("{} warnings were encountered", warnings.len()).into());
+    }
+
+    Ok(())
+}
+
+fn run(opts: Opts) -> Result<(), Box<dyn std::error::Error>> {
     match opts {
         Opts::Compress {
             previous_compressor_state,
             compressor_state_out,
-            recursive,
+            walk_opts,
             dedup,
+            dedup_against,
             no_parse,
+            keep_language,
+            keep_datatype,
+            normalize_iris,
+            normalization_report,
+            expected_terms,
+            stats_out,
+            metadata,
+            incremental,
+            shards,
+            term_counts,
             datasets,
         } => {
+            if let Some(shards) = shards {
+                check_shard_count(shards)?;
+            }
+
             let compressor_state_out = compressor_state_out.as_ref().unwrap_or_else(|| {
                 previous_compressor_state
                     .as_ref()
@@ -281,171 +1121,1256 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             });
 
             let mut compressor = if let Some(pcs) = &previous_compressor_state {
+                // the same IRI would get an id for its normalized form and one for every other spelling
+                match (iri_normalization::is_normalized_state(pcs), normalize_iris) {
+                    (false, true) => return Err(format!("{pcs:?} was compressed without --normalize-iris, extend it without it or compress all datasets again with it").into()),
+                    (true, false) => return Err(format!("{pcs:?} was compressed with --normalize-iris, extend it with it as well").into()),
+                    _ => (),
+                }
+
                 println!("loading previous compressor state...");
                 let frozen = unsafe { RdfTripleDecompressor::load_state(pcs)? };
-                RdfTripleCompressor::from_decompressor(frozen)
+                RdfTripleCompressor::from_decompressor(frozen)?
             } else {
                 RdfTripleCompressor::new()
             };
 
-            for dataset in dataset_iter(datasets, recursive, UNCOMPRESSED_TRIPLE_FILE_EXTENSION) {
+            if let Some(expected_terms) = expected_terms {
+                compressor.reserve_terms(expected_terms);
+            }
+
+            compressor.set_literal_filter(LiteralFilter::new(&keep_language, &keep_datatype));
+            compressor.set_iri_normalization(normalize_iris, normalization_report.is_some());
+
+            if term_counts {
+                let previous_counts = match &previous_compressor_state {
+                    Some(pcs) => TermCounts::read(pcs)?,
+                    None => Some(TermCounts::default()),
+                };
+
+                compressor.set_term_counts(previous_counts.unwrap_or_else(|| {
+                    println!(
+                        "note: the previous compressor state has no term counts, only the new triples are counted"
+                    );
+                    TermCounts::default()
+                }));
+            }
+
+            for path in &dedup_against {
+                println!("seeding deduplication with {path:?}...");
+                let seen = unsafe { CompressedRdfTriples::load(path)? };
+                compressor.seed_dedup(&seen)?;
+            }
+
+            let mut report = CompressionReport { files: Vec::new(), total: CompressionStats::default() };
+            let mut interrupted_at = None;
+
+            // an interrupt finishes the current file and still saves the state, which the compressed files need
+            util::catch_interrupts();
+
+            for dataset in dataset_iter(datasets, &walk_opts, UNCOMPRESSED_TRIPLE_FILE_EXTENSION) {
                 let dataset = dataset?;
 
+                if util::is_interrupted() {
+                    interrupted_at = Some(dataset);
+                    break;
+                }
+
                 println!("compressing {:?}...", dataset);
-                compressor.compress_rdf_triple_file(dataset, dedup, !no_parse)?;
+                let stats = compressor.compress_rdf_triple_file(&dataset, dedup, !no_parse)?;
+                print_compression_stats(&format!("{dataset:?}"), &stats);
+
+                report.total.add(&stats);
+                report.files.push(CompressedFileStats { path: dataset, stats });
+            }
+
+            if report.files.len() > 1 {
+                print_compression_stats("total", &report.total);
+            }
+
+            if let Some(stats_out) = stats_out {
+                report.write(stats_out)?;
+            }
+
+            if let (Some(normalization_report), Some(variants)) = (normalization_report, compressor.iri_variants()) {
+                println!(
+                    "{} IRIs were merged with other spellings",
+                    iri_normalization::merged_variants(variants).count()
+                );
+                iri_normalization::write_variant_report(normalization_report, variants)?;
+            }
+
+            if incremental {
+                println!("appending new terms to compressor state...");
+                compressor.save_state_segment(compressor_state_out)?;
+            } else if let Some(shards) = shards {
+                println!("saving compressor state in {shards} shards...");
+                compressor.save_state_sharded(compressor_state_out, shards)?;
+            } else {
+                println!("saving compressor state...");
+                compressor.save_state(compressor_state_out)?;
+            }
+
+            iri_normalization::record_normalization(compressor_state_out, normalize_iris)?;
+
+            match compressor.term_counts() {
+                Some(counts) => {
+                    println!("saving counts of {} terms...", counts.len());
+                    counts.write(compressor_state_out)?;
+                },
+                None => TermCounts::remove(compressor_state_out)?,
+            }
+
+            if metadata {
+                let state_fingerprint = compressor.state_fingerprint();
+
+                for CompressedFileStats { path, stats } in report.files.iter() {
+                    DatasetMetadata::new(path.clone(), *stats, state_fingerprint)?.write(&compressed_path(path))?;
+                }
             }
 
-            println!("saving compressor state...");
-            compressor.save_state(compressor_state_out)?;
+            if let Some(dataset) = interrupted_at {
+                return Err(format!(
+                    "interrupted after compressing {} files, {dataset:?} and the files after it were not compressed",
+                    report.files.len()
+                )
+                .into());
+            }
         },
         Opts::Generate {
             compressor_state,
             compressed_dataset,
+            dataset_weight,
             query_out,
             prepare_query_out,
             prepare_query_format,
             query_specs,
+            warmup,
+            seed,
+            checkpoint_every,
+            resume,
             g_type,
             output_order,
             append,
+            force,
+            write_buffer_size,
+            max_output_bytes,
+            max_total_triples,
+            selector,
+            pattern,
+            on_missing_terms,
+            term_escaping,
+            line_ending,
+            ascii_escape,
+            split_by_type,
+            template,
+            dry_run,
+            validate_output,
+            check_inserts,
+            perf_report,
+            perf_batch_size,
+            load_opts: LoadOpts { load_into_memory },
+            auto_sort,
         } => {
-            println!("loading compressor state...");
-            let decompressor = unsafe { RdfTripleDecompressor::load_state(compressor_state)? };
+            let seed = if resume {
+                match (manifest::Checkpoint::read(&query_out)?.seed, seed) {
+                    (Some(recorded), Some(seed)) if recorded != seed => {
+                        return Err(format!("--seed {seed} differs from the seed {recorded} of the checkpoint").into())
+                    },
+                    (Some(recorded), _) => Some(recorded),
+                    (None, _) => {
+                        return Err("the checkpoint was written without a seed, the run cannot be replayed".into())
+                    },
+                }
+            } else if checkpoint_every.is_some() && seed.is_none() {
+                let seed = rand::random();
+                println!("checkpointing the run with --seed {seed}");
+                Some(seed)
+            } else {
+                seed
+            };
+
+            // separate random streams for the spec resolution and every generator
+            let stream_seed = |stream: u64| util::stream_seed(seed, stream);
+
+            let write_opts = WriteOpts {
+                append,
+                buffer_capacity: write_buffer_size,
+                missing_terms: on_missing_terms,
+                term_escaping,
+                force,
+                max_output_bytes,
+                max_total_triples,
+                line_ending,
+                ascii_escape,
+                provenance: None,
+                checkpoint_every,
+                resume,
+                seed,
+                split_by_type,
+                template: template.map(QueryTemplate::load).transpose()?.map(Arc::new),
+            };
+
+            let selector = selector.as_deref().map(TripleSelector::load).transpose()?;
+
+            println!("loading compressor state...");
+            let decompressor = load_state(&compressor_state, load_into_memory)?;
 
             println!("loading main dataset...");
-            let dataset_triples = unsafe { CompressedRdfTriples::load(compressed_dataset)? };
+            let dataset_triples = auto_sorted(
+                load_dataset(&compressed_dataset[0], load_into_memory)?,
+                &compressed_dataset[0],
+                load_into_memory,
+                auto_sort,
+            )?;
 
             println!("loaded {} distinct triples from main dataset", dataset_triples.len());
 
-            let query_specs: Vec<_> = query_specs
-                .into_iter()
-                .map(
-                    |QuerySpecOpt { n_queries, n_triples_per_query, query_type }| sparql::QuerySpec {
-                        n_queries,
-                        n_triples_per_query: n_triples_per_query.get_absolute(dataset_triples.len()),
-                        query_type,
-                    },
+            if dataset_triples.is_empty() {
+                return Err(format!("main dataset {:?} contains no triples", compressed_dataset[0]).into());
+            }
+
+            let mut extra_datasets = Vec::new();
+
+            for path in &compressed_dataset[1..] {
+                println!("loading dataset {path:?}...");
+                let triples = auto_sorted(load_dataset(path, load_into_memory)?, path, load_into_memory, auto_sort)?;
+
+                if triples.is_empty() {
+                    return Err(format!("dataset {path:?} contains no triples").into());
+                }
+
+                extra_datasets.push(triples);
+            }
+
+            let dataset_weights = if dataset_weight.is_empty() {
+                vec![1.0; compressed_dataset.len()]
+            } else if dataset_weight.len() != compressed_dataset.len() {
+                return Err(format!(
+                    "got {} dataset weights for {} datasets",
+                    dataset_weight.len(),
+                    compressed_dataset.len()
                 )
+                .into());
+            } else if dataset_weight.iter().any(|weight| !weight.is_finite() || *weight < 0.0)
+                || dataset_weight.iter().sum::<f64>() <= 0.0
+            {
+                return Err("dataset weights must be non-negative and not all zero".into());
+            } else {
+                dataset_weight
+            };
+
+            if !extra_datasets.is_empty() {
+                match &g_type {
+                    GenerateType::Randomized {
+                        used_triples_state: None,
+                        min_per_subject: None,
+                        max_per_subject: None,
+                        ..
+                    }
+                    | GenerateType::Mix { .. } => (),
+                    _ => return Err("multiple datasets are only supported by mix and by randomized generation without --used-triples-state, --min-per-subject and --max-per-subject".into()),
+                }
+
+                if extra_datasets
+                    .iter()
+                    .chain([&dataset_triples])
+                    .any(|dataset| !dataset.is_sorted())
+                {
+                    return Err("multiple datasets must be sorted to leave out the triples shared with an earlier dataset, run sort on them first or pass --auto-sort".into());
+                }
+            }
+
+            let datasets: Vec<&CompressedRdfTriples> =
+                std::iter::once(&dataset_triples).chain(&extra_datasets).collect();
+
+            // a triple in several datasets is only selected from the first of them, so that queries share no triples
+            // without --allow-duplicates and no query holds a triple twice
+            let candidate_ixs = if datasets.len() > 1 {
+                util::check_memory(
+                    (extra_datasets.iter().map(|dataset| dataset.len()).sum::<usize>() * std::mem::size_of::<usize>())
+                        as u64,
+                    "collecting the triples not shared with an earlier dataset",
+                )?;
+
+                rdf::triple_generator::disjoint_candidate_indices(&datasets)
+            } else {
+                vec![None]
+            };
+
+            let n_candidates: Vec<usize> = datasets
+                .iter()
+                .zip(&candidate_ixs)
+                .map(|(dataset, candidate_ixs)| candidate_ixs.as_ref().map_or(dataset.len(), Vec::len))
+                .collect();
+
+            let n_total_triples = n_candidates.iter().sum();
+
+            let used_triples = match &g_type {
+                GenerateType::Randomized { used_triples_state: Some(path), .. } if path.exists() => {
+                    println!("loading used triples state...");
+                    Some(CompressedRdfTriples::read_into_memory(path)?)
+                },
+                _ => None,
+            };
+
+            if used_triples.is_some() {
+                util::check_memory(
+                    (dataset_triples.len() * std::mem::size_of::<usize>()) as u64,
+                    "collecting the unused triples",
+                )?;
+            }
+
+            let unused_ixs = used_triples
+                .as_ref()
+                .map(|used| rdf::triple_generator::unused_triple_indices(&dataset_triples, used));
+
+            let query_specs: Vec<_> = warmup
+                .into_iter()
+                .map(|spec| QuerySpecOpt { warmup: true, ..spec })
+                .chain(query_specs)
                 .collect();
 
+            let workload = match &g_type {
+                GenerateType::Mix { workload, .. } => Some(Workload::read(workload)?),
+                _ => None,
+            };
+
+            let mut rng = util::seeded_rng(stream_seed(0));
+
+            let query_specs: Vec<_> = match &g_type {
+                GenerateType::Randomized { allow_duplicates, .. } => {
+                    let n_distinct_available =
+                        (!allow_duplicates).then(|| unused_ixs.as_ref().map_or(n_total_triples, Vec::len));
+
+                    query_spec::resolve_random_specs(query_specs, n_total_triples, n_distinct_available, &mut rng)?
+                },
+                GenerateType::Changeset { compressed_changesets, traversal_opts, percentage_of, .. } => {
+                    let n_base_triples = match percentage_of {
+                        PercentageBase::Dataset => dataset_triples.len(),
+                        base => {
+                            let mut sizes = Vec::new();
+
+                            for path in changeset_file_iter(
+                                compressed_changesets,
+                                traversal_opts,
+                                COMPRESSED_TRIPLE_FILE_EXTENSION,
+                            ) {
+                                let n_bytes = std::fs::metadata(path?)?.len() as usize;
+                                sizes.push(n_bytes / std::mem::size_of::<CompressedTriple>());
+                            }
+
+                            sizes.retain(|&size| size > 0);
+                            let total: usize = sizes.iter().sum();
+
+                            match base {
+                                PercentageBase::AverageChangeset if !sizes.is_empty() => total / sizes.len(),
+                                _ => total,
+                            }
+                        },
+                    };
+
+                    query_specs
+                        .into_iter()
+                        .flat_map(|spec| spec.resolve(n_base_triples, &mut rng))
+                        .collect()
+                },
+                GenerateType::Mix { .. } => {
+                    if !query_specs.is_empty() {
+                        return Err(
+                            "the query specs of mix are taken from the workload file, including warm-up queries".into(),
+                        );
+                    }
+
+                    let workload = workload.as_ref().expect("workload is read for mix");
+                    let mut resolved = Vec::new();
+                    let mut n_distinct = 0;
+
+                    for (part_ix, part) in workload.parts.iter().enumerate() {
+                        let specs = part.specs()?;
+
+                        let part_specs = match part {
+                            WorkloadPart::Randomized { allow_duplicates, .. } => {
+                                let n_distinct_available = (!allow_duplicates).then_some(n_total_triples);
+                                let part_specs = query_spec::resolve_random_specs(
+                                    specs,
+                                    n_total_triples,
+                                    n_distinct_available,
+                                    &mut rng,
+                                )?;
+
+                                if !allow_duplicates {
+                                    n_distinct +=
+                                        part_specs.iter().map(sparql::QuerySpec::n_selected_triples).sum::<usize>();
+                                }
+
+                                part_specs
+                            },
+                            WorkloadPart::Changeset { .. } => specs
+                                .into_iter()
+                                .flat_map(|spec| spec.resolve(n_total_triples, &mut rng))
+                                .collect(),
+                        };
+
+                        resolved.extend(
+                            part_specs
+                                .into_iter()
+                                .map(|spec| sparql::QuerySpec { mix_part: part_ix, ..spec }),
+                        );
+                    }
+
+                    if n_distinct > n_total_triples {
+                        return Err(format!(
+                            "the randomized parts without duplicates take {n_distinct} distinct triples but the main dataset has only {n_total_triples}"
+                        )
+                        .into());
+                    }
+
+                    resolved
+                },
+            };
+
+            if prepare_query_format == OutputFormat::Compressed
+                && query_specs
+                    .iter()
+                    .any(|spec| spec.graph.is_some() && spec.query_type == QueryType::InsertData)
+            {
+                return Err("compressed prepare output cannot hold the graphs of inserts into named graphs, use --prepare-query-format query or n-triples".into());
+            }
+
+            if query_specs.iter().any(|spec| spec.n_novel_per_query > 0) {
+                if !extra_datasets.is_empty() {
+                    return Err("novel triples cannot be synthesized from multiple datasets".into());
+                }
+
+                if !dataset_triples.is_sorted() {
+                    return Err("main dataset must be sorted to synthesize novel triples, run sort on it first or pass --auto-sort".into());
+                }
+            }
+
+            // the changeset parts of a mix take no triples from the datasets, and only distinct queries
+            // must fit into the datasets in total
+            let part_of = |spec: &sparql::QuerySpec| workload.as_ref().map(|workload| &workload.parts[spec.mix_part]);
+            let selects_from_datasets =
+                |spec: &sparql::QuerySpec| !matches!(part_of(spec), Some(WorkloadPart::Changeset { .. }));
+            let selects_distinct = |spec: &sparql::QuerySpec| match part_of(spec) {
+                Some(part) => matches!(part, WorkloadPart::Randomized { allow_duplicates: false, .. }),
+                None => !matches!(g_type, GenerateType::Randomized { allow_duplicates: true, .. }),
+            };
+
+            // distinct triples taken from every dataset by all queries together, and triples taken by the largest query
+            let mut dataset_totals = vec![0; datasets.len()];
+            let mut dataset_max_shares = vec![0; datasets.len()];
+
+            for spec in query_specs.iter().filter(|spec| selects_from_datasets(spec)) {
+                let n_selected = spec.n_triples_per_query - spec.n_novel_per_query;
+                let shares = rdf::triple_generator::split_by_weights(n_selected, &dataset_weights);
+
+                for (ix, share) in shares.into_iter().enumerate() {
+                    if selects_distinct(spec) {
+                        dataset_totals[ix] += spec.n_queries * share;
+                    }
+                    dataset_max_shares[ix] = dataset_max_shares[ix].max(share);
+                }
+            }
+
+            if datasets.len() > 1 {
+                for (ix, &n_candidates) in n_candidates.iter().enumerate() {
+                    let path = &compressed_dataset[ix];
+
+                    if dataset_max_shares[ix] > n_candidates {
+                        return Err(format!(
+                            "queries take up to {} triples from dataset {path:?} of {n_candidates} triples not in an earlier dataset",
+                            dataset_max_shares[ix],
+                        )
+                        .into());
+                    }
+
+                    if dataset_totals[ix] > n_candidates {
+                        return Err(format!(
+                            "query specs take {} distinct triples from dataset {path:?} but it has only {n_candidates} not in an earlier dataset",
+                            dataset_totals[ix],
+                        )
+                        .into());
+                    }
+                }
+            }
+
+            let triples_per_subject = match &g_type {
+                GenerateType::Randomized { min_per_subject: None, max_per_subject: None, .. } => None,
+                GenerateType::Randomized { min_per_subject, max_per_subject, .. } => {
+                    let min = min_per_subject.unwrap_or(1);
+                    let max = max_per_subject.unwrap_or(usize::MAX);
+
+                    if min == 0 || max < min {
+                        return Err(format!(
+                            "invalid triples per subject: --min-per-subject must be at least 1 and at most --max-per-subject, got {min} and {max}"
+                        )
+                        .into());
+                    }
+
+                    if !dataset_triples.is_sorted() {
+                        return Err("main dataset must be sorted to group the selected triples by subject, run sort on it first or pass --auto-sort".into());
+                    }
+
+                    Some((min, max))
+                },
+                _ => None,
+            };
+
+            if dry_run {
+                match &g_type {
+                    GenerateType::Changeset { compressed_changesets, traversal_opts, .. } => {
+                        if !dataset_triples.is_sorted() {
+                            eprintln!(
+                                "Error: main dataset must be sorted to ensure correct changeset query generation, run sort on it first or pass --auto-sort"
+                            );
+                        }
+
+                        let n_changesets = changeset_file_iter(
+                            compressed_changesets,
+                            traversal_opts,
+                            COMPRESSED_TRIPLE_FILE_EXTENSION,
+                        )
+                        .map(Result::unwrap)
+                        .count();
+
+                        println!("found {n_changesets} changesets");
+                    },
+                    GenerateType::Randomized { .. } | GenerateType::Mix { .. } => (),
+                }
+
+                let avg_term_len = decompressor.average_term_len();
+                let mut query_plan = sparql::OutputPlan::default();
+                let mut prepare_plan = sparql::OutputPlan::default();
+
+                for &sparql::QuerySpec { n_queries, n_triples_per_query, query_type, .. } in &query_specs {
+                    query_plan.add_queries(n_queries, n_triples_per_query, avg_term_len, OutputFormat::Query);
+
+                    if query_type == QueryType::InsertData {
+                        prepare_plan.add_queries(n_queries, n_triples_per_query, avg_term_len, prepare_query_format);
+                    }
+                }
+
+                println!("dry run: would write {query_plan} to {query_out:?}");
+                println!("dry run: would write {prepare_plan} to {prepare_query_out:?}");
+                return Ok(());
+            }
+
+            let generator_registry = TripleGeneratorRegistry::default();
+            let mut novel = generator_registry.create(
+                "novel",
+                &GeneratorContext { seed: stream_seed(1), ..GeneratorContext::new(&dataset_triples) },
+            )?;
+            // a failing selector script fails the query it selects the triples for, the script only runs on
+            // triples matching the patterns
+            let matcher = RefCell::new(PatternMatcher::new(&decompressor, pattern)?);
+            let select = &|triple: CompressedTriple| {
+                let selected = match (matcher.borrow_mut().matches(triple), &selector) {
+                    (Ok(true), Some(selector)) => selector.is_selected(&decompressor, triple),
+                    (matches, _) => matches,
+                };
+
+                selected.map(|selected| selected.then_some(triple)).transpose()
+            };
+
+            // insert triples with the number of their insert query, recorded for --check-inserts
+            let inserted = RefCell::new(Vec::new());
+            let n_insert_queries = Cell::new(0);
+            let record_inserts = &|query_type: QueryType| {
+                let record = check_inserts && query_type == QueryType::InsertData;
+                let query_ix = n_insert_queries.get();
+                let inserted = &inserted;
+
+                if record {
+                    n_insert_queries.set(query_ix + 1);
+                }
+
+                move |triple: &std::io::Result<CompressedTriple>| {
+                    if let (true, Ok(triple)) = (record, triple) {
+                        inserted.borrow_mut().push((*triple, query_ix));
+                    }
+                }
+            };
+
+            if perf_report.is_some() {
+                perf::enable(perf_batch_size);
+            }
+
             match g_type {
-                GenerateType::Changeset { compressed_changesets: compressed_changeset_dir, generate_type } => {
-                    assert!(
-                        dataset_triples.is_sorted(),
-                        "main dataset must be sorted to ensure correct changeset query generation"
-                    );
+                GenerateType::Changeset {
+                    compressed_changesets: compressed_changeset_dir,
+                    traversal_opts,
+                    generate_type,
+                    changeset_weights,
+                    allow_overlap,
+                    insert_pattern,
+                    delete_pattern,
+                    mix_added_removed,
+                    window,
+                    prefer,
+                    truncate,
+                    ..
+                } => {
+                    if window.is_some() && !matches!(generate_type, GenerateChangesetType::FixedSize) {
+                        return Err("--window requires --type fixed-size".into());
+                    }
 
-                    let changesets: Vec<_> =
-                        changeset_file_iter(compressed_changeset_dir, COMPRESSED_TRIPLE_FILE_EXTENSION)
-                            .map(Result::unwrap)
-                            .filter_map(|de| match unsafe { CompressedRdfTriples::load(de.path()) } {
-                                Ok(triples) => Some(triples),
-                                Err(e) => {
-                                    eprintln!("Error: unable to open {:?}: {e:?}", de.path());
-                                    None
-                                },
-                            })
+                    if truncate != ChangesetTruncation::End
+                        && !matches!(generate_type, GenerateChangesetType::FixedSize)
+                    {
+                        return Err("--truncate requires --type fixed-size".into());
+                    }
+
+                    if prefer.is_some() && !matches!(generate_type, GenerateChangesetType::AsIs) {
+                        return Err("--prefer requires --type as-is".into());
+                    }
+
+                    if !dataset_triples.is_sorted() {
+                        return Err("main dataset must be sorted to ensure correct changeset query generation, run sort on it first or pass --auto-sort".into());
+                    }
+
+                    let changesets = load_changesets(&compressed_changeset_dir, &traversal_opts);
+
+                    let (changeset_paths, changesets): (Vec<_>, Vec<_>) = changesets.into_iter().unzip();
+
+                    let weights = match changeset_weights {
+                        Some(changeset_weights) => Some(rdf::triple_generator::read_changeset_weights(
+                            changeset_weights,
+                            &changeset_paths,
+                        )?),
+                        None => None,
+                    };
+
+                    // changeset paths and changesets with their weights, either one pool for all queries or
+                    // one for inserts and one for deletes
+                    let pools = if mix_added_removed {
+                        vec![(changeset_paths, changesets, weights)]
+                    } else {
+                        let mut pools: Vec<_> = (0..2)
+                            .map(|_| (Vec::new(), Vec::new(), weights.as_ref().map(|_| Vec::new())))
                             .collect();
 
-                    match generate_type {
+                        for (ix, (path, changeset)) in changeset_paths.into_iter().zip(changesets).enumerate() {
+                            let pool = match versions::is_added_changeset_file(&path, &insert_pattern, &delete_pattern)
+                            {
+                                Some(true) => &mut pools[0],
+                                Some(false) => &mut pools[1],
+                                None => continue,
+                            };
+
+                            pool.0.push(path);
+                            pool.1.push(changeset);
+
+                            if let (Some(pool_weights), Some(weights)) = (&mut pool.2, &weights) {
+                                pool_weights.push(weights[ix]);
+                            }
+                        }
+
+                        pools
+                    };
+
+                    let pool_ix = |query_type: QueryType| match query_type {
+                        QueryType::DeleteData if pools.len() > 1 => 1,
+                        _ => 0,
+                    };
+
+                    if let Some(spec) = query_specs.iter().find(|spec| pools[pool_ix(spec.query_type)].1.is_empty()) {
+                        return Err(format!(
+                            "no changesets to generate {} queries from",
+                            match spec.query_type {
+                                QueryType::InsertData => "insert",
+                                QueryType::DeleteData => "delete",
+                            }
+                        )
+                        .into());
+                    }
+
+                    let generator_name = match generate_type {
                         GenerateChangesetType::AsIs => {
                             println!("generating queries from changesets...");
-
-                            // TODO: check actual contained size with dataset_triples
-                            sparql::generate_queries(
-                                query_out,
-                                prepare_query_out,
-                                prepare_query_format,
-                                query_specs,
-                                &decompressor,
-                                rdf::triple_generator::as_is_changeset_triple_generator(&changesets),
-                                output_order,
-                                append,
-                            )
+                            "changeset-as-is"
                         },
                         GenerateChangesetType::FixedSize => {
                             println!("generating fixed size queries from changesets...");
+                            "changeset-fixed-size"
+                        },
+                    };
+
+                    let needs_times = window.is_some()
+                        || matches!(prefer, Some(ChangesetPreference::Oldest | ChangesetPreference::Newest));
+
+                    let changeset_times = if needs_times {
+                        pools
+                            .iter()
+                            .map(|(paths, ..)| {
+                                paths
+                                    .iter()
+                                    .map(|path| rdf::triple_generator::changeset_timestamp(path))
+                                    .collect::<Result<Vec<_>, _>>()
+                            })
+                            .collect::<Result<Vec<_>, _>>()?
+                    } else {
+                        Vec::new()
+                    };
+
+                    let selection_log = SelectionLog::default();
+
+                    let mut generators = pools
+                        .iter()
+                        .zip(2..)
+                        .enumerate()
+                        .map(|(pool_ix, ((_, changesets, weights), stream))| {
+                            generator_registry.create(
+                                generator_name,
+                                &GeneratorContext {
+                                    changesets,
+                                    changeset_weights: weights.as_deref(),
+                                    changeset_times: changeset_times.get(pool_ix).map(Vec::as_slice),
+                                    window_secs: window.map(|window| window.as_secs()),
+                                    changeset_preference: prefer,
+                                    truncation: truncate,
+                                    allow_overlap,
+                                    selection_log: Some(&selection_log),
+                                    seed: stream_seed(stream),
+                                    ..GeneratorContext::new(&dataset_triples)
+                                },
+                            )
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    // TODO: check actual contained size with dataset_triples for as-is changesets
+                    let records = sparql::generate_queries_by_type(
+                        &query_out,
+                        &prepare_query_out,
+                        prepare_query_format,
+                        query_specs,
+                        &decompressor,
+                        |size_hint, n_novel, query_type, _| {
+                            generators[pool_ix(query_type)]
+                                .generate(size_hint)
+                                .chain(novel.generate(n_novel))
+                                .filter_map(select)
+                                .inspect(record_inserts(query_type))
+                        },
+                        output_order,
+                        write_opts,
+                    )?;
 
-                            sparql::generate_queries(
-                                query_out,
-                                prepare_query_out,
-                                prepare_query_format,
-                                query_specs,
-                                &decompressor,
-                                rdf::triple_generator::fixed_size_changeset_triple_generator(
-                                    &changesets,
-                                    &dataset_triples,
-                                ),
-                                output_order,
-                                append,
+                    drop(generators);
+                    let mut ranges = selection_log.into_inner();
+
+                    let selections: Vec<_> = records
+                        .iter()
+                        .map(|record| {
+                            let (paths, ..) = &pools[pool_ix(record.query_type)];
+
+                            manifest::QuerySelection {
+                                query_ix: record.query_ix,
+                                query_type: record.query_type,
+                                changesets: std::mem::take(&mut ranges[record.generation_ix])
+                                    .into_iter()
+                                    .map(|range| manifest::ChangesetSelection {
+                                        path: paths[range.changeset_ix].clone(),
+                                        start: range.start,
+                                        end: range.end,
+                                    })
+                                    .collect(),
+                            }
+                        })
+                        .collect();
+
+                    manifest::write_selection_report(&query_out, &selections)
+                },
+                GenerateType::Mix { traversal_opts, .. } => {
+                    let workload = workload.as_ref().expect("workload is read for mix");
+                    println!("generating queries of a workload of {} parts...", workload.parts.len());
+
+                    // paths and changesets of every changeset part in an insert and a delete pool, or one pool for both
+                    let mut part_pools = Vec::new();
+
+                    for (part_ix, part) in workload.parts.iter().enumerate() {
+                        let WorkloadPart::Changeset {
+                            changesets,
+                            insert_pattern,
+                            delete_pattern,
+                            mix_added_removed,
+                            ..
+                        } = part
+                        else {
+                            part_pools.push(Vec::new());
+                            continue;
+                        };
+
+                        if !dataset_triples.is_sorted() {
+                            return Err("main dataset must be sorted to ensure correct changeset query generation, run sort on it first or pass --auto-sort".into());
+                        }
+
+                        let loaded = load_changesets(changesets, &traversal_opts);
+
+                        let pools: Vec<(Vec<_>, Vec<_>)> = if *mix_added_removed {
+                            vec![loaded.into_iter().unzip()]
+                        } else {
+                            let insert_pattern = parse_glob(insert_pattern)?;
+                            let delete_pattern = parse_glob(delete_pattern)?;
+                            let mut pools = vec![(Vec::new(), Vec::new()), (Vec::new(), Vec::new())];
+
+                            for (path, changeset) in loaded {
+                                let pool =
+                                    match versions::is_added_changeset_file(&path, &insert_pattern, &delete_pattern) {
+                                        Some(true) => &mut pools[0],
+                                        Some(false) => &mut pools[1],
+                                        None => continue,
+                                    };
+
+                                pool.0.push(path);
+                                pool.1.push(changeset);
+                            }
+
+                            pools
+                        };
+
+                        let pool_ix = |query_type| usize::from(pools.len() > 1 && query_type == QueryType::DeleteData);
+
+                        if let Some(spec) = query_specs
+                            .iter()
+                            .find(|spec| spec.mix_part == part_ix && pools[pool_ix(spec.query_type)].1.is_empty())
+                        {
+                            return Err(format!(
+                                "no changesets in {changesets:?} to generate {} queries of part {part_ix} from",
+                                match spec.query_type {
+                                    QueryType::InsertData => "insert",
+                                    QueryType::DeleteData => "delete",
+                                }
                             )
+                            .into());
+                        }
+
+                        part_pools.push(pools);
+                    }
+
+                    // every part refers to its generator groups, the second one generating its delete queries.
+                    // A randomized group has a generator per dataset, a changeset group one on the main dataset.
+                    // Randomized parts share a group, so that their distinct queries have no triples in common
+                    let selection_log = SelectionLog::default();
+                    let mut groups: Vec<Vec<_>> = Vec::new();
+                    let mut part_groups = Vec::new();
+                    let mut randomized_groups = [None, None];
+                    let mut stream = 2;
+
+                    for (part, pools) in workload.parts.iter().zip(&part_pools) {
+                        match *part {
+                            WorkloadPart::Randomized { allow_duplicates, .. } => {
+                                let shared = &mut randomized_groups[usize::from(allow_duplicates)];
+
+                                let ix = match *shared {
+                                    Some(ix) => ix,
+                                    None => {
+                                        let mut group = Vec::new();
+
+                                        for ((dataset, candidate_ixs), &n_total_query_triples) in
+                                            datasets.iter().zip(&candidate_ixs).zip(&dataset_totals)
+                                        {
+                                            group.push(generator_registry.create(
+                                                if allow_duplicates { "random" } else { "random-distinct" },
+                                                &GeneratorContext {
+                                                    candidate_ixs: candidate_ixs.as_deref(),
+                                                    n_total_query_triples,
+                                                    seed: stream_seed(stream),
+                                                    ..GeneratorContext::new(dataset)
+                                                },
+                                            )?);
+                                            stream += 1;
+                                        }
+
+                                        groups.push(group);
+                                        *shared = Some(groups.len() - 1);
+                                        groups.len() - 1
+                                    },
+                                };
+
+                                part_groups.push(vec![ix]);
+                            },
+                            WorkloadPart::Changeset { generate_type, allow_overlap, truncate, .. } => {
+                                if truncate != ChangesetTruncation::End
+                                    && generate_type != ChangesetGeneration::FixedSize
+                                {
+                                    return Err("truncate of a changeset part requires the type fixed-size".into());
+                                }
+
+                                let generator_name = match generate_type {
+                                    ChangesetGeneration::AsIs => "changeset-as-is",
+                                    ChangesetGeneration::FixedSize => "changeset-fixed-size",
+                                };
+
+                                let mut ixs = Vec::new();
+
+                                for (_, changesets) in pools {
+                                    groups.push(vec![generator_registry.create(
+                                        generator_name,
+                                        &GeneratorContext {
+                                            changesets,
+                                            allow_overlap,
+                                            truncation: truncate,
+                                            selection_log: Some(&selection_log),
+                                            seed: stream_seed(stream),
+                                            ..GeneratorContext::new(&dataset_triples)
+                                        },
+                                    )?]);
+                                    stream += 1;
+                                    ixs.push(groups.len() - 1);
+                                }
+
+                                part_groups.push(ixs);
+                            },
+                        }
+                    }
+
+                    let mut mix_rng = util::seeded_rng(stream_seed(stream));
+                    // part and pool of every query in the order they are generated, for the selection report
+                    let mut generated = Vec::new();
+
+                    let records = sparql::generate_queries_by_type(
+                        &query_out,
+                        &prepare_query_out,
+                        prepare_query_format,
+                        query_specs,
+                        &decompressor,
+                        |size_hint, n_novel, query_type, mix_part| {
+                            let ixs = &part_groups[mix_part];
+                            let pool_ix = usize::from(ixs.len() > 1 && query_type == QueryType::DeleteData);
+                            let group = &mut groups[ixs[pool_ix]];
+
+                            // keeps the selection log aligned with the queries
+                            if part_pools[mix_part].is_empty() {
+                                selection_log.record_none();
+                            }
+                            generated.push((mix_part, pool_ix));
+
+                            let sizes = match group.len() {
+                                1 => vec![size_hint],
+                                _ => rdf::triple_generator::split_by_weights(size_hint, &dataset_weights),
+                            };
+
+                            let mut triples: Vec<_> = group
+                                .iter_mut()
+                                .zip(sizes)
+                                .flat_map(|(generator, n)| generator.generate(n))
+                                .collect();
+
+                            // mixes the triples of the datasets instead of writing them one dataset after the other
+                            if group.len() > 1 {
+                                triples.shuffle(&mut mix_rng);
+                            }
+
+                            triples
+                                .into_iter()
+                                .chain(novel.generate(n_novel))
+                                .filter_map(select)
+                                .inspect(record_inserts(query_type))
                         },
+                        output_order,
+                        write_opts,
+                    )?;
+
+                    drop(groups);
+                    let mut ranges = selection_log.into_inner();
+
+                    // only the queries of changeset parts read changesets
+                    let selections: Vec<_> = records
+                        .iter()
+                        .filter_map(|record| {
+                            let (mix_part, pool_ix) = generated[record.generation_ix];
+                            let (paths, _) = part_pools[mix_part].get(pool_ix)?;
+
+                            Some(manifest::QuerySelection {
+                                query_ix: record.query_ix,
+                                query_type: record.query_type,
+                                changesets: std::mem::take(&mut ranges[record.generation_ix])
+                                    .into_iter()
+                                    .map(|range| manifest::ChangesetSelection {
+                                        path: paths[range.changeset_ix].clone(),
+                                        start: range.start,
+                                        end: range.end,
+                                    })
+                                    .collect(),
+                            })
+                        })
+                        .collect();
+
+                    if selections.is_empty() {
+                        Ok(())
+                    } else {
+                        manifest::write_selection_report(&query_out, &selections)
                     }
                 },
-                GenerateType::Randomized { allow_duplicates: false } => {
+                GenerateType::Randomized { allow_duplicates: false, used_triples_state, .. } => {
                     println!("generating distinct queries from main dataset...");
 
-                    let total_query_triples: usize = query_specs
+                    let total_query_triples: usize =
+                        query_specs.iter().map(sparql::QuerySpec::n_selected_triples).sum();
+
+                    // the sampled indices, and as much again while sampling
+                    util::check_memory(
+                        (2 * total_query_triples * std::mem::size_of::<usize>()) as u64,
+                        "sampling distinct triples",
+                    )?;
+
+                    let mut generators = datasets
                         .iter()
-                        .map(|sparql::QuerySpec { n_queries, n_triples_per_query, query_type: _ }| {
-                            n_queries * n_triples_per_query
+                        .zip(&candidate_ixs)
+                        .zip(&dataset_totals)
+                        .zip(2..)
+                        .map(|(((dataset, candidate_ixs), &n_total_query_triples), stream)| {
+                            generator_registry.create(
+                                if triples_per_subject.is_some() {
+                                    "subject-grouped-distinct"
+                                } else {
+                                    "random-distinct"
+                                },
+                                &GeneratorContext {
+                                    candidate_ixs: candidate_ixs.as_deref().or(unused_ixs.as_deref()),
+                                    n_total_query_triples,
+                                    seed: stream_seed(stream),
+                                    triples_per_subject: triples_per_subject.unwrap_or((1, usize::MAX)),
+                                    ..GeneratorContext::new(dataset)
+                                },
+                            )
                         })
-                        .sum();
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let mut emitted = Vec::new();
+                    let mut mix_rng = util::seeded_rng(stream_seed(2 + datasets.len() as u64));
 
-                    sparql::generate_queries(
-                        query_out,
-                        prepare_query_out,
+                    sparql::generate_queries_by_type(
+                        &query_out,
+                        &prepare_query_out,
                         prepare_query_format,
                         query_specs,
                         &decompressor,
-                        rdf::triple_generator::random_distinct_triple_generator(&dataset_triples, total_query_triples),
+                        |size_hint, n_novel, query_type, _| {
+                            let mut triples: Vec<_> = generators
+                                .iter_mut()
+                                .zip(rdf::triple_generator::split_by_weights(size_hint, &dataset_weights))
+                                .flat_map(|(generator, n)| generator.generate(n))
+                                .filter_map(select)
+                                .collect();
+
+                            // mixes the triples of the datasets instead of writing them one dataset after the other
+                            if datasets.len() > 1 {
+                                triples.shuffle(&mut mix_rng);
+                            }
+
+                            emitted.extend(triples.iter().filter_map(|triple| triple.as_ref().ok().copied()));
+                            triples
+                                .into_iter()
+                                .chain(novel.generate(n_novel).filter_map(select))
+                                .inspect(record_inserts(query_type))
+                        },
                         output_order,
-                        append,
-                    )
+                        write_opts,
+                    )?;
+
+                    if let Some(used_triples_state) = used_triples_state {
+                        println!("saving used triples state...");
+
+                        let mut used = used_triples.map_or_else(Vec::new, |used| used.to_vec());
+                        used.extend(emitted);
+                        used.sort_unstable();
+                        used.dedup();
+
+                        save_compressed_triples(used_triples_state, &used)
+                    } else {
+                        Ok(())
+                    }
                 },
-                GenerateType::Randomized { allow_duplicates: true } => {
+                GenerateType::Randomized { allow_duplicates: true, .. } => {
                     println!("generating queries from main dataset...");
 
-                    sparql::generate_queries(
-                        query_out,
-                        prepare_query_out,
+                    let mut generators = datasets
+                        .iter()
+                        .zip(2..)
+                        .zip(&candidate_ixs)
+                        .map(|((dataset, stream), candidate_ixs)| {
+                            generator_registry.create(
+                                if triples_per_subject.is_some() {
+                                    "subject-grouped"
+                                } else {
+                                    "random"
+                                },
+                                &GeneratorContext {
+                                    candidate_ixs: candidate_ixs.as_deref(),
+                                    seed: stream_seed(stream),
+                                    triples_per_subject: triples_per_subject.unwrap_or((1, usize::MAX)),
+                                    ..GeneratorContext::new(dataset)
+                                },
+                            )
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let mut mix_rng = util::seeded_rng(stream_seed(2 + datasets.len() as u64));
+
+                    sparql::generate_queries_by_type(
+                        &query_out,
+                        &prepare_query_out,
                         prepare_query_format,
                         query_specs,
                         &decompressor,
-                        rdf::triple_generator::random_triple_generator(&dataset_triples),
+                        |size_hint, n_novel, query_type, _| {
+                            let mut triples: Vec<_> = generators
+                                .iter_mut()
+                                .zip(rdf::triple_generator::split_by_weights(size_hint, &dataset_weights))
+                                .flat_map(|(generator, n)| generator.generate(n))
+                                .collect();
+
+                            if datasets.len() > 1 {
+                                triples.shuffle(&mut mix_rng);
+                            }
+
+                            triples
+                                .into_iter()
+                                .chain(novel.generate(n_novel))
+                                .filter_map(select)
+                                .inspect(record_inserts(query_type))
+                        },
                         output_order,
-                        append,
+                        write_opts,
                     )
+                    .map(drop)
                 },
-            }?
+            }?;
+
+            if let Some(perf_report) = &perf_report {
+                perf::write_report(perf_report)?;
+                println!("wrote performance report to {perf_report:?}");
+            }
+
+            if check_inserts {
+                let datasets: Vec<_> = datasets
+                    .iter()
+                    .copied()
+                    .zip(compressed_dataset.iter().map(PathBuf::as_path))
+                    .collect();
+                check_insert_triples(inserted.into_inner(), &datasets)?;
+            }
+
+            if validate_output {
+                println!("validating generated queries...");
+                sparql::validate_update_file(&query_out)?;
+
+                if prepare_query_format == OutputFormat::Query {
+                    sparql::validate_update_file(&prepare_query_out)?;
+                }
+            }
         },
         Opts::Replicate {
             compressor_state,
             query_out,
             include_dataset,
             exclude_dataset,
-            recursive,
+            walk_opts,
             append,
+            force,
+            write_buffer_size,
+            on_missing_terms,
+            term_escaping,
+            line_ending,
+            ascii_escape,
             compressed_datasets,
             output_format,
+            provenance,
+            split_by_type,
+            template,
+            treat_as,
+            output_order,
+            seed,
+            max_triples_per_query,
+            min_triples_per_query,
+            insert_pattern,
+            delete_pattern,
+            dry_run,
+            validate_output,
+            load_opts: LoadOpts { load_into_memory },
+            auto_sort,
+            containment,
         } => {
+            let write_opts = WriteOpts {
+                append,
+                buffer_capacity: write_buffer_size,
+                missing_terms: on_missing_terms,
+                term_escaping,
+                force,
+                line_ending,
+                ascii_escape,
+                provenance,
+                split_by_type,
+                template: template.map(QueryTemplate::load).transpose()?.map(Arc::new),
+                ..Default::default()
+            };
+
+            if output_format == OutputFormat::Compressed {
+                return Err("--output-format compressed is only supported for the prepare output of generate".into());
+            }
+
+            if provenance.is_some() && output_format != OutputFormat::NTriples {
+                return Err("--provenance requires --output-format n-triples".into());
+            }
+
+            if split_by_type && output_format != OutputFormat::Query {
+                return Err("--split-by-type requires --output-format query".into());
+            }
+
+            if write_opts.template.is_some() && output_format != OutputFormat::Query {
+                return Err("--template requires --output-format query".into());
+            }
+
+            if matches!(
+                output_order,
+                OutputOrder::AlternateInsertDelete | OutputOrder::SortedSizeAscAlternateInsertDelete
+            ) && output_format != OutputFormat::Query
+            {
+                return Err("alternating output orders require --output-format query".into());
+            }
+
+            if let Some(max_triples_per_query) = max_triples_per_query {
+                if max_triples_per_query == 0 {
+                    return Err("--max-triples-per-query must be at least 1".into());
+                }
+
+                if output_format != OutputFormat::Query {
+                    return Err("--max-triples-per-query requires --output-format query".into());
+                }
+            }
+
+            if let Some(min_triples_per_query) = min_triples_per_query {
+                if output_format != OutputFormat::Query {
+                    return Err("--min-triples-per-query requires --output-format query".into());
+                }
+
+                if max_triples_per_query.is_some_and(|max| max < min_triples_per_query) {
+                    return Err("--min-triples-per-query must not be larger than --max-triples-per-query".into());
+                }
+            }
+
             println!("loading compressor state...");
-            let decompressor = unsafe { RdfTripleDecompressor::load_state(compressor_state)? };
+            let decompressor = load_state(&compressor_state, load_into_memory)?;
 
             println!("loading datasets to replicate...");
-            let datasets: Vec<_> = dataset_iter(compressed_datasets, recursive, COMPRESSED_TRIPLE_FILE_EXTENSION)
+            let datasets: Vec<_> = dataset_iter(compressed_datasets, &walk_opts, COMPRESSED_TRIPLE_FILE_EXTENSION)
                 .map(Result::unwrap)
                 .filter_map(|p| {
                     let fname = p.file_name().unwrap();
-                    let fname = fname.as_bytes();
 
                     let query_type = if output_format == OutputFormat::Query {
-                        if fname.ends_with(b"added.compressed_nt") {
+                        if let Some(treat_as) = treat_as {
+                            treat_as.query_type()
+                        } else if insert_pattern.is_match(fname) {
                             QueryType::InsertData
-                        } else if fname.ends_with(b"removed.compressed_nt") {
+                        } else if delete_pattern.is_match(fname) {
                             QueryType::DeleteData
                         } else {
-                            eprintln!("Error: cannot determine query type for {p:?}: unknown file ending (known are added.compressed_nt and removed.compressed_nt)");
+                            warn(Warning::SkippedFile {
+                                path: p,
+                                reason: "cannot determine query type: file name matches neither the insert nor the delete pattern".to_owned(),
+                            });
                             return None
                         }
                     } else {
@@ -453,121 +2378,729 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     };
 
                     let triples = match unsafe { CompressedRdfTriples::load(&p) } {
+                        Ok(triples) if triples.is_empty() => {
+                            warn(Warning::SkippedFile { path: p, reason: "contains no triples".to_owned() });
+                            return None
+                        },
                         Ok(triples) => triples,
                         Err(e) => {
-                            eprintln!("Error: unable to open {p:?}: {e:?}");
+                            warn(Warning::SkippedFile { path: p, reason: format!("unable to open: {e:?}") });
                             return None
                         },
                     };
 
-                    Some((query_type, triples))
+                    Some((query_type, p, triples))
                 })
                 .collect();
 
-            let exclude_dataset = if let Some(exclude_dataset) = exclude_dataset {
-                println!("loading cleaner dataset...");
-                let exclude_dataset = unsafe { CompressedRdfTriples::load(exclude_dataset)? };
-                assert!(
-                    exclude_dataset.is_sorted(),
-                    "exclude dataset must be sorted to ensure correct query generation"
-                );
+            let datasets = sparql::order_replicated(datasets, output_order, seed, |(query_type, _, triples)| {
+                (*query_type, triples.len())
+            })?;
 
-                Some(exclude_dataset)
-            } else {
-                None
+            let sizes: Vec<_> = datasets
+                .iter()
+                .map(|(query_type, _, triples)| (*query_type, triples.len()))
+                .collect();
+            let queries = sparql::plan_replicated(&sizes, min_triples_per_query, max_triples_per_query);
+
+            let include_triples = match &include_dataset {
+                Some(path) => {
+                    println!("loading include dataset...");
+                    Some(load_containment_dataset(path, load_into_memory, auto_sort)?)
+                },
+                None => None,
+            };
+
+            let exclude_triples = match &exclude_dataset {
+                Some(path) => {
+                    println!("loading cleaner dataset...");
+                    Some(load_containment_dataset(path, load_into_memory, auto_sort)?)
+                },
+                None => None,
             };
 
+            let include_index = include_triples
+                .as_ref()
+                .zip(include_dataset.as_deref())
+                .map(|(triples, path)| containment_index(triples, path, containment, "include dataset"))
+                .transpose()?;
+
+            let exclude_index = exclude_triples
+                .as_ref()
+                .zip(exclude_dataset.as_deref())
+                .map(|(triples, path)| containment_index(triples, path, containment, "exclude dataset"))
+                .transpose()?;
+
+            if dry_run {
+                let avg_term_len = decompressor.average_term_len();
+                let mut plan = sparql::OutputPlan::default();
+
+                for query in &queries {
+                    let n_triples = query.iter().map(|slice| slice.range.len()).sum();
+                    plan.add_queries(1, n_triples, avg_term_len, output_format);
+                }
+
+                match output_format {
+                    OutputFormat::Query => println!("dry run: would write {plan} to {query_out:?}"),
+                    OutputFormat::NTriples | OutputFormat::Compressed => println!(
+                        "dry run: would write {} triples, ~{:.2} MiB to {query_out:?}",
+                        plan.n_triples,
+                        plan.n_bytes / (1024.0 * 1024.0)
+                    ),
+                }
+
+                if include_dataset.is_some() || exclude_dataset.is_some() {
+                    println!("dry run: triple counts are before pruning by the include and exclude datasets");
+                }
+
+                return Ok(());
+            }
+
             println!("generating queries by linearly replicating datasets...");
-            sparql::generate_linear_no_size_hint(
-                query_out,
+            let prune_counts = sparql::generate_linear_no_size_hint(
+                &query_out,
                 &decompressor,
-                exclude_dataset.as_ref(),
-                datasets.iter().map(|(query_type, compressed_triples)| (*query_type, compressed_triples)),
-                append,
+                include_index.as_ref(),
+                exclude_index.as_ref(),
+                queries
+                    .iter()
+                    .map(|query| {
+                        let (query_type, path, _) = &datasets[query[0].dataset_ix];
+                        let provenance = provenance.map(|_| sparql::Provenance::of_file(path)).transpose()?;
+                        let triples = query
+                            .iter()
+                            .flat_map(|slice| &datasets[slice.dataset_ix].2[slice.range.clone()]);
+
+                        Ok((*query_type, provenance, triples))
+                    })
+                    .collect::<std::io::Result<Vec<_>>>()?,
+                write_opts,
                 output_format,
             )?;
+
+            // pruned triples by the first and last dataset of the queries, the parts of a split dataset are summed up
+            let mut dataset_prune_counts: BTreeMap<(usize, usize), sparql::PruneCounts> = BTreeMap::new();
+
+            for (query, counts) in queries.iter().zip(prune_counts) {
+                let first = query[0].dataset_ix;
+                let last = query[query.len() - 1].dataset_ix;
+
+                let dataset_counts = dataset_prune_counts.entry((first, last)).or_default();
+                dataset_counts.kept += counts.kept;
+                dataset_counts.not_included += counts.not_included;
+                dataset_counts.excluded += counts.excluded;
+            }
+
+            let mut prune_report = manifest::PruneReport { datasets: Vec::new(), total: Default::default() };
+
+            for ((first, last), counts) in dataset_prune_counts {
+                prune_report.total.kept += counts.kept;
+                prune_report.total.not_included += counts.not_included;
+                prune_report.total.excluded += counts.excluded;
+                prune_report.datasets.push(manifest::DatasetPruning {
+                    datasets: datasets[first..=last].iter().map(|(_, path, _)| path.clone()).collect(),
+                    counts,
+                });
+
+                let path = if first == last {
+                    format!("{:?}", datasets[first].1)
+                } else {
+                    format!("{:?} to {:?}", datasets[first].1, datasets[last].1)
+                };
+
+                if counts.not_included > 0 {
+                    warn(Warning::PrunedTriples {
+                        reason: format!("{path}: not contained in the include dataset"),
+                        n_pruned: counts.not_included,
+                    });
+                }
+
+                if counts.excluded > 0 {
+                    warn(Warning::PrunedTriples {
+                        reason: format!("{path}: contained in the exclude dataset"),
+                        n_pruned: counts.excluded,
+                    });
+                }
+            }
+
+            if include_dataset.is_some() || exclude_dataset.is_some() {
+                let total = &prune_report.total;
+                println!(
+                    "pruning: kept triples = {}, not in the include dataset = {}, in the exclude dataset = {}",
+                    total.kept, total.not_included, total.excluded
+                );
+
+                manifest::write_prune_report(&query_out, &prune_report)?;
+            }
+
+            if validate_output && output_format == OutputFormat::Query {
+                println!("validating generated queries...");
+                sparql::validate_update_file(&query_out)?;
+            }
+        },
+        Opts::VerifyRoundtrip { no_parse, show, file } => {
+            println!("compressing and decompressing {file:?}...");
+            let report = roundtrip::verify_roundtrip(&file, !no_parse)?;
+            print_compression_stats(&format!("{file:?}"), &report.compression);
+
+            println!(
+                "distinct triples: original = {}, after round trip = {}, lost blank node triples = {}, other lost = {}, added = {}",
+                report.n_original,
+                report.n_roundtrip,
+                report.n_lost_blank_nodes,
+                report.lost.len(),
+                report.added.len()
+            );
+
+            for (what, triples) in [("lost", &report.lost), ("added", &report.added)] {
+                for triple in triples.iter().take(show) {
+                    println!("  {what}: {}", String::from_utf8_lossy(triple));
+                }
+            }
+
+            if !report.lost.is_empty() || !report.added.is_empty() {
+                return Err(format!(
+                    "{file:?} does not survive the round trip, {} triples were lost and {} added",
+                    report.lost.len(),
+                    report.added.len()
+                )
+                .into());
+            }
         },
-        Opts::Decompress { compressor_state, recursive, compressed_datasets } => {
+        Opts::Decompress { compressor_state, walk_opts, out_dir, gzip, hdt, pattern, compressed_datasets } => {
             println!("loading compressor state...");
             let decompressor = unsafe { RdfTripleDecompressor::load_state(compressor_state)? };
+            let mut matcher = PatternMatcher::new(&decompressor, pattern)?;
+            let mut is_selected = |triple| matcher.matches(triple);
 
-            for dataset in dataset_iter(compressed_datasets, recursive, COMPRESSED_TRIPLE_FILE_EXTENSION) {
+            let roots = compressed_datasets.clone();
+
+            for dataset in dataset_iter(compressed_datasets, &walk_opts, COMPRESSED_TRIPLE_FILE_EXTENSION) {
                 let dataset = dataset?;
 
                 println!("decompressing {dataset:?}...");
-                decompressor.decompress_rdf_triple_file(dataset, BufWriter::new(std::io::stdout().lock()))?;
+
+                let Some(out_dir) = &out_dir else {
+                    decompressor.decompress_selected_rdf_triple_file(
+                        dataset,
+                        BufWriter::new(std::io::stdout().lock()),
+                        &mut is_selected,
+                    )?;
+                    continue;
+                };
+
+                let extension = if gzip {
+                    "nt.gz"
+                } else if hdt {
+                    HDT_FILE_EXTENSION
+                } else {
+                    UNCOMPRESSED_TRIPLE_FILE_EXTENSION
+                };
+                let out_path = decompressed_path(out_dir, &roots, &dataset, extension);
+
+                if let Some(parent) = out_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                let out = AtomicFile::create(&out_path, false)?;
+
+                if hdt {
+                    let triples = unsafe { CompressedRdfTriples::load(&dataset)? };
+                    let mut writer = BufWriter::new(out);
+
+                    let mut selected = Vec::new();
+                    for &triple in triples.iter() {
+                        if is_selected(triple)? {
+                            selected.push(decompressor.decompress_known_rdf_triple(triple)?);
+                        }
+                    }
+
+                    rdf::hdt_export::write_hdt(&mut writer, selected)?;
+                    commit_buffered(writer)?;
+                } else if gzip {
+                    let mut writer = BufWriter::new(GzipWriter::new(out));
+                    decompressor.decompress_selected_rdf_triple_file(&dataset, &mut writer, &mut is_selected)?;
+                    writer.into_inner().map_err(std::io::IntoInnerError::into_error)?.finish()?;
+                } else {
+                    let mut writer = BufWriter::new(out);
+                    decompressor.decompress_selected_rdf_triple_file(&dataset, &mut writer, &mut is_selected)?;
+                    commit_buffered(writer)?;
+                }
+            }
+        },
+        Opts::Stats { compare: Some(compare), compressor_state, .. } => {
+            let [old_path, new_path]: [PathBuf; 2] = compare.try_into().expect("clap requires two values");
+
+            let decompressor = match compressor_state {
+                Some(path) => Some(load_state(&path, false)?),
+                None => None,
+            };
+
+            let old = unsafe { CompressedRdfTriples::load(&old_path)? };
+            let new = unsafe { CompressedRdfTriples::load(&new_path)? };
+
+            for (path, dataset) in [(&old_path, &old), (&new_path, &new)] {
+                if !dataset.is_sorted() {
+                    return Err(format!("{path:?} must be sorted to compare it, run sort on it first").into());
+                }
+            }
+
+            let diff = stats::compare_datasets(&old, &new)?;
+            let [subjects, predicates, objects] = diff.distinct_deltas();
+
+            println!(
+                "{new_path:?} compared to {old_path:?}: added triples = {}, removed triples = {}, common triples = {}",
+                diff.n_added, diff.n_removed, diff.n_common
+            );
+            println!("change of distinct subjects = {subjects:+}, distinct predicates = {predicates:+}, distinct objects = {objects:+}");
+
+            for (predicate, [old_count, new_count]) in diff.changed_predicates() {
+                let term = match &decompressor {
+                    Some(decompressor) => decompressor.decompress_term(predicate)?,
+                    None => None,
+                };
+
+                let predicate = match term {
+                    Some(term) => String::from_utf8_lossy(term).into_owned(),
+                    None => predicate.to_string(),
+                };
+
+                println!(
+                    "predicate {predicate}: {old_count} -> {new_count} ({:+})",
+                    new_count as i64 - old_count as i64
+                );
             }
         },
-        Opts::Stats { recursive, compressed_datasets } => {
+        Opts::Stats { walk_opts, compressor_state, top_terms, compressed_datasets, .. } => {
+            let decompressor = match &compressor_state {
+                Some(compressor_state) => Some(unsafe { RdfTripleDecompressor::load_state(compressor_state)? }),
+                None => None,
+            };
+
+            let state_fingerprint = match &decompressor {
+                Some(decompressor) => Some(format!("{:032x}", decompressor.state_fingerprint()?)),
+                None => None,
+            };
+
+            if let (Some(k), Some(compressor_state), Some(decompressor)) = (top_terms, &compressor_state, &decompressor)
+            {
+                let counts = TermCounts::read(compressor_state)?.ok_or_else(|| {
+                    format!("{compressor_state:?} has no term counts, compress with --term-counts to count the terms")
+                })?;
+
+                println!("{k} most frequent of {} terms:", counts.len());
+
+                for (id, count) in counts.top(k) {
+                    let term = match decompressor.decompress_term(id)? {
+                        Some(term) => String::from_utf8_lossy(term).into_owned(),
+                        None => id.to_string(),
+                    };
+
+                    println!("  {term}: {count}");
+                }
+            }
+
             let mut sum_total = 0;
 
-            for path in dataset_iter(compressed_datasets, recursive, COMPRESSED_TRIPLE_FILE_EXTENSION) {
+            for path in dataset_iter(compressed_datasets, &walk_opts, COMPRESSED_TRIPLE_FILE_EXTENSION) {
                 let path = path?;
                 match unsafe { CompressedRdfTriples::load(&path) } {
                     Ok(dataset) => {
-                        type BuildHasher = BuildHasherDefault<ahash::AHasher>;
-
-                        let mut subjects_dedup = HashSet::with_hasher(BuildHasher::default());
-                        let mut predicates_dedup = HashSet::with_hasher(BuildHasher::default());
-                        let mut objects_dedup = HashSet::with_hasher(BuildHasher::default());
-
-                        for &[s, p, o] in dataset.iter() {
-                            subjects_dedup.insert(s);
-                            predicates_dedup.insert(p);
-                            objects_dedup.insert(o);
-                        }
-
                         let total = dataset.len();
-                        let ns = subjects_dedup.len();
-                        let np = predicates_dedup.len();
-                        let no = objects_dedup.len();
+                        let [ns, np, no] = dataset.distinct_counts()?;
 
                         sum_total += total;
 
                         println!("{path:?}: number of triples = {total}, number of distinct subjects = {ns}, number of distinct predicates = {np}, number of distinct objects = {no}");
+
+                        if let Some(meta) = DatasetMetadata::read(&path)? {
+                            print_dataset_metadata(&meta, state_fingerprint.as_deref());
+                        }
                     },
-                    Err(e) => eprintln!("Error: unable to open {path:?}: {e:?}; skipping"),
+                    Err(e) => warn(Warning::SkippedFile { path, reason: format!("unable to open: {e:?}") }),
                 }
             }
 
             println!("number of total triples = {sum_total}");
         },
-        Opts::Sort { recursive, compressed_datasets } => {
-            for path in dataset_iter(compressed_datasets, recursive, COMPRESSED_TRIPLE_FILE_EXTENSION) {
+        Opts::Fingerprint { walk_opts, compressed_datasets } => {
+            for path in dataset_iter(compressed_datasets, &walk_opts, COMPRESSED_TRIPLE_FILE_EXTENSION) {
+                let path = path?;
+                match unsafe { CompressedRdfTriples::load(&path) } {
+                    Ok(dataset) => println!("{path:?}: {:032x} ({} triples)", dataset.fingerprint(), dataset.len()),
+                    Err(e) => warn(Warning::SkippedFile { path, reason: format!("unable to open: {e:?}") }),
+                }
+            }
+        },
+        Opts::Sort { walk_opts, compressed_datasets } => {
+            // datasets are sorted in place, an interrupt finishes the current one instead of leaving it half sorted
+            util::catch_interrupts();
+            let mut n_sorted = 0;
+
+            for path in dataset_iter(compressed_datasets, &walk_opts, COMPRESSED_TRIPLE_FILE_EXTENSION) {
                 let path = path?;
+
+                if util::is_interrupted() {
+                    return Err(format!(
+                        "interrupted after sorting {n_sorted} datasets, {path:?} and the datasets after it were not sorted"
+                    )
+                    .into());
+                }
+
                 match unsafe { CompressedRdfTriples::load_shared(&path) } {
                     Ok(mut dataset) => {
                         println!("sorting {path:?}...");
                         dataset.sort_unstable();
+                        n_sorted += 1;
                     },
-                    Err(e) => eprintln!("Error: unable to open {path:?}: {e:?}; skipping"),
+                    Err(e) => warn(Warning::SkippedFile { path, reason: format!("unable to open: {e:?}") }),
+                }
+            }
+        },
+        Opts::Contained {
+            main_dataset: dataset,
+            walk_opts,
+            load_opts: LoadOpts { load_into_memory },
+            auto_sort,
+            containment,
+            summary_only,
+            csv_out,
+            compressed_datasets,
+        } => {
+            println!("loading main dataset...");
+            let dataset_triples = load_containment_dataset(&dataset, load_into_memory, auto_sort)?;
+            let dataset_index = containment_index(&dataset_triples, &dataset, containment, "main dataset")?;
+
+            let paths = dataset_iter(compressed_datasets, &walk_opts, COMPRESSED_TRIPLE_FILE_EXTENSION)
+                .collect::<std::io::Result<Vec<_>>>()?;
+
+            let mut datasets = Vec::with_capacity(paths.len());
+
+            let mut report = |path: PathBuf, counts: std::io::Result<(usize, usize)>| match counts {
+                Ok((_, 0)) => warn(Warning::SkippedFile { path, reason: "contains no triples".to_owned() }),
+                Ok((n_contained, n_triples)) => {
+                    if !summary_only {
+                        println!(
+                            "{n_contained}/{n_triples} ({percentage:.2}%) of triples from {path:?} are contained in the main dataset",
+                            percentage = 100.0 * (n_contained as f32) / (n_triples as f32)
+                        );
+                    }
+
+                    datasets.push(ContainedDataset { path, n_triples, n_contained });
+                },
+                Err(e) => warn(Warning::SkippedFile { path, reason: format!("unable to open: {e:?}") }),
+            };
+
+            // the datasets are checked in parallel against the shared main dataset, but reported in order,
+            // every result as soon as the results of all datasets before it are reported
+            std::thread::scope(|s| {
+                let (tx, rx) = std::sync::mpsc::channel();
+
+                s.spawn(|| {
+                    paths.par_iter().enumerate().for_each_with(tx, |tx, (ix, path)| {
+                        let counts = unsafe { CompressedRdfTriples::load(path) }.map(|dataset| {
+                            let mut cursor = dataset_index.checker();
+                            (dataset.iter().filter(|t| cursor.contains(t)).count(), dataset.len())
+                        });
+
+                        // the receiver only stops early if reporting panicked
+                        let _ = tx.send((ix, counts));
+                    })
+                });
+
+                let mut pending = BTreeMap::new();
+                let mut n_reported = 0;
+
+                for (ix, counts) in rx {
+                    pending.insert(ix, counts);
+
+                    while let Some(counts) = pending.remove(&n_reported) {
+                        report(paths[n_reported].clone(), counts);
+                        n_reported += 1;
+                    }
                 }
+            });
+
+            let n_contained: usize = datasets.iter().map(|dataset| dataset.n_contained).sum();
+            let n_triples: usize = datasets.iter().map(|dataset| dataset.n_triples).sum();
+
+            println!(
+                "total: {n_contained}/{n_triples} ({percentage:.2}%) of triples from {} datasets are contained in the main dataset",
+                datasets.len(),
+                percentage = 100.0 * overlap::fraction(n_contained, n_triples)
+            );
+
+            for (bucket, n_datasets) in contained::histogram(&datasets).into_iter().enumerate() {
+                let width = 100 / contained::N_BUCKETS;
+                println!("  {:>3}-{:>3}%: {n_datasets}", bucket * width, (bucket + 1) * width);
+            }
+
+            if let Some(csv_out) = csv_out {
+                contained::write_csv(csv_out, &datasets)?;
             }
         },
-        Opts::Contained { main_dataset: dataset, recursive, compressed_datasets } => {
+        Opts::Overlap {
+            main_dataset,
+            compressed_changesets,
+            traversal_opts,
+            out,
+            pairwise_out,
+            load_opts: LoadOpts { load_into_memory },
+        } => {
             println!("loading main dataset...");
-            let dataset_triples = unsafe { CompressedRdfTriples::load(dataset)? };
+            let dataset_triples = load_dataset(&main_dataset, load_into_memory)?;
             assert!(
                 dataset_triples.is_sorted(),
-                "dataset triples must be sorted to ensure correct query generation"
+                "main dataset must be sorted to ensure correct overlap computation"
             );
 
-            for path in dataset_iter(compressed_datasets, recursive, COMPRESSED_TRIPLE_FILE_EXTENSION) {
-                let path = path?;
-                match unsafe { CompressedRdfTriples::load(&path) } {
-                    Ok(dataset) => {
-                        let total = dataset.len();
-                        let contained = dataset.iter().filter(|t| dataset_triples.contains(t)).count();
+            println!("loading changesets...");
+            let (changeset_paths, changesets): (Vec<_>, Vec<_>) =
+                changeset_file_iter(compressed_changesets, &traversal_opts, COMPRESSED_TRIPLE_FILE_EXTENSION)
+                    .map(Result::unwrap)
+                    .filter_map(|path| match unsafe { CompressedRdfTriples::load(&path) } {
+                        Ok(triples) => Some((path, triples)),
+                        Err(e) => {
+                            warn(Warning::SkippedFile { path, reason: format!("unable to open: {e:?}") });
+                            None
+                        },
+                    })
+                    .unzip();
 
-                        println!(
-                            "{contained}/{total} ({percentage:.2}%) of triples from {path:?} are contained in the main dataset",
-                            percentage = 100.0 * (contained as f32) / (total as f32)
+            println!("computing overlap of {} changesets...", changesets.len());
+            let overlap = overlap::compute_overlap(&dataset_triples, &changesets, pairwise_out.is_some());
+
+            overlap::write_csv(out, &changeset_paths, &overlap)?;
+
+            if let Some(pairwise_out) = pairwise_out {
+                overlap::write_pairwise_csv(pairwise_out, &changeset_paths, &overlap)?;
+            }
+        },
+        Opts::Versions {
+            base_dataset,
+            compressed_changesets,
+            traversal_opts,
+            insert_pattern,
+            delete_pattern,
+            out_dir,
+        } => {
+            println!("loading base dataset...");
+            let base = unsafe { CompressedRdfTriples::load(&base_dataset)? };
+
+            let changesets = versions::group_changesets(
+                changeset_file_iter(compressed_changesets, &traversal_opts, COMPRESSED_TRIPLE_FILE_EXTENSION)
+                    .map(Result::unwrap),
+                &insert_pattern,
+                &delete_pattern,
+            );
+
+            println!("found {} changesets", changesets.len());
+            versions::materialize_versions(&base, &changesets, &out_dir)?;
+        },
+        Opts::BuildDataset {
+            base_dataset,
+            compressed_changesets,
+            traversal_opts,
+            insert_pattern,
+            delete_pattern,
+            out,
+        } => {
+            let base = match base_dataset {
+                Some(base_dataset) => {
+                    println!("loading base dataset...");
+                    Some(unsafe { CompressedRdfTriples::load(&base_dataset)? })
+                },
+                None => None,
+            };
+
+            let changesets = versions::group_changesets(
+                changeset_file_iter(compressed_changesets, &traversal_opts, COMPRESSED_TRIPLE_FILE_EXTENSION)
+                    .map(Result::unwrap),
+                &insert_pattern,
+                &delete_pattern,
+            );
+
+            println!("found {} changesets", changesets.len());
+            let versions::VersionDelta { n_added, n_removed, n_triples } =
+                versions::build_dataset(base.as_ref(), &changesets, &out)?;
+            println!("added {n_added} triples, removed {n_removed} triples, resulting dataset has {n_triples} triples");
+        },
+        Opts::Load {
+            compressor_state,
+            compressed_dataset,
+            endpoint,
+            graph,
+            method,
+            chunk_size,
+            resume,
+        } => {
+            let target = LoadTarget { endpoint, graph, method };
+            let recorded = resume.then(|| LoadProgress::read(&compressed_dataset)).transpose()?;
+
+            println!("loading compressor state...");
+            let decompressor = unsafe { RdfTripleDecompressor::load_state(compressor_state)? };
+            let dataset = unsafe { CompressedRdfTriples::load(&compressed_dataset)? };
+
+            let mut progress = LoadProgress::new(target, &dataset);
+            if let Some(recorded) = recorded {
+                progress.resume(&recorded, &dataset)?;
+                println!("resuming after {} loaded triples", progress.n_loaded);
+            }
+
+            // an interrupt finishes the current chunk and keeps the progress for --resume
+            util::catch_interrupts();
+
+            let n_triples = dataset.len();
+            let mut last_percentage = None;
+
+            load::load_dataset(
+                &decompressor,
+                &compressed_dataset,
+                &dataset,
+                progress,
+                chunk_size,
+                |n_loaded| {
+                    let percentage = 100 * n_loaded / n_triples;
+                    if last_percentage != Some(percentage) {
+                        println!("loaded {n_loaded}/{n_triples} triples ({percentage}%)");
+                        last_percentage = Some(percentage);
+                    }
+                },
+            )?;
+        },
+        Opts::Sample { compressed_dataset, n_triples, stratify, seed, out } => {
+            let dataset = unsafe { CompressedRdfTriples::load(&compressed_dataset)? };
+
+            println!("sampling {compressed_dataset:?}...");
+            let triples = sample::sample_dataset(&dataset, n_triples, stratify, &mut util::seeded_rng(seed));
+
+            println!("sampled {} of {} triples", triples.len(), dataset.len());
+            save_compressed_triples(out, &triples)?;
+        },
+        Opts::Estimate {
+            compressor_state,
+            compressed_dataset,
+            prepare_query_format,
+            term_escaping,
+            n_samples,
+            load_opts: LoadOpts { load_into_memory },
+            query_specs,
+        } => {
+            println!("loading compressor state...");
+            let decompressor = load_state(&compressor_state, load_into_memory)?;
+
+            println!("loading main dataset...");
+            let dataset_triples = load_dataset(&compressed_dataset, load_into_memory)?;
+
+            let sample = estimate::sample_triples(&decompressor, &dataset_triples, n_samples, term_escaping)?;
+            println!(
+                "sampled {} triples: {:.1} bytes and {:?} per triple",
+                sample.n_sampled, sample.avg_triple_bytes, sample.time_per_triple
+            );
+
+            if sample.n_missing > 0 {
+                warn(Warning::PrunedTriples {
+                    reason: "terms of sampled triples are missing from the compressor state".to_owned(),
+                    n_pruned: sample.n_missing,
+                });
+            }
+
+            let mut rng = rand::thread_rng();
+            let mut query_plan = sparql::OutputPlan::default();
+            let mut prepare_plan = sparql::OutputPlan::default();
+
+            let resolved: Vec<_> = query_specs
+                .into_iter()
+                .map(|spec| (spec.clone(), spec.resolve(dataset_triples.len(), &mut rng)))
+                .collect();
+
+            for (_, queries) in &resolved {
+                for &sparql::QuerySpec { n_queries, n_triples_per_query, query_type, .. } in queries {
+                    query_plan.add_queries_of_triple_len(
+                        n_queries,
+                        n_triples_per_query,
+                        sample.avg_triple_bytes,
+                        OutputFormat::Query,
+                    );
+
+                    if query_type == QueryType::InsertData {
+                        prepare_plan.add_queries_of_triple_len(
+                            n_queries,
+                            n_triples_per_query,
+                            sample.avg_triple_bytes,
+                            prepare_query_format,
                         );
-                    },
-                    Err(e) => eprintln!("Error: unable to open {path:?}: {e:?}; skipping"),
+                    }
+                }
+            }
+
+            println!("estimate: query file of {query_plan}");
+            println!("estimate: preparation file of {prepare_plan}");
+            println!(
+                "estimate: ~{:.1} min to generate",
+                sample.predict_time(query_plan.n_triples + prepare_plan.n_triples).as_secs_f64() / 60.0
+            );
+
+            let totals: Vec<usize> = resolved
+                .iter()
+                .map(|(_, queries)| queries.iter().map(sparql::QuerySpec::n_selected_triples).sum())
+                .collect();
+            let total: usize = totals.iter().sum();
+            let n_available = dataset_triples.len();
+
+            println!(
+                "estimate: distinct generation selects {total} of {n_available} triples, {}",
+                if total <= n_available {
+                    "the specs can be generated without --allow-duplicates"
+                } else {
+                    "the specs need --allow-duplicates or fewer queries"
+                }
+            );
+
+            for ((spec, queries), spec_total) in resolved.iter().zip(&totals) {
+                match query_spec::max_distinct_queries(queries, total - spec_total, n_available) {
+                    Some(max_queries) => println!(
+                        "  {spec}: {spec_total} triples, at most {} with the other specs",
+                        QuerySpecOpt { n_queries: max_queries, ..spec.clone() }
+                    ),
+                    None => println!("  {spec}: selects no triples"),
                 }
             }
         },
+        Opts::ConvertQueries { format, out, gzip, query_file } => {
+            println!("converting {query_file:?}...");
+            let counts = convert::convert_query_file(&query_file, &out, format, gzip)?;
+            println!(
+                "converted {} queries with {} triples",
+                counts.n_queries, counts.n_triples
+            );
+        },
+        Opts::QueryStats { query_file } => {
+            let stats = query_stats::query_file_stats(&query_file)?;
+            let [n_insert_triples, n_delete_triples] = stats.n_triples_by_type;
+
+            println!(
+                "number of queries = {} (warm-up = {}), insert queries = {}, delete queries = {}",
+                stats.n_queries(),
+                stats.n_warmup,
+                stats.n_inserts,
+                stats.n_deletes
+            );
+            println!(
+                "number of triples = {}, in insert queries = {n_insert_triples}, in delete queries = {n_delete_triples}",
+                stats.n_triples()
+            );
+            println!(
+                "triples per query: min = {}, avg = {:.1}, max = {}, p50 = {}, p90 = {}, p99 = {}",
+                stats.percentile(0.0),
+                stats.avg_query_size(),
+                stats.percentile(100.0),
+                stats.percentile(50.0),
+                stats.percentile(90.0),
+                stats.percentile(99.0)
+            );
+            println!("number of bytes = {}", stats.n_bytes);
+        },
     }
 
     Ok(())
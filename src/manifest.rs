@@ -0,0 +1,238 @@
+//! Manifest of a generated query file, written next to it as `<file>.manifest.json`.
+//! Lists every query of the run, split into the warm-up queries and the measured ones.
+//! Changeset based runs additionally write `<file>.changesets.json` with the changesets used per query.
+//! Runs with checkpointing keep their progress in `<file>.checkpoint.json` until they complete.
+//! Replication with an include or exclude dataset writes `<file>.pruning.json` with the pruned triples.
+
+use crate::{
+    sparql::{PruneCounts, QueryType},
+    util::{commit_buffered, AtomicFile},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+fn sidecar_path(query_file: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = query_file.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(suffix);
+    query_file.with_file_name(file_name)
+}
+
+/// A query as it was written
+#[derive(Serialize)]
+pub struct QueryRecord {
+    /// Position of the query among the queries written by this run
+    pub query_ix: usize,
+    pub query_type: QueryType,
+    pub graph: Option<String>,
+    pub requested_triples: Option<usize>,
+    pub n_triples: usize,
+    /// Triples of the query written to the prepare file, none for delete queries and without a prepare file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_prepare_triples: Option<usize>,
+    #[serde(skip)]
+    pub warmup: bool,
+    /// Position of the query in the order its triples were generated, differs from `query_ix`
+    /// if the queries were reordered after generation
+    #[serde(skip)]
+    pub generation_ix: usize,
+}
+
+/// Output budget that ended the generation early
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CutOffReason {
+    MaxOutputBytes,
+    MaxTotalTriples,
+}
+
+/// Records where the generation was stopped because an output budget was reached
+#[derive(Serialize)]
+pub struct CutOff {
+    pub reason: CutOffReason,
+    pub n_written_queries: usize,
+    /// Planned queries that were not generated
+    pub n_skipped_queries: usize,
+}
+
+/// Triples written to the query file and the prepare file, the prepare file is complete if it has as many triples
+/// as the insert queries
+#[derive(Default, Serialize)]
+pub struct TripleTotals {
+    pub n_insert_triples: usize,
+    pub n_delete_triples: usize,
+    pub n_prepare_triples: usize,
+    /// Triples requested by the specs of the written queries
+    pub n_requested_triples: usize,
+    /// Triples the written queries have fewer than requested
+    pub n_missing_triples: usize,
+    /// Written queries with fewer triples than requested
+    pub n_short_queries: usize,
+}
+
+impl TripleTotals {
+    pub fn of(records: &[QueryRecord]) -> Self {
+        let mut totals = TripleTotals::default();
+
+        for record in records {
+            match record.query_type {
+                QueryType::InsertData => totals.n_insert_triples += record.n_triples,
+                QueryType::DeleteData => totals.n_delete_triples += record.n_triples,
+            }
+
+            totals.n_prepare_triples += record.n_prepare_triples.unwrap_or_default();
+
+            if let Some(requested) = record.requested_triples {
+                totals.n_requested_triples += requested;
+
+                if record.n_triples < requested {
+                    totals.n_missing_triples += requested - record.n_triples;
+                    totals.n_short_queries += 1;
+                }
+            }
+        }
+
+        totals
+    }
+}
+
+#[derive(Serialize)]
+pub struct Manifest {
+    pub warmup: Vec<QueryRecord>,
+    pub measured: Vec<QueryRecord>,
+    pub totals: TripleTotals,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cut_off: Option<CutOff>,
+}
+
+impl Manifest {
+    pub fn new(records: Vec<QueryRecord>, cut_off: Option<CutOff>) -> Self {
+        let totals = TripleTotals::of(&records);
+        let (warmup, measured) = records.into_iter().partition(|record| record.warmup);
+        Manifest { warmup, measured, totals, cut_off }
+    }
+
+    /// Path of the manifest for the query file at `query_file`
+    pub fn path(query_file: &Path) -> PathBuf {
+        sidecar_path(query_file, ".manifest.json")
+    }
+
+    pub fn write(&self, query_file: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(AtomicFile::create(Self::path(query_file), false)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        commit_buffered(writer)
+    }
+}
+
+/// Triples of a changeset that were read for a query, `end` is exclusive.
+/// Fixed size queries only contain the triples of the range that are in the main dataset.
+#[derive(Serialize)]
+pub struct ChangesetSelection {
+    pub path: PathBuf,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The changesets a query was generated from
+#[derive(Serialize)]
+pub struct QuerySelection {
+    pub query_ix: usize,
+    pub query_type: QueryType,
+    pub changesets: Vec<ChangesetSelection>,
+}
+
+/// Path of the changeset selection report for the query file at `query_file`
+pub fn selection_report_path(query_file: &Path) -> PathBuf {
+    sidecar_path(query_file, ".changesets.json")
+}
+
+/// Writes the changesets used per query next to the query file as `<file>.changesets.json`
+pub fn write_selection_report(query_file: &Path, selections: &[QuerySelection]) -> io::Result<()> {
+    let mut writer = BufWriter::new(AtomicFile::create(selection_report_path(query_file), false)?);
+    serde_json::to_writer_pretty(&mut writer, selections)?;
+    commit_buffered(writer)
+}
+
+/// Pruned and kept triples of replicated datasets, the datasets of a merged query are counted together
+#[derive(Serialize)]
+pub struct DatasetPruning {
+    pub datasets: Vec<PathBuf>,
+    #[serde(flatten)]
+    pub counts: PruneCounts,
+}
+
+#[derive(Serialize)]
+pub struct PruneReport {
+    pub datasets: Vec<DatasetPruning>,
+    pub total: PruneCounts,
+}
+
+/// Path of the pruning report for the query file at `query_file`
+pub fn prune_report_path(query_file: &Path) -> PathBuf {
+    sidecar_path(query_file, ".pruning.json")
+}
+
+/// Writes the pruned triples per replicated dataset next to the query file as `<file>.pruning.json`
+pub fn write_prune_report(query_file: &Path, report: &PruneReport) -> io::Result<()> {
+    let mut writer = BufWriter::new(AtomicFile::create(prune_report_path(query_file), false)?);
+    serde_json::to_writer_pretty(&mut writer, report)?;
+    commit_buffered(writer)
+}
+
+/// Uncommitted temporary file of an output and its length at the checkpoint
+#[derive(Serialize, Deserialize)]
+pub struct PartialFile {
+    pub tmp_path: PathBuf,
+    pub len: u64,
+}
+
+/// Progress of an interrupted generation run, from which it can be resumed
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Seed the run was started with, resuming replays the generation of the written queries with it
+    pub seed: Option<u64>,
+    /// Number of queries the run planned, resuming a run with a different plan is refused
+    pub n_planned: usize,
+    /// Number of triples of every query written so far
+    pub n_triples: Vec<usize>,
+    pub in_warmup: bool,
+    pub query_file: PartialFile,
+    pub prepare_file: Option<PartialFile>,
+}
+
+impl Checkpoint {
+    /// Path of the checkpoint for the query file at `query_file`
+    pub fn path(query_file: &Path) -> PathBuf {
+        sidecar_path(query_file, ".checkpoint.json")
+    }
+
+    pub fn read(query_file: &Path) -> io::Result<Self> {
+        let path = Self::path(query_file);
+        let file = File::open(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("unable to open checkpoint {path:?} to resume from: {e}"),
+            )
+        })?;
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn write(&self, query_file: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(AtomicFile::create(Self::path(query_file), false)?);
+        serde_json::to_writer(&mut writer, self)?;
+        commit_buffered(writer)
+    }
+
+    /// Removes the checkpoint of a completed run, if there is one
+    pub fn remove(query_file: &Path) -> io::Result<()> {
+        match std::fs::remove_file(Self::path(query_file)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Overlap of changesets with the main dataset and with each other, helps to diagnose why
+//! changeset based generation yields fewer triples than requested
+
+use crate::{
+    rdf::triple_compressor::{CompressedRdfTriples, CompressedTriple},
+    util::{commit_buffered, AtomicFile},
+};
+use rayon::slice::ParallelSliceMut;
+use std::{
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Overlap counts per changeset, all counts are of distinct triples
+pub struct Overlap {
+    pub n_triples: Vec<usize>,
+    pub in_main_dataset: Vec<usize>,
+    /// Triples contained in at least one other changeset
+    pub in_other_changesets: Vec<usize>,
+    /// `pairwise[i][j]` is the number of triples of changeset `i` contained in changeset `j`
+    pub pairwise: Option<Vec<Vec<usize>>>,
+}
+
+/// Computes the overlap by sorting the triples of all changesets together, so that every triple
+/// is checked against the main dataset once. The main dataset must be sorted.
+pub fn compute_overlap(
+    main_dataset: &CompressedRdfTriples,
+    changesets: &[CompressedRdfTriples],
+    pairwise: bool,
+) -> Overlap {
+    let n_changesets = changesets.len();
+
+    let mut occurrences: Vec<(CompressedTriple, usize)> = changesets
+        .iter()
+        .enumerate()
+        .flat_map(|(ix, changeset)| changeset.iter().map(move |&triple| (triple, ix)))
+        .collect();
+
+    occurrences.par_sort_unstable();
+    occurrences.dedup();
+
+    let mut overlap = Overlap {
+        n_triples: vec![0; n_changesets],
+        in_main_dataset: vec![0; n_changesets],
+        in_other_changesets: vec![0; n_changesets],
+        pairwise: pairwise.then(|| vec![vec![0; n_changesets]; n_changesets]),
+    };
+
+    let mut main_cursor = main_dataset.containment_cursor();
+    let mut start = 0;
+
+    while start < occurrences.len() {
+        let triple = occurrences[start].0;
+        let mut end = start + 1;
+        while end < occurrences.len() && occurrences[end].0 == triple {
+            end += 1;
+        }
+
+        let group = &occurrences[start..end];
+        let in_main_dataset = main_cursor.contains(&triple);
+
+        for &(_, ix) in group {
+            overlap.n_triples[ix] += 1;
+            overlap.in_main_dataset[ix] += in_main_dataset as usize;
+            overlap.in_other_changesets[ix] += (group.len() > 1) as usize;
+        }
+
+        if let Some(pairwise) = &mut overlap.pairwise {
+            for &(_, i) in group {
+                for &(_, j) in group {
+                    if i != j {
+                        pairwise[i][j] += 1;
+                    }
+                }
+            }
+        }
+
+        start = end;
+    }
+
+    overlap
+}
+
+/// Fraction of `count` in `total`, 0 for an empty total
+pub fn fraction(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// Quotes `path` for use as a CSV field
+pub(crate) fn csv_path(path: &Path) -> String {
+    format!("\"{}\"", path.display().to_string().replace('"', "\"\""))
+}
+
+/// Writes one row per changeset with its number of triples and the fractions contained in the main dataset
+/// and in other changesets
+pub fn write_csv<P: AsRef<Path>>(path: P, changesets: &[PathBuf], overlap: &Overlap) -> io::Result<()> {
+    let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+
+    writeln!(writer, "changeset,n_triples,in_main_dataset,in_other_changesets")?;
+
+    for (ix, changeset) in changesets.iter().enumerate() {
+        let n_triples = overlap.n_triples[ix];
+
+        writeln!(
+            writer,
+            "{},{n_triples},{:.6},{:.6}",
+            csv_path(changeset),
+            fraction(overlap.in_main_dataset[ix], n_triples),
+            fraction(overlap.in_other_changesets[ix], n_triples)
+        )?;
+    }
+
+    commit_buffered(writer)
+}
+
+/// Writes the matrix of the fractions of the triples of the changeset in each row contained in the changeset of each column
+pub fn write_pairwise_csv<P: AsRef<Path>>(path: P, changesets: &[PathBuf], overlap: &Overlap) -> io::Result<()> {
+    let Some(pairwise) = &overlap.pairwise else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "pairwise overlap was not computed",
+        ));
+    };
+
+    let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+
+    write!(writer, "changeset")?;
+    for changeset in changesets {
+        write!(writer, ",{}", csv_path(changeset))?;
+    }
+    writeln!(writer)?;
+
+    for (ix, changeset) in changesets.iter().enumerate() {
+        write!(writer, "{}", csv_path(changeset))?;
+
+        for &count in &pairwise[ix] {
+            write!(writer, ",{:.6}", fraction(count, overlap.n_triples[ix]))?;
+        }
+
+        writeln!(writer)?;
+    }
+
+    commit_buffered(writer)
+}
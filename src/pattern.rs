@@ -0,0 +1,232 @@
+//! Triple patterns selecting the triples of compressed datasets, e.g. `?s <http://xmlns.com/foaf/0.1/name> "Berlin"@en`.
+//! Every position is a term in N-Triples syntax, a variable `?name` or `*` matching any term, or `~regex~` searching the
+//! bytes of the term in N-Triples syntax for a regular expression, e.g. `~@en$~` for English literals. The regex is not
+//! anchored and cannot contain `~`. A variable occurring twice requires the same term at both positions. Terms are
+//! resolved to their ids once, so that matching compares the ids of the compressed triples and only decompresses terms
+//! checked against a regex, caching the result per term for a bounded number of terms.
+
+use crate::rdf::{
+    ntriples_tokenizer::{skip_whitespace, term_end},
+    triple_compressor::{compressor::term_id, decompressor::RdfTripleDecompressor, CompressedTriple, TripleElementId},
+};
+use regex::bytes::Regex;
+use std::{collections::HashMap, fmt, hash::BuildHasherDefault, io, str::FromStr};
+
+#[derive(Clone)]
+enum TermPattern {
+    Any,
+    Term { id: TripleElementId, term: String },
+    Regex(Regex),
+}
+
+/// A parsed triple pattern, see the [module documentation](self) for the syntax
+#[derive(Clone)]
+pub struct TriplePattern {
+    source: String,
+    terms: [TermPattern; 3],
+    /// positions that have to hold the same term because they have the same variable
+    same_terms: Vec<(usize, usize)>,
+}
+
+impl fmt::Display for TriplePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for TriplePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        let mut pos = 0;
+
+        let mut terms = [TermPattern::Any, TermPattern::Any, TermPattern::Any];
+        let mut variables: Vec<(usize, &str)> = Vec::new();
+        let mut same_terms = Vec::new();
+
+        for (ix, term) in terms.iter_mut().enumerate() {
+            pos = skip_whitespace(bytes, pos);
+
+            let end = match bytes.get(pos) {
+                Some(b'?' | b'*') => memchr::memchr2(b' ', b'\t', &bytes[pos..]).map_or(bytes.len(), |off| pos + off),
+                Some(b'~') => match memchr::memchr(b'~', &bytes[pos + 1..]) {
+                    Some(off) => pos + off + 2,
+                    None => {
+                        return Err(format!(
+                            "invalid pattern '{s}': unterminated regex at column {}",
+                            pos + 1
+                        ))
+                    },
+                },
+                _ => term_end(bytes, pos).map_err(|e| format!("invalid pattern '{s}': {e}"))?,
+            };
+
+            let token = &s[pos..end];
+            pos = end;
+
+            *term = match token.as_bytes()[0] {
+                b'*' if token.len() == 1 => TermPattern::Any,
+                b'*' => {
+                    return Err(format!(
+                        "invalid pattern '{s}': unexpected '{token}', use * alone or ?name"
+                    ))
+                },
+                b'?' => {
+                    let name = &token[1..];
+
+                    if !name.is_empty() {
+                        if let Some(&(first_ix, _)) = variables.iter().find(|&&(_, other)| other == name) {
+                            same_terms.push((first_ix, ix));
+                        } else {
+                            variables.push((ix, name));
+                        }
+                    }
+
+                    TermPattern::Any
+                },
+                b'~' => TermPattern::Regex(
+                    Regex::new(&token[1..token.len() - 1])
+                        .map_err(|e| format!("invalid pattern '{s}': invalid regex {token}: {e}"))?,
+                ),
+                _ => TermPattern::Term { id: term_id(token.as_bytes()), term: token.to_owned() },
+            };
+        }
+
+        pos = skip_whitespace(bytes, pos);
+        if bytes.get(pos) == Some(&b'.') {
+            pos = skip_whitespace(bytes, pos + 1);
+        }
+
+        if pos != bytes.len() {
+            return Err(format!(
+                "invalid pattern '{s}': unexpected content at column {}",
+                pos + 1
+            ));
+        }
+
+        Ok(TriplePattern { source: s.to_owned(), terms, same_terms })
+    }
+}
+
+/// Number of cached regex matches after which the cache is cleared, so that a regex over e.g. all objects
+/// does not cache a result for every term of the dictionary
+const MAX_CACHED_REGEX_MATCHES: usize = 1 << 20;
+
+/// Matches compressed triples against patterns, a triple matches if it matches any of them
+pub struct PatternMatcher<'d> {
+    decompressor: &'d RdfTripleDecompressor,
+    patterns: Vec<TriplePattern>,
+    /// whether the term with the id matches the regex at a position, keyed by `3 * pattern index + position`.
+    /// Holds at most [`MAX_CACHED_REGEX_MATCHES`] entries
+    regex_matches: HashMap<(usize, TripleElementId), bool, BuildHasherDefault<ahash::AHasher>>,
+}
+
+impl<'d> PatternMatcher<'d> {
+    /// Creates a matcher for `patterns`, without patterns every triple matches.
+    /// Patterns with terms that are not in the compressor state are reported, they cannot match any triple
+    pub fn new(decompressor: &'d RdfTripleDecompressor, patterns: Vec<TriplePattern>) -> io::Result<Self> {
+        for pattern in &patterns {
+            for term in &pattern.terms {
+                if let TermPattern::Term { id, term } = term {
+                    if decompressor.decompress_term(*id)?.is_none() {
+                        println!("pattern '{pattern}': {term} is not in the compressor state, the pattern matches no triples");
+                    }
+                }
+            }
+        }
+
+        Ok(Self { decompressor, patterns, regex_matches: HashMap::default() })
+    }
+
+    /// Returns whether `triple` matches any of the patterns, fails if a term checked against a regex cannot be loaded
+    pub fn matches(&mut self, triple: CompressedTriple) -> io::Result<bool> {
+        let Self { decompressor, patterns, regex_matches } = self;
+
+        if patterns.is_empty() {
+            return Ok(true);
+        }
+
+        'patterns: for (pattern_ix, pattern) in patterns.iter().enumerate() {
+            let ids_match = pattern.terms.iter().zip(triple).all(|(term, id)| match term {
+                TermPattern::Term { id: expected, .. } => id == *expected,
+                TermPattern::Any | TermPattern::Regex(_) => true,
+            });
+
+            if !ids_match || !pattern.same_terms.iter().all(|&(a, b)| triple[a] == triple[b]) {
+                continue;
+            }
+
+            for (pos, (term, id)) in pattern.terms.iter().zip(triple).enumerate() {
+                let TermPattern::Regex(regex) = term else {
+                    continue;
+                };
+
+                let key = (3 * pattern_ix + pos, id);
+                let is_match = match regex_matches.get(&key) {
+                    Some(&is_match) => is_match,
+                    None => {
+                        let is_match = decompressor.decompress_term(id)?.is_some_and(|term| regex.is_match(term));
+
+                        if regex_matches.len() >= MAX_CACHED_REGEX_MATCHES {
+                            regex_matches.clear();
+                        }
+                        regex_matches.insert(key, is_match);
+                        is_match
+                    },
+                };
+
+                if !is_match {
+                    continue 'patterns;
+                }
+            }
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdf::triple_compressor::compressor::RdfTripleCompressor;
+
+    #[test]
+    fn regex_searches_the_term_bytes() {
+        let dir = std::env::temp_dir().join(format!("sudg-pattern-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let nt_path = dir.join("dataset.nt");
+        std::fs::write(
+            &nt_path,
+            "<http://a> <http://p> \"Berlin\"@en .\n<http://a> <http://p> \"Berlin\"@de .\n",
+        )
+        .unwrap();
+
+        let state_path = dir.join("dataset.compressor_state");
+        let mut compressor = RdfTripleCompressor::new();
+        compressor
+            .compress_rdf_triple_file_to(&nt_path, dir.join("dataset.compressed_nt"), false, false)
+            .unwrap();
+        compressor.save_state(&state_path).unwrap();
+        let decompressor = RdfTripleDecompressor::read_state_into_memory(&state_path).unwrap();
+
+        let triple = |o: &[u8]| [term_id(b"<http://a>"), term_id(b"<http://p>"), term_id(o)];
+        let mut matcher = PatternMatcher::new(&decompressor, vec!["* * ~@en$~".parse().unwrap()]).unwrap();
+        assert!(matcher.matches(triple(b"\"Berlin\"@en")).unwrap());
+        assert!(!matcher.matches(triple(b"\"Berlin\"@de")).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_regex_is_reported() {
+        let err = "* * ~(~".parse::<TriplePattern>().err().unwrap();
+        assert!(err.starts_with("invalid pattern '* * ~(~': invalid regex ~(~"), "{err}");
+
+        let err = "* * ~@en".parse::<TriplePattern>().err().unwrap();
+        assert!(err.contains("unterminated regex at column 5"), "{err}");
+    }
+}
@@ -0,0 +1,262 @@
+//! Timings of the phases of query generation for `--perf-report`, to back performance related changes with numbers
+//! and to tune buffer sizes. Like the warnings they are collected globally, so that the generators can time their
+//! containment checks without a timer threaded through them. Nothing is timed unless [`enable`] was called.
+//!
+//! Queries may be written in another order than they were generated, so sampling and containment are recorded per
+//! generated query and added to the batch the query is written in, while decompression and I/O are timed per batch.
+
+use crate::util::{commit_buffered, AtomicFile};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::{self, BufWriter},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+/// A phase of query generation
+#[derive(Clone, Copy)]
+pub enum Phase {
+    /// checking whether selected triples are contained in the main dataset
+    Containment,
+    /// resolving the terms of the selected triples
+    Decompression,
+    /// serializing and writing the queries
+    Io,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PHASE_NANOS: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+/// Time spent in every phase by the queries of a batch, sampling without the containment checks
+#[derive(Clone, Serialize)]
+pub struct BatchTimings {
+    pub first_query_ix: usize,
+    pub n_queries: usize,
+    pub n_triples: usize,
+    pub sampling_secs: f64,
+    /// only fixed-size changeset and novel generation check their triples against the main dataset,
+    /// this is zero for the other generators
+    pub containment_secs: f64,
+    pub decompression_secs: f64,
+    pub io_secs: f64,
+}
+
+#[derive(Serialize)]
+pub struct PerfReport {
+    pub batch_size: usize,
+    pub total_secs: f64,
+    /// sampling of the queries written before a checkpoint, which are generated again when resuming
+    pub replay_secs: f64,
+    pub batches: Vec<BatchTimings>,
+}
+
+struct Recorder {
+    batch_size: usize,
+    started: Instant,
+    /// accumulated nanoseconds of every phase when the current batch started
+    batch_start: [u64; 3],
+    /// sampling and containment nanoseconds of the generated queries that were not written yet, by generation index
+    sampled: HashMap<usize, (u64, u64)>,
+    replay_secs: f64,
+    current: BatchTimings,
+    batches: Vec<BatchTimings>,
+}
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+fn phase_nanos() -> [u64; 3] {
+    std::array::from_fn(|ix| PHASE_NANOS[ix].load(Ordering::Relaxed))
+}
+
+/// Starts timing the phases, the written queries are grouped into batches of `batch_size` queries
+pub fn enable(batch_size: usize) {
+    *RECORDER.lock().unwrap() = Some(Recorder::new(batch_size));
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `f`, adding the time it takes to `phase` if timing is enabled
+pub fn timed<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    PHASE_NANOS[phase as usize].fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    result
+}
+
+/// Runs `f` selecting the triples of the query generated at `generation_ix`, recording the time it takes for the batch
+/// the query is written in
+pub fn sampled<T>(generation_ix: usize, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    let containment_start = PHASE_NANOS[Phase::Containment as usize].load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    let containment = PHASE_NANOS[Phase::Containment as usize].load(Ordering::Relaxed) - containment_start;
+
+    if let Some(recorder) = RECORDER.lock().unwrap().as_mut() {
+        recorder
+            .sampled
+            .insert(generation_ix, (nanos.saturating_sub(containment), containment));
+    }
+
+    result
+}
+
+impl Recorder {
+    fn new(batch_size: usize) -> Self {
+        Recorder {
+            batch_size: batch_size.max(1),
+            started: Instant::now(),
+            batch_start: phase_nanos(),
+            sampled: HashMap::new(),
+            replay_secs: 0.0,
+            current: BatchTimings {
+                first_query_ix: 0,
+                n_queries: 0,
+                n_triples: 0,
+                sampling_secs: 0.0,
+                containment_secs: 0.0,
+                decompression_secs: 0.0,
+                io_secs: 0.0,
+            },
+            batches: Vec::new(),
+        }
+    }
+
+    fn dequeue(&mut self, generation_ix: usize, replayed: bool) {
+        if let Some((sampling, containment)) = self.sampled.remove(&generation_ix) {
+            let (sampling, containment) = (sampling as f64 / 1e9, containment as f64 / 1e9);
+
+            if replayed {
+                self.replay_secs += sampling + containment;
+            } else {
+                self.current.sampling_secs += sampling;
+                self.current.containment_secs += containment;
+            }
+        }
+    }
+
+    fn written(&mut self, query_ix: usize, n_triples: usize) {
+        if self.current.n_queries == 0 {
+            self.current.first_query_ix = query_ix;
+        }
+
+        self.current.n_queries += 1;
+        self.current.n_triples += n_triples;
+
+        if self.current.n_queries == self.batch_size {
+            self.finish_batch();
+        }
+    }
+
+    fn finish_batch(&mut self) {
+        let now = phase_nanos();
+        let secs = |phase: Phase| (now[phase as usize] - self.batch_start[phase as usize]) as f64 / 1e9;
+
+        self.current.decompression_secs = secs(Phase::Decompression);
+        self.current.io_secs = secs(Phase::Io);
+
+        let first_query_ix = self.current.first_query_ix + self.current.n_queries;
+        self.batches.push(self.current.clone());
+
+        self.batch_start = now;
+        self.current = BatchTimings {
+            first_query_ix,
+            n_queries: 0,
+            n_triples: 0,
+            sampling_secs: 0.0,
+            containment_secs: 0.0,
+            ..self.current
+        };
+    }
+}
+
+/// Adds the sampling of the query generated at `generation_ix` to the current batch, or to the replay time if it was
+/// written before the checkpoint that is resumed. Called when the query is about to be written or was replayed
+pub fn query_dequeued(generation_ix: usize, replayed: bool) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if let Some(recorder) = RECORDER.lock().unwrap().as_mut() {
+        recorder.dequeue(generation_ix, replayed);
+    }
+}
+
+/// Records that the query at `query_ix` with `n_triples` triples was written, closing the batch once it is full
+pub fn query_written(query_ix: usize, n_triples: usize) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if let Some(recorder) = RECORDER.lock().unwrap().as_mut() {
+        recorder.written(query_ix, n_triples);
+    }
+}
+
+/// Writes the timings of all batches as JSON to `path`, the time after the last full batch is reported as a final batch
+pub fn write_report<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let mut recorder = RECORDER.lock().unwrap();
+    let Some(recorder) = recorder.as_mut() else {
+        return Err(io::Error::other("performance timing was not enabled"));
+    };
+
+    if recorder.current.n_queries > 0 || phase_nanos() != recorder.batch_start {
+        recorder.finish_batch();
+    }
+
+    let report = PerfReport {
+        batch_size: recorder.batch_size,
+        total_secs: recorder.started.elapsed().as_secs_f64(),
+        replay_secs: recorder.replay_secs,
+        batches: recorder.batches.clone(),
+    };
+
+    let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+    serde_json::to_writer_pretty(&mut writer, &report)?;
+    commit_buffered(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_is_added_to_the_batch_the_query_is_written_in() {
+        let mut recorder = Recorder::new(2);
+        // generated as 0, 1, 2, 3 and written as 1, 3, 0 after replaying 2
+        for generation_ix in 0..4 {
+            recorder
+                .sampled
+                .insert(generation_ix, ((generation_ix as u64 + 1) * 1_000_000_000, 0));
+        }
+        recorder.sampled.insert(3, (4_000_000_000, 500_000_000));
+
+        recorder.dequeue(2, true);
+        for (query_ix, generation_ix) in [(1, 1), (2, 3), (3, 0)] {
+            recorder.dequeue(generation_ix, false);
+            recorder.written(query_ix, 10);
+        }
+        recorder.finish_batch();
+
+        assert_eq!(recorder.replay_secs, 3.0);
+        assert_eq!(recorder.batches.len(), 2);
+        assert_eq!(recorder.batches[0].first_query_ix, 1);
+        assert_eq!(recorder.batches[0].sampling_secs, 6.0);
+        assert_eq!(recorder.batches[0].containment_secs, 0.5);
+        assert_eq!(recorder.batches[1].first_query_ix, 3);
+        assert_eq!(recorder.batches[1].sampling_secs, 1.0);
+        assert_eq!(recorder.batches[1].containment_secs, 0.0);
+    }
+}
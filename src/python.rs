@@ -0,0 +1,226 @@
+//! Python bindings, built as the `sparql_updategen` extension module when the `python` feature is enabled
+
+use crate::{
+    query_spec::{self, QuerySpecOpt},
+    rdf::{
+        self,
+        triple_compressor::{
+            compressor::RdfTripleCompressor, decompressor::RdfTripleDecompressor, CompressedRdfTriples,
+            COMPRESSED_TRIPLE_FILE_EXTENSION,
+        },
+        triple_generator::GeneratorContext,
+    },
+    sparql::{self, OutputFormat, OutputOrder, QuerySpec, WriteOpts},
+    util::{changeset_file_iter, TraversalOpts},
+};
+use clap::ArgEnum;
+use pyo3::{
+    exceptions::{PyIOError, PyValueError},
+    prelude::*,
+};
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+fn io_err(e: std::io::Error) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+fn parse_arg_enum<T: ArgEnum>(value: &str) -> PyResult<T> {
+    T::from_str(value, true).map_err(PyValueError::new_err)
+}
+
+fn parse_query_specs(specs: &[String]) -> PyResult<Vec<QuerySpecOpt>> {
+    specs.iter().map(|spec| spec.parse().map_err(PyValueError::new_err)).collect()
+}
+
+fn resolve_query_specs(specs: Vec<QuerySpecOpt>, n_total_triples: usize) -> Vec<QuerySpec> {
+    let mut rng = rand::thread_rng();
+    specs
+        .into_iter()
+        .flat_map(|spec| spec.resolve(n_total_triples, &mut rng))
+        .collect()
+}
+
+/// Compress n-triples files, writing `<dataset>.compressed_nt` next to each of them,
+/// and save the resulting compressor state to `state_out`
+#[pyfunction]
+#[pyo3(signature = (datasets, state_out, previous_state = None, dedup = false, parse = true))]
+fn compress(
+    py: Python<'_>,
+    datasets: Vec<PathBuf>,
+    state_out: PathBuf,
+    previous_state: Option<PathBuf>,
+    dedup: bool,
+    parse: bool,
+) -> PyResult<()> {
+    py.allow_threads(|| -> std::io::Result<()> {
+        let mut compressor = match previous_state {
+            Some(pcs) => RdfTripleCompressor::from_decompressor(unsafe { RdfTripleDecompressor::load_state(pcs)? })?,
+            None => RdfTripleCompressor::new(),
+        };
+
+        for dataset in datasets {
+            compressor.compress_rdf_triple_file(dataset, dedup, parse)?;
+        }
+
+        compressor.save_state(state_out)
+    })
+    .map_err(io_err)
+}
+
+/// Decompress a compressed dataset back into an n-triples file
+#[pyfunction]
+fn decompress(py: Python<'_>, state: PathBuf, dataset: PathBuf, out: PathBuf) -> PyResult<()> {
+    py.allow_threads(|| -> std::io::Result<()> {
+        let decompressor = unsafe { RdfTripleDecompressor::load_state(state)? };
+        decompressor.decompress_rdf_triple_file(dataset, BufWriter::new(File::create(out)?))
+    })
+    .map_err(io_err)
+}
+
+/// Generate queries by selecting random triples from the dataset, see `sparql-update-data-generator generate randomized`
+#[pyfunction]
+#[pyo3(signature = (
+    state, dataset, query_out, prepare_out, specs,
+    allow_duplicates = false, output_order = "as-specified", prepare_format = "query", append = false
+))]
+#[allow(clippy::too_many_arguments)]
+fn generate(
+    py: Python<'_>,
+    state: PathBuf,
+    dataset: PathBuf,
+    query_out: PathBuf,
+    prepare_out: PathBuf,
+    specs: Vec<String>,
+    allow_duplicates: bool,
+    output_order: &str,
+    prepare_format: &str,
+    append: bool,
+) -> PyResult<()> {
+    let specs = parse_query_specs(&specs)?;
+    let output_order: OutputOrder = parse_arg_enum(output_order)?;
+    let prepare_format: OutputFormat = parse_arg_enum(prepare_format)?;
+
+    py.allow_threads(|| -> std::io::Result<()> {
+        let decompressor = unsafe { RdfTripleDecompressor::load_state(state)? };
+        let dataset_triples = unsafe { CompressedRdfTriples::load(dataset)? };
+        let query_specs = query_spec::resolve_random_specs(
+            specs,
+            dataset_triples.len(),
+            (!allow_duplicates).then_some(dataset_triples.len()),
+            &mut rand::thread_rng(),
+        )
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        if allow_duplicates {
+            sparql::generate_queries(
+                query_out,
+                prepare_out,
+                prepare_format,
+                query_specs,
+                &decompressor,
+                rdf::triple_generator::random_triple_generator(&dataset_triples, None, None),
+                output_order,
+                WriteOpts { append, ..Default::default() },
+            )
+        } else {
+            let total_query_triples = query_specs.iter().map(|spec| spec.n_queries * spec.n_triples_per_query).sum();
+
+            sparql::generate_queries(
+                query_out,
+                prepare_out,
+                prepare_format,
+                query_specs,
+                &decompressor,
+                rdf::triple_generator::random_distinct_triple_generator(
+                    &dataset_triples,
+                    None,
+                    total_query_triples,
+                    None,
+                ),
+                output_order,
+                WriteOpts { append, ..Default::default() },
+            )
+        }
+    })
+    .map_err(io_err)
+}
+
+/// Generate queries from a directory tree of compressed changesets, see `sparql-update-data-generator generate changeset`
+#[pyfunction]
+#[pyo3(signature = (
+    state, dataset, changesets, query_out, prepare_out, specs,
+    fixed_size = false, output_order = "as-specified", prepare_format = "query", append = false
+))]
+#[allow(clippy::too_many_arguments)]
+fn generate_from_changesets(
+    py: Python<'_>,
+    state: PathBuf,
+    dataset: PathBuf,
+    changesets: PathBuf,
+    query_out: PathBuf,
+    prepare_out: PathBuf,
+    specs: Vec<String>,
+    fixed_size: bool,
+    output_order: &str,
+    prepare_format: &str,
+    append: bool,
+) -> PyResult<()> {
+    let specs = parse_query_specs(&specs)?;
+    let output_order: OutputOrder = parse_arg_enum(output_order)?;
+    let prepare_format: OutputFormat = parse_arg_enum(prepare_format)?;
+
+    py.allow_threads(|| -> std::io::Result<()> {
+        let decompressor = unsafe { RdfTripleDecompressor::load_state(state)? };
+        let dataset_triples = unsafe { CompressedRdfTriples::load(dataset)? };
+
+        if !dataset_triples.is_sorted() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "main dataset must be sorted to ensure correct changeset query generation",
+            ));
+        }
+
+        let changesets = changeset_file_iter(changesets, &TraversalOpts::default(), COMPRESSED_TRIPLE_FILE_EXTENSION)
+            .map(|path| unsafe { CompressedRdfTriples::load(path?) })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let query_specs = resolve_query_specs(specs, dataset_triples.len());
+
+        if fixed_size {
+            sparql::generate_queries(
+                query_out,
+                prepare_out,
+                prepare_format,
+                query_specs,
+                &decompressor,
+                rdf::triple_generator::fixed_size_changeset_triple_generator(&GeneratorContext {
+                    changesets: &changesets,
+                    ..GeneratorContext::new(&dataset_triples)
+                }),
+                output_order,
+                WriteOpts { append, ..Default::default() },
+            )
+        } else {
+            sparql::generate_queries(
+                query_out,
+                prepare_out,
+                prepare_format,
+                query_specs,
+                &decompressor,
+                rdf::triple_generator::as_is_changeset_triple_generator(&changesets, None, None, None, None, None),
+                output_order,
+                WriteOpts { append, ..Default::default() },
+            )
+        }
+    })
+    .map_err(io_err)
+}
+
+#[pymodule]
+fn sparql_updategen(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compress, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    m.add_function(wrap_pyfunction!(generate, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_from_changesets, m)?)?;
+    Ok(())
+}
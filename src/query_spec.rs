@@ -0,0 +1,624 @@
+use crate::{
+    sparql::{QuerySpec, QueryType},
+    warnings::{warn, Warning},
+};
+use rand::{seq::SliceRandom, Rng};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// A query spec as given on the command line, sizes are not yet resolved against a dataset.
+///
+/// Grammar: `<i|d><N_QUERIES>x<SIZE>[@GRAPH][#comment]` where counts accept `k`, `M` and `G` suffixes
+/// (`i10x5k`, `d1.5kx100`), and `SIZE` is either a count, a percentage of the dataset (`d10x0.1%`)
+/// or an inclusive range of counts (`d100x100-1k`) from which each query's size is drawn uniformly.
+/// `m<N_QUERIES>x<SIZE>:<INSERTS>/<DELETES>` mixes both types in the given ratio (`m200x1000:70/30`),
+/// the types are assigned to the queries in random order.
+/// Insert and mixed specs may give the share of novel triples per insert query after the size (`i100x1k~30%`),
+/// these are synthesized from terms of the dataset instead of being re-inserted from it.
+/// `GRAPH` is the IRI of a named graph the queries target (`i100x500@http://example.org/g1`),
+/// it has to be enclosed in `<>` if it contains a `#`.
+#[derive(Clone)]
+pub struct QuerySpecOpt {
+    pub n_queries: usize,
+    pub n_triples_per_query: QuerySizeOpt,
+    pub query_type: QueryTypeOpt,
+    /// Fraction of the triples of every insert query that are synthesized novel triples
+    pub novel: Option<f64>,
+    pub graph: Option<Arc<str>>,
+    /// Whether the queries belong to the warm-up section, set for specs given with `--warmup`
+    pub warmup: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum QuerySizeOpt {
+    Percentage(f64),
+    Absolute(usize),
+    Range(usize, usize),
+}
+
+#[derive(Clone, Copy)]
+pub enum QueryTypeOpt {
+    Fixed(QueryType),
+    /// Insert and delete queries in the ratio `inserts` to `deletes`
+    Mixed {
+        inserts: usize,
+        deletes: usize,
+    },
+}
+
+impl From<QueryType> for QueryTypeOpt {
+    fn from(query_type: QueryType) -> Self {
+        QueryTypeOpt::Fixed(query_type)
+    }
+}
+
+/// Formats an error message for `spec` with a caret pointing at the byte offset `pos`
+fn spec_error(spec: &str, pos: usize, msg: impl Display) -> String {
+    let col = spec[..pos.min(spec.len())].chars().count();
+    format!("invalid query spec, {msg}\n  {spec}\n  {}^", " ".repeat(col))
+}
+
+/// Parses a count like `100`, `5k` or `1.5M` starting at byte offset `start`.
+/// Returns the count and the offset of the first byte after it.
+fn parse_count(spec: &str, start: usize) -> Result<(usize, usize), String> {
+    let digits_end = spec[start..]
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map_or(spec.len(), |off| start + off);
+
+    if digits_end == start {
+        return Err(spec_error(spec, start, "expected a number"));
+    }
+
+    let (multiplier, end) = match spec.as_bytes().get(digits_end) {
+        Some(b'k' | b'K') => (1_000, digits_end + 1),
+        Some(b'M') => (1_000_000, digits_end + 1),
+        Some(b'G') => (1_000_000_000, digits_end + 1),
+        _ => (1, digits_end),
+    };
+
+    let digits = &spec[start..digits_end];
+
+    let value = if digits.contains('.') {
+        let value = digits
+            .parse::<f64>()
+            .map_err(|e| spec_error(spec, start, format!("malformed number: {e}")))?
+            * multiplier as f64;
+
+        if value.fract() != 0.0 {
+            return Err(spec_error(
+                spec,
+                start,
+                format!("{} does not denote a whole number", &spec[start..end]),
+            ));
+        }
+
+        value as usize
+    } else {
+        digits
+            .parse::<usize>()
+            .ok()
+            .and_then(|value| value.checked_mul(multiplier))
+            .ok_or_else(|| spec_error(spec, start, "number is too large"))?
+    };
+
+    Ok((value, end))
+}
+
+/// Parses the query size part of a spec starting at byte offset `start`.
+/// Returns the size and the offset of the first byte after it.
+fn parse_size(spec: &str, start: usize) -> Result<(QuerySizeOpt, usize), String> {
+    let number_end = spec[start..]
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map_or(spec.len(), |off| start + off);
+
+    if spec.as_bytes().get(number_end) == Some(&b'%') {
+        let percent = spec[start..number_end]
+            .parse::<f64>()
+            .map_err(|e| spec_error(spec, start, format!("malformed percentage: {e}")))?;
+
+        if percent <= 0.0 {
+            return Err(spec_error(spec, start, "percentage must be greater than 0"));
+        }
+
+        return Ok((QuerySizeOpt::Percentage(percent / 100.0), number_end + 1));
+    }
+
+    let (lower, end) = parse_count(spec, start)?;
+
+    if lower == 0 {
+        return Err(spec_error(spec, start, "query size must be at least 1"));
+    }
+
+    if spec.as_bytes().get(end) != Some(&b'-') {
+        return Ok((QuerySizeOpt::Absolute(lower), end));
+    }
+
+    let (upper, range_end) = parse_count(spec, end + 1)?;
+
+    if upper < lower {
+        return Err(spec_error(
+            spec,
+            end + 1,
+            format!("upper bound {upper} of size range is smaller than lower bound {lower}"),
+        ));
+    }
+
+    Ok((QuerySizeOpt::Range(lower, upper), range_end))
+}
+
+/// Byte offset of the `#` starting the comment of `spec`, a `#` inside `<>` belongs to a graph IRI
+fn comment_start(spec: &str) -> Option<usize> {
+    let mut in_iri = false;
+
+    spec.char_indices().find_map(|(ix, c)| {
+        match c {
+            '<' => in_iri = true,
+            '>' => in_iri = false,
+            '#' if !in_iri => return Some(ix),
+            _ => (),
+        }
+
+        None
+    })
+}
+
+/// Parses the graph IRI of a spec starting at byte offset `start`, with or without enclosing `<>`
+fn parse_graph(spec: &str, start: usize) -> Result<Arc<str>, String> {
+    let graph = &spec[start..];
+    let (graph, graph_start) = match graph.strip_prefix('<').and_then(|graph| graph.strip_suffix('>')) {
+        Some(graph) => (graph, start + 1),
+        None => (graph, start),
+    };
+
+    if graph.is_empty() {
+        return Err(spec_error(spec, start, "expected a graph IRI after '@'"));
+    }
+
+    if let Some(off) =
+        graph.find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\'))
+    {
+        return Err(spec_error(
+            spec,
+            graph_start + off,
+            "character is not allowed in a graph IRI",
+        ));
+    }
+
+    Ok(graph.into())
+}
+
+/// Parses the `:<INSERTS>/<DELETES>` ratio of a mixed spec starting at byte offset `start`.
+/// Returns the mix and the offset of the first byte after it.
+fn parse_mix(spec: &str, start: usize) -> Result<(QueryTypeOpt, usize), String> {
+    if spec.as_bytes().get(start) != Some(&b':') {
+        return Err(spec_error(
+            spec,
+            start,
+            "expected ':<INSERTS>/<DELETES>' ratio after the size of a mixed spec",
+        ));
+    }
+
+    let (inserts, delim) = parse_count(spec, start + 1)?;
+
+    if spec.as_bytes().get(delim) != Some(&b'/') {
+        return Err(spec_error(
+            spec,
+            delim,
+            "expected delimiter '/' between insert and delete ratio",
+        ));
+    }
+
+    let (deletes, end) = parse_count(spec, delim + 1)?;
+
+    if inserts == 0 && deletes == 0 {
+        return Err(spec_error(spec, start + 1, "ratio must not be 0/0"));
+    }
+
+    Ok((QueryTypeOpt::Mixed { inserts, deletes }, end))
+}
+
+/// Parses the `~<PERCENT>%` share of novel triples starting at byte offset `start`.
+/// Returns the share as a fraction and the offset of the first byte after it.
+fn parse_novel(spec: &str, start: usize) -> Result<(f64, usize), String> {
+    let number_end = spec[start..]
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .map_or(spec.len(), |off| start + off);
+
+    if spec.as_bytes().get(number_end) != Some(&b'%') {
+        return Err(spec_error(
+            spec,
+            number_end,
+            "expected '%' after the share of novel triples",
+        ));
+    }
+
+    let percent = spec[start..number_end]
+        .parse::<f64>()
+        .map_err(|e| spec_error(spec, start, format!("malformed percentage: {e}")))?;
+
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(spec_error(
+            spec,
+            start,
+            "share of novel triples must be between 0% and 100%",
+        ));
+    }
+
+    Ok((percent / 100.0, number_end + 1))
+}
+
+impl FromStr for QuerySpecOpt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = match comment_start(s) {
+            Some(comment_start) => &s[..comment_start],
+            None => s,
+        }
+        .trim_end();
+
+        let start = spec.len() - spec.trim_start().len();
+
+        let query_type = match spec.as_bytes().get(start) {
+            Some(b'i') => Some(QueryType::InsertData),
+            Some(b'd') => Some(QueryType::DeleteData),
+            Some(b'm') => None,
+            _ => {
+                return Err(spec_error(
+                    spec,
+                    start,
+                    "expected query type 'i' (insert), 'd' (delete) or 'm' (mixed)",
+                ))
+            },
+        };
+
+        let (n_queries, delim) = parse_count(spec, start + 1)?;
+
+        if n_queries == 0 {
+            return Err(spec_error(spec, start + 1, "query count must be at least 1"));
+        }
+
+        if spec.as_bytes().get(delim) != Some(&b'x') {
+            return Err(spec_error(
+                spec,
+                delim,
+                "expected delimiter 'x' between query count and query size",
+            ));
+        }
+
+        let (n_triples_per_query, end) = parse_size(spec, delim + 1)?;
+
+        let (query_type, end) = match query_type {
+            Some(query_type) => (QueryTypeOpt::Fixed(query_type), end),
+            None => parse_mix(spec, end)?,
+        };
+
+        let (novel, end) = match spec.as_bytes().get(end) {
+            Some(b'~') if matches!(query_type, QueryTypeOpt::Fixed(QueryType::DeleteData)) => {
+                return Err(spec_error(spec, end, "delete queries cannot contain novel triples"))
+            },
+            Some(b'~') => {
+                let (novel, end) = parse_novel(spec, end + 1)?;
+                (Some(novel), end)
+            },
+            _ => (None, end),
+        };
+
+        let graph = match spec.as_bytes().get(end) {
+            None => None,
+            Some(b'@') => Some(parse_graph(spec, end + 1)?),
+            Some(_) => return Err(spec_error(spec, end, "unexpected character after query size")),
+        };
+
+        Ok(QuerySpecOpt { n_queries, n_triples_per_query, query_type, novel, graph, warmup: false })
+    }
+}
+
+impl Display for QuerySpecOpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let query_type = match self.query_type {
+            QueryTypeOpt::Fixed(QueryType::InsertData) => 'i',
+            QueryTypeOpt::Fixed(QueryType::DeleteData) => 'd',
+            QueryTypeOpt::Mixed { .. } => 'm',
+        };
+
+        write!(f, "{query_type}{}x", self.n_queries)?;
+
+        match self.n_triples_per_query {
+            QuerySizeOpt::Percentage(percent) => write!(f, "{}%", percent * 100.0),
+            QuerySizeOpt::Absolute(n) => write!(f, "{n}"),
+            QuerySizeOpt::Range(lower, upper) => write!(f, "{lower}-{upper}"),
+        }?;
+
+        if let QueryTypeOpt::Mixed { inserts, deletes } = self.query_type {
+            write!(f, ":{inserts}/{deletes}")?;
+        }
+
+        if let Some(novel) = self.novel {
+            write!(f, "~{}%", novel * 100.0)?;
+        }
+
+        match &self.graph {
+            Some(graph) => write!(f, "@<{graph}>"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl QuerySizeOpt {
+    /// Resolves the size of a single query, drawing from `rng` for ranges
+    pub fn get_absolute<R: Rng>(self, n_total_triples: usize, rng: &mut R) -> usize {
+        match self {
+            QuerySizeOpt::Absolute(n) => n,
+            QuerySizeOpt::Percentage(percent) => (n_total_triples as f64 * percent) as usize,
+            QuerySizeOpt::Range(lower, upper) => rng.gen_range(lower..=upper),
+        }
+    }
+}
+
+impl QueryTypeOpt {
+    /// Types of `n_queries` queries, mixed types are split by their ratio and shuffled
+    fn assign<R: Rng>(self, n_queries: usize, rng: &mut R) -> Vec<QueryType> {
+        match self {
+            QueryTypeOpt::Fixed(query_type) => vec![query_type; n_queries],
+            QueryTypeOpt::Mixed { inserts, deletes } => {
+                let n_inserts = (n_queries as f64 * inserts as f64 / (inserts + deletes) as f64).round() as usize;
+
+                let mut query_types = vec![QueryType::InsertData; n_inserts];
+                query_types.resize(n_queries, QueryType::DeleteData);
+                query_types.shuffle(rng);
+                query_types
+            },
+        }
+    }
+}
+
+impl QuerySpecOpt {
+    /// Resolves the spec against a dataset of `n_total_triples` triples.
+    /// Range and mixed specs are split up into one spec per run of queries of the same size and type.
+    /// Percentages that resolve to empty queries are skipped with a warning.
+    /// The share of novel triples is resolved per insert query, rounded to whole triples.
+    pub fn resolve<R: Rng>(self, n_total_triples: usize, rng: &mut R) -> Vec<QuerySpec> {
+        if let QuerySizeOpt::Percentage(percent) = self.n_triples_per_query {
+            if (n_total_triples as f64 * percent) < 1.0 {
+                warn(Warning::SkippedQueries {
+                    spec: self.to_string(),
+                    n_queries: self.n_queries,
+                    reason: format!("the percentage of {n_total_triples} triples is less than one triple"),
+                });
+                return Vec::new();
+            }
+        }
+
+        let QuerySpecOpt { n_queries, n_triples_per_query, query_type, novel, graph, warmup } = self;
+
+        let n_novel = |n_triples: usize, query_type: QueryType| match (novel, query_type) {
+            (Some(novel), QueryType::InsertData) => (n_triples as f64 * novel).round() as usize,
+            _ => 0,
+        };
+
+        match (n_triples_per_query, query_type) {
+            (QuerySizeOpt::Range(..), _) | (_, QueryTypeOpt::Mixed { .. }) => {
+                let mut specs: Vec<QuerySpec> = Vec::new();
+
+                for query_type in query_type.assign(n_queries, rng) {
+                    let n_triples = n_triples_per_query.get_absolute(n_total_triples, rng);
+
+                    match specs.last_mut() {
+                        Some(last) if last.query_type == query_type && last.n_triples_per_query == n_triples => {
+                            last.n_queries += 1
+                        },
+                        _ => specs.push(QuerySpec {
+                            n_queries: 1,
+                            n_triples_per_query: n_triples,
+                            n_novel_per_query: n_novel(n_triples, query_type),
+                            query_type,
+                            graph: graph.clone(),
+                            warmup,
+                            mix_part: 0,
+                        }),
+                    }
+                }
+
+                specs
+            },
+            (_, QueryTypeOpt::Fixed(query_type)) => {
+                let n_triples = n_triples_per_query.get_absolute(n_total_triples, rng);
+
+                vec![QuerySpec {
+                    n_queries,
+                    n_triples_per_query: n_triples,
+                    n_novel_per_query: n_novel(n_triples, query_type),
+                    query_type,
+                    graph,
+                    warmup,
+                    mix_part: 0,
+                }]
+            },
+        }
+    }
+}
+
+/// Largest number of queries a spec resolved into `queries` may have so that, next to the `n_other` triples
+/// selected by the other specs, all selected triples fit into `n_available` distinct triples.
+/// Based on the average number of selected triples per query of the spec, `None` if its queries select none.
+pub fn max_distinct_queries(queries: &[QuerySpec], n_other: usize, n_available: usize) -> Option<usize> {
+    let n_queries: usize = queries.iter().map(|query| query.n_queries).sum();
+    let n_selected: usize = queries.iter().map(QuerySpec::n_selected_triples).sum();
+
+    if n_selected == 0 {
+        return None;
+    }
+
+    let avg_selected = n_selected as f64 / n_queries as f64;
+    Some((n_available.saturating_sub(n_other) as f64 / avg_selected).floor() as usize)
+}
+
+/// Resolves all specs for generation by random selection from a dataset of `n_total_triples` triples.
+/// Fails with a list of the offending specs if the dataset cannot serve them: every query must fit
+/// into the dataset and, for distinct generation, all queries together must fit into the
+/// `n_distinct_available` triples available for selection. Novel triples are not selected and count towards neither.
+pub fn resolve_random_specs<R: Rng>(
+    specs: Vec<QuerySpecOpt>,
+    n_total_triples: usize,
+    n_distinct_available: Option<usize>,
+    rng: &mut R,
+) -> Result<Vec<QuerySpec>, String> {
+    let resolved: Vec<_> = specs
+        .into_iter()
+        .map(|spec| (spec.clone(), spec.resolve(n_total_triples, rng)))
+        .collect();
+
+    let oversized: Vec<_> = resolved
+        .iter()
+        .filter_map(|(spec, queries)| {
+            let max_size = queries.iter().map(|query| query.n_triples_per_query).max()?;
+            (max_size > n_total_triples).then(|| format!("  {spec}: queries of up to {max_size} triples"))
+        })
+        .collect();
+
+    if !oversized.is_empty() {
+        return Err(format!(
+            "query specs request queries larger than the main dataset of {n_total_triples} triples:\n{}",
+            oversized.join("\n")
+        ));
+    }
+
+    if let Some(n_available) = n_distinct_available {
+        let totals: Vec<_> = resolved
+            .iter()
+            .map(|(spec, queries)| {
+                let total: usize = queries.iter().map(QuerySpec::n_selected_triples).sum();
+                (spec, total)
+            })
+            .collect();
+
+        let total: usize = totals.iter().map(|(_, total)| total).sum();
+
+        if total > n_available {
+            let listing: Vec<_> = totals
+                .iter()
+                .map(|(spec, total)| format!("  {spec}: {total} triples"))
+                .collect();
+
+            return Err(format!(
+                "query specs request {total} distinct triples but only {n_available} are available:\n{}",
+                listing.join("\n")
+            ));
+        }
+    }
+
+    Ok(resolved.into_iter().flat_map(|(_, queries)| queries).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::seeded_rng;
+
+    #[test]
+    fn zero_counts_and_sizes_are_rejected() {
+        for spec in ["i0x10", "i10x0", "d10x0-5", "i10x0%", "m10x-1%:1/1"] {
+            assert!(spec.parse::<QuerySpecOpt>().is_err(), "{spec} was accepted");
+        }
+    }
+
+    #[test]
+    fn percentages_below_one_triple_are_skipped() {
+        let spec: QuerySpecOpt = "i3x0.5%#tiny".parse().unwrap();
+        assert!(spec.resolve(100, &mut seeded_rng(Some(1))).is_empty());
+
+        let skipped = crate::warnings::collected()
+            .into_iter()
+            .any(|warning| matches!(warning, Warning::SkippedQueries { spec, n_queries: 3, .. } if spec == "i3x0.5%"));
+        assert!(skipped);
+
+        let spec: QuerySpecOpt = "i3x1%".parse().unwrap();
+        let resolved = spec.resolve(100, &mut seeded_rng(Some(1)));
+        assert_eq!(
+            resolved
+                .iter()
+                .map(|spec| spec.n_queries * spec.n_triples_per_query)
+                .sum::<usize>(),
+            3
+        );
+    }
+
+    #[test]
+    fn mixed_specs_parse_their_ratio() {
+        let spec: QuerySpecOpt = "m200x1k:70/30".parse().unwrap();
+        assert!(matches!(
+            spec.query_type,
+            QueryTypeOpt::Mixed { inserts: 70, deletes: 30 }
+        ));
+        assert_eq!(spec.to_string(), "m200x1000:70/30");
+
+        for spec in ["m10x5", "m10x5:0/0", "m10x5:1-2", "m10x5:1/", "d10x5:1/1"] {
+            assert!(spec.parse::<QuerySpecOpt>().is_err(), "{spec} was accepted");
+        }
+    }
+
+    #[test]
+    fn mixed_specs_assign_types_by_their_ratio() {
+        let mut rng = seeded_rng(Some(1));
+
+        let query_types = QueryTypeOpt::Mixed { inserts: 70, deletes: 30 }.assign(200, &mut rng);
+        assert_eq!(query_types.len(), 200);
+        assert_eq!(
+            query_types
+                .iter()
+                .filter(|&&query_type| query_type == QueryType::InsertData)
+                .count(),
+            140
+        );
+
+        let query_types = QueryTypeOpt::Mixed { inserts: 0, deletes: 1 }.assign(10, &mut rng);
+        assert!(query_types.iter().all(|&query_type| query_type == QueryType::DeleteData));
+
+        let spec: QuerySpecOpt = "m200x10:70/30".parse().unwrap();
+        let resolved = spec.resolve(1000, &mut rng);
+        let n_queries = |query_type| {
+            resolved
+                .iter()
+                .filter(|spec| spec.query_type == query_type)
+                .map(|spec| spec.n_queries)
+                .sum::<usize>()
+        };
+        assert_eq!(
+            (n_queries(QueryType::InsertData), n_queries(QueryType::DeleteData)),
+            (140, 60)
+        );
+        assert!(resolved.iter().all(|spec| spec.n_triples_per_query == 10));
+    }
+
+    fn graph_of(spec: &str) -> Result<Option<Arc<str>>, String> {
+        spec.parse::<QuerySpecOpt>().map(|spec| spec.graph)
+    }
+
+    #[test]
+    fn graphs_may_be_enclosed_in_brackets() {
+        assert_eq!(
+            graph_of("i10x5@http://example.org/g").unwrap().as_deref(),
+            Some("http://example.org/g")
+        );
+        assert_eq!(
+            graph_of("i10x5@<http://example.org/g#1>").unwrap().as_deref(),
+            Some("http://example.org/g#1")
+        );
+        assert_eq!(
+            graph_of("i10x5@<http://example.org/g> # comment").unwrap().as_deref(),
+            Some("http://example.org/g")
+        );
+    }
+
+    #[test]
+    fn graphs_reject_disallowed_characters() {
+        assert!(graph_of("i10x5@<http://example.org/a>b>").is_err());
+        assert!(graph_of("i10x5@<http://example.org/<a>").is_err());
+        assert!(graph_of("i10x5@http://example.org/a b").is_err());
+        assert!(graph_of("i10x5@<>").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! Counts over an existing query file, to check hand-edited or merged workloads before running them
+
+use crate::{convert::read_queries, sparql::QueryType};
+use std::{io, path::Path};
+
+/// Counts of a query file, warm-up queries are counted like the measured ones
+pub struct QueryFileStats {
+    pub n_warmup: usize,
+    pub n_inserts: usize,
+    pub n_deletes: usize,
+    /// Triples of all insert and all delete queries
+    pub n_triples_by_type: [usize; 2],
+    /// Triples of every query, sorted ascending
+    pub query_sizes: Vec<usize>,
+    /// Length of the query file
+    pub n_bytes: u64,
+}
+
+impl QueryFileStats {
+    pub fn n_queries(&self) -> usize {
+        self.query_sizes.len()
+    }
+
+    pub fn n_triples(&self) -> usize {
+        self.n_triples_by_type.iter().sum()
+    }
+
+    pub fn avg_query_size(&self) -> f64 {
+        if self.query_sizes.is_empty() {
+            0.0
+        } else {
+            self.n_triples() as f64 / self.n_queries() as f64
+        }
+    }
+
+    /// Query size at percentile `p` (0 to 100) by nearest rank, 0 if there are no queries
+    pub fn percentile(&self, p: f64) -> usize {
+        let rank = (p / 100.0 * self.query_sizes.len() as f64).ceil() as usize;
+        self.query_sizes.get(rank.saturating_sub(1)).copied().unwrap_or_default()
+    }
+}
+
+/// Parses every query of the query file at `query_file` and counts its queries and triples
+pub fn query_file_stats(query_file: &Path) -> io::Result<QueryFileStats> {
+    let mut stats = QueryFileStats {
+        n_warmup: 0,
+        n_inserts: 0,
+        n_deletes: 0,
+        n_triples_by_type: [0; 2],
+        query_sizes: Vec::new(),
+        n_bytes: std::fs::metadata(query_file)?.len(),
+    };
+
+    read_queries(query_file, |_, query| {
+        let n_triples = query.triples.len();
+
+        match query.query_type {
+            QueryType::InsertData => {
+                stats.n_inserts += 1;
+                stats.n_triples_by_type[0] += n_triples;
+            },
+            QueryType::DeleteData => {
+                stats.n_deletes += 1;
+                stats.n_triples_by_type[1] += n_triples;
+            },
+        }
+
+        if query.warmup {
+            stats.n_warmup += 1;
+        }
+
+        stats.query_sizes.push(n_triples);
+        Ok(())
+    })?;
+
+    stats.query_sizes.sort_unstable();
+    Ok(stats)
+}
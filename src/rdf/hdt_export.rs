@@ -0,0 +1,555 @@
+//! Export of decompressed triples to HDT, with a four section dictionary of plain front coded sections
+//! and bitmap triples in SPO order. Terms are written as HDT stores them and `compress` reads them: IRIs
+//! without their angle brackets, literals with the escapes of their lexical form decoded.
+
+use crate::rdf::{term_escaping::unescape_literal, triple_compressor::RawTriple};
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+const HDT_CONTAINER: &str = "<http://purl.org/HDT/hdt#HDTv1>";
+const DICTIONARY_FOUR: &str = "<http://purl.org/HDT/hdt#dictionaryFour>";
+const TRIPLES_BITMAP: &str = "<http://purl.org/HDT/hdt#triplesBitmap>";
+const DATASET_IRI: &str = "urn:sparql-update-data-generator:dataset";
+
+const CONTROL_GLOBAL: u8 = 1;
+const CONTROL_HEADER: u8 = 2;
+const CONTROL_DICTIONARY: u8 = 3;
+const CONTROL_TRIPLES: u8 = 4;
+
+const TYPE_PFC: u8 = 2;
+const TYPE_BITMAP_PLAIN: u8 = 1;
+const TYPE_LOG_ARRAY: u8 = 1;
+/// strings per block of a front coded section
+const PFC_BLOCK_SIZE: usize = 16;
+/// triple order SPO
+const ORDER_SPO: u8 = 1;
+
+const fn crc_table(poly: u32, reflected: bool) -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut ix = 0;
+
+    while ix < 256 {
+        let mut crc = ix as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if reflected {
+                if crc & 1 != 0 {
+                    (crc >> 1) ^ poly
+                } else {
+                    crc >> 1
+                }
+            } else if crc & 0x80 != 0 {
+                ((crc << 1) ^ poly) & 0xff
+            } else {
+                (crc << 1) & 0xff
+            };
+
+            bit += 1;
+        }
+
+        table[ix] = crc;
+        ix += 1;
+    }
+
+    table
+}
+
+/// CRC-8/SMBUS of the headers of sections
+static CRC8: [u32; 256] = crc_table(0x07, false);
+/// CRC-16/ARC of control information
+static CRC16: [u32; 256] = crc_table(0xa001, true);
+/// CRC-32C of section data
+static CRC32C: [u32; 256] = crc_table(0x82f6_3b78, true);
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &b| CRC8[(crc ^ b) as usize] as u8)
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc: u16, &b| {
+        (crc >> 8) ^ CRC16[((crc ^ b as u16) & 0xff) as usize] as u16
+    })
+}
+
+fn crc32c_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(crc, |crc, &b| (crc >> 8) ^ CRC32C[((crc ^ b as u32) & 0xff) as usize])
+}
+
+fn crc32c(bytes: &[u8]) -> u32 {
+    !crc32c_update(!0, bytes)
+}
+
+/// Appends `value` in the variable length encoding of HDT, 7 bits per byte with the high bit marking the last byte
+fn push_vbyte(out: &mut Vec<u8>, mut value: u64) {
+    while value > 0x7f {
+        out.push((value & 0x7f) as u8);
+        value >>= 7;
+    }
+
+    out.push(value as u8 | 0x80);
+}
+
+/// Writes the data of a section followed by its CRC-32C, without buffering all of it
+struct Crc32cWriter<'w, W: Write> {
+    out: &'w mut W,
+    crc: u32,
+}
+
+impl<'w, W: Write> Crc32cWriter<'w, W> {
+    fn new(out: &'w mut W) -> Self {
+        Self { out, crc: !0 }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc = crc32c_update(self.crc, bytes);
+        self.out.write_all(bytes)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.out.write_all(&(!self.crc).to_le_bytes())
+    }
+}
+
+fn write_control_info<W: Write>(out: &mut W, control_type: u8, format: &str, properties: &str) -> io::Result<()> {
+    let mut bytes = b"$HDT".to_vec();
+    bytes.push(control_type);
+    bytes.extend_from_slice(format.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(properties.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(&crc16(&bytes).to_le_bytes());
+
+    out.write_all(&bytes)
+}
+
+fn write_section_header<W: Write>(out: &mut W, mut header: Vec<u8>) -> io::Result<()> {
+    header.push(crc8(&header));
+    out.write_all(&header)
+}
+
+/// Writes `bits` as plain bitmap of `n_bits` bits, bit `i` is bit `i % 8` of byte `i / 8`
+fn write_bitmap<W: Write>(out: &mut W, bits: &[u8], n_bits: u64) -> io::Result<()> {
+    let mut header = vec![TYPE_BITMAP_PLAIN];
+    push_vbyte(&mut header, n_bits);
+    write_section_header(out, header)?;
+
+    let mut data = Crc32cWriter::new(out);
+    data.write(&bits[..n_bits.div_ceil(8) as usize])?;
+    data.finish()
+}
+
+fn bits_needed(max: u64) -> u32 {
+    u64::BITS - max.leading_zeros()
+}
+
+/// Writes `values` as log array with the bits per entry of the largest value, packed little endian
+fn write_log_array<W: Write>(out: &mut W, values: &[u64]) -> io::Result<()> {
+    let n_bits = bits_needed(values.iter().copied().max().unwrap_or(0));
+
+    let mut header = vec![TYPE_LOG_ARRAY, n_bits as u8];
+    push_vbyte(&mut header, values.len() as u64);
+    write_section_header(out, header)?;
+
+    let mut data = Crc32cWriter::new(out);
+    let mut word = 0u64;
+    let mut word_bits = 0;
+    let mut buf = Vec::with_capacity(8 * 1024);
+
+    for &value in values {
+        word |= value << word_bits;
+        word_bits += n_bits;
+
+        if word_bits >= u64::BITS {
+            buf.extend_from_slice(&word.to_le_bytes());
+            word_bits -= u64::BITS;
+            // the bits of `value` that did not fit into the finished word
+            word = if word_bits == 0 {
+                0
+            } else {
+                value >> (n_bits - word_bits)
+            };
+
+            if buf.len() >= 8 * 1024 {
+                data.write(&buf)?;
+                buf.clear();
+            }
+        }
+    }
+
+    buf.extend_from_slice(&word.to_le_bytes()[..word_bits.div_ceil(8) as usize]);
+    data.write(&buf)?;
+    data.finish()
+}
+
+/// Writes the sorted `terms` as plain front coded section: every block starts with a full term,
+/// the following terms only store the length of the prefix they share with their predecessor and the rest
+fn write_pfc_section<W: Write>(out: &mut W, terms: &[&[u8]]) -> io::Result<()> {
+    let mut text = Vec::new();
+    let mut block_offsets = Vec::with_capacity(terms.len().div_ceil(PFC_BLOCK_SIZE) + 1);
+
+    for (ix, term) in terms.iter().enumerate() {
+        if ix.is_multiple_of(PFC_BLOCK_SIZE) {
+            block_offsets.push(text.len() as u64);
+            text.extend_from_slice(term);
+        } else {
+            let previous = terms[ix - 1];
+            let shared = previous.iter().zip(term.iter()).take_while(|(a, b)| a == b).count();
+
+            push_vbyte(&mut text, shared as u64);
+            text.extend_from_slice(&term[shared..]);
+        }
+
+        text.push(0);
+    }
+
+    block_offsets.push(text.len() as u64);
+
+    let mut header = vec![TYPE_PFC];
+    push_vbyte(&mut header, terms.len() as u64);
+    push_vbyte(&mut header, text.len() as u64);
+    push_vbyte(&mut header, PFC_BLOCK_SIZE as u64);
+    write_section_header(out, header)?;
+
+    write_log_array(out, &block_offsets)?;
+
+    let mut data = Crc32cWriter::new(out);
+    data.write(&text)?;
+    data.finish()
+}
+
+/// Term of a triple as HDT stores it
+fn hdt_term(term: &[u8]) -> Cow<'_, [u8]> {
+    match term {
+        [b'<', iri @ .., b'>'] => Cow::Borrowed(iri),
+        [b'"', ..] => unescape_literal(term),
+        _ => Cow::Borrowed(term),
+    }
+}
+
+/// The four dictionary sections, subjects and objects that are both share their ids
+struct Dictionary<'t> {
+    shared: Vec<&'t [u8]>,
+    subjects: Vec<&'t [u8]>,
+    predicates: Vec<&'t [u8]>,
+    objects: Vec<&'t [u8]>,
+}
+
+fn sorted_difference<'t>(terms: &[&'t [u8]], shared: &[&[u8]]) -> Vec<&'t [u8]> {
+    terms
+        .iter()
+        .copied()
+        .filter(|term| shared.binary_search(term).is_err())
+        .collect()
+}
+
+impl<'t> Dictionary<'t> {
+    fn new(triples: &[RawTriple<'t>]) -> Self {
+        let sorted = |position: usize| {
+            let mut terms: Vec<_> = triples.iter().map(|triple| triple[position]).collect();
+            terms.sort_unstable();
+            terms.dedup();
+            terms
+        };
+
+        let subjects = sorted(0);
+        let predicates = sorted(1);
+        let objects = sorted(2);
+
+        let shared: Vec<_> = subjects
+            .iter()
+            .copied()
+            .filter(|term| objects.binary_search(term).is_ok())
+            .collect();
+
+        Dictionary {
+            subjects: sorted_difference(&subjects, &shared),
+            objects: sorted_difference(&objects, &shared),
+            shared,
+            predicates,
+        }
+    }
+
+    fn n_terms(&self) -> usize {
+        self.shared.len() + self.subjects.len() + self.predicates.len() + self.objects.len()
+    }
+
+    /// Id of a subject, shared terms come first
+    fn subject_id(&self, term: &[u8]) -> u64 {
+        match self.shared.binary_search(&term) {
+            Ok(ix) => ix as u64 + 1,
+            Err(_) => (self.shared.len() + self.subjects.binary_search(&term).unwrap()) as u64 + 1,
+        }
+    }
+
+    fn predicate_id(&self, term: &[u8]) -> u64 {
+        self.predicates.binary_search(&term).unwrap() as u64 + 1
+    }
+
+    fn object_id(&self, term: &[u8]) -> u64 {
+        match self.shared.binary_search(&term) {
+            Ok(ix) => ix as u64 + 1,
+            Err(_) => (self.shared.len() + self.objects.binary_search(&term).unwrap()) as u64 + 1,
+        }
+    }
+}
+
+/// Bitmaps and arrays of triples in SPO order: the predicates of every subject, the objects of every subject
+/// and predicate, and bitmaps marking the last predicate of every subject and the last object of every pair
+#[derive(Default)]
+struct BitmapTriples {
+    predicates: Vec<u64>,
+    predicate_ends: Vec<u8>,
+    objects: Vec<u64>,
+    object_ends: Vec<u8>,
+}
+
+fn push_bit(bits: &mut Vec<u8>, ix: usize, bit: bool) {
+    if ix.is_multiple_of(8) {
+        bits.push(0);
+    }
+
+    if bit {
+        *bits.last_mut().unwrap() |= 1 << (ix % 8);
+    }
+}
+
+impl BitmapTriples {
+    /// `triples` have to be sorted and without duplicates
+    fn new(triples: &[[u64; 3]]) -> Self {
+        let mut bitmap = BitmapTriples::default();
+
+        for (ix, &[subject, predicate, object]) in triples.iter().enumerate() {
+            let next = triples.get(ix + 1);
+            let same_subject = matches!(next, Some(&[s, _, _]) if s == subject);
+            let same_pair = matches!(next, Some(&[s, p, _]) if s == subject && p == predicate);
+
+            push_bit(&mut bitmap.object_ends, bitmap.objects.len(), !same_pair);
+            bitmap.objects.push(object);
+
+            if !same_pair {
+                push_bit(&mut bitmap.predicate_ends, bitmap.predicates.len(), !same_subject);
+                bitmap.predicates.push(predicate);
+            }
+        }
+
+        bitmap
+    }
+}
+
+/// Writes `triples` as HDT file to `out`, the terms are in N-Triples syntax as returned by the decompressor.
+/// All terms are kept in memory while the dictionary is built. Duplicate triples are written once.
+pub fn write_hdt<'t, W: Write>(mut out: W, triples: impl IntoIterator<Item = RawTriple<'t>>) -> io::Result<()> {
+    let terms: Vec<[Cow<[u8]>; 3]> = triples.into_iter().map(|triple| triple.map(hdt_term)).collect();
+    let triples: Vec<RawTriple> = terms.iter().map(|triple| triple.each_ref().map(|term| &**term)).collect();
+
+    let dictionary = Dictionary::new(&triples);
+
+    let mut ids: Vec<[u64; 3]> = triples
+        .iter()
+        .map(|&[s, p, o]| {
+            [
+                dictionary.subject_id(s),
+                dictionary.predicate_id(p),
+                dictionary.object_id(o),
+            ]
+        })
+        .collect();
+    drop(triples);
+
+    ids.sort_unstable();
+    ids.dedup();
+
+    write_control_info(&mut out, CONTROL_GLOBAL, HDT_CONTAINER, "")?;
+
+    let header = format!(
+        "<{DATASET_IRI}> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://purl.org/HDT/hdt#Dataset> .\n\
+         <{DATASET_IRI}> <http://rdfs.org/ns/void#triples> \"{}\" .\n",
+        ids.len()
+    );
+    write_control_info(
+        &mut out,
+        CONTROL_HEADER,
+        "ntriples",
+        &format!("length={};", header.len()),
+    )?;
+    out.write_all(header.as_bytes())?;
+
+    write_control_info(
+        &mut out,
+        CONTROL_DICTIONARY,
+        DICTIONARY_FOUR,
+        &format!("elements={};", dictionary.n_terms()),
+    )?;
+
+    for section in [
+        &dictionary.shared,
+        &dictionary.subjects,
+        &dictionary.predicates,
+        &dictionary.objects,
+    ] {
+        write_pfc_section(&mut out, section)?;
+    }
+
+    drop(dictionary);
+    drop(terms);
+
+    let bitmap = BitmapTriples::new(&ids);
+    drop(ids);
+
+    write_control_info(
+        &mut out,
+        CONTROL_TRIPLES,
+        TRIPLES_BITMAP,
+        &format!("order={ORDER_SPO};"),
+    )?;
+    write_bitmap(&mut out, &bitmap.predicate_ends, bitmap.predicates.len() as u64)?;
+    write_bitmap(&mut out, &bitmap.object_ends, bitmap.objects.len() as u64)?;
+    write_log_array(&mut out, &bitmap.predicates)?;
+    write_log_array(&mut out, &bitmap.objects)?;
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_their_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xf4);
+        assert_eq!(crc16(b"123456789"), 0xbb3d);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn vbyte_marks_the_last_byte() {
+        let mut out = Vec::new();
+        push_vbyte(&mut out, 5);
+        push_vbyte(&mut out, 300);
+        assert_eq!(out, [0x85, 0x2c, 0x82]);
+    }
+
+    #[test]
+    fn log_array_packs_entries_little_endian() {
+        let mut out = Vec::new();
+        write_log_array(&mut out, &[1, 2, 3]).unwrap();
+
+        // type, bits per entry, vbyte count, crc8, one data byte and the crc32c of the data
+        assert_eq!(&out[..3], &[TYPE_LOG_ARRAY, 2, 0x83]);
+        assert_eq!(out[4], 0b0011_1001);
+        assert_eq!(out[5..], crc32c(&[0b0011_1001]).to_le_bytes());
+    }
+
+    #[test]
+    fn log_array_entries_span_words() {
+        let values: Vec<u64> = (0..100).map(|ix| ix * 12345 % 9973).collect();
+        let mut out = Vec::new();
+        write_log_array(&mut out, &values).unwrap();
+
+        let n_bits = bits_needed(9972) as usize;
+        let data = &out[4..out.len() - 4];
+        assert_eq!(data.len(), (n_bits * values.len()).div_ceil(8));
+
+        for (ix, &value) in values.iter().enumerate() {
+            let decoded = (0..n_bits).fold(0, |decoded, bit| {
+                let pos = ix * n_bits + bit;
+                decoded | (((data[pos / 8] >> (pos % 8)) & 1) as u64) << bit
+            });
+            assert_eq!(decoded, value);
+        }
+    }
+
+    #[test]
+    fn pfc_section_front_codes_blocks() {
+        let mut out = Vec::new();
+        write_pfc_section(&mut out, &[b"http://a/x", b"http://a/y"]).unwrap();
+
+        let text = b"http://a/x\0\x89y\0";
+        let header_len = 5;
+        assert_eq!(
+            out[..4],
+            [TYPE_PFC, 0x82, 0x80 | text.len() as u8, 0x80 | PFC_BLOCK_SIZE as u8]
+        );
+
+        // block offsets 0 and the text length
+        let offsets_len = 4 + 1 + 4;
+        let data = &out[header_len + offsets_len..];
+        assert_eq!(&data[..text.len()], text);
+    }
+
+    #[test]
+    fn shared_terms_get_the_first_ids() {
+        let triples: [RawTriple; 3] = [[b"a", b"p", b"b"], [b"b", b"p", b"\"literal\""], [b"c", b"q", b"a"]];
+        let dictionary = Dictionary::new(&triples);
+
+        assert_eq!(dictionary.shared, [b"a", b"b"]);
+        assert_eq!(dictionary.subjects, [b"c"]);
+        assert_eq!(dictionary.objects, [b"\"literal\""]);
+        assert_eq!(dictionary.subject_id(b"c"), 3);
+        assert_eq!(dictionary.object_id(b"\"literal\""), 3);
+        assert_eq!(dictionary.object_id(b"b"), 2);
+        assert_eq!(dictionary.predicate_id(b"q"), 2);
+    }
+
+    #[test]
+    fn bitmap_triples_mark_group_ends() {
+        let bitmap = BitmapTriples::new(&[[1, 1, 1], [1, 1, 2], [1, 2, 1], [2, 1, 3]]);
+
+        assert_eq!(bitmap.predicates, [1, 2, 1]);
+        assert_eq!(bitmap.predicate_ends, [0b110]);
+        assert_eq!(bitmap.objects, [1, 2, 1, 3]);
+        assert_eq!(bitmap.object_ends, [0b1110]);
+    }
+
+    #[test]
+    fn iris_lose_their_brackets() {
+        assert_eq!(&*hdt_term(b"<http://a>"), b"http://a");
+        assert_eq!(&*hdt_term(b"\"<a>\"@en"), b"\"<a>\"@en");
+        assert_eq!(&*hdt_term(b"\"a\\tb\""), b"\"a\tb\"");
+    }
+
+    #[cfg(feature = "hdt")]
+    #[test]
+    fn written_hdt_is_read_back() {
+        let triples: [RawTriple; 4] = [
+            [b"<http://a>", b"<http://p>", b"<http://b>"],
+            [b"<http://b>", b"<http://p>", b"\"say \\\"hi\\\"\\nnow\"@en"],
+            [
+                b"<http://b>",
+                b"<http://q>",
+                b"\"1\"^^<http://www.w3.org/2001/XMLSchema#integer>",
+            ],
+            [b"<http://a>", b"<http://p>", b"<http://b>"],
+        ];
+
+        let mut out = Vec::new();
+        write_hdt(&mut out, triples).unwrap();
+
+        let hdt = hdt::Hdt::new(&out[..]).unwrap();
+        let mut read: Vec<_> = hdt
+            .triples()
+            .map(|(s, p, o)| (s.to_string(), p.to_string(), o.to_string()))
+            .collect();
+        read.sort();
+
+        let expected = [
+            ("http://a", "http://p", "http://b"),
+            ("http://b", "http://p", "\"say \"hi\"\nnow\"@en"),
+            (
+                "http://b",
+                "http://q",
+                "\"1\"^^<http://www.w3.org/2001/XMLSchema#integer>",
+            ),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(s, p, o)| (s.to_owned(), p.to_owned(), o.to_owned()))
+            .collect();
+        assert_eq!(read, expected);
+    }
+}
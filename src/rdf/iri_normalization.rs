@@ -0,0 +1,263 @@
+//! Syntax based normalization of IRIs (RFC 3986 section 6.2.2 and RFC 3987 section 5.3.2.2) before they are added
+//! to the dictionary, so that the same IRI spelled differently across dumps gets a single id: IRIs are brought into
+//! Unicode normalization form C, the scheme and host are lowercased, percent-encodings get uppercase hex digits and
+//! percent-encoded unreserved characters are decoded.
+
+use crate::util::{commit_buffered, AtomicFile};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Spellings of the IRI terms that were normalized by their normalized form, including the normalized form itself
+/// if it occurred unmodified
+pub type IriVariants = BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>;
+
+/// The normalized IRIs that were spelled in at least two ways, the ones with a single spelling were only rewritten
+pub fn merged_variants(variants: &IriVariants) -> impl Iterator<Item = (&Vec<u8>, &BTreeSet<Vec<u8>>)> {
+    variants.iter().filter(|(_, spellings)| spellings.len() > 1)
+}
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Length of the scheme at the start of `iri`, if it has one
+fn scheme_len(iri: &[u8]) -> Option<usize> {
+    let len = iri.iter().position(|&b| b == b':')?;
+    let scheme = &iri[..len];
+
+    let valid = scheme.first().is_some_and(u8::is_ascii_alphabetic)
+        && scheme
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'));
+
+    valid.then_some(len)
+}
+
+/// Range of the host in `iri`, whose scheme is `scheme_len` bytes long, if it has an authority
+fn host_range(iri: &[u8], scheme_len: usize) -> Option<std::ops::Range<usize>> {
+    let rest = iri[scheme_len + 1..].strip_prefix(b"//")?;
+    let authority_len = rest.iter().position(|&b| matches!(b, b'/' | b'?' | b'#')).unwrap_or(rest.len());
+
+    let authority_start = scheme_len + 3;
+    let authority = &iri[authority_start..authority_start + authority_len];
+
+    let host_start = authority.iter().rposition(|&b| b == b'@').map_or(0, |at| at + 1);
+    let host_end = match authority.iter().rposition(|&b| b == b':') {
+        // a colon inside an IPv6 literal does not start the port
+        Some(colon) if colon > host_start && !authority[colon..].contains(&b']') => colon,
+        _ => authority.len(),
+    };
+
+    Some(authority_start + host_start..authority_start + host_end)
+}
+
+/// Whether normalizing `iri` (without its angle brackets) leaves it unchanged, checked without allocating
+fn is_normalized(iri: &[u8]) -> bool {
+    if !iri.is_ascii() && std::str::from_utf8(iri).is_ok_and(|iri| !is_nfc(iri)) {
+        return false;
+    }
+
+    let percent_encodings_normalized = memchr::memchr_iter(b'%', iri).all(|ix| match iri.get(ix + 1..ix + 3) {
+        Some(&[hi, lo]) => match hex_value(hi).zip(hex_value(lo)) {
+            Some((hi_value, lo_value)) => {
+                !is_unreserved((hi_value << 4) | lo_value) && !hi.is_ascii_lowercase() && !lo.is_ascii_lowercase()
+            },
+            None => true,
+        },
+        _ => true,
+    });
+
+    let case_normalized = scheme_len(iri).is_none_or(|scheme_len| {
+        let host = host_range(iri, scheme_len).unwrap_or_default();
+        !iri[..scheme_len].iter().chain(&iri[host]).any(u8::is_ascii_uppercase)
+    });
+
+    percent_encodings_normalized && case_normalized
+}
+
+/// Normalized form of the IRI term of `iri` (without its angle brackets)
+fn normalize(iri: &[u8]) -> Vec<u8> {
+    // IRIs that are not valid UTF-8 are normalized without composing them
+    let composed: String;
+    let iri = match std::str::from_utf8(iri) {
+        Ok(iri) if !is_nfc(iri) => {
+            composed = iri.nfc().collect();
+            composed.as_bytes()
+        },
+        _ => iri,
+    };
+
+    let mut normalized = Vec::with_capacity(iri.len() + 2);
+    normalized.push(b'<');
+
+    let mut ix = 0;
+    while ix < iri.len() {
+        let decoded = match iri.get(ix..ix + 3) {
+            Some(&[b'%', hi, lo]) => hex_value(hi).zip(hex_value(lo)).map(|(hi, lo)| (hi << 4) | lo),
+            _ => None,
+        };
+
+        match decoded {
+            Some(b) if is_unreserved(b) => normalized.push(b),
+            Some(_) => normalized.extend(iri[ix..ix + 3].to_ascii_uppercase()),
+            None => {
+                normalized.push(iri[ix]);
+                ix += 1;
+                continue;
+            },
+        }
+
+        ix += 3;
+    }
+
+    let iri = &mut normalized[1..];
+
+    if let Some(scheme_len) = scheme_len(iri) {
+        iri[..scheme_len].make_ascii_lowercase();
+
+        if let Some(host) = host_range(iri, scheme_len) {
+            iri[host].make_ascii_lowercase();
+        }
+    }
+
+    normalized.push(b'>');
+    normalized
+}
+
+/// Normalized form of the term if it is an IRI term `<...>` that is not normalized yet.
+/// Normalized IRIs are recognized without allocating, as they are the common case
+pub fn normalize_iri(term: &[u8]) -> Option<Vec<u8>> {
+    let iri = term.strip_prefix(b"<")?.strip_suffix(b">")?;
+
+    if is_normalized(iri) {
+        return None;
+    }
+
+    let normalized = normalize(iri);
+    (normalized != term).then_some(normalized)
+}
+
+/// Path of the marker next to the compressor state at `state_path` recording that its IRIs were normalized
+pub fn normalized_state_marker_path(state_path: &Path) -> PathBuf {
+    let mut file_name = state_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".normalized-iris");
+    state_path.with_file_name(file_name)
+}
+
+/// Whether the compressor state at `state_path` was compressed with `--normalize-iris`
+pub fn is_normalized_state(state_path: &Path) -> bool {
+    normalized_state_marker_path(state_path).exists()
+}
+
+/// Records whether the compressor state at `state_path` was compressed with `--normalize-iris`, so that a state
+/// is only extended in the same mode and no IRI gets an id for its normalized form and one for another spelling
+pub fn record_normalization(state_path: &Path, normalized: bool) -> io::Result<()> {
+    let marker_path = normalized_state_marker_path(state_path);
+
+    if normalized {
+        return std::fs::write(marker_path, b"");
+    }
+
+    match std::fs::remove_file(marker_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the normalized IRIs that merged several spellings with their spellings as JSON object
+pub fn write_variant_report<P: AsRef<Path>>(path: P, variants: &IriVariants) -> io::Result<()> {
+    let report: BTreeMap<_, Vec<_>> = merged_variants(variants)
+        .map(|(normalized, originals)| {
+            (
+                String::from_utf8_lossy(normalized).into_owned(),
+                originals
+                    .iter()
+                    .map(|original| String::from_utf8_lossy(original).into_owned())
+                    .collect(),
+            )
+        })
+        .collect();
+
+    let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+    serde_json::to_writer_pretty(&mut writer, &report)?;
+    commit_buffered(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_percent_encodings_and_unicode() {
+        assert_eq!(
+            normalize_iri(b"<HTTP://Example.ORG/a%2fb%7e>").as_deref(),
+            Some(&b"<http://example.org/a%2Fb~>"[..])
+        );
+        assert_eq!(
+            normalize_iri("<http://example.org/caf\u{65}\u{301}>".as_bytes()).as_deref(),
+            Some("<http://example.org/caf\u{e9}>".as_bytes())
+        );
+        assert_eq!(normalize_iri(b"<http://example.org/A>"), None);
+        assert_eq!(normalize_iri(b"\"HTTP://literal\""), None);
+    }
+
+    #[test]
+    fn scan_agrees_with_normalization() {
+        let iris: [&[u8]; 9] = [
+            b"http://example.org/A%2F",
+            b"http://example.org/a%2f",
+            b"http://example.org/%41",
+            b"http://user@Example.org:8080/",
+            b"http://[::1]:80/",
+            b"HTTP://example.org/",
+            b"urn:Example:A",
+            b"http://example.org/caf\xc3\xa9",
+            b"http://example.org/cafe\xcc\x81",
+        ];
+
+        for iri in iris {
+            let term = [&b"<"[..], iri, b">"].concat();
+            assert_eq!(
+                is_normalized(iri),
+                normalize(iri) == term,
+                "{}",
+                String::from_utf8_lossy(iri)
+            );
+        }
+    }
+
+    #[test]
+    fn normalization_is_recorded_next_to_the_state() {
+        let state_path = std::env::temp_dir().join(format!("sudg-normalized-{}.compressor_state", std::process::id()));
+
+        assert!(!is_normalized_state(&state_path));
+        record_normalization(&state_path, true).unwrap();
+        assert!(is_normalized_state(&state_path));
+        record_normalization(&state_path, false).unwrap();
+        assert!(!is_normalized_state(&state_path));
+    }
+
+    #[test]
+    fn only_several_spellings_are_merged() {
+        let variants = IriVariants::from([
+            (b"<http://a>".to_vec(), BTreeSet::from([b"<HTTP://a>".to_vec()])),
+            (
+                b"<http://b>".to_vec(),
+                BTreeSet::from([b"<HTTP://b>".to_vec(), b"<http://b>".to_vec()]),
+            ),
+        ]);
+
+        let merged: Vec<_> = merged_variants(&variants)
+            .map(|(normalized, _)| normalized.as_slice())
+            .collect();
+        assert_eq!(merged, vec![&b"<http://b>"[..]]);
+    }
+}
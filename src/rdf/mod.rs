@@ -1,2 +1,6 @@
+pub mod hdt_export;
+pub mod iri_normalization;
+pub mod ntriples_tokenizer;
+pub mod term_escaping;
 pub mod triple_compressor;
 pub mod triple_generator;
@@ -0,0 +1,124 @@
+//! Minimal N-Triples tokenizer for the `--no-parse` mode.
+//! It only splits lines into their terms and preserves the bytes of every term exactly.
+//! Delimiters are searched with `memchr`, which scans many bytes at once.
+
+use crate::rdf::triple_compressor::RawTriple;
+
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+pub(crate) fn skip_whitespace(line: &[u8], mut pos: usize) -> usize {
+    while pos < line.len() && is_whitespace(line[pos]) {
+        pos += 1;
+    }
+
+    pos
+}
+
+/// Returns the end of the IRI starting at `pos`, exclusive
+fn iri_end(line: &[u8], pos: usize) -> Result<usize, String> {
+    match memchr::memchr(b'>', &line[pos..]) {
+        Some(off) => Ok(pos + off + 1),
+        None => Err(format!("unterminated IRI at column {}", pos + 1)),
+    }
+}
+
+/// Returns the end of the literal starting at `pos`, exclusive, including its language tag or datatype
+fn literal_end(line: &[u8], pos: usize) -> Result<usize, String> {
+    let mut end = pos + 1;
+
+    loop {
+        let found = line.get(end..).and_then(|rest| memchr::memchr2(b'"', b'\\', rest));
+
+        match found {
+            Some(off) if line[end + off] == b'\\' => end += off + 2,
+            Some(off) => {
+                end += off;
+                break;
+            },
+            None => return Err(format!("unterminated literal at column {}", pos + 1)),
+        }
+    }
+
+    end += 1;
+
+    match line.get(end) {
+        Some(b'@') => {
+            end += 1;
+            while end < line.len() && (line[end].is_ascii_alphanumeric() || line[end] == b'-') {
+                end += 1;
+            }
+
+            Ok(end)
+        },
+        Some(b'^') if line.get(end + 1) == Some(&b'^') && line.get(end + 2) == Some(&b'<') => iri_end(line, end + 2),
+        Some(b'^') => Err(format!("malformed datatype at column {}", end + 1)),
+        _ => Ok(end),
+    }
+}
+
+/// Returns the end of the term starting at `pos`, exclusive
+pub(crate) fn term_end(line: &[u8], pos: usize) -> Result<usize, String> {
+    match line.get(pos) {
+        Some(b'<') => iri_end(line, pos),
+        Some(b'"') => literal_end(line, pos),
+        Some(b'_') if line.get(pos + 1) == Some(&b':') => {
+            Ok(memchr::memchr2(b' ', b'\t', &line[pos..]).map_or(line.len(), |off| pos + off))
+        },
+        Some(&b) => Err(format!("unexpected character {:?} at column {}", b as char, pos + 1)),
+        None => Err("expected a term but the line ended".to_owned()),
+    }
+}
+
+/// Splits `input` at `\n` like `input.split(|&b| b == b'\n')`, but with a vectorized search for the line breaks
+pub fn split_lines(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = Some(input);
+
+    std::iter::from_fn(move || {
+        let line = rest?;
+
+        match memchr::memchr(b'\n', line) {
+            Some(end) => {
+                rest = Some(&line[end + 1..]);
+                Some(&line[..end])
+            },
+            None => {
+                rest = None;
+                Some(line)
+            },
+        }
+    })
+}
+
+/// Splits an N-Triples line into its subject, predicate and object.
+/// Returns `Ok(None)` for blank and comment lines and a description of the problem for malformed lines.
+/// Trailing `\r` from CRLF line endings is ignored.
+pub fn tokenize_line(line: &[u8]) -> Result<Option<RawTriple<'_>>, String> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    let mut pos = skip_whitespace(line, 0);
+    if pos == line.len() || line[pos] == b'#' {
+        return Ok(None);
+    }
+
+    let mut terms = [&line[..0]; 3];
+    for term in &mut terms {
+        pos = skip_whitespace(line, pos);
+        let end = term_end(line, pos)?;
+        *term = &line[pos..end];
+        pos = end;
+    }
+
+    pos = skip_whitespace(line, pos);
+    if line.get(pos) != Some(&b'.') {
+        return Err(format!("expected '.' at column {}", pos + 1));
+    }
+
+    pos = skip_whitespace(line, pos + 1);
+    if pos != line.len() && line[pos] != b'#' {
+        return Err(format!("unexpected content after '.' at column {}", pos + 1));
+    }
+
+    Ok(Some(terms))
+}
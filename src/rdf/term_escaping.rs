@@ -0,0 +1,236 @@
+//! Escaping of stored terms for the inside of single line SPARQL queries.
+//! Terms read in `--no-parse` mode are stored exactly as in the input, which may be legal N-Triples
+//! but break a query, e.g. SPARQL replaces `\u0022` by a quote before parsing, which then ends the literal.
+
+use std::{borrow::Cow, io::Write};
+
+/// Decodes the `u`/`U` escape at the start of `rest`, returning the character and the length of the escape
+fn decode_uchar(rest: &str) -> Option<(char, usize)> {
+    let n_digits = match rest.as_bytes().first()? {
+        b'u' => 4,
+        b'U' => 8,
+        _ => return None,
+    };
+
+    let digits = rest.get(1..1 + n_digits)?;
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let c = char::from_u32(u32::from_str_radix(digits, 16).ok()?).unwrap_or(char::REPLACEMENT_CHARACTER);
+    Some((c, 1 + n_digits))
+}
+
+fn push_char(out: &mut Vec<u8>, c: char) {
+    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+fn is_iri_char_allowed(c: char) -> bool {
+    !c.is_ascii() || (c > ' ' && !matches!(c, '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\'))
+}
+
+/// Appends the IRI with escapes decoded and disallowed characters percent encoded
+fn escape_iri_into(out: &mut Vec<u8>, iri: &str) {
+    let Some(mut rest) = iri.strip_prefix('<').and_then(|iri| iri.strip_suffix('>')) else {
+        out.extend_from_slice(iri.as_bytes());
+        return;
+    };
+
+    out.push(b'<');
+
+    while let Some(c) = rest.chars().next() {
+        let (c, len) = match c {
+            '\\' => decode_uchar(&rest[1..]).map_or((c, 1), |(c, len)| (c, len + 1)),
+            c => (c, c.len_utf8()),
+        };
+        rest = &rest[len..];
+
+        if is_iri_char_allowed(c) {
+            push_char(out, c);
+        } else {
+            write!(out, "%{:02X}", c as u32).unwrap();
+        }
+    }
+
+    out.push(b'>');
+}
+
+fn push_literal_char(out: &mut Vec<u8>, c: char) {
+    match c {
+        '"' => out.extend_from_slice(b"\\\""),
+        '\\' => out.extend_from_slice(b"\\\\"),
+        '\n' => out.extend_from_slice(b"\\n"),
+        '\r' => out.extend_from_slice(b"\\r"),
+        c => push_char(out, c),
+    }
+}
+
+/// Appends the literal with `u`/`U` escapes decoded, line breaks and stray backslashes escaped
+fn escape_literal_into(out: &mut Vec<u8>, literal: &str) {
+    let Some(end) = literal.rfind('"').filter(|&end| end > 0) else {
+        out.extend_from_slice(literal.as_bytes());
+        return;
+    };
+
+    let mut rest = &literal[1..end];
+    let suffix = &literal[end + 1..];
+
+    out.push(b'"');
+
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+
+        if c != '\\' {
+            push_literal_char(out, c);
+            continue;
+        }
+
+        if let Some((c, len)) = decode_uchar(rest) {
+            push_literal_char(out, c);
+            rest = &rest[len..];
+        } else if let Some(e @ (b't' | b'b' | b'n' | b'r' | b'f' | b'"' | b'\'' | b'\\')) = rest.bytes().next() {
+            out.extend_from_slice(&[b'\\', e]);
+            rest = &rest[1..];
+        } else {
+            out.extend_from_slice(b"\\\\");
+        }
+    }
+
+    out.push(b'"');
+
+    match suffix.strip_prefix("^^") {
+        Some(datatype) => {
+            out.extend_from_slice(b"^^");
+            escape_iri_into(out, datatype);
+        },
+        None => out.extend_from_slice(suffix.as_bytes()),
+    }
+}
+
+/// Appends `term` to `out` so that it is valid inside a single line SPARQL query, invalid UTF-8 is replaced
+pub fn escape_term_into(out: &mut Vec<u8>, term: &[u8]) {
+    let term = String::from_utf8_lossy(term);
+    let term: &str = &term;
+
+    match term.as_bytes().first() {
+        Some(b'<') => escape_iri_into(out, term),
+        Some(b'"') => escape_literal_into(out, term),
+        _ => out.extend_from_slice(term.as_bytes()),
+    }
+}
+
+/// Appends `bytes` with every non-ASCII character replaced by its `\u`/`\U` escape, invalid UTF-8 is replaced.
+/// The escapes are valid in IRIs and literals of both SPARQL and N-Triples.
+pub fn escape_non_ascii_into(out: &mut Vec<u8>, bytes: &[u8]) {
+    for c in String::from_utf8_lossy(bytes).chars() {
+        match c as u32 {
+            0..=0x7f => out.push(c as u8),
+            code @ 0x80..=0xffff => write!(out, "\\u{code:04X}").unwrap(),
+            code => write!(out, "\\U{code:08X}").unwrap(),
+        }
+    }
+}
+
+/// The N-Triples literal with the escapes of its lexical form decoded, as HDT stores literals.
+/// Returns the literal itself if it has no escapes or is not valid UTF-8
+pub fn unescape_literal(literal: &[u8]) -> Cow<'_, [u8]> {
+    let Some((lexical, suffix)) = std::str::from_utf8(literal).ok().and_then(|literal| {
+        literal
+            .rfind('"')
+            .filter(|&end| end > 0)
+            .map(|end| (&literal[1..end], &literal[end..]))
+    }) else {
+        return Cow::Borrowed(literal);
+    };
+
+    if !lexical.contains('\\') {
+        return Cow::Borrowed(literal);
+    }
+
+    let mut out = Vec::with_capacity(literal.len());
+    out.push(b'"');
+
+    let mut rest = lexical;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+
+        if c != '\\' {
+            push_char(&mut out, c);
+            continue;
+        }
+
+        let decoded = decode_uchar(rest).or_else(|| {
+            let c = match rest.bytes().next()? {
+                b't' => '\t',
+                b'b' => '\u{8}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b'f' => '\u{c}',
+                e @ (b'"' | b'\'' | b'\\') => e as char,
+                _ => return None,
+            };
+            Some((c, 1))
+        });
+
+        match decoded {
+            Some((c, len)) => {
+                push_char(&mut out, c);
+                rest = &rest[len..];
+            },
+            None => out.push(b'\\'),
+        }
+    }
+
+    out.extend_from_slice(suffix.as_bytes());
+    Cow::Owned(out)
+}
+
+/// The literal of an HDT file in N-Triples syntax, with quotes, backslashes and line breaks of its lexical form
+/// escaped. Returns the literal itself if nothing needs to be escaped
+pub fn ntriples_literal(literal: &str) -> Cow<'_, str> {
+    let Some(end) = literal.rfind('"').filter(|&end| end > 0) else {
+        return Cow::Borrowed(literal);
+    };
+
+    let lexical = &literal[1..end];
+    if !lexical.contains(['"', '\\', '\n', '\r']) {
+        return Cow::Borrowed(literal);
+    }
+
+    let mut out = Vec::with_capacity(literal.len() + 2);
+    out.push(b'"');
+    for c in lexical.chars() {
+        push_literal_char(&mut out, c);
+    }
+    out.extend_from_slice(&literal.as_bytes()[end..]);
+
+    // only ASCII was inserted into valid UTF-8
+    Cow::Owned(String::from_utf8(out).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_are_unescaped_for_hdt() {
+        assert_eq!(
+            &*unescape_literal(br#""say \"hi\"\n\u00E9"@en"#),
+            "\"say \"hi\"\n\u{e9}\"@en".as_bytes()
+        );
+        assert!(matches!(unescape_literal(b"\"plain\"@en"), Cow::Borrowed(_)));
+        assert!(matches!(unescape_literal(b"<http://a>"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn hdt_literals_are_escaped_for_ntriples() {
+        let literal = "\"say \"hi\"\n\u{e9}\"^^<http://www.w3.org/2001/XMLSchema#string>";
+        let escaped = ntriples_literal(literal);
+        assert_eq!(
+            escaped,
+            "\"say \\\"hi\\\"\\n\u{e9}\"^^<http://www.w3.org/2001/XMLSchema#string>"
+        );
+        assert_eq!(unescape_literal(escaped.as_bytes()), literal.as_bytes());
+        assert!(matches!(ntriples_literal("\"plain\""), Cow::Borrowed(_)));
+    }
+}
@@ -1,27 +1,326 @@
 use super::TripleElementId;
-use crate::rdf::triple_compressor::{CompressedTriple, RawTriple, TripleId};
+use crate::{
+    rdf::{
+        iri_normalization::{normalize_iri, IriVariants},
+        ntriples_tokenizer::{split_lines, tokenize_line},
+        triple_compressor::{
+            shard_of, state_fingerprint, state_segment_path, state_shard_path,
+            term_arena::{TermArena, TermRef},
+            term_counts::TermCounts,
+            CompressedTriple, RawTriple, TripleId,
+        },
+    },
+    remote,
+    util::{check_memory, commit_buffered, AtomicFile},
+    warnings::{warn, Warning},
+};
+use memory_mapped::MemoryMapped;
+use rayon::slice::ParallelSliceMut;
 use rio_api::{
-    model::{Subject, Term, Triple},
+    model::{Literal, Subject, Term, Triple},
     parser::TriplesParser,
 };
 use rio_turtle::NTriplesParser;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashSet},
-    fs::{File, OpenOptions},
+    collections::{BTreeSet, HashMap, HashSet},
+    ffi::OsString,
+    fs::File,
     hash::{BuildHasher, BuildHasherDefault, Hash},
     io::{BufRead, BufReader, BufWriter, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::UNIX_EPOCH,
 };
 
+const TRIPLE_BATCH_SIZE: usize = 1 << 12;
+
+/// Collects compressed triples into batches for the writer thread, a channel send per triple
+/// costs about as much as compressing it
+struct TripleBatcher {
+    tx: Sender<Vec<CompressedTriple>>,
+    batch: Vec<CompressedTriple>,
+}
+
+impl TripleBatcher {
+    fn new(tx: Sender<Vec<CompressedTriple>>) -> Self {
+        Self { tx, batch: Vec::with_capacity(TRIPLE_BATCH_SIZE) }
+    }
+
+    fn push(&mut self, triple: CompressedTriple) {
+        self.batch.push(triple);
+
+        if self.batch.len() >= TRIPLE_BATCH_SIZE {
+            let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(TRIPLE_BATCH_SIZE));
+            self.tx.send(batch).unwrap();
+        }
+    }
+}
+
+impl Drop for TripleBatcher {
+    fn drop(&mut self) {
+        if !self.batch.is_empty() {
+            // the writer thread only stops early on an error, which is reported by it
+            let _ = self.tx.send(std::mem::take(&mut self.batch));
+        }
+    }
+}
+
+/// Counts of a compressed file
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CompressionStats {
+    /// Size of the input file, unknown for remote inputs
+    pub input_bytes: Option<u64>,
+    pub output_bytes: u64,
+    /// Triples written to the compressed file
+    pub n_triples: usize,
+    /// Terms that were not in the dictionary yet
+    pub n_new_terms: usize,
+    /// Triples skipped by deduplication
+    pub n_duplicates: usize,
+    /// Triples with unsupported terms, e.g. quoted triples, that are not compressed
+    pub n_filtered: usize,
+    /// Triples with blank nodes, which are not compressed
+    #[serde(default)]
+    pub n_blank_nodes: usize,
+    /// Lines that could not be parsed
+    pub n_malformed: usize,
+    /// Triples with a literal excluded by the language tags or datatypes to keep
+    #[serde(default)]
+    pub n_filtered_literals: usize,
+    /// IRI terms that were replaced by their normalized form
+    #[serde(default)]
+    pub n_normalized_iris: usize,
+}
+
+impl CompressionStats {
+    /// Adds the counts of `other`, the input size stays unknown if it is unknown for either
+    pub fn add(&mut self, other: &CompressionStats) {
+        self.input_bytes = self.input_bytes.zip(other.input_bytes).map(|(a, b)| a + b);
+        self.output_bytes += other.output_bytes;
+        self.n_triples += other.n_triples;
+        self.n_new_terms += other.n_new_terms;
+        self.n_duplicates += other.n_duplicates;
+        self.n_filtered += other.n_filtered;
+        self.n_blank_nodes += other.n_blank_nodes;
+        self.n_malformed += other.n_malformed;
+        self.n_filtered_literals += other.n_filtered_literals;
+        self.n_normalized_iris += other.n_normalized_iris;
+    }
+}
+
+#[derive(Serialize)]
+pub struct CompressedFileStats {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub stats: CompressionStats,
+}
+
+/// Counts of every file of a compress run and their total
+#[derive(Serialize)]
+pub struct CompressionReport {
+    pub files: Vec<CompressedFileStats>,
+    pub total: CompressionStats,
+}
+
+impl CompressionReport {
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        commit_buffered(writer)
+    }
+}
+
+/// Provenance of a compressed file, written next to it as `<file>.meta.json`
+#[derive(Serialize, Deserialize)]
+pub struct DatasetMetadata {
+    pub source: PathBuf,
+    /// Modification time of the source in seconds since the Unix epoch, unknown for remote sources
+    pub source_mtime: Option<u64>,
+    #[serde(flatten)]
+    pub stats: CompressionStats,
+    pub tool_version: String,
+    /// Fingerprint of the compressor state the file was compressed into, as hex
+    pub state_fingerprint: String,
+}
+
+impl DatasetMetadata {
+    pub fn new(source: PathBuf, stats: CompressionStats, state_fingerprint: u128) -> std::io::Result<Self> {
+        let source_mtime = if remote::is_remote(&source) {
+            None
+        } else {
+            let mtime = std::fs::metadata(&source)?.modified()?;
+            Some(mtime.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs())
+        };
+
+        Ok(DatasetMetadata {
+            source,
+            source_mtime,
+            stats,
+            tool_version: env!("CARGO_PKG_VERSION").to_owned(),
+            state_fingerprint: format!("{state_fingerprint:032x}"),
+        })
+    }
+
+    /// Path of the metadata of the compressed file at `compressed_path`
+    pub fn path(compressed_path: &Path) -> PathBuf {
+        let mut file_name = compressed_path.file_name().map(OsString::from).unwrap_or_default();
+        file_name.push(".meta.json");
+        compressed_path.with_file_name(file_name)
+    }
+
+    /// Reads the metadata of the compressed file at `compressed_path`, `None` if it has none
+    pub fn read(compressed_path: &Path) -> std::io::Result<Option<Self>> {
+        match File::open(Self::path(compressed_path)) {
+            Ok(file) => Ok(Some(serde_json::from_reader(BufReader::new(file))?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn write(&self, compressed_path: &Path) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(AtomicFile::create(Self::path(compressed_path), false)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        commit_buffered(writer)
+    }
+}
+
+/// Path `compress_rdf_triple_file` writes the compressed triples of the input at `path` to,
+/// `dump.nt` and `dump.nt.gz` are both written to `dump.compressed_nt`
+pub fn compressed_path(path: &Path) -> PathBuf {
+    if remote::is_remote(path) {
+        remote::local_path(path)
+    } else if crate::util::has_extension(path, "gz") {
+        path.with_extension("")
+    } else {
+        path.to_owned()
+    }
+    .with_extension(super::COMPRESSED_TRIPLE_FILE_EXTENSION)
+}
+
+const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Language tags and datatypes of the literals to keep, triples with other literals are not compressed.
+/// An empty list keeps all literals of that kind, plain literals are always kept.
+#[derive(Clone, Default)]
+pub struct LiteralFilter {
+    /// Language tags compared case-insensitively, a tag also keeps its subtags (`en` keeps `en-GB`)
+    languages: Vec<String>,
+    /// Datatype IRIs without angle brackets
+    datatypes: Vec<String>,
+}
+
+impl LiteralFilter {
+    /// Datatypes may use the `xsd:` prefix
+    pub fn new(languages: &[String], datatypes: &[String]) -> Self {
+        LiteralFilter {
+            languages: languages.to_vec(),
+            datatypes: datatypes
+                .iter()
+                .map(|datatype| match datatype.strip_prefix("xsd:") {
+                    Some(local_name) => format!("{XSD_NAMESPACE}{local_name}"),
+                    None => datatype.trim_start_matches('<').trim_end_matches('>').to_owned(),
+                })
+                .collect(),
+        }
+    }
+
+    fn keeps_language(&self, language: &str) -> bool {
+        self.languages.is_empty()
+            || self.languages.iter().any(|keep| {
+                let language = language.as_bytes();
+
+                language.len() >= keep.len()
+                    && language[..keep.len()].eq_ignore_ascii_case(keep.as_bytes())
+                    && matches!(language.get(keep.len()), None | Some(b'-'))
+            })
+    }
+
+    fn keeps_datatype(&self, datatype: &str) -> bool {
+        self.datatypes.is_empty() || self.datatypes.iter().any(|keep| keep == datatype)
+    }
+
+    fn keeps_literal(&self, literal: &Literal) -> bool {
+        match literal {
+            Literal::Simple { .. } => true,
+            Literal::LanguageTaggedString { language, .. } => self.keeps_language(language),
+            Literal::Typed { datatype, .. } => self.keeps_datatype(datatype.iri),
+        }
+    }
+
+    /// Checks an object in N-Triples syntax, IRIs are always kept
+    fn keeps_raw(&self, object: &[u8]) -> bool {
+        if !object.starts_with(b"\"") {
+            return true;
+        }
+
+        let Some(closing_quote) = object.iter().rposition(|&b| b == b'"') else {
+            return true;
+        };
+
+        let suffix = String::from_utf8_lossy(&object[closing_quote + 1..]);
+
+        if let Some(language) = suffix.strip_prefix('@') {
+            self.keeps_language(language)
+        } else if let Some(datatype) = suffix.strip_prefix("^^<").and_then(|rest| rest.strip_suffix('>')) {
+            self.keeps_datatype(datatype)
+        } else {
+            true
+        }
+    }
+}
+
+/// Size of a deduplication set with `capacity` entries, every entry takes its hash and a control byte
+fn dedup_bytes(capacity: usize) -> u64 {
+    (capacity * (std::mem::size_of::<TripleId>() + 1)) as u64
+}
+
 fn hash_single<T: Hash>(to_hash: T) -> u64 {
     type BuildH = BuildHasherDefault<ahash::AHasher>;
     BuildH::default().hash_one(to_hash)
 }
 
+/// Id of `term` in every compressor state containing it, `term` in N-Triples syntax as it is stored in the state.
+/// It is an ahash hash with fixed keys, which only agrees between builds with the same ahash version and target
+/// features: e.g. enabling AES (`-C target-cpu=native` on most x86-64 machines) switches ahash to another algorithm,
+/// so extending a state or looking up terms by id (e.g. in triple patterns) needs a build for the same target features
+/// as the one that compressed it. Decompression only reads the ids from the state and works with any build
+pub fn term_id(term: &[u8]) -> TripleElementId {
+    hash_single(term)
+}
+
+/// Removes the segments appended to the state at `path` and its shards from `first_stale_shard` on,
+/// which a newly written full state replaces
+fn remove_state_files(path: &Path, first_stale_shard: usize) -> std::io::Result<()> {
+    let segment_paths = (1..).map(|n| state_segment_path(path, n)).take_while(|p| p.exists());
+    let shard_paths = (first_stale_shard..)
+        .map(|n| state_shard_path(path, n))
+        .take_while(|p| p.exists());
+
+    for stale_path in segment_paths.chain(shard_paths) {
+        std::fs::remove_file(stale_path)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Default)]
 pub struct RdfTripleCompressor {
-    translations: BTreeMap<TripleElementId, Vec<u8>>,
+    translations: HashMap<TripleElementId, TermRef, BuildHasherDefault<ahash::AHasher>>,
+    terms: TermArena,
+    /// End of the terms taken over from a previous state, later terms are new
+    previous_end: Option<TermRef>,
     dedup: HashSet<TripleId, BuildHasherDefault<ahash::AHasher>>,
+    /// Capacity of `dedup` at which its growth was last checked against `--max-memory`
+    dedup_checked_capacity: Option<usize>,
+    /// Counts of the file that is currently compressed
+    stats: CompressionStats,
+    literal_filter: LiteralFilter,
+    normalize_iris: bool,
+    /// Spellings of the normalized IRIs, only collected if requested
+    iri_variants: Option<IriVariants>,
+    /// Occurrences of the terms in the written triples, only counted if requested
+    term_counts: Option<TermCounts>,
 }
 
 impl RdfTripleCompressor {
@@ -29,6 +328,93 @@ impl RdfTripleCompressor {
         let hash = hash_single(triple);
         self.dedup.insert(hash)
     }
+
+    /// Whether `triple` is written, counts it as duplicate otherwise
+    fn keep_triple(&mut self, dedup: bool, triple: [TripleElementId; 3]) -> bool {
+        if !dedup || self.found_new_triple(triple) {
+            if let Some(term_counts) = &mut self.term_counts {
+                term_counts.add(triple);
+            }
+
+            true
+        } else {
+            self.stats.n_duplicates += 1;
+            false
+        }
+    }
+
+    /// Fails if the next growth of the deduplication set (with `dedup`) or of the term counts would exceed `--max-memory`
+    fn check_growth(&mut self, dedup: bool) -> std::io::Result<()> {
+        if dedup {
+            self.check_dedup_memory()?;
+        }
+
+        match &mut self.term_counts {
+            Some(term_counts) => term_counts.check_memory(),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if the next growth of the full deduplication set would exceed `--max-memory`
+    fn check_dedup_memory(&mut self) -> std::io::Result<()> {
+        let capacity = self.dedup.capacity();
+        if self.dedup.len() < capacity || self.dedup_checked_capacity == Some(capacity) {
+            return Ok(());
+        }
+
+        self.dedup_checked_capacity = Some(capacity);
+
+        // the set doubles on growth
+        check_memory(
+            dedup_bytes(2 * capacity.max(16)),
+            "growing the deduplication set (compress without --dedup to avoid it)",
+        )
+    }
+
+    fn insert_term(&mut self, term: &[u8]) -> TripleElementId {
+        if self.normalize_iris {
+            if let Some(normalized) = normalize_iri(term) {
+                self.stats.n_normalized_iris += 1;
+
+                // a normalized form already in the dictionary without variants occurred unmodified before
+                let occurred = self.translations.contains_key(&term_id(&normalized));
+                let id = self.insert_exact_term(&normalized);
+
+                if let Some(variants) = &mut self.iri_variants {
+                    variants
+                        .entry(normalized)
+                        .or_insert_with_key(|normalized| {
+                            if occurred {
+                                BTreeSet::from([normalized.clone()])
+                            } else {
+                                BTreeSet::new()
+                            }
+                        })
+                        .insert(term.to_owned());
+                }
+
+                return id;
+            }
+
+            // an unmodified occurrence of a normalized form is one of its spellings as well
+            if let Some(spellings) = self.iri_variants.as_mut().and_then(|variants| variants.get_mut(term)) {
+                if !spellings.contains(term) {
+                    spellings.insert(term.to_owned());
+                }
+            }
+        }
+
+        self.insert_exact_term(term)
+    }
+
+    fn insert_exact_term(&mut self, term: &[u8]) -> TripleElementId {
+        let hash = term_id(term);
+        let terms = &mut self.terms;
+
+        self.translations.entry(hash).or_insert_with(|| terms.push(term));
+
+        hash
+    }
 }
 
 impl RdfTripleCompressor {
@@ -36,40 +422,163 @@ impl RdfTripleCompressor {
         Self::default()
     }
 
+    /// Only compresses the triples whose literals are kept by `filter`
+    pub fn set_literal_filter(&mut self, filter: LiteralFilter) {
+        self.literal_filter = filter;
+    }
+
+    /// Normalizes IRI terms before adding them to the dictionary, see [`normalize_iri`].
+    /// With `collect_variants` the original spellings are kept for [`RdfTripleCompressor::iri_variants`].
+    pub fn set_iri_normalization(&mut self, normalize: bool, collect_variants: bool) {
+        self.normalize_iris = normalize;
+        self.iri_variants = (normalize && collect_variants).then(IriVariants::new);
+    }
+
+    /// Original spellings of the normalized IRIs of all files compressed so far, if they are collected
+    pub fn iri_variants(&self) -> Option<&IriVariants> {
+        self.iri_variants.as_ref()
+    }
+
+    /// Counts the occurrences of the terms in the written triples, starting from `counts`
+    pub fn set_term_counts(&mut self, counts: TermCounts) {
+        self.term_counts = Some(counts);
+    }
+
+    /// Occurrences of the terms in the triples of all files compressed so far, if they are counted
+    pub fn term_counts(&self) -> Option<&TermCounts> {
+        self.term_counts.as_ref()
+    }
+
+    /// Pre-sizes the dictionary for a total of `n_expected_terms` distinct terms,
+    /// avoiding repeated rehashing while it grows
+    pub fn reserve_terms(&mut self, n_expected_terms: usize) {
+        self.translations
+            .reserve(n_expected_terms.saturating_sub(self.translations.len()));
+    }
+
+    /// Order independent hash of the ids of all terms, equal to the fingerprint of the state once it is saved
+    pub fn state_fingerprint(&self) -> u128 {
+        state_fingerprint(self.translations.keys().copied())
+    }
+
+    /// Writes the full state to `path`, replacing the segments previously appended to it
     pub fn save_state<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
-        let header_size = self.translations.len() * std::mem::size_of::<(TripleElementId, usize, usize)>();
+        let path = path.as_ref();
+        self.write_state(path, |_| true)?;
+
+        remove_state_files(path, 0)
+    }
+
+    /// Writes the full state to `path` split into `n_shards` shards by the leading bits of the ids, which the decompressor
+    /// checks when loading the state but only maps or reads once an id of them is looked up. `path` itself gets an empty state, the shards are written next to it
+    /// as `<state>.shard-<n>`. `n_shards` must be a power of two between 2 and [`MAX_STATE_SHARDS`].
+    ///
+    /// [`MAX_STATE_SHARDS`]: super::MAX_STATE_SHARDS
+    pub fn save_state_sharded<P: AsRef<Path>>(&mut self, path: P, n_shards: usize) -> std::io::Result<()> {
+        super::check_shard_count(n_shards)?;
+
+        let path = path.as_ref();
+        let entries = self.sorted_entries(|_| true);
+
+        // the entries are sorted by id, so every shard is a contiguous range of them
+        let mut start = 0;
+        for shard in 0..n_shards {
+            let end = start + entries[start..].partition_point(|&(id, _)| shard_of(id, n_shards) == shard);
+            self.write_entries(&state_shard_path(path, shard), &entries[start..end])?;
+            start = end;
+        }
+
+        self.write_entries(path, &[])?;
 
-        let f = OpenOptions::new().write(true).create(true).truncate(true).open(&path)?;
-        let mut bw = BufWriter::new(f);
+        remove_state_files(path, n_shards)
+    }
+
+    /// Writes only the terms that are new since the compressor was created from the previous state at `path`
+    /// as a new segment appended to it, instead of rewriting the whole state. Nothing is written if there are no new terms.
+    pub fn save_state_segment<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        let Some(previous_end) = self.previous_end else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "a state segment can only be saved by a compressor created from a previous state",
+            ));
+        };
+
+        if self.terms.end() == previous_end {
+            return Ok(());
+        }
+
+        let path = path.as_ref();
+        let n = (1..).find(|&n| !state_segment_path(path, n).exists()).unwrap();
+        self.write_state(&state_segment_path(path, n), |term| term >= previous_end)
+    }
+
+    fn write_state(&self, path: &Path, include: impl Fn(TermRef) -> bool) -> std::io::Result<()> {
+        self.write_entries(path, &self.sorted_entries(include))
+    }
+
+    /// Entries of the terms selected by `include` sorted by id, since the header is searched by id
+    fn sorted_entries(&self, include: impl Fn(TermRef) -> bool) -> Vec<(TripleElementId, TermRef)> {
+        let mut entries: Vec<_> = self
+            .translations
+            .iter()
+            .filter(|&(_, &term)| include(term))
+            .map(|(&hash, &term)| (hash, term))
+            .collect();
+        entries.par_sort_unstable_by_key(|&(hash, _)| hash);
+        entries
+    }
+
+    fn write_entries(&self, path: &Path, entries: &[(TripleElementId, TermRef)]) -> std::io::Result<()> {
+        let header_size = entries.len() * std::mem::size_of::<(TripleElementId, usize, usize)>();
+
+        let mut bw = BufWriter::new(AtomicFile::create(path, false)?);
 
         bw.write_all(&header_size.to_ne_bytes())?;
 
         let mut data_segment_off: usize = 0;
-        for (hash, rdf_str) in &self.translations {
+        for &(hash, term) in entries {
             bw.write_all(&hash.to_ne_bytes())?;
             bw.write_all(&data_segment_off.to_ne_bytes())?;
 
-            data_segment_off += rdf_str.len();
+            data_segment_off += self.terms.get(term).len();
             bw.write_all(&data_segment_off.to_ne_bytes())?;
         }
 
-        for rdf_str in self.translations.values() {
-            bw.write_all(rdf_str)?;
+        for &(_, term) in entries {
+            bw.write_all(self.terms.get(term))?;
         }
 
-        Ok(())
+        commit_buffered(bw)
     }
 
-    pub fn from_decompressor(frozen: super::decompressor::RdfTripleDecompressor) -> Self {
-        let mut translations = BTreeMap::default();
+    /// Marks `triples` as seen, so that deduplicating compression skips them.
+    /// Lets incremental compression of new changesets leave out triples of existing compressed datasets.
+    pub fn seed_dedup(&mut self, triples: &[CompressedTriple]) -> std::io::Result<()> {
+        check_memory(
+            dedup_bytes(self.dedup.len() + triples.len()),
+            "seeding the deduplication set (compress without --dedup-against to avoid it)",
+        )?;
+        self.dedup.reserve(triples.len());
+
+        for &triple in triples {
+            self.found_new_triple(triple);
+            self.check_dedup_memory()?;
+        }
 
-        for (hash, s_beg, s_end) in frozen.header {
-            let rdf_data = frozen.data_segment[s_beg..s_end].to_owned();
+        Ok(())
+    }
+
+    pub fn from_decompressor(frozen: super::decompressor::RdfTripleDecompressor) -> std::io::Result<Self> {
+        let mut compressor = Self::new();
+        compressor.reserve_terms(frozen.n_terms());
 
-            translations.insert(hash, rdf_data);
+        for (hash, term) in frozen.terms()? {
+            let term = compressor.terms.push(term);
+            compressor.translations.insert(hash, term);
         }
 
-        Self { translations, dedup: HashSet::default() }
+        compressor.previous_end = Some(compressor.terms.end());
+        Ok(compressor)
     }
 
     pub fn compress_parsed_rdf_triple(&mut self, Triple { subject, predicate, object }: Triple) -> [TripleElementId; 3] {
@@ -77,150 +586,393 @@ impl RdfTripleCompressor {
         let predicate = predicate.to_string().into_bytes();
         let object = object.to_string().into_bytes();
 
-        let subject_hash = hash_single(&subject);
-        let predicate_hash = hash_single(&predicate);
-        let object_hash = hash_single(&object);
-
-        self.translations
-            .entry(subject_hash)
-            .or_insert(subject);
-        self.translations
-            .entry(predicate_hash)
-            .or_insert(predicate);
-        self.translations
-            .entry(object_hash)
-            .or_insert(object);
-
-        [subject_hash, predicate_hash, object_hash]
+        [
+            self.insert_term(&subject),
+            self.insert_term(&predicate),
+            self.insert_term(&object),
+        ]
     }
 
     pub fn compress_raw_rdf_triple(&mut self, [subject, predicate, object]: RawTriple) -> [TripleElementId; 3] {
-        let subject_hash = hash_single(subject);
-        let predicate_hash = hash_single(predicate);
-        let object_hash = hash_single(object);
-
-        self.translations
-            .entry(subject_hash)
-            .or_insert_with(|| subject.to_owned());
-        self.translations
-            .entry(predicate_hash)
-            .or_insert_with(|| predicate.to_owned());
-        self.translations
-            .entry(object_hash)
-            .or_insert_with(|| object.to_owned());
-
-        [subject_hash, predicate_hash, object_hash]
+        [
+            self.insert_term(subject),
+            self.insert_term(predicate),
+            self.insert_term(object),
+        ]
     }
 
     fn compress_parsed_rdf_triple_file<R: BufRead>(
         &mut self,
         dedup: bool,
-        tx: std::sync::mpsc::Sender<CompressedTriple>,
+        mut out: TripleBatcher,
         mut parser: NTriplesParser<R>,
     ) -> std::io::Result<()> {
         while !parser.is_end() {
             let res: Result<(), std::io::Error> = parser.parse_step(&mut |triple| {
-                let subject @ Subject::NamedNode(_) = triple.subject else {
-                    return Ok(());
+                let subject = match triple.subject {
+                    subject @ Subject::NamedNode(_) => subject,
+                    Subject::BlankNode(_) => {
+                        self.stats.n_blank_nodes += 1;
+                        return Ok(());
+                    },
+                    Subject::Triple(_) => {
+                        self.stats.n_filtered += 1;
+                        return Ok(());
+                    },
                 };
 
                 let predicate = triple.predicate;
 
-                let object @ (Term::NamedNode(_) | Term::Literal(_)) = triple.object else {
-                    return Ok(());
+                let object = match triple.object {
+                    object @ (Term::NamedNode(_) | Term::Literal(_)) => object,
+                    Term::BlankNode(_) => {
+                        self.stats.n_blank_nodes += 1;
+                        return Ok(());
+                    },
+                    Term::Triple(_) => {
+                        self.stats.n_filtered += 1;
+                        return Ok(());
+                    },
                 };
 
+                if let Term::Literal(literal) = &object {
+                    if !self.literal_filter.keeps_literal(literal) {
+                        self.stats.n_filtered_literals += 1;
+                        return Ok(());
+                    }
+                }
+
                 let triple = self.compress_parsed_rdf_triple(Triple { subject, predicate, object });
 
-                if !dedup || self.found_new_triple(triple) {
-                    tx.send(triple).unwrap();
+                if self.keep_triple(dedup, triple) {
+                    out.push(triple);
                 }
 
                 Ok(())
             });
 
             if let Err(e) = res {
+                self.stats.n_malformed += 1;
                 eprintln!("{e}")
             }
+
+            self.check_growth(dedup)?;
         }
 
         Ok(())
     }
 
-    fn compress_raw_rdf_triple_file<R: BufRead>(
+    /// Compresses the lines without parsing them, `path` is only used for warnings
+    fn compress_raw_rdf_lines<L: AsRef<[u8]>>(
         &mut self,
+        path: &Path,
         dedup: bool,
-        tx: std::sync::mpsc::Sender<CompressedTriple>,
-        reader: R,
+        mut out: TripleBatcher,
+        lines: impl Iterator<Item = std::io::Result<L>>,
     ) -> std::io::Result<()> {
-        for line in reader.split(b'\n') {
+        for (line_ix, line) in lines.enumerate() {
             let line = line?;
 
-            if line.is_empty() || line.starts_with(b"#") {
+            let [subject, predicate, object] = match tokenize_line(line.as_ref()) {
+                Ok(Some(triple)) => triple,
+                Ok(None) => continue,
+                Err(reason) => {
+                    self.stats.n_malformed += 1;
+                    warn(Warning::MalformedLine { path: path.to_owned(), line: line_ix + 1, reason });
+                    continue;
+                },
+            };
+
+            if subject.starts_with(b"_") | object.starts_with(b"_") {
+                self.stats.n_blank_nodes += 1;
                 continue;
             }
 
-            let mut split = line.splitn(3, |&b| b == b' ');
+            if !self.literal_filter.keeps_raw(object) {
+                self.stats.n_filtered_literals += 1;
+                continue;
+            }
 
-            let subject = split.next().unwrap();
-            let predicate = split.next().unwrap();
-            let object = split.next().unwrap();
+            let triple = self.compress_raw_rdf_triple([subject, predicate, object]);
 
-            assert!(object.ends_with(b" ."));
-            let object = &object[..object.len() - 2];
+            if self.keep_triple(dedup, triple) {
+                out.push(triple);
+            }
 
-            if subject.starts_with(b"_") | object.starts_with(b"_") {
+            self.check_growth(dedup)?;
+        }
+
+        Ok(())
+    }
+
+    /// Compresses the triples of an HDT file, its terms are converted into N-Triples syntax, the lexical forms of
+    /// literals are escaped
+    #[cfg(feature = "hdt")]
+    fn compress_hdt_file<R: BufRead>(&mut self, dedup: bool, mut out: TripleBatcher, reader: R) -> std::io::Result<()> {
+        let hdt =
+            hdt::Hdt::new(reader).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+
+        for (subject, predicate, object) in hdt.triples() {
+            if subject.starts_with("_:") || object.starts_with("_:") {
+                self.stats.n_blank_nodes += 1;
                 continue;
             }
 
-            let triple = self.compress_raw_rdf_triple([subject, predicate, object]);
+            let subject = format!("<{subject}>");
+            let predicate = format!("<{predicate}>");
+            let object = if object.starts_with('"') {
+                crate::rdf::term_escaping::ntriples_literal(&object).into_owned()
+            } else {
+                format!("<{object}>")
+            };
+
+            if !self.literal_filter.keeps_raw(object.as_bytes()) {
+                self.stats.n_filtered_literals += 1;
+                continue;
+            }
+
+            let triple = self.compress_raw_rdf_triple([subject.as_bytes(), predicate.as_bytes(), object.as_bytes()]);
 
-            if !dedup || self.found_new_triple(triple) {
-                tx.send(triple).unwrap();
+            if self.keep_triple(dedup, triple) {
+                out.push(triple);
             }
+
+            self.check_growth(dedup)?;
         }
 
         Ok(())
     }
 
+    #[cfg(not(feature = "hdt"))]
+    fn compress_hdt_file<R: BufRead>(&mut self, _dedup: bool, _out: TripleBatcher, _reader: R) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "compressing HDT files requires the hdt feature",
+        ))
+    }
+
+    /// Compresses the N-Triples file, or HDT file if it has the `hdt` extension, at `path` into a file
+    /// next to it with the compressed triple file extension. Remote URLs are streamed and their
+    /// output is written to the current directory. Local N-Triples files are memory mapped if `parse` is not set.
+    /// Returns the counts of the file.
     pub fn compress_rdf_triple_file<P: AsRef<Path>>(
         &mut self,
         path: P,
         dedup: bool,
         parse: bool,
-    ) -> std::io::Result<()> {
-        let out_path = path.as_ref().with_extension(super::COMPRESSED_TRIPLE_FILE_EXTENSION);
+    ) -> std::io::Result<CompressionStats> {
+        let path = path.as_ref();
+        self.compress_rdf_triple_file_to(path, compressed_path(path), dedup, parse)
+    }
 
-        let mut bw = BufWriter::new(File::options().write(true).create_new(true).open(out_path)?);
-        let input_triples = BufReader::new(File::open(path)?);
+    /// Like [`RdfTripleCompressor::compress_rdf_triple_file`] but writes the compressed triples to `out_path`
+    pub fn compress_rdf_triple_file_to<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        path: P,
+        out_path: Q,
+        dedup: bool,
+        parse: bool,
+    ) -> std::io::Result<CompressionStats> {
+        let path = path.as_ref();
+        let is_remote = remote::is_remote(path);
+
+        let is_hdt = matches!(path.extension(), Some(ext) if ext == super::HDT_FILE_EXTENSION);
+        // remote objects are decompressed by `remote::open`
+        let is_gzip = !is_remote && crate::util::has_extension(path, "gz");
+        let input_bytes = if is_remote {
+            None
+        } else {
+            Some(std::fs::metadata(path)?.len())
+        };
+        // empty files cannot be mapped
+        let map_input = !parse && !is_hdt && !is_gzip && input_bytes.is_some_and(|n_bytes| n_bytes > 0);
+
+        let n_terms_before = self.translations.len();
+        self.stats = CompressionStats { input_bytes, ..Default::default() };
+        let compressor = &mut *self;
+
+        let mut bw = BufWriter::new(AtomicFile::create_new(out_path)?);
+        let open_input = move || -> std::io::Result<Box<dyn BufRead + Send>> {
+            if is_remote {
+                Ok(Box::new(BufReader::new(remote::open(path)?)))
+            } else if is_gzip {
+                Ok(Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(File::open(
+                    path,
+                )?))))
+            } else {
+                Ok(Box::new(BufReader::new(File::open(path)?)))
+            }
+        };
 
         let (writer_res, reader_res) = std::thread::scope(move |s| {
-            let (tx, rx) = std::sync::mpsc::channel::<[TripleElementId; 3]>();
+            let (tx, rx) = std::sync::mpsc::channel::<Vec<CompressedTriple>>();
+
+            let writer = s.spawn(move || -> std::io::Result<(BufWriter<AtomicFile>, usize)> {
+                let mut n_triples = 0;
 
-            let writer = s.spawn(move || -> std::io::Result<()> {
-                while let Ok([s, p, o]) = rx.recv() {
-                    bw.write_all(&s.to_ne_bytes())?;
-                    bw.write_all(&p.to_ne_bytes())?;
-                    bw.write_all(&o.to_ne_bytes())?;
+                while let Ok(batch) = rx.recv() {
+                    n_triples += batch.len();
+
+                    for [s, p, o] in batch {
+                        bw.write_all(&s.to_ne_bytes())?;
+                        bw.write_all(&p.to_ne_bytes())?;
+                        bw.write_all(&o.to_ne_bytes())?;
+                    }
                 }
 
-                Ok(())
+                Ok((bw, n_triples))
             });
 
-            let reader = if parse {
+            let out = TripleBatcher::new(tx);
+
+            let reader = if is_hdt {
+                s.spawn(move || -> std::io::Result<()> { compressor.compress_hdt_file(dedup, out, open_input()?) })
+            } else if parse {
                 s.spawn(move || -> std::io::Result<()> {
-                    self.compress_parsed_rdf_triple_file(dedup, tx, NTriplesParser::new(input_triples))
+                    compressor.compress_parsed_rdf_triple_file(dedup, out, NTriplesParser::new(open_input()?))
+                })
+            } else if map_input {
+                s.spawn(move || -> std::io::Result<()> {
+                    // Safety: the input file must not be modified while it is compressed
+                    let input = unsafe { MemoryMapped::<[u8]>::open_slice(path)?.assume_init() };
+                    let lines = split_lines(&input).map(Ok::<_, std::io::Error>);
+
+                    compressor.compress_raw_rdf_lines(path, dedup, out, lines)
                 })
             } else {
-                s.spawn(move || -> std::io::Result<()> { self.compress_raw_rdf_triple_file(dedup, tx, input_triples) })
+                s.spawn(move || -> std::io::Result<()> {
+                    compressor.compress_raw_rdf_lines(path, dedup, out, open_input()?.split(b'\n'))
+                })
             };
 
             (writer.join(), reader.join())
         });
 
-        writer_res.unwrap()?;
+        let (bw, n_triples) = writer_res.unwrap()?;
         reader_res.unwrap()?;
 
-        Ok(())
+        commit_buffered(bw)?;
+
+        self.stats.n_triples = n_triples;
+        self.stats.output_bytes = (n_triples * std::mem::size_of::<CompressedTriple>()) as u64;
+        self.stats.n_new_terms = self.translations.len() - n_terms_before;
+
+        if self.stats.n_blank_nodes > 0 {
+            warn(Warning::PrunedTriples {
+                reason: format!("{path:?} has triples with blank nodes, which are not supported"),
+                n_pruned: self.stats.n_blank_nodes,
+            });
+        }
+
+        Ok(self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdf::iri_normalization;
+
+    #[test]
+    fn remote_dumps_are_compressed_into_the_current_directory() {
+        assert_eq!(
+            compressed_path(Path::new("https://example.org/dumps/dump.nt.gz")),
+            Path::new("dump.compressed_nt")
+        );
+        assert_eq!(
+            compressed_path(Path::new("s3://bucket/dump.nt")),
+            Path::new("dump.compressed_nt")
+        );
+        assert_eq!(
+            compressed_path(Path::new("dumps/dump.nt")),
+            Path::new("dumps/dump.compressed_nt")
+        );
+        assert_eq!(
+            compressed_path(Path::new("dumps/dump.nt.gz")),
+            Path::new("dumps/dump.compressed_nt")
+        );
+    }
+
+    fn literal_filter(languages: &[&str], datatypes: &[&str]) -> LiteralFilter {
+        let to_owned = |values: &[&str]| values.iter().map(|&value| value.to_owned()).collect::<Vec<_>>();
+        LiteralFilter::new(&to_owned(languages), &to_owned(datatypes))
+    }
+
+    #[test]
+    fn languages_keep_their_subtags_only() {
+        let filter = literal_filter(&["en"], &[]);
+
+        assert!(filter.keeps_language("en"));
+        assert!(filter.keeps_language("EN"));
+        assert!(filter.keeps_language("en-GB"));
+        assert!(!filter.keeps_language("eng"));
+        assert!(!filter.keeps_language("e"));
+        assert!(!filter.keeps_language("de"));
+
+        let filter = literal_filter(&["en-GB"], &[]);
+        assert!(filter.keeps_language("en-gb"));
+        assert!(!filter.keeps_language("en"));
+        assert!(!filter.keeps_language("en-US"));
+
+        assert!(literal_filter(&[], &[]).keeps_language("de"));
+    }
+
+    #[test]
+    fn datatypes_expand_the_xsd_prefix() {
+        let filter = literal_filter(&[], &["xsd:integer", "<http://example.org/type>"]);
+
+        assert!(filter.keeps_datatype("http://www.w3.org/2001/XMLSchema#integer"));
+        assert!(filter.keeps_datatype("http://example.org/type"));
+        assert!(!filter.keeps_datatype("http://www.w3.org/2001/XMLSchema#string"));
+        assert!(!filter.keeps_datatype("xsd:integer"));
+    }
+
+    #[test]
+    fn raw_objects_are_filtered_by_their_suffix() {
+        let filter = literal_filter(&["en"], &["xsd:integer"]);
+
+        assert!(filter.keeps_raw(b"<http://example.org/a>"));
+        assert!(filter.keeps_raw(b"_:b0"));
+        assert!(filter.keeps_raw(br#""plain""#));
+        assert!(filter.keeps_raw(br#""hello"@en-GB"#));
+        assert!(!filter.keeps_raw(br#""hallo"@de"#));
+        assert!(filter.keeps_raw(br#""1"^^<http://www.w3.org/2001/XMLSchema#integer>"#));
+        assert!(!filter.keeps_raw(br#""1.0"^^<http://www.w3.org/2001/XMLSchema#decimal>"#));
+        // the suffix starts after the last quote, escaped quotes inside the literal are no problem
+        assert!(!filter.keeps_raw(br#""say \"hi\"@en"@de"#));
+    }
+
+    fn spellings(compressor: &RdfTripleCompressor, normalized: &[u8]) -> Vec<Vec<u8>> {
+        compressor.iri_variants().unwrap()[normalized].iter().cloned().collect()
+    }
+
+    #[test]
+    fn unmodified_occurrences_are_spellings_of_their_normalized_form() {
+        let mut compressor = RdfTripleCompressor::new();
+        compressor.set_iri_normalization(true, true);
+
+        // unmodified before and after the variant
+        compressor.insert_term(b"<http://a>");
+        compressor.insert_term(b"<HTTP://a>");
+        compressor.insert_term(b"<http://b>");
+        compressor.insert_term(b"<HTTP://b>");
+        compressor.insert_term(b"<http://b>");
+        // only rewritten
+        compressor.insert_term(b"<HTTP://c>");
+        compressor.insert_term(b"<HTTP://c>");
+
+        assert_eq!(
+            spellings(&compressor, b"<http://a>"),
+            vec![b"<HTTP://a>".to_vec(), b"<http://a>".to_vec()]
+        );
+        assert_eq!(
+            spellings(&compressor, b"<http://b>"),
+            vec![b"<HTTP://b>".to_vec(), b"<http://b>".to_vec()]
+        );
+        assert_eq!(spellings(&compressor, b"<http://c>"), vec![b"<HTTP://c>".to_vec()]);
+        assert_eq!(
+            iri_normalization::merged_variants(compressor.iri_variants().unwrap()).count(),
+            2
+        );
     }
 }
@@ -1,68 +1,303 @@
-use super::CompressedRdfTriples;
+use super::{
+    check_shard_count, read_slice, shard_of, state_fingerprint, state_segment_path, state_shard_path,
+    CompressedRdfTriples, Storage,
+};
 use crate::rdf::triple_compressor::{CompressedTriple, RawTriple, TripleElementId};
 use memory_mapped::MemoryMapped;
 use std::{
+    collections::HashMap,
     fs::File,
+    hash::BuildHasherDefault,
     io::{Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
-pub struct RdfTripleDecompressor {
-    pub(super) header: MemoryMapped<[(TripleElementId, usize, usize)]>,
-    pub(super) data_segment: MemoryMapped<[u8]>,
-}
+/// Reads the size of the header at the start of a state file
+fn read_header_size(f: &mut File) -> std::io::Result<usize> {
+    let mut header_size_buf = [0; std::mem::size_of::<usize>()];
+    f.read_exact(&mut header_size_buf)?;
 
-impl RdfTripleDecompressor {
-    fn search_header(&self, hash: TripleElementId) -> Option<&(TripleElementId, usize, usize)> {
-        let ix = self.header.binary_search_by_key(&hash, |(h, _, _)| *h).ok()?;
-        Some(&self.header[ix])
-    }
+    Ok(usize::from_ne_bytes(header_size_buf))
+}
 
-    pub unsafe fn load_state<P: AsRef<Path>>(path: P) -> std::io::Result<RdfTripleDecompressor> {
-        let header_size = {
-            let mut f = File::open(path.as_ref())?;
+/// A single state file, either a full state or a segment appended by an incremental compression run
+pub(super) struct StateSegment {
+    pub(super) header: Storage<(TripleElementId, usize, usize)>,
+    pub(super) data_segment: Storage<u8>,
+}
 
-            let mut header_size_buf = [0; std::mem::size_of::<usize>()];
-            f.read_exact(&mut header_size_buf)?;
+impl StateSegment {
+    fn search(&self, id: TripleElementId) -> Option<&[u8]> {
+        let ix = self.header.binary_search_by_key(&id, |(h, _, _)| *h).ok()?;
+        let (_, start, end) = self.header[ix];
+        Some(&self.data_segment[start..end])
+    }
 
-            usize::from_ne_bytes(header_size_buf)
-        };
+    /// Memory maps the state file at `path`
+    ///
+    /// # Safety
+    /// See [`RdfTripleDecompressor::load_state`]
+    unsafe fn load(path: &Path) -> std::io::Result<Self> {
+        let header_size = read_header_size(&mut File::open(path)?)?;
 
         let header = MemoryMapped::options()
             .read(true)
             .byte_offset(std::mem::size_of::<usize>())
             .byte_len(header_size)
-            .open_slice(path.as_ref())?
+            .open_slice(path)?
             .assume_init();
 
         let data_segment = MemoryMapped::options()
             .read(true)
             .byte_offset(std::mem::size_of::<usize>() + header_size)
-            .open_slice(path.as_ref())?
+            .open_slice(path)?
             .assume_init();
 
-        Ok(Self { header, data_segment })
+        Ok(Self { header: Storage::Mapped(header), data_segment: Storage::Mapped(data_segment) })
+    }
+
+    fn read_into_memory(path: &Path) -> std::io::Result<Self> {
+        let mut f = File::open(path)?;
+        let file_len = f.metadata()?.len() as usize;
+        let header_size = read_header_size(&mut f)?;
+
+        let data_segment_size = file_len
+            .checked_sub(std::mem::size_of::<usize>() + header_size)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "header exceeds file length"))?;
+
+        let header = unsafe { read_slice(&mut f, header_size)? };
+        let data_segment = unsafe { read_slice(&mut f, data_segment_size)? };
+
+        Ok(Self { header: Storage::InMemory(header), data_segment: Storage::InMemory(data_segment) })
+    }
+}
+
+/// Paths of the state at `path` followed by the paths of all its appended segments
+fn state_file_paths(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    std::iter::once(path.to_owned()).chain(
+        (1..)
+            .map(move |n| state_segment_path(path, n))
+            .take_while(|segment_path| segment_path.exists()),
+    )
+}
+
+/// A shard of a sharded state, loaded when it is first searched
+struct StateShard {
+    path: PathBuf,
+    into_memory: bool,
+    n_terms: usize,
+    data_segment_len: usize,
+    segment: OnceLock<StateSegment>,
+}
+
+impl StateShard {
+    /// Reads and checks the sizes of the shard at `path` without loading it
+    fn open(path: PathBuf, into_memory: bool) -> std::io::Result<Self> {
+        let invalid = |reason: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("compressor state shard {path:?}: {reason}"),
+            )
+        };
+
+        let mut f = File::open(&path)?;
+        let file_len = f.metadata()?.len() as usize;
+        let header_size = read_header_size(&mut f).map_err(|_| invalid("shorter than its header size"))?;
+
+        let entry_size = std::mem::size_of::<(TripleElementId, usize, usize)>();
+        if !header_size.is_multiple_of(entry_size) {
+            return Err(invalid("header size is not a multiple of the entry size"));
+        }
+
+        let data_segment_len = file_len
+            .checked_sub(std::mem::size_of::<usize>() + header_size)
+            .ok_or_else(|| invalid("header exceeds file length"))?;
+
+        Ok(Self {
+            path,
+            into_memory,
+            n_terms: header_size / entry_size,
+            data_segment_len,
+            segment: OnceLock::new(),
+        })
+    }
+
+    fn segment(&self) -> std::io::Result<&StateSegment> {
+        if let Some(segment) = self.segment.get() {
+            return Ok(segment);
+        }
+
+        let segment = if self.into_memory {
+            StateSegment::read_into_memory(&self.path)
+        } else {
+            // the shards were opened by `RdfTripleDecompressor::load_state`, whose safety contract covers them
+            unsafe { StateSegment::load(&self.path) }
+        }
+        .map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("unable to load compressor state shard {:?}: {e}", self.path),
+            )
+        })?;
+
+        // another thread may have loaded it in the meantime, then its segment is kept
+        Ok(self.segment.get_or_init(|| segment))
+    }
+}
+
+/// Opens the shards of the state at `path`, none if it is not sharded
+fn open_shards(path: &Path, into_memory: bool) -> std::io::Result<Vec<StateShard>> {
+    let shards = (0..)
+        .map(|n| state_shard_path(path, n))
+        .take_while(|shard_path| shard_path.exists())
+        .map(|shard_path| StateShard::open(shard_path, into_memory))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    if !shards.is_empty() {
+        check_shard_count(shards.len()).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("compressor state {path:?}: {e}"),
+            )
+        })?;
+    }
+
+    Ok(shards)
+}
+
+pub struct RdfTripleDecompressor {
+    pub(super) segments: Vec<StateSegment>,
+    shards: Vec<StateShard>,
+}
+
+impl RdfTripleDecompressor {
+    fn search_header(&self, id: TripleElementId) -> std::io::Result<Option<&[u8]>> {
+        let sharded = match self.shards.len() {
+            0 => None,
+            n_shards => self.shards[shard_of(id, n_shards)].segment()?.search(id),
+        };
+
+        Ok(sharded.or_else(|| self.segments.iter().find_map(|segment| segment.search(id))))
+    }
+
+    /// Memory maps the compressor state at `path` together with the segments appended to it.
+    /// The shards of a sharded state are only checked and mapped once an id of them is looked up.
+    ///
+    /// # Safety
+    /// The files must not be modified while they are mapped and must be written by `RdfTripleCompressor::save_state`,
+    /// `RdfTripleCompressor::save_state_sharded` or `RdfTripleCompressor::save_state_segment`.
+    pub unsafe fn load_state<P: AsRef<Path>>(path: P) -> std::io::Result<RdfTripleDecompressor> {
+        let segments = state_file_paths(path.as_ref())
+            .map(|path| StateSegment::load(&path))
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self { segments, shards: open_shards(path.as_ref(), false)? })
+    }
+
+    /// Reads the compressor state at `path` and its appended segments fully into memory, avoiding random reads on slow
+    /// storage. The shards of a sharded state are only read once an id of them is looked up.
+    pub fn read_state_into_memory<P: AsRef<Path>>(path: P) -> std::io::Result<RdfTripleDecompressor> {
+        let segments = state_file_paths(path.as_ref())
+            .map(|path| StateSegment::read_into_memory(&path))
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self { segments, shards: open_shards(path.as_ref(), true)? })
     }
 
-    pub fn decompress_rdf_triple(&self, [subject, predicate, object]: CompressedTriple) -> Option<RawTriple> {
-        let &(_, s_start, s_end) = self.search_header(subject)?;
-        let &(_, p_start, p_end) = self.search_header(predicate)?;
-        let &(_, o_start, o_end) = self.search_header(object)?;
+    /// Average length in bytes of a dictionary entry, useful to estimate output sizes without decompressing
+    pub fn average_term_len(&self) -> f64 {
+        let n_bytes = self.segments.iter().map(|segment| segment.data_segment.len()).sum::<usize>()
+            + self.shards.iter().map(|shard| shard.data_segment_len).sum::<usize>();
 
-        Some([
-            &self.data_segment[s_start..s_end],
-            &self.data_segment[p_start..p_end],
-            &self.data_segment[o_start..o_end],
-        ])
+        match self.n_terms() {
+            0 => 0.0,
+            n_terms => n_bytes as f64 / n_terms as f64,
+        }
+    }
+
+    /// Number of entries in the dictionary
+    pub fn n_terms(&self) -> usize {
+        self.segments.iter().map(|segment| segment.header.len()).sum::<usize>()
+            + self.shards.iter().map(|shard| shard.n_terms).sum::<usize>()
+    }
+
+    /// Order independent hash of the ids of all terms, compare with [`DatasetMetadata::state_fingerprint`]
+    ///
+    /// [`DatasetMetadata::state_fingerprint`]: super::compressor::DatasetMetadata::state_fingerprint
+    pub fn state_fingerprint(&self) -> std::io::Result<u128> {
+        Ok(state_fingerprint(self.terms()?.map(|(id, _)| id)))
+    }
+
+    /// Returns the term with the given id, or `None` if the id is not in the dictionary.
+    /// Fails if the shard of the id cannot be loaded
+    pub fn decompress_term(&self, id: TripleElementId) -> std::io::Result<Option<&[u8]>> {
+        self.search_header(id)
+    }
+
+    /// Iterates over all dictionary entries as pairs of id and term, the ids are ascending within
+    /// the state and within each of its appended segments. Loads all shards of a sharded state first
+    pub fn terms(&self) -> std::io::Result<impl Iterator<Item = (TripleElementId, &[u8])> + '_> {
+        let shards = self
+            .shards
+            .iter()
+            .map(StateShard::segment)
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(shards.into_iter().chain(&self.segments).flat_map(|segment| {
+            segment
+                .header
+                .iter()
+                .map(|&(id, start, end)| (id, &segment.data_segment[start..end]))
+        }))
+    }
+
+    /// Returns the terms of `triple`, or `None` if one of them is not in the dictionary
+    pub fn decompress_rdf_triple(
+        &self,
+        [subject, predicate, object]: CompressedTriple,
+    ) -> std::io::Result<Option<RawTriple>> {
+        let terms = (
+            self.decompress_term(subject)?,
+            self.decompress_term(predicate)?,
+            self.decompress_term(object)?,
+        );
+
+        Ok(match terms {
+            (Some(subject), Some(predicate), Some(object)) => Some([subject, predicate, object]),
+            _ => None,
+        })
+    }
+
+    pub fn decompress_rdf_triple_file<P: AsRef<Path>, W: Write>(&self, path: P, out: W) -> std::io::Result<()> {
+        self.decompress_selected_rdf_triple_file(path, out, |_| Ok(true))
+    }
+
+    /// Returns the terms of `triple`, failing if one of them is not in the dictionary
+    pub fn decompress_known_rdf_triple(&self, triple: CompressedTriple) -> std::io::Result<RawTriple> {
+        self.decompress_rdf_triple(triple)?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "dataset contains ids missing from the compressor state, it was compressed with another state",
+            )
+        })
     }
 
-    pub fn decompress_rdf_triple_file<P: AsRef<Path>, W: Write>(&self, path: P, mut out: W) -> std::io::Result<()> {
+    /// Decompresses only the triples of the dataset at `path` for which `is_selected` returns true
+    pub fn decompress_selected_rdf_triple_file<P: AsRef<Path>, W: Write>(
+        &self,
+        path: P,
+        mut out: W,
+        mut is_selected: impl FnMut(CompressedTriple) -> std::io::Result<bool>,
+    ) -> std::io::Result<()> {
         let in_triples = unsafe { CompressedRdfTriples::load(path)? };
 
         for &triple in in_triples.iter() {
-            let [s, p, o] = self
-                .decompress_rdf_triple(triple)
-                .expect("using same compressor state for compression and decompression");
+            if !is_selected(triple)? {
+                continue;
+            }
+
+            let [s, p, o] = self.decompress_known_rdf_triple(triple)?;
 
             out.write_all(s)?;
             out.write_all(b" ")?;
@@ -75,3 +310,122 @@ impl RdfTripleDecompressor {
         Ok(())
     }
 }
+
+/// Caches the terms of already resolved ids, so that repeated subjects and predicates
+/// do not need a search over the header every time
+pub struct DecompressionCache<'d> {
+    decompressor: &'d RdfTripleDecompressor,
+    terms: HashMap<TripleElementId, &'d [u8], BuildHasherDefault<ahash::AHasher>>,
+}
+
+impl<'d> DecompressionCache<'d> {
+    pub fn new(decompressor: &'d RdfTripleDecompressor) -> Self {
+        Self { decompressor, terms: HashMap::default() }
+    }
+
+    fn term(&mut self, id: TripleElementId) -> std::io::Result<Option<&'d [u8]>> {
+        if let Some(&term) = self.terms.get(&id) {
+            return Ok(Some(term));
+        }
+
+        let term = self.decompressor.search_header(id)?;
+        if let Some(term) = term {
+            self.terms.insert(id, term);
+        }
+
+        Ok(term)
+    }
+
+    /// Resolves all ids of `triples` that are not cached yet, in ascending order
+    pub fn resolve_batch(&mut self, triples: &[CompressedTriple]) -> std::io::Result<()> {
+        let mut missing: Vec<_> = triples
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|id| !self.terms.contains_key(id))
+            .collect();
+
+        missing.sort_unstable();
+        missing.dedup();
+
+        for id in missing {
+            self.term(id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decompress_rdf_triple(
+        &mut self,
+        [subject, predicate, object]: CompressedTriple,
+    ) -> std::io::Result<Option<RawTriple<'d>>> {
+        let terms = (self.term(subject)?, self.term(predicate)?, self.term(object)?);
+
+        Ok(match terms {
+            (Some(subject), Some(predicate), Some(object)) => Some([subject, predicate, object]),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdf::triple_compressor::compressor::{term_id, RdfTripleCompressor};
+
+    #[test]
+    fn broken_shard_fails_loading_the_state() {
+        let dir = std::env::temp_dir().join(format!("sudg-shards-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let nt_path = dir.join("dataset.nt");
+        std::fs::write(
+            &nt_path,
+            "<http://a> <http://p> <http://b> .\n<http://c> <http://p> \"d\" .\n",
+        )
+        .unwrap();
+
+        let state_path = dir.join("dataset.compressor_state");
+        let mut compressor = RdfTripleCompressor::new();
+        compressor
+            .compress_rdf_triple_file_to(&nt_path, dir.join("dataset.compressed_nt"), false, false)
+            .unwrap();
+        compressor.save_state_sharded(&state_path, 2).unwrap();
+
+        let decompressor = RdfTripleDecompressor::read_state_into_memory(&state_path).unwrap();
+        assert_eq!(decompressor.n_terms(), 5);
+
+        // shorter than the size of its header
+        std::fs::write(state_shard_path(&state_path, 1), [0; 4]).unwrap();
+        assert!(RdfTripleDecompressor::read_state_into_memory(&state_path).is_err());
+        assert!(unsafe { RdfTripleDecompressor::load_state(&state_path) }.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lookup_in_unreadable_shard_fails() {
+        let dir = std::env::temp_dir().join(format!("sudg-lazy-shards-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let nt_path = dir.join("dataset.nt");
+        std::fs::write(&nt_path, "<http://a> <http://p> <http://b> .\n").unwrap();
+
+        let state_path = dir.join("dataset.compressor_state");
+        let mut compressor = RdfTripleCompressor::new();
+        compressor
+            .compress_rdf_triple_file_to(&nt_path, dir.join("dataset.compressed_nt"), false, false)
+            .unwrap();
+        compressor.save_state_sharded(&state_path, 2).unwrap();
+
+        // the shards are only checked when the state is loaded, so removing one afterwards surfaces on lookup
+        let decompressor = RdfTripleDecompressor::read_state_into_memory(&state_path).unwrap();
+        let id = term_id(b"<http://a>");
+        std::fs::remove_file(state_shard_path(&state_path, shard_of(id, 2))).unwrap();
+
+        assert!(decompressor.decompress_term(id).is_err());
+        assert!(decompressor.terms().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,44 +1,421 @@
 pub mod compressor;
 pub mod decompressor;
+mod term_arena;
+pub mod term_counts;
 
-use crate::MemoryMapped;
+use crate::{
+    util::{check_memory, commit_buffered, fits_in_memory, fits_memory_limit, AtomicFile},
+    warnings::{warn, Warning},
+};
+use clap::ArgEnum;
+use memory_mapped::MemoryMapped;
+use rayon::{
+    iter::IntoParallelIterator,
+    slice::{ParallelSlice, ParallelSliceMut},
+};
 use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    fs::File,
+    hash::BuildHasherDefault,
+    io::{self, BufWriter, Read, Write},
     ops::{Deref, DerefMut},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 pub const COMPRESSED_TRIPLE_FILE_EXTENSION: &str = "compressed_nt";
 pub const UNCOMPRESSED_TRIPLE_FILE_EXTENSION: &str = "nt";
+pub const HDT_FILE_EXTENSION: &str = "hdt";
 
 pub type TripleId = u64;
 pub type TripleElementId = u64;
 pub type RawTriple<'a> = [&'a [u8]; 3];
 pub type CompressedTriple = [TripleElementId; 3];
 
-pub struct CompressedRdfTriples(MemoryMapped<[CompressedTriple]>);
+/// splitmix64 finalizer, fixed instead of ahash since ahash differs by CPU features
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Order independent combination of `hashes`, their wrapping sum and their xor
+fn combine_unordered(hashes: impl Iterator<Item = u64>) -> u128 {
+    let (sum, xor) = hashes.fold((0u64, 0u64), |(sum, xor), hash| (sum.wrapping_add(hash), xor ^ hash));
+    (u128::from(sum) << 64) | u128::from(xor)
+}
+
+/// Order dependent hash of `triples` following the triples already hashed into `hash`, which is 0 for none
+pub fn chain_hash(hash: u64, triples: &[CompressedTriple]) -> u64 {
+    triples
+        .iter()
+        .fold(hash, |hash, &[s, p, o]| mix(hash ^ mix(mix(mix(s) ^ p) ^ o)))
+}
+
+/// Order independent hash of the term ids of a compressor state. As the ids are [`term_id`]s, states with the same terms
+/// only have the same fingerprint if they were compressed by builds with the same ahash version and target features
+///
+/// [`term_id`]: compressor::term_id
+fn state_fingerprint(ids: impl Iterator<Item = TripleElementId>) -> u128 {
+    combine_unordered(ids.map(mix))
+}
+
+/// Storage of loaded data, either memory mapped or read fully into memory
+pub enum Storage<T: 'static> {
+    Mapped(MemoryMapped<[T]>),
+    InMemory(Box<[T]>),
+}
+
+impl<T> Deref for Storage<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Storage::Mapped(mapped) => mapped,
+            Storage::InMemory(owned) => owned,
+        }
+    }
+}
+
+impl<T> DerefMut for Storage<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Storage::Mapped(mapped) => mapped,
+            Storage::InMemory(owned) => owned,
+        }
+    }
+}
+
+/// Path of the `n`-th segment appended to the compressor state at `path` by an incremental compression run
+pub fn state_segment_path(path: &Path, n: usize) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".segment-{n}"));
+    path.with_file_name(file_name)
+}
+
+/// Largest number of shards a compressor state can be split into
+pub const MAX_STATE_SHARDS: usize = 256;
+
+/// Path of the `n`-th shard of a compressor state saved with [`RdfTripleCompressor::save_state_sharded`]
+///
+/// [`RdfTripleCompressor::save_state_sharded`]: compressor::RdfTripleCompressor::save_state_sharded
+pub fn state_shard_path(path: &Path, n: usize) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".shard-{n}"));
+    path.with_file_name(file_name)
+}
+
+/// Checks that a state can be split into `n_shards` shards, a power of two between 2 and [`MAX_STATE_SHARDS`]
+pub fn check_shard_count(n_shards: usize) -> io::Result<()> {
+    if n_shards.is_power_of_two() && (2..=MAX_STATE_SHARDS).contains(&n_shards) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the number of shards must be a power of two between 2 and {MAX_STATE_SHARDS}, got {n_shards}"),
+        ))
+    }
+}
+
+/// Shard of the term with `id` in a state of `n_shards` shards, given by the leading bits of the id.
+/// `n_shards` must be a power of two of at least 2
+fn shard_of(id: TripleElementId, n_shards: usize) -> usize {
+    (id >> (TripleElementId::BITS - n_shards.trailing_zeros())) as usize
+}
+
+/// Reads `n_bytes` bytes from `reader` as a slice of `T` in native byte order
+///
+/// # Safety
+/// Every bit pattern must be a valid `T`.
+pub(crate) unsafe fn read_slice<T: Copy, R: Read>(reader: &mut R, n_bytes: usize) -> io::Result<Box<[T]>> {
+    let elem_size = std::mem::size_of::<T>();
+    let n_elems = n_bytes / elem_size;
+
+    if n_elems * elem_size != n_bytes {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("length {n_bytes} is not a multiple of the element size {elem_size}"),
+        ));
+    }
+
+    let mut buf = Vec::<T>::with_capacity(n_elems);
+    std::ptr::write_bytes(buf.as_mut_ptr(), 0, n_elems);
+    buf.set_len(n_elems);
+
+    reader.read_exact(std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, n_bytes))?;
+
+    Ok(buf.into_boxed_slice())
+}
+
+/// Writes `triples` to `path` in the compressed dataset format
+pub fn save_compressed_triples<P: AsRef<Path>>(path: P, triples: &[CompressedTriple]) -> io::Result<()> {
+    let mut bw = BufWriter::new(AtomicFile::create(path, false)?);
+
+    for [s, p, o] in triples {
+        bw.write_all(&s.to_ne_bytes())?;
+        bw.write_all(&p.to_ne_bytes())?;
+        bw.write_all(&o.to_ne_bytes())?;
+    }
+
+    commit_buffered(bw)
+}
+
+pub struct CompressedRdfTriples(Storage<CompressedTriple>);
 
 impl CompressedRdfTriples {
+    /// Memory maps the compressed triples at `path` read-only, empty files cannot be mapped and are loaded as no triples
+    ///
+    /// # Safety
+    /// The file must not be modified while it is mapped and its length must be a multiple of the triple size.
     pub unsafe fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
-        Ok(CompressedRdfTriples(MemoryMapped::open_slice(path)?.assume_init()))
+        if std::fs::metadata(&path)?.len() == 0 {
+            return Ok(CompressedRdfTriples(Storage::InMemory(Box::new([]))));
+        }
+
+        Ok(CompressedRdfTriples(Storage::Mapped(
+            MemoryMapped::open_slice(path)?.assume_init(),
+        )))
     }
 
+    #[cfg(test)]
+    pub(crate) fn from_triples(triples: Vec<CompressedTriple>) -> Self {
+        CompressedRdfTriples(Storage::InMemory(triples.into_boxed_slice()))
+    }
+
+    /// Reads the compressed triples at `path` fully into memory, avoiding random reads on slow storage
+    pub fn read_into_memory<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut f = File::open(path)?;
+        let n_bytes = f.metadata()?.len() as usize;
+
+        Ok(CompressedRdfTriples(Storage::InMemory(unsafe {
+            read_slice(&mut f, n_bytes)?
+        })))
+    }
+
+    /// Memory maps the compressed triples at `path` read-write, changes are written back to the file.
+    /// Empty files are loaded as no triples like in [`CompressedRdfTriples::load`].
+    ///
+    /// # Safety
+    /// The file must not be modified by others while it is mapped and its length must be a multiple of the triple size.
     pub unsafe fn load_shared<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
-        Ok(CompressedRdfTriples(
+        if std::fs::metadata(&path)?.len() == 0 {
+            return Ok(CompressedRdfTriples(Storage::InMemory(Box::new([]))));
+        }
+
+        Ok(CompressedRdfTriples(Storage::Mapped(
             MemoryMapped::options()
                 .read(true)
                 .write(true)
                 .open_shared_slice(path)?
                 .assume_init(),
-        ))
+        )))
     }
 
     pub fn contains(&self, triple: &CompressedTriple) -> bool {
         self.0.binary_search(triple).is_ok()
     }
+
+    /// Counts the distinct subjects, predicates and objects by sorting a copy of each column.
+    /// Avoids the memory overhead of a hash set per column, the columns are sorted in parallel
+    /// unless the copies of all three columns would exceed `--max-memory`.
+    pub fn distinct_counts(&self) -> io::Result<[usize; 3]> {
+        fn count_distinct(mut column: Vec<TripleElementId>) -> usize {
+            column.par_sort_unstable();
+            column.dedup();
+            column.len()
+        }
+
+        let column_bytes = std::mem::size_of_val::<[CompressedTriple]>(self) as u64 / 3;
+
+        if !fits_memory_limit(3 * column_bytes) {
+            check_memory(column_bytes, "copying a column to count its distinct terms")?;
+            warn(Warning::MemoryLimitFallback {
+                step: "copying all columns to count their distinct terms".to_owned(),
+                fallback: "counting one column at a time".to_owned(),
+            });
+
+            return Ok([0, 1, 2].map(|pos| count_distinct(self.iter().map(|triple| triple[pos]).collect())));
+        }
+
+        let mut subjects = Vec::with_capacity(self.len());
+        let mut predicates = Vec::with_capacity(self.len());
+        let mut objects = Vec::with_capacity(self.len());
+
+        for &[s, p, o] in self.iter() {
+            subjects.push(s);
+            predicates.push(p);
+            objects.push(o);
+        }
+
+        let (ns, (np, no)) = rayon::join(
+            move || count_distinct(subjects),
+            move || rayon::join(move || count_distinct(predicates), move || count_distinct(objects)),
+        );
+
+        Ok([ns, np, no])
+    }
+
+    /// Order independent hash of the triples, the wrapping sum and the xor of a hash per triple.
+    /// It hashes the ids rather than the terms, so datasets with the same triples (and multiplicities) only have the
+    /// same fingerprint if they were compressed with the same compressor state, or at least by builds with the same
+    /// ahash version and target features, see [`term_id`](compressor::term_id).
+    pub fn fingerprint(&self) -> u128 {
+        combine_unordered(self.iter().map(|&[s, p, o]| mix(mix(mix(s) ^ p) ^ o)))
+    }
+
+    /// Creates a cursor for repeated containment checks, the triples must be sorted
+    pub fn containment_cursor(&self) -> ContainmentCursor<'_> {
+        ContainmentCursor { triples: &self.0, pos: 0 }
+    }
+
+    /// Parallel iterator over the triples
+    pub fn par_iter(&self) -> rayon::slice::Iter<'_, CompressedTriple> {
+        (&*self.0).into_par_iter()
+    }
+
+    /// Parallel iterator over consecutive chunks of `chunk_size` triples, the last chunk may be shorter
+    pub fn par_chunks(&self, chunk_size: usize) -> rayon::slice::Chunks<'_, CompressedTriple> {
+        ParallelSlice::par_chunks(&*self.0, chunk_size)
+    }
+
+    /// Returns whether all of `triples` are contained, the triples of `self` must be sorted.
+    /// Sorted `triples` are checked in a single pass through [`CompressedRdfTriples::containment_cursor`].
+    pub fn contains_all<'t>(&self, triples: impl IntoIterator<Item = &'t CompressedTriple>) -> bool {
+        let mut cursor = self.containment_cursor();
+        triples.into_iter().all(|triple| cursor.contains(triple))
+    }
+
+    /// Triples contained in both `self` and `other`, which must both be sorted
+    pub fn intersect_sorted(&self, other: &[CompressedTriple]) -> Vec<CompressedTriple> {
+        let mut common = Vec::new();
+        let (mut a, mut b) = (self.iter().peekable(), other.iter().peekable());
+
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            match x.cmp(y) {
+                Ordering::Less => {
+                    a.next();
+                },
+                Ordering::Greater => {
+                    b.next();
+                },
+                Ordering::Equal => {
+                    common.push(**x);
+                    a.next();
+                    b.next();
+                },
+            }
+        }
+
+        common
+    }
+}
+
+/// Containment checks against sorted triples that remember the position of the last probe.
+/// Probes in ascending order gallop forward from there, turning a scan over a sorted probe
+/// dataset into a linear merge. Probes out of order fall back to a search over all triples.
+pub struct ContainmentCursor<'a> {
+    triples: &'a [CompressedTriple],
+    pos: usize,
+}
+
+impl ContainmentCursor<'_> {
+    pub fn contains(&mut self, triple: &CompressedTriple) -> bool {
+        // the triples before `pos` are smaller than the last probe, but may contain this one if it is not larger
+        if self.pos > 0 && self.triples[self.pos - 1] >= *triple {
+            self.pos = 0;
+        }
+
+        let rest = &self.triples[self.pos..];
+
+        let mut bound = 1;
+        while bound < rest.len() && rest[bound - 1] < *triple {
+            bound *= 2;
+        }
+
+        match rest[..bound.min(rest.len())].binary_search(triple) {
+            Ok(ix) => {
+                self.pos += ix;
+                true
+            },
+            Err(ix) => {
+                self.pos += ix;
+                false
+            },
+        }
+    }
+}
+
+/// How containment in a dataset is checked
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContainmentStrategy {
+    /// search the sorted triples, the dataset must be sorted
+    BinarySearch,
+    /// build an in-memory hash set of the triples, works for unsorted datasets
+    Hash,
+    /// binary search if the dataset is sorted, otherwise a hash set if it fits into memory
+    Auto,
+}
+
+/// A dataset prepared for containment checks according to a [`ContainmentStrategy`]
+pub enum ContainmentIndex<'a> {
+    Sorted(&'a [CompressedTriple]),
+    Hashed(HashSet<CompressedTriple, BuildHasherDefault<ahash::AHasher>>),
+}
+
+impl<'a> ContainmentIndex<'a> {
+    pub fn new(triples: &'a CompressedRdfTriples, strategy: ContainmentStrategy) -> io::Result<Self> {
+        // every entry takes the triple and a control byte, at the maximum load factor of 7/8
+        let hash_bytes = (triples.len() * (std::mem::size_of::<CompressedTriple>() + 1) * 8 / 7) as u64;
+        let is_sorted = strategy != ContainmentStrategy::Hash && triples.is_sorted();
+
+        match strategy {
+            ContainmentStrategy::BinarySearch | ContainmentStrategy::Auto if is_sorted => {
+                Ok(ContainmentIndex::Sorted(triples))
+            },
+            ContainmentStrategy::BinarySearch => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the dataset must be sorted for binary search containment checks, run sort on it first, pass --auto-sort or use --containment hash",
+            )),
+            ContainmentStrategy::Auto if !(fits_in_memory(hash_bytes) && fits_memory_limit(hash_bytes)) => {
+                Err(io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    format!(
+                        "the dataset is not sorted and a hash set of its triples would need {hash_bytes} bytes, which do not fit into memory. Run sort on it first or pass --auto-sort"
+                    ),
+                ))
+            },
+            ContainmentStrategy::Hash | ContainmentStrategy::Auto => {
+                check_memory(hash_bytes, "building the containment hash set")?;
+                Ok(ContainmentIndex::Hashed(triples.iter().copied().collect()))
+            },
+        }
+    }
+
+    /// Creates a checker for repeated containment checks
+    pub fn checker(&self) -> ContainmentChecker<'_> {
+        match self {
+            ContainmentIndex::Sorted(triples) => ContainmentChecker::Sorted(ContainmentCursor { triples, pos: 0 }),
+            ContainmentIndex::Hashed(triples) => ContainmentChecker::Hashed(triples),
+        }
+    }
+}
+
+pub enum ContainmentChecker<'a> {
+    Sorted(ContainmentCursor<'a>),
+    Hashed(&'a HashSet<CompressedTriple, BuildHasherDefault<ahash::AHasher>>),
+}
+
+impl ContainmentChecker<'_> {
+    pub fn contains(&mut self, triple: &CompressedTriple) -> bool {
+        match self {
+            ContainmentChecker::Sorted(cursor) => cursor.contains(triple),
+            ContainmentChecker::Hashed(triples) => triples.contains(triple),
+        }
+    }
 }
 
 impl Deref for CompressedRdfTriples {
-    type Target = MemoryMapped<[CompressedTriple]>;
+    type Target = [CompressedTriple];
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -51,6 +428,15 @@ impl DerefMut for CompressedRdfTriples {
     }
 }
 
+impl<'a> IntoParallelIterator for &'a CompressedRdfTriples {
+    type Item = &'a CompressedTriple;
+    type Iter = rayon::slice::Iter<'a, CompressedTriple>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
 impl<'a> IntoIterator for &'a CompressedRdfTriples {
     type Item = &'a CompressedTriple;
     type IntoIter = std::slice::Iter<'a, CompressedTriple>;
@@ -59,3 +445,63 @@ impl<'a> IntoIterator for &'a CompressedRdfTriples {
         self.0.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_files_load_as_no_triples() {
+        let path = std::env::temp_dir().join(format!("sudg-empty-{}.compressed_nt", std::process::id()));
+        File::create(&path).unwrap();
+
+        let triples = unsafe { CompressedRdfTriples::load(&path) }.unwrap();
+        let shared = unsafe { CompressedRdfTriples::load_shared(&path) }.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(triples.is_empty());
+        assert!(shared.is_empty());
+    }
+
+    #[test]
+    fn cursor_finds_probes_out_of_order() {
+        let triples = CompressedRdfTriples::from_triples(vec![[1, 1, 1], [1, 2, 3], [2, 2, 2], [4, 4, 4]]);
+        let mut cursor = triples.containment_cursor();
+
+        for probe in [
+            [4, 4, 4],
+            [1, 2, 3],
+            [1, 1, 1],
+            [1, 1, 1],
+            [3, 3, 3],
+            [2, 2, 2],
+            [0, 0, 0],
+            [4, 4, 4],
+        ] {
+            assert_eq!(cursor.contains(&probe), triples.contains(&probe), "{probe:?}");
+        }
+    }
+
+    #[test]
+    fn sorted_triples_intersect() {
+        let triples = CompressedRdfTriples::from_triples(vec![[1, 1, 1], [1, 2, 3], [2, 2, 2], [4, 4, 4]]);
+
+        assert_eq!(
+            triples.intersect_sorted(&[[0, 0, 0], [1, 2, 3], [2, 2, 2], [3, 3, 3], [4, 4, 4], [5, 5, 5]]),
+            vec![[1, 2, 3], [2, 2, 2], [4, 4, 4]]
+        );
+        assert!(triples.intersect_sorted(&[[3, 3, 3]]).is_empty());
+        assert!(triples.intersect_sorted(&[]).is_empty());
+    }
+
+    #[test]
+    fn contains_all_checks_sorted_and_unsorted_triples() {
+        let triples = CompressedRdfTriples::from_triples(vec![[1, 1, 1], [1, 2, 3], [2, 2, 2], [4, 4, 4]]);
+
+        assert!(triples.contains_all(&[[1, 1, 1], [2, 2, 2], [4, 4, 4]]));
+        assert!(triples.contains_all(&[[4, 4, 4], [1, 2, 3], [1, 1, 1]]));
+        assert!(!triples.contains_all(&[[1, 1, 1], [3, 3, 3], [4, 4, 4]]));
+        assert!(!triples.contains_all(&[[4, 4, 4], [0, 0, 0]]));
+        assert!(triples.contains_all(&[]));
+    }
+}
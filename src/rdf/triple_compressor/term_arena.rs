@@ -0,0 +1,52 @@
+//! Append-only storage for the terms of the compressor dictionary. Terms are stored back to back
+//! in large chunks instead of one heap allocation each, which keeps the allocator out of the hot
+//! path and memory from fragmenting with hundreds of millions of terms.
+
+const CHUNK_SIZE: usize = 32 << 20;
+
+/// Location of a term in a [`TermArena`], later pushed terms compare greater
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TermRef {
+    chunk: u32,
+    start: u32,
+    len: u32,
+}
+
+#[derive(Default)]
+pub struct TermArena {
+    chunks: Vec<Vec<u8>>,
+}
+
+impl TermArena {
+    /// Appends `term`, terms never straddle chunks so that they can be returned as a single slice
+    pub fn push(&mut self, term: &[u8]) -> TermRef {
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.capacity() - chunk.len() >= term.len());
+
+        if !fits {
+            self.chunks.push(Vec::with_capacity(CHUNK_SIZE.max(term.len())));
+        }
+
+        let chunk_ix = self.chunks.len() - 1;
+        let chunk = &mut self.chunks[chunk_ix];
+        let start = chunk.len();
+        chunk.extend_from_slice(term);
+
+        TermRef { chunk: chunk_ix as u32, start: start as u32, len: term.len() as u32 }
+    }
+
+    /// Location just past the last term, all terms pushed afterwards compare greater or equal
+    pub fn end(&self) -> TermRef {
+        match self.chunks.last() {
+            Some(chunk) => TermRef { chunk: (self.chunks.len() - 1) as u32, start: chunk.len() as u32, len: 0 },
+            None => TermRef { chunk: 0, start: 0, len: 0 },
+        }
+    }
+
+    pub fn get(&self, term: TermRef) -> &[u8] {
+        let start = term.start as usize;
+        &self.chunks[term.chunk as usize][start..start + term.len as usize]
+    }
+}
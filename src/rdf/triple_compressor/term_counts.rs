@@ -0,0 +1,167 @@
+//! Occurrence counts of the terms of a compressor state, stored next to it as `<state>.term-counts`.
+//! They are optional and only kept up to date by `compress --term-counts`, so that the most frequent terms
+//! are known without scanning the compressed datasets again.
+
+use super::{CompressedTriple, TripleElementId};
+use crate::util::{check_memory, commit_buffered, AtomicFile};
+use std::{
+    collections::HashMap,
+    hash::BuildHasherDefault,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Path of the term counts of the compressor state at `state_path`
+pub fn term_counts_path(state_path: &Path) -> PathBuf {
+    let mut file_name = state_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".term-counts");
+    state_path.with_file_name(file_name)
+}
+
+/// Bytes taken by the counts of `capacity` terms
+fn counts_bytes(capacity: usize) -> u64 {
+    (capacity * (std::mem::size_of::<(TripleElementId, u64)>() + 1)) as u64
+}
+
+/// Number of written triples every term occurs in, a term occurring twice in a triple is counted twice
+#[derive(Default)]
+pub struct TermCounts {
+    counts: HashMap<TripleElementId, u64, BuildHasherDefault<ahash::AHasher>>,
+    /// capacity of `counts` whose growth was already checked against `--max-memory`
+    checked_capacity: Option<usize>,
+}
+
+impl TermCounts {
+    pub fn add(&mut self, triple: CompressedTriple) {
+        for id in triple {
+            *self.counts.entry(id).or_default() += 1;
+        }
+    }
+
+    /// Fails if the next growth of the counts would exceed `--max-memory`
+    pub fn check_memory(&mut self) -> io::Result<()> {
+        let capacity = self.counts.capacity();
+        // a triple adds up to three terms
+        if self.counts.len() + 3 <= capacity || self.checked_capacity == Some(capacity) {
+            return Ok(());
+        }
+
+        self.checked_capacity = Some(capacity);
+
+        // the map doubles on growth
+        check_memory(
+            counts_bytes(2 * capacity.max(16)),
+            "growing the term counts (compress without --term-counts to avoid it)",
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// The `k` most frequent terms with their counts, descending, terms of equal counts by id
+    pub fn top(&self, k: usize) -> Vec<(TripleElementId, u64)> {
+        let order = |(a_id, a_count): &(TripleElementId, u64), (b_id, b_count): &(TripleElementId, u64)| {
+            b_count.cmp(a_count).then(a_id.cmp(b_id))
+        };
+
+        let mut counts: Vec<_> = self.counts.iter().map(|(&id, &count)| (id, count)).collect();
+
+        // only the top terms are sorted
+        if k < counts.len() {
+            counts.select_nth_unstable_by(k, order);
+            counts.truncate(k);
+        }
+
+        counts.sort_unstable_by(order);
+        counts
+    }
+
+    /// Reads the term counts of the compressor state at `state_path`, `None` if it has none
+    pub fn read(state_path: &Path) -> io::Result<Option<Self>> {
+        let bytes = match std::fs::read(term_counts_path(state_path)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let entry_size = 2 * std::mem::size_of::<u64>();
+        if bytes.len() % entry_size != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("term counts of {state_path:?} have a length that is not a multiple of {entry_size}"),
+            ));
+        }
+
+        check_memory(
+            counts_bytes(bytes.len() / entry_size),
+            &format!("reading the term counts of {state_path:?}"),
+        )?;
+
+        let counts = bytes
+            .chunks_exact(entry_size)
+            .map(|entry| {
+                let (id, count) = entry.split_at(std::mem::size_of::<u64>());
+                (
+                    TripleElementId::from_ne_bytes(id.try_into().unwrap()),
+                    u64::from_ne_bytes(count.try_into().unwrap()),
+                )
+            })
+            .collect();
+
+        Ok(Some(TermCounts { counts, checked_capacity: None }))
+    }
+
+    /// Writes the counts next to the compressor state at `state_path` as pairs of id and count sorted by id
+    pub fn write(&self, state_path: &Path) -> io::Result<()> {
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_unstable();
+
+        let mut writer = BufWriter::new(AtomicFile::create(term_counts_path(state_path), false)?);
+
+        for (id, count) in counts {
+            writer.write_all(&id.to_ne_bytes())?;
+            writer.write_all(&count.to_ne_bytes())?;
+        }
+
+        commit_buffered(writer)
+    }
+
+    /// Removes the term counts of the compressor state at `state_path` if it has some, so that they don't go stale
+    pub fn remove(state_path: &Path) -> io::Result<()> {
+        match std::fs::remove_file(term_counts_path(state_path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_survive_writing_and_reading() {
+        let state_path = std::env::temp_dir().join(format!("sudg-term-counts-{}.compressor_state", std::process::id()));
+        assert!(TermCounts::read(&state_path).unwrap().is_none());
+
+        let mut counts = TermCounts::default();
+        counts.add([1, 2, 3]);
+        counts.add([1, 2, 4]);
+        counts.add([1, 5, 1]);
+        counts.write(&state_path).unwrap();
+
+        let read = TermCounts::read(&state_path).unwrap().unwrap();
+        assert_eq!(read.len(), 5);
+        assert_eq!(read.top(usize::MAX), counts.top(usize::MAX));
+        assert_eq!(read.top(2), vec![(1, 4), (2, 2)]);
+        assert_eq!(read.top(3), vec![(1, 4), (2, 2), (3, 1)]);
+
+        TermCounts::remove(&state_path).unwrap();
+        assert!(TermCounts::read(&state_path).unwrap().is_none());
+    }
+}
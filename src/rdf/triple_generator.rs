@@ -1,15 +1,59 @@
 use crate::{
+    perf::{self, Phase},
     rdf::triple_compressor::{CompressedRdfTriples, CompressedTriple, TripleElementId},
+    util::seeded_rng,
+    warnings::{warn, Warning},
+};
+use clap::ArgEnum;
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+use std::{
+    borrow::Borrow,
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
 };
-use rand::{Rng, SeedableRng};
-use std::collections::HashSet;
 
-pub fn random_distinct_triple_generator(
-    triples: &CompressedRdfTriples,
+/// Indices of the triples that are not contained in `used`, which must be sorted
+pub fn unused_triple_indices(triples: &CompressedRdfTriples, used: &CompressedRdfTriples) -> Vec<usize> {
+    (0..triples.len()).filter(|&ix| !used.contains(&triples[ix])).collect()
+}
+
+/// Indices of the triples of every dataset that are not contained in an earlier dataset, `None` for the first one.
+/// Selecting only from them keeps triples shared by several datasets from being selected once from each.
+/// All datasets but the last must be sorted.
+pub fn disjoint_candidate_indices(datasets: &[&CompressedRdfTriples]) -> Vec<Option<Vec<usize>>> {
+    datasets
+        .iter()
+        .enumerate()
+        .map(|(dataset_ix, dataset)| {
+            (dataset_ix > 0).then(|| {
+                (0..dataset.len())
+                    .filter(|&ix| !datasets[..dataset_ix].iter().any(|earlier| earlier.contains(&dataset[ix])))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// Selects distinct random triples, only from the triples at `candidate_ixs` if given
+pub fn random_distinct_triple_generator<'d>(
+    triples: &'d CompressedRdfTriples,
+    candidate_ixs: Option<&'d [usize]>,
     n_total_query_triples: usize,
-) -> impl FnMut(usize) -> Vec<CompressedTriple> + '_ {
-    let mut rng = rand::rngs::StdRng::from_entropy();
-    let mut ixs = rand::seq::index::sample(&mut rng, triples.len(), n_total_query_triples).into_vec();
+    seed: Option<u64>,
+) -> impl FnMut(usize) -> Vec<CompressedTriple> + 'd {
+    let mut rng = seeded_rng(seed);
+    let mut ixs = match candidate_ixs {
+        Some(candidate_ixs) => rand::seq::index::sample(&mut rng, candidate_ixs.len(), n_total_query_triples)
+            .into_iter()
+            .map(|ix| candidate_ixs[ix])
+            .collect(),
+        None => rand::seq::index::sample(&mut rng, triples.len(), n_total_query_triples).into_vec(),
+    };
     ixs.sort_unstable();
     let mut itr = ixs.into_iter();
 
@@ -27,11 +71,22 @@ pub fn random_distinct_triple_generator(
     }
 }
 
-pub fn random_triple_generator(triples: &CompressedRdfTriples) -> impl FnMut(usize) -> Vec<CompressedTriple> + '_ {
-    let mut rng = rand::rngs::StdRng::from_entropy();
+/// Selects random triples, only from the triples at `candidate_ixs` if given
+pub fn random_triple_generator<'d>(
+    triples: &'d CompressedRdfTriples,
+    candidate_ixs: Option<&'d [usize]>,
+    seed: Option<u64>,
+) -> impl FnMut(usize) -> Vec<CompressedTriple> + 'd {
+    let mut rng = seeded_rng(seed);
 
     move |size_hint: usize| {
-        let mut ixs = rand::seq::index::sample(&mut rng, triples.len(), size_hint).into_vec();
+        let mut ixs = match candidate_ixs {
+            Some(candidate_ixs) => rand::seq::index::sample(&mut rng, candidate_ixs.len(), size_hint)
+                .into_iter()
+                .map(|ix| candidate_ixs[ix])
+                .collect(),
+            None => rand::seq::index::sample(&mut rng, triples.len(), size_hint).into_vec(),
+        };
         ixs.sort_unstable();
 
         ixs.into_iter()
@@ -40,45 +95,731 @@ pub fn random_triple_generator(triples: &CompressedRdfTriples) -> impl FnMut(usi
     }
 }
 
-pub fn fixed_size_changeset_triple_generator<'a, 'c, 'd>(
-    changesets: &'c [CompressedRdfTriples],
-    dataset: &'d CompressedRdfTriples,
-) -> impl FnMut(usize) -> Box<dyn Iterator<Item = &'c [TripleElementId; 3]> + Send + 'a>
-where
-    'c: 'a,
-    'd: 'a,
-{
-    let start_off = rand::thread_rng().gen_range(0..changesets.len());
+/// Synthesizes triples that are not contained in `triples`, which must be sorted, by combining the subject,
+/// predicate and object of random triples, so that the compressor state knows all of their terms.
+/// Gives up on a query after 100 attempts per requested triple, e.g. if the dataset is close to complete.
+pub fn novel_triple_generator(
+    triples: &CompressedRdfTriples,
+    seed: Option<u64>,
+) -> impl FnMut(usize) -> Vec<CompressedTriple> + '_ {
+    const MAX_ATTEMPTS_PER_TRIPLE: usize = 100;
+
+    let mut rng = seeded_rng(seed);
 
     move |size_hint: usize| {
-        let itr = changesets[start_off..]
+        let mut buf = HashSet::with_capacity(size_hint);
+
+        if triples.is_empty() {
+            return Vec::new();
+        }
+
+        for _ in 0..size_hint * MAX_ATTEMPTS_PER_TRIPLE {
+            if buf.len() == size_hint {
+                break;
+            }
+
+            let mut pick = |pos: usize| triples[rng.gen_range(0..triples.len())][pos];
+            let triple = [pick(0), pick(1), pick(2)];
+
+            if !perf::timed(Phase::Containment, || triples.contains(&triple)) {
+                buf.insert(triple);
+            }
+        }
+
+        let mut buf: Vec<_> = buf.into_iter().collect();
+        buf.sort_unstable();
+        buf
+    }
+}
+
+/// Selects runs of triples sharing a subject from `triples`, which must be sorted, with between `min` and `max` triples
+/// per subject and query. The last run of a query may be shorter than `min` to fill the query exactly.
+/// With `distinct` no triple is selected twice, only the triples at `candidate_ixs` (sorted) are selected if given.
+/// Gives up on a query after 100 attempts per requested triple.
+pub fn subject_grouped_triple_generator<'d>(
+    triples: &'d CompressedRdfTriples,
+    (min, max): (usize, usize),
+    distinct: bool,
+    candidate_ixs: Option<&'d [usize]>,
+    seed: Option<u64>,
+) -> impl FnMut(usize) -> Vec<CompressedTriple> + 'd {
+    const MAX_ATTEMPTS_PER_TRIPLE: usize = 100;
+
+    let mut rng = seeded_rng(seed);
+    let mut used = HashSet::new();
+
+    move |size_hint: usize| {
+        let mut buf = Vec::with_capacity(size_hint);
+        let mut subjects = HashSet::new();
+
+        if triples.is_empty() {
+            return buf;
+        }
+
+        for _ in 0..size_hint * MAX_ATTEMPTS_PER_TRIPLE {
+            if buf.len() >= size_hint {
+                break;
+            }
+
+            let subject = triples[rng.gen_range(0..triples.len())][0];
+            if !subjects.insert(subject) {
+                continue;
+            }
+
+            let start = triples.partition_point(|triple| triple[0] < subject);
+            let end = triples.partition_point(|triple| triple[0] <= subject);
+            let available: Vec<usize> = (start..end)
+                .filter(|ix| !used.contains(ix))
+                .filter(|ix| match candidate_ixs {
+                    Some(candidate_ixs) => candidate_ixs.binary_search(ix).is_ok(),
+                    None => true,
+                })
+                .collect();
+
+            let n_missing = size_hint - buf.len();
+            if available.len() < min.min(n_missing) {
+                continue;
+            }
+
+            let n_take = rng.gen_range(min..=max).min(available.len()).min(n_missing);
+            let mut picked = rand::seq::index::sample(&mut rng, available.len(), n_take).into_vec();
+            picked.sort_unstable();
+
+            for ix in picked.into_iter().map(|ix| available[ix]) {
+                if distinct {
+                    used.insert(ix);
+                }
+
+                buf.push(triples[ix]);
+            }
+        }
+
+        buf
+    }
+}
+
+/// Reads the JSON object of changeset weights at `path` and returns the weight of every changeset in `changesets`.
+/// A key applies to all changesets whose path ends with it, unlisted changesets have weight 1.
+pub fn read_changeset_weights<P: AsRef<Path>>(path: P, changesets: &[PathBuf]) -> io::Result<Vec<f64>> {
+    let weights: BTreeMap<PathBuf, f64> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+    if let Some((key, weight)) = weights.iter().find(|(_, weight)| !weight.is_finite() || **weight < 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("weight {weight} of {key:?} is not a finite non-negative number"),
+        ));
+    }
+
+    for key in weights.keys() {
+        if !changesets.iter().any(|changeset| changeset.ends_with(key)) {
+            warn(Warning::SkippedFile {
+                path: key.clone(),
+                reason: "weight given for a path that matches no changeset".to_owned(),
+            });
+        }
+    }
+
+    Ok(changesets
+        .iter()
+        .map(|changeset| {
+            weights
+                .iter()
+                .find(|(key, _)| changeset.ends_with(key))
+                .map_or(1.0, |(_, &weight)| weight)
+        })
+        .collect())
+}
+
+/// Range of the triples of a changeset that was read for a query, `end` is exclusive
+#[derive(Clone, Copy)]
+pub struct ChangesetRange {
+    pub changeset_ix: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Changeset ranges read by the changeset generators per query, in the order the queries were generated
+#[derive(Default)]
+pub struct SelectionLog(RefCell<Vec<Vec<ChangesetRange>>>);
+
+impl SelectionLog {
+    fn record(&self, ranges: Vec<ChangesetRange>) {
+        self.0.borrow_mut().push(ranges);
+    }
+
+    /// Records a query that read no changesets, e.g. a randomized query of a mixed workload
+    pub fn record_none(&self) {
+        self.record(Vec::new());
+    }
+
+    pub fn into_inner(self) -> Vec<Vec<ChangesetRange>> {
+        self.0.into_inner()
+    }
+}
+
+/// Orders the indices of `weights` by weighted random sampling without replacement, leaving out zero weights
+fn weighted_order<R: Rng>(weights: &[f64], rng: &mut R) -> Vec<usize> {
+    let mut keys: Vec<_> = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, &weight)| weight > 0.0)
+        .map(|(ix, &weight)| (rng.gen::<f64>().powf(1.0 / weight), ix))
+        .collect();
+
+    keys.sort_unstable_by(|(a, _), (b, _)| b.total_cmp(a));
+    keys.into_iter().map(|(_, ix)| ix).collect()
+}
+
+/// Splits `n` into parts proportional to `weights` by largest remainder, so that the parts sum up to `n`
+pub fn split_by_weights(n: usize, weights: &[f64]) -> Vec<usize> {
+    let total: f64 = weights.iter().sum();
+    let exact: Vec<f64> = weights.iter().map(|weight| n as f64 * weight / total).collect();
+    let mut parts: Vec<usize> = exact.iter().map(|part| part.floor() as usize).collect();
+
+    let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+    by_remainder.sort_by(|&a, &b| (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor())));
+
+    let n_missing = n.saturating_sub(parts.iter().sum());
+    for ix in by_remainder.into_iter().cycle().take(n_missing) {
+        parts[ix] += 1;
+    }
+
+    parts
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Time of the changeset at `path` in seconds since the Unix epoch. Taken from a `<year>/<month>/<day>/<hour>` directory
+/// sequence in the path as in the DBpedia live changesets (trailing parts may be missing), otherwise the modification
+/// time of the file.
+pub fn changeset_timestamp(path: &Path) -> io::Result<u64> {
+    let parts: Vec<&str> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|component| component.as_os_str().to_str())
+        .collect();
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    if let Some(start) = parts.iter().position(|part| part.len() == 4 && is_number(part)) {
+        let fields: Vec<u32> = parts[start..]
             .iter()
-            .chain(changesets[..start_off].iter().rev())
-            .flat_map(|compressed_triples| compressed_triples.iter())
-            .filter(|triple| dataset.contains(triple))
-            .take(size_hint);
+            .take(4)
+            .take_while(|part| is_number(part))
+            .filter_map(|part| part.parse().ok())
+            .collect();
+        let field = |ix: usize, default: u32| fields.get(ix).copied().unwrap_or(default);
+        let (month, day, hour) = (field(1, 1), field(2, 1), field(3, 0));
+
+        if (1..=12).contains(&month) && (1..=31).contains(&day) && hour < 24 {
+            let secs = days_from_civil(fields[0] as i64, month, day) * 24 * 60 * 60 + hour as i64 * 60 * 60;
+            return Ok(secs.max(0) as u64);
+        }
+    }
+
+    Ok(std::fs::metadata(path)?
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs()))
+}
+
+/// Which of the stitched triples a fixed-size query keeps when the last changeset it reads has more than it needs
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangesetTruncation {
+    /// cut off the end, the next query continues with the cut off triples
+    #[default]
+    End,
+    /// read the last changeset fully and cut off the start of the stitched triples, dropping the surplus
+    Start,
+    /// read the last changeset fully and keep a uniform sample of the stitched triples in their order, dropping the rest
+    Uniform,
+}
+
+/// Takes triples contained in the dataset of `ctx` from its changesets, starting at a random changeset.
+/// With changeset weights the changesets are visited in a weighted random order instead.
+/// With changeset timestamps and a window length the changesets are visited in chronological order starting at a
+/// random window, and every query starts at a window and only stitches the following consecutive windows,
+/// it never wraps around from the last to the first one.
+/// Every query continues where the previous one stopped, so that queries do not share triples,
+/// unless overlap is allowed, which starts every query at the first changeset again.
+/// How a query is cut to its size in the last changeset it reads is chosen by the truncation of `ctx`.
+/// The read changeset ranges are recorded in the selection log if given.
+pub fn fixed_size_changeset_triple_generator<'d>(
+    ctx: &GeneratorContext<'d>,
+) -> impl FnMut(usize) -> Box<dyn Iterator<Item = &'d [TripleElementId; 3]> + Send + 'd> {
+    let GeneratorContext { changesets, dataset, allow_overlap, truncation, selection_log: log, seed, .. } = *ctx;
+    let weights = ctx.changeset_weights;
+    let time_windows = ctx.changeset_times.zip(ctx.window_secs);
+
+    let mut rng = seeded_rng(seed);
+
+    // positions in `order` at which a time window starts, and the position of the wrap around to the first window
+    let mut windows: Option<(Vec<usize>, usize)> = None;
+
+    let order: Vec<usize> = match (time_windows, weights) {
+        _ if changesets.is_empty() => Vec::new(),
+        (Some((times, window_len)), _) => {
+            let mut order: Vec<usize> = (0..changesets.len()).collect();
+            order.sort_by_key(|&ix| times[ix]);
+
+            let first_time = times[order[0]];
+            let window_of = |ix: usize| (times[ix] - first_time) / window_len;
+            let starts: Vec<usize> = (0..order.len())
+                .filter(|&pos| pos == 0 || window_of(order[pos]) != window_of(order[pos - 1]))
+                .collect();
+
+            let start_off = starts[rng.gen_range(0..starts.len())];
+            order.rotate_left(start_off);
+
+            let wrap = order.len() - start_off;
+            let mut starts: Vec<usize> = starts.into_iter().map(|pos| (pos + wrap) % order.len()).collect();
+            starts.sort_unstable();
+            windows = Some((starts, wrap));
 
-        Box::new(itr)
+            order
+        },
+        (None, Some(weights)) => weighted_order(weights, &mut rng),
+        (None, None) => {
+            let start_off = rng.gen_range(0..changesets.len());
+            (start_off..changesets.len()).chain((0..start_off).rev()).collect()
+        },
+    };
+
+    // position in `order` and in the changeset at that position
+    let mut cursor = (0, 0);
+
+    move |size_hint: usize| {
+        if allow_overlap {
+            cursor = (0, 0);
+        }
+
+        let mut triples = Vec::with_capacity(size_hint);
+        let mut ranges = Vec::new();
+        let mut range_start = cursor.1;
+
+        let end_range = |ranges: &mut Vec<_>, changeset_ix, start, end| {
+            if end > start {
+                ranges.push(ChangesetRange { changeset_ix, start, end });
+            }
+        };
+
+        // timed as a whole, the loop does little besides the containment checks
+        perf::timed(Phase::Containment, || {
+            while triples.len() < size_hint {
+                let (order_ix, triple_ix) = cursor;
+                let Some(&changeset_ix) = order.get(order_ix) else {
+                    break;
+                };
+
+                match changesets[changeset_ix].get(triple_ix) {
+                    Some(triple) => {
+                        cursor.1 += 1;
+
+                        if dataset.contains(triple) {
+                            triples.push(triple);
+                        }
+                    },
+                    None => {
+                        end_range(&mut ranges, changeset_ix, range_start, triple_ix);
+                        cursor = (order_ix + 1, 0);
+                        range_start = 0;
+
+                        if windows.as_ref().is_some_and(|&(_, wrap)| cursor.0 == wrap) {
+                            break;
+                        }
+                    },
+                }
+            }
+        });
+
+        if truncation != ChangesetTruncation::End && triples.len() == size_hint && cursor.1 > 0 {
+            // the rest of the changeset the query stopped in, so that the surplus is cut
+            if let Some(&changeset_ix) = order.get(cursor.0) {
+                let changeset = &changesets[changeset_ix];
+                perf::timed(Phase::Containment, || {
+                    triples.extend(changeset[cursor.1..].iter().filter(|triple| dataset.contains(triple)))
+                });
+
+                end_range(&mut ranges, changeset_ix, range_start, changeset.len());
+                cursor = (cursor.0 + 1, 0);
+                range_start = 0;
+            }
+
+            if truncation == ChangesetTruncation::Start {
+                triples.drain(..triples.len() - size_hint);
+            } else {
+                let mut keep = rand::seq::index::sample(&mut rng, triples.len(), size_hint).into_vec();
+                keep.sort_unstable();
+                triples = keep.into_iter().map(|ix| triples[ix]).collect();
+            }
+        }
+
+        if let Some(&changeset_ix) = order.get(cursor.0) {
+            end_range(&mut ranges, changeset_ix, range_start, cursor.1);
+        }
+
+        // the next query starts at the next window that this one did not read from
+        if let Some((starts, _)) = &windows {
+            if cursor.1 > 0 || starts.binary_search(&cursor.0).is_err() {
+                let next_start = starts.iter().copied().find(|&pos| pos > cursor.0);
+                cursor = (next_start.unwrap_or(order.len()), 0);
+            }
+        }
+
+        if let Some(log) = log {
+            log.record(ranges);
+        }
+
+        Box::new(triples.into_iter())
     }
 }
 
+/// Which of the changesets closest in size to a query as-is generation prefers
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChangesetPreference {
+    /// the changeset with the earliest timestamp
+    Oldest,
+    /// the changeset with the latest timestamp
+    Newest,
+    /// a random one, reproducible with the seed
+    RandomSeeded,
+}
+
+/// Uses a whole unused changeset per query, the one closest in size to the query.
+/// With `weights` the size difference is divided by the weight of the changeset.
+/// Changesets equally close are chosen by `prefer`, oldest and newest by the changeset `times`, and
+/// otherwise by their position in `changesets`, which are expected in path order.
+/// Once all changesets are used the queries stay empty.
+/// The used changesets are recorded in `log` if given.
 pub fn as_is_changeset_triple_generator<'c>(
     changesets: &'c [CompressedRdfTriples],
+    weights: Option<&'c [f64]>,
+    prefer: Option<ChangesetPreference>,
+    times: Option<&[u64]>,
+    seed: Option<u64>,
+    log: Option<&'c SelectionLog>,
 ) -> impl FnMut(usize) -> Box<dyn Iterator<Item = &'c [TripleElementId; 3]> + Send + 'c> {
     let mut used = HashSet::new();
+    let weight = move |ix: usize| weights.map_or(1.0, |weights| weights[ix]);
+
+    // the sorts are stable, so changesets of the same time stay in path order
+    let mut by_preference: Vec<usize> = (0..changesets.len()).collect();
+    match (prefer, times) {
+        (Some(ChangesetPreference::Oldest), Some(times)) => by_preference.sort_by_key(|&ix| times[ix]),
+        (Some(ChangesetPreference::Newest), Some(times)) => by_preference.sort_by_key(|&ix| Reverse(times[ix])),
+        (Some(ChangesetPreference::RandomSeeded), _) => by_preference.shuffle(&mut seeded_rng(seed)),
+        _ => (),
+    }
+
+    let mut rank = vec![0; changesets.len()];
+    for (r, &ix) in by_preference.iter().enumerate() {
+        rank[ix] = r;
+    }
 
     move |size_hint: usize| {
-        let (used_ix, changeset) = changesets
+        let closest = changesets
             .iter()
             .enumerate()
-            .filter(|(ix, _)| !used.contains(ix))
-            .min_by_key(|(_, triples)| triples.len().abs_diff(size_hint))
-            .expect("more than 0 changesets");
+            .filter(|&(ix, _)| !used.contains(&ix) && weight(ix) > 0.0)
+            .min_by(|(a_ix, a), (b_ix, b)| {
+                let a_cost = a.len().abs_diff(size_hint) as f64 / weight(*a_ix);
+                let b_cost = b.len().abs_diff(size_hint) as f64 / weight(*b_ix);
+                a_cost.total_cmp(&b_cost).then(rank[*a_ix].cmp(&rank[*b_ix]))
+            });
+
+        let Some((used_ix, changeset)) = closest else {
+            if let Some(log) = log {
+                log.record(Vec::new());
+            }
 
-        println!("using changeset: {used_ix}");
+            return Box::new(std::iter::empty()) as Box<dyn Iterator<Item = _> + Send>;
+        };
 
         used.insert(used_ix);
 
+        if let Some(log) = log {
+            log.record(vec![ChangesetRange {
+                changeset_ix: used_ix,
+                start: 0,
+                end: changeset.len(),
+            }]);
+        }
+
         Box::new(changeset.iter())
     }
 }
+
+/// A strategy selecting the triples of the queries, called once per query with the requested
+/// number of triples. Generators may return fewer triples if they run out.
+pub trait TripleGenerator<'a> {
+    fn generate(&mut self, size_hint: usize) -> Box<dyn Iterator<Item = CompressedTriple> + 'a>;
+}
+
+impl<'a, F, I> TripleGenerator<'a> for F
+where
+    F: FnMut(usize) -> I,
+    I: IntoIterator,
+    I::IntoIter: 'a,
+    I::Item: Borrow<CompressedTriple>,
+{
+    fn generate(&mut self, size_hint: usize) -> Box<dyn Iterator<Item = CompressedTriple> + 'a> {
+        Box::new(self(size_hint).into_iter().map(|triple| *triple.borrow()))
+    }
+}
+
+/// Inputs available to the generators created by a [`TripleGeneratorRegistry`],
+/// generators ignore the ones they do not use
+pub struct GeneratorContext<'d> {
+    pub dataset: &'d CompressedRdfTriples,
+    /// Indices of the dataset triples the random generators may select, all if `None`
+    pub candidate_ixs: Option<&'d [usize]>,
+    /// Number of triples of all queries together
+    pub n_total_query_triples: usize,
+    pub changesets: &'d [CompressedRdfTriples],
+    pub changeset_weights: Option<&'d [f64]>,
+    pub allow_overlap: bool,
+    /// Records the changeset ranges read by the changeset generators
+    pub selection_log: Option<&'d SelectionLog>,
+    /// Seed of the random generators, so that a run can be replayed
+    pub seed: Option<u64>,
+    /// Minimum and maximum number of triples per subject and query of the subject grouped generators
+    pub triples_per_subject: (usize, usize),
+    /// Timestamps of the changesets
+    pub changeset_times: Option<&'d [u64]>,
+    /// Length in seconds of the time windows fixed-size queries are stitched from, requires `changeset_times`
+    pub window_secs: Option<u64>,
+    /// Tie break between changesets equally close in size of the as-is generator
+    pub changeset_preference: Option<ChangesetPreference>,
+    /// How the fixed-size generator cuts a query to its size
+    pub truncation: ChangesetTruncation,
+}
+
+impl<'d> GeneratorContext<'d> {
+    pub fn new(dataset: &'d CompressedRdfTriples) -> Self {
+        GeneratorContext {
+            dataset,
+            candidate_ixs: None,
+            n_total_query_triples: 0,
+            changesets: &[],
+            changeset_weights: None,
+            allow_overlap: false,
+            selection_log: None,
+            seed: None,
+            triples_per_subject: (1, usize::MAX),
+            changeset_times: None,
+            window_secs: None,
+            changeset_preference: None,
+            truncation: ChangesetTruncation::End,
+        }
+    }
+}
+
+pub type GeneratorConstructor = for<'d> fn(&GeneratorContext<'d>) -> Box<dyn TripleGenerator<'d> + 'd>;
+
+/// Triple generators by name, [`Default`] contains the built-in generators
+pub struct TripleGeneratorRegistry {
+    constructors: BTreeMap<&'static str, GeneratorConstructor>,
+}
+
+impl Default for TripleGeneratorRegistry {
+    fn default() -> Self {
+        let mut registry = TripleGeneratorRegistry { constructors: BTreeMap::new() };
+
+        registry.register("random", |ctx| {
+            Box::new(random_triple_generator(ctx.dataset, ctx.candidate_ixs, ctx.seed))
+        });
+        registry.register("random-distinct", |ctx| {
+            Box::new(random_distinct_triple_generator(
+                ctx.dataset,
+                ctx.candidate_ixs,
+                ctx.n_total_query_triples,
+                ctx.seed,
+            ))
+        });
+        registry.register("subject-grouped", |ctx| {
+            Box::new(subject_grouped_triple_generator(
+                ctx.dataset,
+                ctx.triples_per_subject,
+                false,
+                None,
+                ctx.seed,
+            ))
+        });
+        registry.register("subject-grouped-distinct", |ctx| {
+            Box::new(subject_grouped_triple_generator(
+                ctx.dataset,
+                ctx.triples_per_subject,
+                true,
+                ctx.candidate_ixs,
+                ctx.seed,
+            ))
+        });
+        registry.register("novel", |ctx| Box::new(novel_triple_generator(ctx.dataset, ctx.seed)));
+        registry.register("changeset-as-is", |ctx| {
+            Box::new(as_is_changeset_triple_generator(
+                ctx.changesets,
+                ctx.changeset_weights,
+                ctx.changeset_preference,
+                ctx.changeset_times,
+                ctx.seed,
+                ctx.selection_log,
+            ))
+        });
+        registry.register("changeset-fixed-size", |ctx| {
+            Box::new(fixed_size_changeset_triple_generator(ctx))
+        });
+
+        registry
+    }
+}
+
+impl TripleGeneratorRegistry {
+    /// Adds a generator, replacing any generator already registered under `name`
+    pub fn register(&mut self, name: &'static str, constructor: GeneratorConstructor) {
+        self.constructors.insert(name, constructor);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.constructors.keys().copied()
+    }
+
+    pub fn create<'d>(
+        &self,
+        name: &str,
+        ctx: &GeneratorContext<'d>,
+    ) -> Result<Box<dyn TripleGenerator<'d> + 'd>, String> {
+        match self.constructors.get(name) {
+            Some(constructor) => Ok(constructor(ctx)),
+            None => Err(format!(
+                "unknown triple generator {name:?}, available are: {}",
+                self.names().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disjoint_candidates_skip_triples_of_earlier_datasets() {
+        let first = CompressedRdfTriples::from_triples(vec![[1, 1, 1], [2, 2, 2]]);
+        let second = CompressedRdfTriples::from_triples(vec![[2, 2, 2], [3, 3, 3]]);
+        let third = CompressedRdfTriples::from_triples(vec![[3, 3, 3], [1, 1, 1], [4, 4, 4]]);
+
+        assert_eq!(
+            disjoint_candidate_indices(&[&first, &second, &third]),
+            vec![None, Some(vec![1]), Some(vec![2])]
+        );
+    }
+
+    #[test]
+    fn random_triples_come_from_the_candidates() {
+        let triples = CompressedRdfTriples::from_triples(vec![[1, 1, 1], [2, 2, 2], [3, 3, 3]]);
+        let mut generator = random_triple_generator(&triples, Some(&[0, 2]), Some(7));
+
+        for _ in 0..10 {
+            assert_eq!(generator(2), vec![[1, 1, 1], [3, 3, 3]]);
+        }
+    }
+
+    fn subject_runs(query: &[CompressedTriple]) -> Vec<usize> {
+        query.chunk_by(|a, b| a[0] == b[0]).map(<[_]>::len).collect()
+    }
+
+    #[test]
+    fn subject_groups_respect_the_run_lengths() {
+        let triples =
+            CompressedRdfTriples::from_triples((1..=10).flat_map(|s| (0..5).map(move |p| [s, p, 0])).collect());
+        let mut generator = subject_grouped_triple_generator(&triples, (2, 3), true, None, Some(3));
+
+        let mut selected = HashSet::new();
+        for query_ix in 0..4 {
+            let query = generator(10);
+            if query_ix == 0 {
+                assert_eq!(query.len(), 10);
+            }
+
+            let runs = subject_runs(&query);
+            let (last, rest) = runs.split_last().unwrap();
+            assert!(rest.iter().all(|run| (2..=3).contains(run)), "{runs:?}");
+            assert!(*last <= 3);
+
+            // every subject is picked once per query and no triple twice over all queries
+            assert_eq!(
+                query.iter().map(|triple| triple[0]).collect::<HashSet<_>>().len(),
+                runs.len()
+            );
+            assert!(query.iter().all(|&triple| selected.insert(triple)));
+        }
+    }
+
+    #[test]
+    fn subject_groups_come_from_the_candidates() {
+        let triples =
+            CompressedRdfTriples::from_triples((1..=4).flat_map(|s| (0..4).map(move |p| [s, p, 0])).collect());
+        let candidate_ixs: Vec<usize> = (0..triples.len()).filter(|ix| ix % 2 == 0).collect();
+        let mut generator = subject_grouped_triple_generator(&triples, (1, 2), false, Some(&candidate_ixs), Some(5));
+
+        for _ in 0..10 {
+            let query = generator(4);
+            assert_eq!(query.len(), 4);
+            assert!(query.iter().all(|triple| triple[1] % 2 == 0));
+        }
+    }
+
+    #[test]
+    fn as_is_queries_stay_empty_once_all_changesets_are_used() {
+        let changesets = [CompressedRdfTriples::from_triples(vec![[1, 2, 3], [4, 5, 6]])];
+        let log = SelectionLog::default();
+        let mut generator = as_is_changeset_triple_generator(&changesets, None, None, None, None, Some(&log));
+
+        assert_eq!(generator(2).count(), 2);
+        assert_eq!(generator(2).count(), 0);
+        drop(generator);
+
+        let ranges = log.into_inner();
+        assert_eq!(ranges.len(), 2);
+        assert!(ranges[1].is_empty());
+    }
+
+    #[test]
+    fn truncated_queries_keep_their_size_and_order() {
+        let changesets = [
+            CompressedRdfTriples::from_triples(vec![[1, 1, 1], [2, 2, 2]]),
+            CompressedRdfTriples::from_triples(vec![[3, 3, 3], [4, 4, 4], [5, 5, 5]]),
+        ];
+        let dataset = CompressedRdfTriples::from_triples((1..=5).map(|x| [x, x, x]).collect());
+
+        for truncation in [ChangesetTruncation::Start, ChangesetTruncation::Uniform] {
+            for seed in 0..10 {
+                let ctx = GeneratorContext {
+                    changesets: &changesets,
+                    allow_overlap: true,
+                    truncation,
+                    seed: Some(seed),
+                    ..GeneratorContext::new(&dataset)
+                };
+                let mut generator = fixed_size_changeset_triple_generator(&ctx);
+
+                // starting at the first changeset reads 1 to 5, starting at the second one only reads 3 to 5
+                let query: Vec<_> = generator(3).map(|triple| triple[0]).collect();
+                assert_eq!(query.len(), 3);
+                assert!(query.windows(2).all(|pair| pair[0] < pair[1]));
+
+                if truncation == ChangesetTruncation::Start {
+                    assert_eq!(query, vec![3, 4, 5]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Streaming of datasets from HTTP(S) URLs and S3 object storage, so that dumps larger than
+//! the local disk can be compressed without a local copy
+
+use std::{
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+const REMOTE_SCHEMES: [&str; 3] = ["http://", "https://", "s3://"];
+
+/// Returns whether `path` is a URL with a supported scheme instead of a local path
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| REMOTE_SCHEMES.iter().any(|scheme| path.starts_with(scheme)))
+}
+
+/// Translates `s3://bucket/key` into an HTTPS URL. Uses the endpoint in `AWS_ENDPOINT_URL` with path style
+/// addressing if set, otherwise the virtual hosted style URL on AWS.
+fn s3_to_https(url: &str) -> io::Result<String> {
+    let (bucket, key) = url.strip_prefix("s3://").and_then(|rest| rest.split_once('/')).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{url} is not of the form s3://bucket/key"),
+        )
+    })?;
+
+    Ok(match std::env::var("AWS_ENDPOINT_URL") {
+        Ok(endpoint) => format!("{}/{bucket}/{key}", endpoint.trim_end_matches('/')),
+        Err(_) => format!("https://{bucket}.s3.amazonaws.com/{key}"),
+    })
+}
+
+/// The path of `url` without its query and fragment
+fn url_path(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or_default()
+}
+
+/// Opens a streaming reader over the body of the object at `url`, gzipped objects (`.gz`) are decompressed.
+/// S3 requests are not signed, so S3 objects must be publicly readable; use a presigned HTTPS URL for private buckets.
+pub fn open(url: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let url = url
+        .to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{url:?} is not valid UTF-8")))?;
+
+    let url = if url.starts_with("s3://") {
+        s3_to_https(url)?
+    } else {
+        url.to_owned()
+    };
+
+    let response = ureq::get(&url)
+        .call()
+        .map_err(|e| io::Error::other(format!("unable to fetch {url}: {e}")))?;
+
+    let body = response.into_reader();
+
+    if url_path(&url).ends_with(".gz") {
+        Ok(Box::new(flate2::read::MultiGzDecoder::new(body)))
+    } else {
+        Ok(body)
+    }
+}
+
+/// Local path for the output derived from the remote `url`, the file name of the URL in the current directory
+/// without a `.gz` extension, as the object is decompressed when it is read
+pub fn local_path(url: &Path) -> PathBuf {
+    let path = url_path(url.to_str().unwrap_or_default());
+    let path = path.strip_suffix(".gz").unwrap_or(path);
+    PathBuf::from(Path::new(path).file_name().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_paths_leave_out_the_query_and_gzip_extension() {
+        assert_eq!(
+            local_path(Path::new("https://example.org/dumps/dump.nt")),
+            Path::new("dump.nt")
+        );
+        assert_eq!(
+            local_path(Path::new("https://example.org/dump.nt.gz?version=2#x")),
+            Path::new("dump.nt")
+        );
+        assert_eq!(
+            local_path(Path::new("s3://bucket/dumps/dump.nt.gz")),
+            Path::new("dump.nt")
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Round trip of an N-Triples file through compression and decompression, to find the triples that do not
+//! survive the compressed pipeline before committing to it
+
+use crate::{
+    rdf::{
+        ntriples_tokenizer::tokenize_line,
+        triple_compressor::{
+            compressor::{term_id, CompressionStats, RdfTripleCompressor},
+            decompressor::RdfTripleDecompressor,
+            CompressedRdfTriples, CompressedTriple, RawTriple,
+        },
+    },
+    util,
+};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Differences between the distinct triples of a file and of its compressed and decompressed copy
+pub struct RoundtripReport {
+    pub compression: CompressionStats,
+    pub n_original: usize,
+    pub n_roundtrip: usize,
+    /// Original triples with a blank node, compression drops them
+    pub n_lost_blank_nodes: usize,
+    /// Other original triples missing after the round trip, e.g. literals normalized by parsing
+    pub lost: Vec<Vec<u8>>,
+    /// Triples after the round trip that are not in the original file
+    pub added: Vec<Vec<u8>>,
+}
+
+/// Joins the terms of `triple` by a single space, so that differences in whitespace are not reported
+fn canonicalize([subject, predicate, object]: RawTriple) -> Vec<u8> {
+    [subject, predicate, object].join(&b' ')
+}
+
+/// Ids of the terms of `triple` as their bytes are, so that equal ids mean equal terms
+fn exact_ids(triple: RawTriple) -> CompressedTriple {
+    triple.map(term_id)
+}
+
+fn sorted_distinct<T: Ord>(mut triples: Vec<T>) -> Vec<T> {
+    triples.sort_unstable();
+    triples.dedup();
+    triples
+}
+
+/// Path of a file in the temporary directory for an intermediate result of the round trip of the input at `path`
+fn roundtrip_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = OsString::from(format!("sudg-roundtrip-{}-", std::process::id()));
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(suffix);
+    std::env::temp_dir().join(file_name)
+}
+
+/// Calls `f` with every triple of the N-Triples file at `path`, malformed lines are skipped
+fn for_each_triple(path: &Path, mut f: impl FnMut(RawTriple)) -> io::Result<()> {
+    for line in BufReader::new(File::open(path)?).split(b'\n') {
+        if let Ok(Some(triple)) = tokenize_line(&line?) {
+            f(triple);
+        }
+    }
+
+    Ok(())
+}
+
+/// Compresses the N-Triples file at `path` with a new compressor state, decompresses it again and compares both.
+/// The intermediate files are written to the temporary directory and removed afterwards, malformed lines are not
+/// compared. Triples are compared by the ids of their exact terms, only the differing ones are kept as text.
+pub fn verify_roundtrip(path: &Path, parse: bool) -> io::Result<RoundtripReport> {
+    let compressed_path = roundtrip_path(path, ".compressed_nt");
+    let state_path = roundtrip_path(path, ".compressor_state");
+
+    let roundtrip = (|| -> io::Result<(CompressionStats, RdfTripleDecompressor, Vec<CompressedTriple>)> {
+        let mut compressor = RdfTripleCompressor::new();
+        let compression = compressor.compress_rdf_triple_file_to(path, &compressed_path, false, parse)?;
+        compressor.save_state(&state_path)?;
+        drop(compressor);
+
+        let decompressor = RdfTripleDecompressor::read_state_into_memory(&state_path)?;
+        let compressed = unsafe { CompressedRdfTriples::load(&compressed_path)? };
+
+        // the decompressed triples and the original ones, with whether they have a blank node
+        let n_original = compression.n_triples
+            + compression.n_duplicates
+            + compression.n_filtered
+            + compression.n_blank_nodes
+            + compression.n_filtered_literals;
+        util::check_memory(
+            (compressed.len() * std::mem::size_of::<CompressedTriple>()
+                + n_original * std::mem::size_of::<(CompressedTriple, bool)>()) as u64,
+            "comparing the triples of the round trip",
+        )?;
+
+        let triples = compressed
+            .iter()
+            .map(|&triple| decompressor.decompress_known_rdf_triple(triple).map(exact_ids))
+            .collect::<io::Result<_>>()?;
+
+        Ok((compression, decompressor, triples))
+    })();
+
+    // a failed round trip is reported rather than a failure to clean up after it
+    let mut cleanup = Ok(());
+    for intermediate in [&compressed_path, &state_path] {
+        match std::fs::remove_file(intermediate) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => cleanup = cleanup.and(Err(e)),
+            _ => (),
+        }
+    }
+
+    let (compression, decompressor, roundtrip) = roundtrip?;
+    cleanup?;
+    let roundtrip = sorted_distinct(roundtrip);
+
+    let mut original = Vec::new();
+    for_each_triple(path, |triple @ [subject, _, object]| {
+        let has_blank_node = subject.starts_with(b"_:") || object.starts_with(b"_:");
+        original.push((exact_ids(triple), has_blank_node));
+    })?;
+    let original = sorted_distinct(original);
+
+    let mut report = RoundtripReport {
+        compression,
+        n_original: original.len(),
+        n_roundtrip: roundtrip.len(),
+        n_lost_blank_nodes: 0,
+        lost: Vec::new(),
+        added: Vec::new(),
+    };
+
+    let mut lost = Vec::new();
+    let mut added = Vec::new();
+    let mut roundtrip = roundtrip.into_iter().peekable();
+
+    for (triple, has_blank_node) in original {
+        while let Some(triple) = roundtrip.next_if(|added| *added < triple) {
+            added.push(triple);
+        }
+
+        if roundtrip.next_if_eq(&triple).is_some() {
+            continue;
+        }
+
+        if has_blank_node {
+            report.n_lost_blank_nodes += 1;
+        } else {
+            lost.push(triple);
+        }
+    }
+    added.extend(roundtrip);
+
+    // the ids of an added triple are the ids of its terms in the state, the lost triples are read again
+    for triple in added {
+        report
+            .added
+            .push(canonicalize(decompressor.decompress_known_rdf_triple(triple)?));
+    }
+
+    if !lost.is_empty() {
+        for_each_triple(path, |triple| {
+            if lost.binary_search(&exact_ids(triple)).is_ok() {
+                report.lost.push(canonicalize(triple));
+            }
+        })?;
+        report.lost = sorted_distinct(report.lost);
+    }
+
+    report.added.sort_unstable();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_blank_node_triples_are_lost_without_parsing() {
+        let path = std::env::temp_dir().join(format!("sudg-roundtrip-input-{}.nt", std::process::id()));
+        std::fs::write(
+            &path,
+            "<http://a> <http://p> \"x\"@en .\n<http://a>  <http://p> <http://b> .\n_:b <http://p> <http://b> .\n\
+             <http://a> <http://p> <http://b> .\nnot a triple\n",
+        )
+        .unwrap();
+
+        let report = verify_roundtrip(&path, false).unwrap();
+        assert_eq!(report.n_original, 3);
+        assert_eq!(report.n_roundtrip, 2);
+        assert_eq!(report.n_lost_blank_nodes, 1);
+        assert!(report.lost.is_empty());
+        assert!(report.added.is_empty());
+
+        assert!(!roundtrip_path(&path, ".compressed_nt").exists());
+        assert!(!roundtrip_path(&path, ".compressor_state").exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! Random samples of a compressed dataset, to build miniature datasets that a workload can be tried out on
+
+use crate::rdf::triple_compressor::{CompressedTriple, TripleElementId};
+use clap::ArgEnum;
+use rand::Rng;
+use std::{collections::HashMap, hash::BuildHasherDefault};
+
+/// How the triples of a sample are drawn
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stratification {
+    /// draw the triples uniformly
+    None,
+    /// draw from every predicate in proportion to its triples, so that the sample keeps the predicate distribution
+    Predicate,
+}
+
+type PredicateMap<T> = HashMap<TripleElementId, T, BuildHasherDefault<ahash::AHasher>>;
+
+/// Splits `n_samples` over the predicates in proportion to `counts` by largest remainder,
+/// predicates with fewer than `n_triples / n_samples` triples may get none
+fn predicate_quotas(counts: &PredicateMap<usize>, n_triples: usize, n_samples: usize) -> PredicateMap<usize> {
+    let mut quotas: Vec<_> = counts
+        .iter()
+        .map(|(&predicate, &count)| {
+            let exact = count as u128 * n_samples as u128;
+            let quota = (exact / n_triples as u128) as usize;
+            (predicate, quota, exact % n_triples as u128)
+        })
+        .collect();
+
+    let n_remaining = n_samples - quotas.iter().map(|&(_, quota, _)| quota).sum::<usize>();
+
+    // ties by predicate, so that the quotas do not depend on the iteration order of the map
+    quotas.sort_unstable_by(|(a, _, a_rem), (b, _, b_rem)| b_rem.cmp(a_rem).then(a.cmp(b)));
+
+    quotas
+        .into_iter()
+        .enumerate()
+        .map(|(ix, (predicate, quota, _))| (predicate, quota + usize::from(ix < n_remaining)))
+        .collect()
+}
+
+/// Draws `n_samples` of the triples of `dataset` without replacement, all of them if it has fewer.
+/// The sample is sorted.
+pub fn sample_dataset(
+    dataset: &[CompressedTriple],
+    n_samples: usize,
+    stratification: Stratification,
+    rng: &mut impl Rng,
+) -> Vec<CompressedTriple> {
+    let n_samples = n_samples.min(dataset.len());
+
+    let mut sample: Vec<_> = match stratification {
+        Stratification::None => rand::seq::index::sample(rng, dataset.len(), n_samples)
+            .into_iter()
+            .map(|ix| dataset[ix])
+            .collect(),
+        Stratification::Predicate => {
+            let mut counts = PredicateMap::default();
+            for &[_, predicate, _] in dataset {
+                *counts.entry(predicate).or_default() += 1;
+            }
+
+            let quotas = predicate_quotas(&counts, dataset.len(), n_samples);
+
+            // a reservoir per predicate, so that the dataset is only read twice and not grouped by predicate
+            let mut reservoirs: PredicateMap<(usize, Vec<CompressedTriple>)> = PredicateMap::default();
+            for &triple @ [_, predicate, _] in dataset {
+                let quota = quotas[&predicate];
+                let (n_seen, reservoir) = reservoirs.entry(predicate).or_insert_with(|| (0, Vec::with_capacity(quota)));
+                *n_seen += 1;
+
+                if reservoir.len() < quota {
+                    reservoir.push(triple);
+                } else {
+                    let ix = rng.gen_range(0..*n_seen);
+                    if ix < quota {
+                        reservoir[ix] = triple;
+                    }
+                }
+            }
+
+            reservoirs.into_values().flat_map(|(_, reservoir)| reservoir).collect()
+        },
+    };
+
+    sample.sort_unstable();
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotas_sum_to_the_number_of_samples() {
+        let counts: PredicateMap<usize> = [(1, 5), (2, 3), (3, 1), (4, 1)].into_iter().collect();
+
+        for n_samples in 0..=10 {
+            let quotas = predicate_quotas(&counts, 10, n_samples);
+            assert_eq!(quotas.values().sum::<usize>(), n_samples);
+            assert!(quotas.iter().all(|(predicate, &quota)| quota <= counts[predicate]));
+        }
+
+        let quotas = predicate_quotas(&counts, 10, 5);
+        assert_eq!((quotas[&1], quotas[&2]), (3, 2));
+    }
+
+    #[test]
+    fn stratified_samples_keep_the_predicate_shares() {
+        let dataset: Vec<CompressedTriple> = (0..100).map(|ix| [ix, if ix < 80 { 1 } else { 2 }, ix]).collect();
+        let sample = sample_dataset(
+            &dataset,
+            10,
+            Stratification::Predicate,
+            &mut crate::util::seeded_rng(Some(1)),
+        );
+
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample.iter().filter(|[_, predicate, _]| *predicate == 1).count(), 8);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,61 @@
+//! User scripts selecting the triples of the generated queries, so that one-off experiment logic
+//! (e.g. only triples about a certain subject) does not require recompiling.
+//! Scripts are written in [rhai](https://rhai.rs) and require the `scripting` feature.
+
+use crate::rdf::triple_compressor::{decompressor::RdfTripleDecompressor, CompressedTriple};
+use std::{io, path::Path};
+
+/// A loaded selector script, it must define `fn select(subject, predicate, object)` returning whether
+/// a triple is kept. Terms are passed as strings in N-Triples syntax, e.g. `"<http://dbpedia.org/resource/Berlin>"`.
+pub struct TripleSelector {
+    #[cfg(feature = "scripting")]
+    engine: rhai::Engine,
+    #[cfg(feature = "scripting")]
+    ast: rhai::AST,
+}
+
+impl TripleSelector {
+    #[cfg(feature = "scripting")]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("unable to compile {path:?}: {e}")))?;
+
+        Ok(TripleSelector { engine, ast })
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn load(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "selector scripts require the scripting feature",
+        ))
+    }
+
+    /// Runs the script on `triple`. Triples with terms missing from `decompressor` are kept,
+    /// they are handled by the missing term policy when writing.
+    /// Fails if the script fails, e.g. because `select` is not defined or does not return a bool
+    #[cfg(feature = "scripting")]
+    pub fn is_selected(&self, decompressor: &RdfTripleDecompressor, triple: CompressedTriple) -> io::Result<bool> {
+        let Some(terms) = decompressor.decompress_rdf_triple(triple)? else {
+            return Ok(true);
+        };
+
+        let [subject, predicate, object] = terms.map(|term| String::from_utf8_lossy(term).into_owned());
+
+        self.engine
+            .call_fn::<bool>(
+                &mut rhai::Scope::new(),
+                &self.ast,
+                "select",
+                (subject, predicate, object),
+            )
+            .map_err(|e| io::Error::other(format!("selector script failed: {e}")))
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn is_selected(&self, _decompressor: &RdfTripleDecompressor, _triple: CompressedTriple) -> io::Result<bool> {
+        unreachable!("selector scripts cannot be loaded without the scripting feature")
+    }
+}
@@ -1,18 +1,33 @@
 use crate::{
-    rdf::triple_compressor::{decompressor::RdfTripleDecompressor, RawTriple},
-    CompressedRdfTriples, OutputFormat,
+    manifest::{Checkpoint, CutOff, CutOffReason, Manifest, PartialFile, QueryRecord},
+    perf::{self, Phase},
+    rdf::{
+        term_escaping::{escape_non_ascii_into, escape_term_into},
+        triple_compressor::{
+            decompressor::{DecompressionCache, RdfTripleDecompressor},
+            save_compressed_triples, CompressedRdfTriples, CompressedTriple, ContainmentIndex, RawTriple,
+        },
+    },
+    template::QueryTemplate,
+    util::{commit_buffered, seeded_rng, stream_seed, AtomicFile, OUTPUT_ORDER_STREAM},
+    warnings::{warn, Warning},
 };
 use clap::ArgEnum;
 use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 use std::{
     borrow::Borrow,
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fmt,
     fs::File,
-    hash::Hash,
+    hash::{BuildHasherDefault, Hash},
     io,
-    io::{BufWriter, Write},
-    path::Path,
+    io::{BufRead, BufReader, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
-use crate::rdf::triple_compressor::CompressedTriple;
 
 #[derive(Copy, Clone, ArgEnum)]
 pub enum OutputOrder {
@@ -21,21 +36,666 @@ pub enum OutputOrder {
     SortedSizeAsc,
     SortedSizeDesc,
     SortedSizeAscAlternateInsertDelete,
+    AlternateInsertDelete,
+    /// As specified, but deletes are moved behind the inserts that add their triples, so that no delete is a no-op
+    InsertsBeforeMatchingDeletes,
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Query,
+    NTriples,
+    /// sorted compressed triples without duplicates, only supported for the prepare output
+    Compressed,
+}
+
+/// How the source dataset of every triple is recorded in N-Triples output
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvenanceFormat {
+    /// N-Quads with the `file://` IRI of the source dataset as graph
+    NQuads,
+    /// Tab separated subject, predicate, object and the modification time of the source dataset
+    /// in seconds since the Unix epoch
+    Tsv,
+}
+
+/// Source of replicated triples
+#[derive(Clone)]
+pub struct Provenance {
+    /// Graph IRI without angle brackets
+    pub graph: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl Provenance {
+    /// Provenance of the triples of the dataset at `path`, its `file://` IRI and modification time
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut graph = "file://".to_owned();
+        for b in std::fs::canonicalize(path)?.to_string_lossy().bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => graph.push(b as char),
+                b => graph.push_str(&format!("%{b:02X}")),
+            }
+        }
+
+        let timestamp = std::fs::metadata(path)?
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+
+        Ok(Provenance { graph, timestamp })
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum QueryType {
     InsertData,
     DeleteData,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct QuerySpec {
     pub n_queries: usize,
     pub n_triples_per_query: usize,
+    /// How many of the triples of every query are synthesized novel triples instead of selected from the dataset
+    pub n_novel_per_query: usize,
     pub query_type: QueryType,
+    /// Named graph the queries target, the default graph if `None`
+    pub graph: Option<Arc<str>>,
+    /// Whether the queries belong to the warm-up section at the top of the output
+    pub warmup: bool,
+    /// Part of the workload of `generate mix` the queries are generated by, 0 outside of mixes
+    pub mix_part: usize,
+}
+
+impl QuerySpec {
+    /// Number of triples of all queries of the spec that are selected from the dataset
+    pub fn n_selected_triples(&self) -> usize {
+        self.n_queries * (self.n_triples_per_query - self.n_novel_per_query)
+    }
+}
+
+/// Approximate size of what a generation run would write to a single output file, used for dry runs
+#[derive(Clone, Copy, Default)]
+pub struct OutputPlan {
+    pub n_queries: usize,
+    pub n_triples: usize,
+    pub n_bytes: f64,
+}
+
+impl OutputPlan {
+    /// Account for `n_queries` queries of `n_triples_per_query` triples each written in `format`
+    pub fn add_queries(
+        &mut self,
+        n_queries: usize,
+        n_triples_per_query: usize,
+        avg_term_len: f64,
+        format: OutputFormat,
+    ) {
+        // two spaces between the terms and " . " or " .\n" after the object
+        const TRIPLE_OVERHEAD: usize = 2 + b" . ".len();
+
+        let triple_bytes = 3.0 * avg_term_len + TRIPLE_OVERHEAD as f64;
+        self.add_queries_of_triple_len(n_queries, n_triples_per_query, triple_bytes, format);
+    }
+
+    /// Like [`OutputPlan::add_queries`] but with the average length of a serialized triple including its terminator
+    pub fn add_queries_of_triple_len(
+        &mut self,
+        n_queries: usize,
+        n_triples_per_query: usize,
+        triple_bytes: f64,
+        format: OutputFormat,
+    ) {
+        // "DELETE DATA { " and "INSERT DATA { " have the same length
+        const QUERY_OVERHEAD: usize = b"DELETE DATA { ".len() + b"}\n".len();
+
+        let triple_bytes = match format {
+            OutputFormat::Compressed => std::mem::size_of::<CompressedTriple>() as f64,
+            OutputFormat::Query | OutputFormat::NTriples => triple_bytes,
+        };
+
+        self.n_queries += n_queries;
+        self.n_triples += n_queries * n_triples_per_query;
+        self.n_bytes += (n_queries * n_triples_per_query) as f64 * triple_bytes;
+
+        if format == OutputFormat::Query {
+            self.n_bytes += (n_queries * QUERY_OVERHEAD) as f64;
+        }
+    }
+}
+
+impl fmt::Display for OutputPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} queries, {} triples, ~{:.2} MiB",
+            self.n_queries,
+            self.n_triples,
+            self.n_bytes / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// How to handle triples containing ids that are missing from the compressor state,
+/// which happens if a dataset was compressed with a different state
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum MissingTermPolicy {
+    /// Abort the run, reporting the offending triple
+    Abort,
+    /// Leave the triple out of the output and report the number of skipped triples
+    Skip,
+}
+
+fn missing_term_error(query_ix: usize, triple: CompressedTriple) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("triple {triple:?} of query {query_ix} contains ids missing from the compressor state"),
+    )
+}
+
+/// How terms are written into SPARQL queries
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TermEscaping {
+    /// Write the terms exactly as stored
+    Verbatim,
+    /// Escape line breaks, decode `\u` escapes and percent encode characters not allowed in IRIs,
+    /// so that terms captured without parsing cannot break the queries
+    Escape,
 }
 
+/// Line terminator of the written queries and N-Triples
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
+pub const DEFAULT_WRITE_BUFFER_CAPACITY: usize = 1 << 20;
+
+/// Settings for writing the output files
+#[derive(Clone)]
+pub struct WriteOpts {
+    /// Append to existing output files instead of overwriting them
+    pub append: bool,
+    /// Capacity in bytes of the output buffers
+    pub buffer_capacity: usize,
+    /// How to handle triples whose ids are missing from the compressor state
+    pub missing_terms: MissingTermPolicy,
+    /// How terms are written into SPARQL queries
+    pub term_escaping: TermEscaping,
+    /// Append even if the output files were written with different settings
+    pub force: bool,
+    /// Stop generating before the next query once the query file has reached this size in bytes
+    pub max_output_bytes: Option<u64>,
+    /// Stop generating before the next query once this many triples were written
+    pub max_total_triples: Option<usize>,
+    pub line_ending: LineEnding,
+    /// Write non-ASCII characters as `\u` escapes
+    pub ascii_escape: bool,
+    /// Record the source of every triple in N-Triples output, the queries need to carry a [`Provenance`]
+    pub provenance: Option<ProvenanceFormat>,
+    /// Write a checkpoint of generated query files every this many queries
+    pub checkpoint_every: Option<usize>,
+    /// Continue the interrupted run recorded in the checkpoint of the query file instead of starting over
+    pub resume: bool,
+    /// Seed of the run, shuffles randomized output orders and is recorded in checkpoints
+    pub seed: Option<u64>,
+    /// Additionally write the insert and the delete queries to separate files, see [`split_path`]
+    pub split_by_type: bool,
+    /// Template the SPARQL queries are written with instead of plain `INSERT DATA`/`DELETE DATA` blocks
+    pub template: Option<Arc<QueryTemplate>>,
+}
+
+impl Default for WriteOpts {
+    fn default() -> Self {
+        WriteOpts {
+            append: false,
+            buffer_capacity: DEFAULT_WRITE_BUFFER_CAPACITY,
+            missing_terms: MissingTermPolicy::Abort,
+            term_escaping: TermEscaping::Escape,
+            force: false,
+            max_output_bytes: None,
+            max_total_triples: None,
+            line_ending: LineEnding::Lf,
+            ascii_escape: false,
+            provenance: None,
+            checkpoint_every: None,
+            resume: false,
+            seed: None,
+            split_by_type: false,
+            template: None,
+        }
+    }
+}
+
+/// Settings an output file was written with. They are recorded in a sidecar file next to it,
+/// so that appending with incompatible settings can be refused.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct OutputSettings {
+    format: OutputFormat,
+    term_escaping: Option<TermEscaping>,
+    #[serde(default)]
+    line_ending: LineEnding,
+    #[serde(default)]
+    ascii_escape: bool,
+    #[serde(default)]
+    provenance: Option<ProvenanceFormat>,
+}
+
+impl OutputSettings {
+    fn sidecar_path(path: &Path) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_owned();
+        file_name.push(".settings.json");
+        path.with_file_name(file_name)
+    }
+
+    /// Fails if `path` was written with different settings
+    fn check_append(&self, path: &Path) -> io::Result<()> {
+        let sidecar_path = Self::sidecar_path(path);
+        if !path.exists() || !sidecar_path.exists() {
+            return Ok(());
+        }
+
+        let recorded: OutputSettings = serde_json::from_reader(BufReader::new(File::open(&sidecar_path)?))?;
+
+        if recorded != *self {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path:?} was written with {recorded:?}, refusing to append with {self:?} (use --force to append anyway)"),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn record(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(AtomicFile::create(Self::sidecar_path(path), false)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        commit_buffered(writer)
+    }
+}
+
+impl WriteOpts {
+    /// Returns which budget is exhausted by a query file of `n_bytes` bytes with `n_triples` triples written
+    fn exhausted_budget(&self, n_bytes: u64, n_triples: usize) -> Option<CutOffReason> {
+        if self.max_output_bytes.is_some_and(|max| n_bytes >= max) {
+            Some(CutOffReason::MaxOutputBytes)
+        } else if self.max_total_triples.is_some_and(|max| n_triples >= max) {
+            Some(CutOffReason::MaxTotalTriples)
+        } else {
+            None
+        }
+    }
+
+    fn settings(&self, format: OutputFormat) -> OutputSettings {
+        OutputSettings {
+            format,
+            term_escaping: (format == OutputFormat::Query).then_some(self.term_escaping),
+            line_ending: self.line_ending,
+            ascii_escape: self.ascii_escape,
+            provenance: (format == OutputFormat::NTriples).then_some(self.provenance).flatten(),
+        }
+    }
+
+    /// Terminator of a triple in N-Triples output, also writes the provenance column of the triple if enabled
+    fn ntriples_terminator(&self, provenance: Option<&Provenance>) -> Vec<u8> {
+        let mut terminator = match (self.provenance, provenance) {
+            (Some(ProvenanceFormat::NQuads), Some(provenance)) => format!(" <{}> .", provenance.graph).into_bytes(),
+            (Some(ProvenanceFormat::Tsv), Some(provenance)) => format!("\t{}", provenance.timestamp).into_bytes(),
+            _ => b" .".to_vec(),
+        };
+
+        terminator.extend_from_slice(self.line_ending.as_bytes());
+        terminator
+    }
+
+    /// Writer continuing the output file `path` at the state recorded in a checkpoint. Its temporary file is kept
+    /// if the run fails again, the checkpoint still refers to it.
+    fn resumed_writer(&self, path: &Path, partial: &PartialFile) -> io::Result<BufWriter<AtomicFile>> {
+        let mut file = AtomicFile::resume(path, partial.tmp_path.clone(), partial.len)?;
+        file.keep_on_drop();
+
+        Ok(BufWriter::with_capacity(self.buffer_capacity, file))
+    }
+
+    fn writer(&self, path: &Path, format: OutputFormat) -> io::Result<BufWriter<AtomicFile>> {
+        if self.append && !self.force {
+            self.settings(format).check_append(path)?;
+        }
+
+        Ok(BufWriter::with_capacity(
+            self.buffer_capacity,
+            AtomicFile::create(path, self.append)?,
+        ))
+    }
+
+    /// Writes `triples` sorted and without duplicates as compressed dataset, together with the triples already
+    /// in `path` if appending
+    fn write_compressed(&self, path: &Path, mut triples: Vec<CompressedTriple>) -> io::Result<()> {
+        if self.append && !self.force {
+            self.settings(OutputFormat::Compressed).check_append(path)?;
+        }
+
+        if self.append && path.exists() {
+            let existing = unsafe { CompressedRdfTriples::load(path)? };
+            triples.extend_from_slice(&existing);
+        }
+
+        triples.sort_unstable();
+        triples.dedup();
+
+        save_compressed_triples(path, &triples)?;
+        self.settings(OutputFormat::Compressed).record(path)
+    }
+
+    /// Commits the output file written in `format` and records its settings
+    fn commit(&self, writer: BufWriter<AtomicFile>, path: &Path, format: OutputFormat) -> io::Result<()> {
+        commit_buffered(writer)?;
+        self.settings(format).record(path)
+    }
+}
+
+/// Serializes `s p o` followed by `terminator` into `buf`, replacing its previous contents.
+/// Writing whole triples at once avoids many tiny writes into the output buffer.
+pub(crate) fn serialize_triple<'b>(
+    buf: &'b mut Vec<u8>,
+    triple: RawTriple,
+    terminator: &[u8],
+    escaping: TermEscaping,
+    ascii_escape: bool,
+) -> &'b [u8] {
+    buf.clear();
+
+    for (ix, term) in triple.into_iter().enumerate() {
+        if ix > 0 {
+            buf.push(b' ');
+        }
+
+        match escaping {
+            TermEscaping::Verbatim => buf.extend_from_slice(term),
+            TermEscaping::Escape => escape_term_into(buf, term),
+        }
+    }
+
+    if ascii_escape && !buf.is_ascii() {
+        let serialized = std::mem::take(buf);
+        escape_non_ascii_into(buf, &serialized);
+    }
+
+    buf.extend_from_slice(terminator);
+    buf
+}
+
+/// Requested size, type and graph of a single query
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct QueryPlan {
+    n_triples: usize,
+    n_novel: usize,
+    query_type: QueryType,
+    graph: Option<Arc<str>>,
+    warmup: bool,
+    mix_part: usize,
+}
+
+/// Interleaves inserts and deletes, keeping their relative order. If there are more queries
+/// of one type, the remaining ones are appended at the end.
+fn alternate_insert_delete<T>(queries: Vec<T>, query_type: impl Fn(&T) -> QueryType) -> Vec<T> {
+    let (ins, del): (Vec<_>, Vec<_>) = queries
+        .into_iter()
+        .partition(|query| query_type(query) == QueryType::InsertData);
+
+    if ins.len() != del.len() {
+        warn(Warning::UnevenAlternation { n_inserts: ins.len(), n_deletes: del.len() });
+    }
+
+    let n_alternating = ins.len().min(del.len());
+    let mut ins = ins.into_iter();
+    let mut del = del.into_iter();
+
+    let mut out: Vec<_> = ins
+        .by_ref()
+        .zip(del.by_ref())
+        .take(n_alternating)
+        .flat_map(|(i, d)| [i, d])
+        .collect();
+
+    out.extend(ins);
+    out.extend(del);
+    out
+}
+
+/// Orders replicated datasets like generated queries by `order`, `key` returns the query type and the number of
+/// triples of a dataset. `as-specified` keeps the order of the datasets, `inserts-before-matching-deletes` is not supported.
+pub fn order_replicated<T>(
+    mut datasets: Vec<T>,
+    order: OutputOrder,
+    seed: Option<u64>,
+    key: impl Fn(&T) -> (QueryType, usize),
+) -> io::Result<Vec<T>> {
+    match order {
+        OutputOrder::AsSpecified => (),
+        OutputOrder::Randomized => datasets.shuffle(&mut seeded_rng(seed)),
+        OutputOrder::SortedSizeAsc => datasets.sort_by_key(|dataset| key(dataset).1),
+        OutputOrder::SortedSizeDesc => datasets.sort_by_key(|dataset| std::cmp::Reverse(key(dataset).1)),
+        OutputOrder::SortedSizeAscAlternateInsertDelete => {
+            datasets.sort_by_key(|dataset| key(dataset).1);
+            datasets = alternate_insert_delete(datasets, |dataset| key(dataset).0);
+        },
+        OutputOrder::AlternateInsertDelete => datasets = alternate_insert_delete(datasets, |dataset| key(dataset).0),
+        OutputOrder::InsertsBeforeMatchingDeletes => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "inserts-before-matching-deletes is not supported for replicated datasets",
+            ))
+        },
+    }
+
+    Ok(datasets)
+}
+
+/// A query with its generated triples
+struct PlannedQuery {
+    plan: QueryPlan,
+    /// Position in the order the triples were generated
+    generation_ix: usize,
+    triples: Vec<CompressedTriple>,
+    /// Why selecting the triples failed, the query must not be written then
+    error: Option<io::Error>,
+}
+
+/// A triple returned by the factory of [`generate_queries_by_type`], selecting it can fail,
+/// e.g. if a selector script fails
+pub trait GeneratedTriple {
+    fn into_triple(self) -> io::Result<CompressedTriple>;
+}
+
+impl GeneratedTriple for CompressedTriple {
+    fn into_triple(self) -> io::Result<CompressedTriple> {
+        Ok(self)
+    }
+}
+
+impl GeneratedTriple for &CompressedTriple {
+    fn into_triple(self) -> io::Result<CompressedTriple> {
+        Ok(*self)
+    }
+}
+
+impl GeneratedTriple for io::Result<CompressedTriple> {
+    fn into_triple(self) -> io::Result<CompressedTriple> {
+        self
+    }
+}
+
+/// Reorders the queries so that every delete only removes triples that are present at its position.
+/// The preparation removes the triples of all inserts beforehand, so a delete of such a triple is
+/// deferred until an insert added it back to the same graph. Apart from that the order is kept,
+/// deferring a warm-up delete into the measured queries is an error.
+fn order_inserts_before_matching_deletes(queries: Vec<PlannedQuery>) -> io::Result<Vec<PlannedQuery>> {
+    type TripleSet = HashSet<CompressedTriple, BuildHasherDefault<ahash::AHasher>>;
+    type GraphTripleSets = HashMap<Option<Arc<str>>, TripleSet>;
+
+    /// Appends the delete to `ordered` if all its triples are present, otherwise returns it
+    fn try_delete(
+        absent: &mut GraphTripleSets,
+        ordered: &mut Vec<PlannedQuery>,
+        query: PlannedQuery,
+    ) -> Option<PlannedQuery> {
+        let absent = absent.entry(query.plan.graph.clone()).or_default();
+
+        if query.triples.iter().any(|triple| absent.contains(triple)) {
+            return Some(query);
+        }
+
+        absent.extend(query.triples.iter().copied());
+        ordered.push(query);
+        None
+    }
+
+    let mut absent = GraphTripleSets::new();
+    for query in queries.iter().filter(|query| query.plan.query_type == QueryType::InsertData) {
+        absent
+            .entry(query.plan.graph.clone())
+            .or_default()
+            .extend(query.triples.iter().copied());
+    }
+
+    let mut ordered = Vec::with_capacity(queries.len());
+    let mut deferred = Vec::new();
+
+    for query in queries {
+        match query.plan.query_type {
+            QueryType::DeleteData => deferred.extend(try_delete(&mut absent, &mut ordered, query)),
+            QueryType::InsertData => {
+                let graph_absent = absent.entry(query.plan.graph.clone()).or_default();
+                for triple in &query.triples {
+                    graph_absent.remove(triple);
+                }
+
+                ordered.push(query);
+
+                deferred = deferred
+                    .into_iter()
+                    .filter_map(|query| try_delete(&mut absent, &mut ordered, query))
+                    .collect();
+            },
+        }
+    }
+
+    if !deferred.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} delete queries remove triples that are neither in the main dataset nor inserted by an earlier query",
+                deferred.len()
+            ),
+        ));
+    }
+
+    if ordered
+        .iter()
+        .skip_while(|query| query.plan.warmup)
+        .any(|query| query.plan.warmup)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "warm-up delete queries remove triples that are only inserted by measured queries",
+        ));
+    }
+
+    Ok(ordered)
+}
+
+/// Path of the file only containing the queries of `query_type` next to the query file at `path`,
+/// e.g. `queries.insert.txt` and `queries.delete.txt` for `queries.txt`
+pub fn split_path(path: &Path, query_type: QueryType) -> PathBuf {
+    let infix = match query_type {
+        QueryType::InsertData => "insert",
+        QueryType::DeleteData => "delete",
+    };
+
+    let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(infix);
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+/// Writes to `first` and, if given, the same bytes to `second`
+struct Tee<'w> {
+    first: &'w mut BufWriter<AtomicFile>,
+    second: Option<&'w mut BufWriter<AtomicFile>>,
+}
+
+impl Write for Tee<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n_written = self.first.write(buf)?;
+
+        if let Some(second) = &mut self.second {
+            second.write_all(&buf[..n_written])?;
+        }
+
+        Ok(n_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.first.flush()?;
+
+        match &mut self.second {
+            Some(second) => second.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checkpointing state of a run of `n_planned` queries, `resumed` is the checkpoint it continues from
+struct Checkpointing {
+    n_planned: usize,
+    resumed: Option<Checkpoint>,
+}
+
+/// State of a flushed output file for a checkpoint
+fn partial_file(writer: &BufWriter<AtomicFile>) -> PartialFile {
+    PartialFile { tmp_path: writer.get_ref().tmp_path().to_owned(), len: writer.get_ref().size() }
+}
+
+/// A query to be written together with its triples
+struct QueryOutput<I> {
+    query_type: QueryType,
+    graph: Option<Arc<str>>,
+    /// Number of triples the query was requested with, if known
+    requested_triples: Option<usize>,
+    warmup: bool,
+    /// Source of the triples, only written to N-Triples output
+    provenance: Option<Provenance>,
+    triples: I,
+}
+
+/// Writes the queries of `query_specs`, the factory is passed the size of each query.
+/// Novel triples of a spec are not synthesized but requested from the factory like the others.
+#[allow(clippy::too_many_arguments)]
 pub fn generate_queries<P, P2, Q, F, I, T>(
     out_query: P,
     out_prepare: P2,
@@ -44,7 +704,7 @@ pub fn generate_queries<P, P2, Q, F, I, T>(
     decompressor: &RdfTripleDecompressor,
     mut triple_generator_factory: F,
     order: OutputOrder,
-    append: bool,
+    write_opts: WriteOpts,
 ) -> io::Result<()>
 where
     P: AsRef<Path>,
@@ -53,250 +713,1024 @@ where
     F: FnMut(usize) -> I,
     I: IntoIterator<Item = T>,
     T: Borrow<[u64; 3]> + Eq + Hash,
+{
+    generate_queries_by_type(
+        out_query,
+        out_prepare,
+        prepare_format,
+        query_specs,
+        decompressor,
+        |size_hint, n_novel, _, _| {
+            triple_generator_factory(size_hint + n_novel)
+                .into_iter()
+                .map(|triple| *triple.borrow())
+        },
+        order,
+        write_opts,
+    )
+    .map(drop)
+}
+
+/// Like [`generate_queries`], but the factory is passed the number of triples to select, the number of novel
+/// triples to synthesize, the type of the query it generates the triples for and the [`QuerySpec::mix_part`] of its spec.
+/// Returns the records of the written queries in the order they were written.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_queries_by_type<P, P2, Q, F, I, T>(
+    out_query: P,
+    out_prepare: P2,
+    prepare_format: OutputFormat,
+    query_specs: Q,
+    decompressor: &RdfTripleDecompressor,
+    mut triple_generator_factory: F,
+    order: OutputOrder,
+    write_opts: WriteOpts,
+) -> io::Result<Vec<QueryRecord>>
+where
+    P: AsRef<Path>,
+    P2: AsRef<Path>,
+    Q: IntoIterator<Item = QuerySpec>,
+    F: FnMut(usize, usize, QueryType, usize) -> I,
+    I: IntoIterator<Item = T>,
+    T: GeneratedTriple,
 {
     let generators: Vec<_> = {
-        let mut tmp: Vec<_> = query_specs
+        let (warmup, mut tmp): (Vec<_>, Vec<_>) = query_specs
             .into_iter()
-            .flat_map(|QuerySpec { n_queries, n_triples_per_query, query_type }| {
-                std::iter::repeat((n_triples_per_query, query_type)).take(n_queries)
-            })
-            .collect();
+            .flat_map(
+                |QuerySpec {
+                     n_queries,
+                     n_triples_per_query,
+                     n_novel_per_query,
+                     query_type,
+                     graph,
+                     warmup,
+                     mix_part,
+                 }| {
+                    std::iter::repeat(QueryPlan {
+                        n_triples: n_triples_per_query,
+                        n_novel: n_novel_per_query,
+                        query_type,
+                        graph,
+                        warmup,
+                        mix_part,
+                    })
+                    .take(n_queries)
+                },
+            )
+            .partition(|plan| plan.warmup);
 
         match order {
-            OutputOrder::AsSpecified => (),
-            OutputOrder::Randomized => tmp.shuffle(&mut rand::thread_rng()),
-            OutputOrder::SortedSizeAsc => tmp.sort_by_key(|&(size, _)| size),
-            OutputOrder::SortedSizeDesc => tmp.sort_by_key(|&(size, _)| std::cmp::Reverse(size)),
+            OutputOrder::AsSpecified | OutputOrder::InsertsBeforeMatchingDeletes => (),
+            // not the stream of the spec resolution, which would draw the same numbers
+            OutputOrder::Randomized => tmp.shuffle(&mut seeded_rng(stream_seed(write_opts.seed, OUTPUT_ORDER_STREAM))),
+            OutputOrder::SortedSizeAsc => tmp.sort_by_key(|plan| plan.n_triples),
+            OutputOrder::SortedSizeDesc => tmp.sort_by_key(|plan| std::cmp::Reverse(plan.n_triples)),
             OutputOrder::SortedSizeAscAlternateInsertDelete => {
-                if tmp.len() % 2 != 0 {
-                    eprintln!("Error: need even number of queries to be able to sort as alternating");
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "need even number of queries to be able to sort as alternating",
-                    ));
-                }
-
                 tmp.sort_unstable();
+                tmp = alternate_insert_delete(tmp, |plan| plan.query_type);
+            },
+            OutputOrder::AlternateInsertDelete => tmp = alternate_insert_delete(tmp, |plan| plan.query_type),
+        }
 
-                let (ins, del): (Vec<_>, Vec<_>) = tmp
-                    .into_iter()
-                    .partition(|&(_, query_type)| query_type == QueryType::InsertData);
+        // the warm-up queries always come first and in the specified order
+        warmup.into_iter().chain(tmp).collect()
+    };
 
-                tmp = ins.into_iter().zip(del).flat_map(|(i, d)| [i, d]).collect();
-            },
+    let n_planned = generators.len();
+
+    let resumed = if write_opts.resume {
+        let checkpoint = Checkpoint::read(out_query.as_ref())?;
+
+        if checkpoint.n_planned != n_planned || checkpoint.seed != write_opts.seed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the checkpoint was written by a run of {} queries with seed {:?}, not {n_planned} queries with seed {:?}. Resume with the same arguments",
+                    checkpoint.n_planned, checkpoint.seed, write_opts.seed
+                ),
+            ));
         }
 
-        tmp
+        Some(checkpoint)
+    } else {
+        None
     };
 
-    let queries = generators.into_iter().map(|(n_triples, query_type)| {
-        let triple_set = triple_generator_factory(n_triples).into_iter().map(|triple| {
-            decompressor
-                .decompress_rdf_triple(*triple.borrow())
-                .expect("to use same compressor as used for compression")
+    let generate = |(generation_ix, plan): (usize, QueryPlan)| {
+        let triples = perf::sampled(generation_ix, || {
+            triple_generator_factory(
+                plan.n_triples - plan.n_novel,
+                plan.n_novel,
+                plan.query_type,
+                plan.mix_part,
+            )
+            .into_iter()
+            .map(GeneratedTriple::into_triple)
+            .collect::<io::Result<_>>()
         });
 
-        (query_type, Some(n_triples), triple_set)
+        match triples {
+            Ok(triples) => PlannedQuery { plan, generation_ix, triples, error: None },
+            Err(e) => PlannedQuery { plan, generation_ix, triples: Vec::new(), error: Some(e) },
+        }
+    };
+
+    let mut planned: Box<dyn Iterator<Item = PlannedQuery> + '_> =
+        if let OutputOrder::InsertsBeforeMatchingDeletes = order {
+            let planned = generators.into_iter().enumerate().map(generate).collect();
+            Box::new(order_inserts_before_matching_deletes(planned)?.into_iter())
+        } else {
+            Box::new(generators.into_iter().enumerate().map(generate))
+        };
+
+    let mut records = Vec::new();
+    let mut generation_ixs = Vec::new();
+
+    // triples of the insert queries for the compressed prepare output, with the number of their query
+    let compressed_prepare = prepare_format == OutputFormat::Compressed;
+    let mut prepare_triples = Vec::new();
+
+    // the written queries are generated again to bring the generators into the state at the checkpoint
+    if let Some(checkpoint) = &resumed {
+        println!(
+            "replaying the {} queries written before the checkpoint...",
+            checkpoint.n_triples.len()
+        );
+
+        for (query_ix, &n_triples) in checkpoint.n_triples.iter().enumerate() {
+            let Some(PlannedQuery { plan, generation_ix, triples, error }) = planned.next() else {
+                break;
+            };
+            perf::query_dequeued(generation_ix, true);
+
+            if let Some(e) = error {
+                return Err(e);
+            }
+
+            // the compressed prepare output is only written at the end, so it is collected again
+            if compressed_prepare && plan.query_type == QueryType::InsertData {
+                for triple in triples {
+                    if decompressor.decompress_rdf_triple(triple)?.is_some() {
+                        prepare_triples.push((triple, query_ix));
+                    }
+                }
+            }
+
+            generation_ixs.push(generation_ix);
+            records.push(QueryRecord {
+                query_ix,
+                query_type: plan.query_type,
+                graph: plan.graph.as_deref().map(str::to_owned),
+                requested_triples: Some(plan.n_triples),
+                n_triples,
+                // the prepare file of the checkpoint contains the triples of every insert query written before it
+                n_prepare_triples: (plan.query_type == QueryType::InsertData).then_some(n_triples),
+                warmup: plan.warmup,
+                generation_ix,
+            });
+        }
+    }
+
+    let mut cache = DecompressionCache::new(decompressor);
+    let missing_terms = write_opts.missing_terms;
+
+    let queries =
+        planned
+            .zip(records.len()..)
+            .map(|(PlannedQuery { plan, generation_ix, triples, error }, query_ix)| {
+                generation_ixs.push(generation_ix);
+                perf::query_dequeued(generation_ix, false);
+                let collect_prepare = compressed_prepare && plan.query_type == QueryType::InsertData;
+
+                let (triple_set, n_missing) = perf::timed(Phase::Decompression, || {
+                    let mut n_missing = 0;
+                    let mut triple_set = Vec::with_capacity(triples.len());
+
+                    if let Err(e) = cache.resolve_batch(&triples) {
+                        triple_set.push(Err(e));
+                        return (triple_set, n_missing);
+                    }
+
+                    for triple in triples {
+                        match cache.decompress_rdf_triple(triple) {
+                            Ok(Some(raw)) => {
+                                if collect_prepare {
+                                    prepare_triples.push((triple, query_ix));
+                                }
+
+                                triple_set.push(Ok(raw))
+                            },
+                            Ok(None) if missing_terms == MissingTermPolicy::Skip => n_missing += 1,
+                            Ok(None) => {
+                                triple_set.push(Err(missing_term_error(query_ix, triple)));
+                                break;
+                            },
+                            Err(e) => {
+                                triple_set.push(Err(e));
+                                break;
+                            },
+                        }
+                    }
+
+                    (triple_set, n_missing)
+                });
+
+                if n_missing > 0 {
+                    warn(Warning::PrunedTriples {
+                        reason: format!("terms of query {query_ix} are missing from the compressor state"),
+                        n_pruned: n_missing,
+                    });
+                }
+
+                // fails writing the query, so that the output is not committed
+                let triple_set = match error {
+                    Some(e) => vec![Err(e)],
+                    None => triple_set,
+                };
+
+                QueryOutput {
+                    query_type: plan.query_type,
+                    graph: plan.graph,
+                    requested_triples: Some(plan.n_triples),
+                    warmup: plan.warmup,
+                    provenance: None,
+                    triples: triple_set.into_iter(),
+                }
+            });
+
+    let (written, cut_off) = write_update_data_queries(
+        &out_query,
+        (!compressed_prepare).then_some((out_prepare.as_ref(), prepare_format)),
+        write_opts.clone(),
+        queries,
+        Some(Checkpointing { n_planned, resumed }),
+    )?;
+    records.extend(written);
+
+    if compressed_prepare {
+        // the compressed prepare output holds every triple once, even if several insert queries have it
+        let (prepare_triples, n_prepare_triples) = dedup_prepare_triples(prepare_triples, records.len());
+        write_opts.write_compressed(out_prepare.as_ref(), prepare_triples)?;
+
+        for record in &mut records {
+            if record.query_type == QueryType::InsertData {
+                record.n_prepare_triples = Some(n_prepare_triples[record.query_ix]);
+            }
+        }
+    }
+
+    for (record, generation_ix) in records.iter_mut().zip(generation_ixs) {
+        record.generation_ix = generation_ix;
+    }
+
+    let cut_off = cut_off.map(|reason| {
+        let cut_off = CutOff {
+            reason,
+            n_written_queries: records.len(),
+            n_skipped_queries: n_planned - records.len(),
+        };
+        println!(
+            "output budget reached, stopped after {} queries, skipping {}",
+            cut_off.n_written_queries, cut_off.n_skipped_queries
+        );
+        cut_off
     });
 
-    write_update_data_queries(out_query, Some((out_prepare, prepare_format)), append, queries)
+    let manifest = Manifest::new(records, cut_off);
+    manifest.write(out_query.as_ref())?;
+
+    let totals = &manifest.totals;
+    println!(
+        "wrote {} insert triples, {} delete triples and {} triples to the prepare file, {} triples short of the specs in {} queries",
+        totals.n_insert_triples,
+        totals.n_delete_triples,
+        totals.n_prepare_triples,
+        totals.n_missing_triples,
+        totals.n_short_queries
+    );
+
+    Ok(manifest.warmup.into_iter().chain(manifest.measured).collect())
 }
 
+/// Removes the duplicates of the prepare triples, which are paired with the index of their query, and returns the
+/// remaining triples together with the number of them every one of the `n_queries` queries contributed.
+/// A triple of several queries counts for the first of them.
+fn dedup_prepare_triples(
+    mut prepare_triples: Vec<(CompressedTriple, usize)>,
+    n_queries: usize,
+) -> (Vec<CompressedTriple>, Vec<usize>) {
+    prepare_triples.sort_unstable();
+    prepare_triples.dedup_by_key(|(triple, _)| *triple);
+
+    let mut n_per_query = vec![0; n_queries];
+    for &(_, query_ix) in &prepare_triples {
+        n_per_query[query_ix] += 1;
+    }
+
+    (
+        prepare_triples.into_iter().map(|(triple, _)| triple).collect(),
+        n_per_query,
+    )
+}
+
+/// The triples in `range` of the replicated dataset at `dataset_ix`
+pub struct ReplicatedSlice {
+    pub dataset_ix: usize,
+    pub range: Range<usize>,
+}
+
+/// Plans the queries replicating datasets of the given query type and number of triples, one query per dataset by default.
+/// Datasets of more than `max_triples` triples are split into consecutive queries of at most `max_triples` triples, and
+/// consecutive datasets of the same type are merged into one query while it has fewer than `min_triples` triples
+/// and stays within `max_triples`.
+pub fn plan_replicated(
+    datasets: &[(QueryType, usize)],
+    min_triples: Option<usize>,
+    max_triples: Option<usize>,
+) -> Vec<Vec<ReplicatedSlice>> {
+    let max_triples = max_triples.unwrap_or(usize::MAX).max(1);
+    let min_triples = min_triples.unwrap_or(0);
+
+    // query type, number of triples and slices of every query
+    let mut queries: Vec<(QueryType, usize, Vec<ReplicatedSlice>)> = Vec::new();
+
+    for (dataset_ix, &(query_type, size)) in datasets.iter().enumerate() {
+        for start in (0..size).step_by(max_triples) {
+            let slice = ReplicatedSlice { dataset_ix, range: start..start.saturating_add(max_triples).min(size) };
+            let n_triples = slice.range.len();
+
+            match queries.last_mut() {
+                Some((last_type, last_n_triples, slices))
+                    if *last_type == query_type
+                        && *last_n_triples < min_triples
+                        && *last_n_triples + n_triples <= max_triples =>
+                {
+                    *last_n_triples += n_triples;
+                    slices.push(slice);
+                },
+                _ => queries.push((query_type, n_triples, vec![slice])),
+            }
+        }
+    }
+
+    queries.into_iter().map(|(_, _, slices)| slices).collect()
+}
+
+/// Number of triples pruned from a replicated dataset
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct PruneCounts {
+    /// Triples that passed the include and exclude datasets
+    pub kept: usize,
+    /// Triples that are not contained in the include dataset
+    pub not_included: usize,
+    /// Triples that are contained in the exclude dataset
+    pub excluded: usize,
+}
+
+/// Writes one query per generator, keeping only triples contained in `include_dataset` and not
+/// contained in `exclude_dataset`. The provenance of a generator is only used for N-Triples output.
+/// Returns the number of pruned triples per generator.
 pub fn generate_linear_no_size_hint<P, F, I, T>(
     out_file: P,
     decompressor: &RdfTripleDecompressor,
-    exclude_dataset: Option<&CompressedRdfTriples>,
+    include_dataset: Option<&ContainmentIndex>,
+    exclude_dataset: Option<&ContainmentIndex>,
     generators: F,
-    append: bool,
+    write_opts: WriteOpts,
     output_format: OutputFormat,
-) -> io::Result<()>
+) -> io::Result<Vec<PruneCounts>>
 where
     P: AsRef<Path>,
-    F: IntoIterator<Item = (QueryType, I)>,
+    F: IntoIterator<Item = (QueryType, Option<Provenance>, I)>,
     I: IntoIterator<Item = T>,
     T: Borrow<CompressedTriple> + Eq + Hash,
 {
+    let generators: Vec<_> = generators.into_iter().collect();
+    let prune_counts = vec![Cell::new(PruneCounts::default()); generators.len()];
+    let n_missing = Cell::new(0);
+
     let queries: Vec<_> = generators
         .into_iter()
-        .map(|(query_type, triple_generator)| {
-            let triples = triple_generator
-                .into_iter()
-                .filter(|triple| exclude_dataset.map(|exclude| !exclude.contains(triple.borrow())).unwrap_or(true))
-                .map(|triple| {
-                    decompressor
-                        .decompress_rdf_triple(*triple.borrow())
-                        .expect("to use same compressor as used for compression")
-                });
+        .zip(&prune_counts)
+        .enumerate()
+        .map(
+            |(query_ix, ((query_type, provenance, triple_generator), prune_counts))| {
+                let n_missing = &n_missing;
+                let mut include_cursor = include_dataset.map(ContainmentIndex::checker);
+                let mut exclude_cursor = exclude_dataset.map(ContainmentIndex::checker);
 
-            (query_type, None, triples)
-        })
+                let triples = triple_generator
+                    .into_iter()
+                    .filter(move |triple| {
+                        let mut counts = prune_counts.get();
+
+                        if let Some(include) = &mut include_cursor {
+                            if !include.contains(triple.borrow()) {
+                                counts.not_included += 1;
+                                prune_counts.set(counts);
+                                return false;
+                            }
+                        }
+
+                        if let Some(exclude) = &mut exclude_cursor {
+                            if exclude.contains(triple.borrow()) {
+                                counts.excluded += 1;
+                                prune_counts.set(counts);
+                                return false;
+                            }
+                        }
+
+                        counts.kept += 1;
+                        prune_counts.set(counts);
+                        true
+                    })
+                    .filter_map(move |triple| {
+                        let triple = *triple.borrow();
+
+                        match decompressor.decompress_rdf_triple(triple) {
+                            Ok(Some(raw)) => Some(Ok(raw)),
+                            Ok(None) if write_opts.missing_terms == MissingTermPolicy::Skip => {
+                                n_missing.set(n_missing.get() + 1);
+                                None
+                            },
+                            Ok(None) => Some(Err(missing_term_error(query_ix, triple))),
+                            Err(e) => Some(Err(e)),
+                        }
+                    });
+
+                QueryOutput {
+                    query_type,
+                    graph: None,
+                    requested_triples: None,
+                    warmup: false,
+                    provenance,
+                    triples,
+                }
+            },
+        )
         .collect();
 
     match output_format {
-        OutputFormat::Query => write_update_data_queries(out_file, None::<(&Path, OutputFormat)>, append, queries),
-        OutputFormat::NTriples => write_ntriples_file(out_file, append, queries),
+        OutputFormat::Query => {
+            write_update_data_queries(out_file, None::<(&Path, OutputFormat)>, write_opts, queries, None)?;
+        },
+        OutputFormat::NTriples => write_ntriples_file(out_file, write_opts, queries)?,
+        OutputFormat::Compressed => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed output is only supported for the prepare output",
+            ))
+        },
+    }
+
+    if n_missing.get() > 0 {
+        warn(Warning::PrunedTriples {
+            reason: "terms are missing from the compressor state".to_owned(),
+            n_pruned: n_missing.get(),
+        });
+    }
+
+    Ok(prune_counts.into_iter().map(Cell::into_inner).collect())
+}
+
+/// Starts a `DATA` block of `operation`, nested into a `GRAPH` block if `graph` is given.
+/// With a `template` its part before the triples is written instead.
+fn open_data_block<W: Write>(
+    out: &mut W,
+    template: Option<&QueryTemplate>,
+    operation: &str,
+    graph: Option<&str>,
+    query_ix: usize,
+) -> io::Result<()> {
+    if let Some(template) = template {
+        return template.write_head(out, operation, graph, query_ix);
+    }
+
+    write!(out, "{operation} {{ ")?;
+
+    if let Some(graph) = graph {
+        write!(out, "GRAPH <{graph}> {{ ")?;
+    }
+
+    Ok(())
+}
+
+fn close_data_block<W: Write>(
+    out: &mut W,
+    template: Option<&QueryTemplate>,
+    operation: &str,
+    graph: Option<&str>,
+    query_ix: usize,
+    line_ending: LineEnding,
+) -> io::Result<()> {
+    if let Some(template) = template {
+        template.write_tail(out, operation, graph, query_ix)?;
+    } else {
+        if graph.is_some() {
+            out.write_all(b"} ")?;
+        }
+
+        out.write_all(b"}")?;
     }
+
+    out.write_all(line_ending.as_bytes())
 }
 
+/// Marker lines delimiting the warm-up section of a query file, without line ending
+const WARMUP_START_MARKER: &[u8] = b"# warm-up start";
+const WARMUP_END_MARKER: &[u8] = b"# warm-up end";
+
+/// Writes the queries, returning a record of every written query. If an output budget of `write_opts`
+/// is reached, the remaining queries are not pulled from `queries` and the exhausted budget is returned.
+/// With `checkpointing` a checkpoint is written every `checkpoint_every` queries of `write_opts`.
+/// With `split_by_type` of `write_opts` every query is additionally written to the file of its type.
 fn write_update_data_queries<'a, P, P2, I>(
     out_file: P,
     prepare_out_file: Option<(P2, OutputFormat)>,
-    append: bool,
-    queries: impl IntoIterator<Item = (QueryType, Option<usize>, I)>,
-) -> io::Result<()>
+    write_opts: WriteOpts,
+    queries: impl IntoIterator<Item = QueryOutput<I>>,
+    checkpointing: Option<Checkpointing>,
+) -> io::Result<(Vec<QueryRecord>, Option<CutOffReason>)>
 where
     P: AsRef<Path>,
     P2: AsRef<Path>,
-    I: Iterator<Item = RawTriple<'a>>,
+    I: Iterator<Item = io::Result<RawTriple<'a>>>,
 {
-    let f = File::options()
-        .append(append)
-        .truncate(!append)
-        .create(true)
-        .write(true)
-        .open(out_file)?;
+    let out_file = out_file.as_ref();
+    let resumed = checkpointing.as_ref().and_then(|checkpointing| checkpointing.resumed.as_ref());
 
-    let mut writer = BufWriter::new(f);
+    let mut writer = match resumed {
+        Some(checkpoint) => write_opts.resumed_writer(out_file, &checkpoint.query_file)?,
+        None => write_opts.writer(out_file, OutputFormat::Query)?,
+    };
 
+    let prepare_out_file = prepare_out_file.as_ref().map(|(path, format)| (path.as_ref(), *format));
     let mut prepare_writer = if let Some((prepare_out_file, prepare_format)) = prepare_out_file {
-        let prepare_f = File::options()
-            .append(append)
-            .truncate(!append)
-            .create(true)
-            .write(true)
-            .open(prepare_out_file)?;
-
-        Some((BufWriter::new(prepare_f), prepare_format))
+        let prepare_writer = match resumed.and_then(|checkpoint| checkpoint.prepare_file.as_ref()) {
+            Some(partial) => write_opts.resumed_writer(prepare_out_file, partial)?,
+            None => write_opts.writer(prepare_out_file, prepare_format)?,
+        };
+
+        Some((prepare_writer, prepare_format))
     } else {
         None
     };
 
-    let write_query = |out: &mut BufWriter<File>,
-                       mut prepare_out: Option<&mut (BufWriter<File>, OutputFormat)>,
-                       expected_n_triples: Option<usize>,
-                       query: I|
-     -> io::Result<()> {
+    let split_query_types = [QueryType::InsertData, QueryType::DeleteData];
+    let mut split_writers = if write_opts.split_by_type {
+        let [inserts, deletes] = split_query_types.map(|query_type| split_path(out_file, query_type));
+        Some([
+            write_opts.writer(&inserts, OutputFormat::Query)?,
+            write_opts.writer(&deletes, OutputFormat::Query)?,
+        ])
+    } else {
+        None
+    };
+
+    let mut triple_buf = Vec::new();
+    let ntriples_terminator = write_opts.ntriples_terminator(None);
+    let template = write_opts.template.as_deref();
+
+    let mut write_query = |out: &mut Tee,
+                           mut prepare_out: Option<&mut (BufWriter<AtomicFile>, OutputFormat)>,
+                           query_ix: usize,
+                           graph: Option<&str>,
+                           expected_n_triples: Option<usize>,
+                           query: I|
+     -> io::Result<(usize, Option<usize>)> {
         let mut cnt = 0;
+        let mut prepare_cnt = None;
 
         if let Some((prepare_out, prepare_format)) = &mut prepare_out {
-            out.write_all(b"INSERT DATA { ")?;
+            let mut n_prepare = 0;
+            open_data_block(out, template, "INSERT DATA", graph, query_ix)?;
 
             if *prepare_format == OutputFormat::Query {
-                prepare_out.write_all(b"DELETE DATA { ")?;
+                open_data_block(prepare_out, template, "DELETE DATA", graph, query_ix)?;
             }
 
-            for [s, p, o] in query {
-                out.write_all(s)?;
-                out.write_all(b" ")?;
-                out.write_all(p)?;
-                out.write_all(b" ")?;
-                out.write_all(o)?;
-                out.write_all(b" . ")?;
-
-                prepare_out.write_all(s)?;
-                prepare_out.write_all(b" ")?;
-                prepare_out.write_all(p)?;
-                prepare_out.write_all(b" ")?;
-                prepare_out.write_all(o)?;
-
-                if *prepare_format == OutputFormat::Query {
-                    prepare_out.write_all(b" . ")?;
-                } else {
-                    prepare_out.write_all(b" .\n")?;
-                }
+            // triples of queries on a named graph are written as N-Quads, so that the graph is kept
+            let quad_terminator = graph.filter(|_| *prepare_format == OutputFormat::NTriples).map(|graph| {
+                let mut terminator = format!(" <{graph}> .").into_bytes();
+                terminator.extend_from_slice(write_opts.line_ending.as_bytes());
+                terminator
+            });
 
+            for triple in query {
+                let triple = triple?;
+                out.write_all(serialize_triple(
+                    &mut triple_buf,
+                    triple,
+                    b" . ",
+                    write_opts.term_escaping,
+                    write_opts.ascii_escape,
+                ))?;
                 cnt += 1;
+
+                if *prepare_format == OutputFormat::NTriples {
+                    serialize_triple(
+                        &mut triple_buf,
+                        triple,
+                        quad_terminator.as_deref().unwrap_or(&ntriples_terminator),
+                        TermEscaping::Verbatim,
+                        write_opts.ascii_escape,
+                    );
+                }
+
+                prepare_out.write_all(&triple_buf)?;
+                n_prepare += 1;
             }
 
-            out.write_all(b"}\n")?;
+            prepare_cnt = Some(n_prepare);
+
+            close_data_block(out, template, "INSERT DATA", graph, query_ix, write_opts.line_ending)?;
 
             if *prepare_format == OutputFormat::Query {
-                prepare_out.write_all(b"}\n")?;
+                close_data_block(
+                    prepare_out,
+                    template,
+                    "DELETE DATA",
+                    graph,
+                    query_ix,
+                    write_opts.line_ending,
+                )?;
             }
         } else {
-            out.write_all(b"DELETE DATA { ")?;
+            open_data_block(out, template, "DELETE DATA", graph, query_ix)?;
 
-            for [s, p, o] in query {
-                out.write_all(s)?;
-                out.write_all(b" ")?;
-                out.write_all(p)?;
-                out.write_all(b" ")?;
-                out.write_all(o)?;
-                out.write_all(b" . ")?;
+            for triple in query {
+                let triple = triple?;
+                out.write_all(serialize_triple(
+                    &mut triple_buf,
+                    triple,
+                    b" . ",
+                    write_opts.term_escaping,
+                    write_opts.ascii_escape,
+                ))?;
 
                 cnt += 1;
             }
 
-            out.write_all(b"}\n")?;
+            close_data_block(out, template, "DELETE DATA", graph, query_ix, write_opts.line_ending)?;
         }
 
         if let Some(expected_n_triples) = expected_n_triples {
             if cnt != expected_n_triples {
-                println!("Warning: requested query size {expected_n_triples} cannot be fulfilled closest available size is {cnt}");
+                warn(Warning::UnfulfilledQuerySize { query_ix, requested: expected_n_triples, actual: cnt });
             }
         }
 
-        Ok(())
+        Ok((cnt, prepare_cnt))
     };
 
-    for (query_type, n_triples, query) in queries {
-        match query_type {
-            QueryType::DeleteData => {
-                write_query(&mut writer, None, n_triples, query)?;
-            },
-            QueryType::InsertData => {
-                write_query(&mut writer, prepare_writer.as_mut(), n_triples, query)?;
-            },
+    let mut records = Vec::new();
+    // sizes of all written queries, including the ones written before resuming
+    let mut written_sizes = resumed.map_or_else(Vec::new, |checkpoint| checkpoint.n_triples.clone());
+    let mut in_warmup = resumed.is_some_and(|checkpoint| checkpoint.in_warmup);
+    let mut n_total_triples = written_sizes.iter().sum();
+    let mut n_since_checkpoint = 0;
+    let mut cut_off = None;
+    let mut queries = queries.into_iter();
+
+    for query_ix in written_sizes.len().. {
+        // checked before pulling the next query, so that it is not generated
+        let n_bytes = writer.get_ref().size() + writer.buffer().len() as u64;
+        cut_off = write_opts.exhausted_budget(n_bytes, n_total_triples);
+        if cut_off.is_some() {
+            break;
+        }
+
+        let Some(QueryOutput { query_type, graph, requested_triples, warmup, triples, .. }) = queries.next() else {
+            break;
+        };
+
+        if warmup != in_warmup {
+            for out in std::iter::once(&mut writer).chain(split_writers.iter_mut().flatten()) {
+                out.write_all(if warmup { WARMUP_START_MARKER } else { WARMUP_END_MARKER })?;
+                out.write_all(write_opts.line_ending.as_bytes())?;
+            }
+
+            in_warmup = warmup;
+        }
+
+        let prepare_out = match query_type {
+            QueryType::DeleteData => None,
+            QueryType::InsertData => prepare_writer.as_mut(),
+        };
+
+        let split_out = split_writers.as_mut().map(|[inserts, deletes]| match query_type {
+            QueryType::InsertData => inserts,
+            QueryType::DeleteData => deletes,
+        });
+
+        let (n_triples, n_prepare_triples) = perf::timed(Phase::Io, || {
+            write_query(
+                &mut Tee { first: &mut writer, second: split_out },
+                prepare_out,
+                query_ix,
+                graph.as_deref(),
+                requested_triples,
+                triples,
+            )
+        })?;
+        n_total_triples += n_triples;
+        perf::query_written(query_ix, n_triples);
+
+        records.push(QueryRecord {
+            query_ix,
+            query_type,
+            graph: graph.as_deref().map(str::to_owned),
+            requested_triples,
+            n_triples,
+            n_prepare_triples,
+            warmup,
+            generation_ix: query_ix,
+        });
+
+        if let (Some(checkpointing), Some(checkpoint_every)) = (&checkpointing, write_opts.checkpoint_every) {
+            written_sizes.push(n_triples);
+            n_since_checkpoint += 1;
+
+            if n_since_checkpoint == checkpoint_every {
+                n_since_checkpoint = 0;
+                writer.flush()?;
+                // an error return must not remove the temporary files the checkpoint resumes
+                writer.get_mut().keep_on_drop();
+
+                if let Some((prepare_writer, _)) = &mut prepare_writer {
+                    prepare_writer.flush()?;
+                    prepare_writer.get_mut().keep_on_drop();
+                }
+
+                Checkpoint {
+                    seed: write_opts.seed,
+                    n_planned: checkpointing.n_planned,
+                    n_triples: written_sizes.clone(),
+                    in_warmup,
+                    query_file: partial_file(&writer),
+                    prepare_file: prepare_writer.as_ref().map(|(prepare_writer, _)| partial_file(prepare_writer)),
+                }
+                .write(out_file)?;
+            }
         }
     }
 
-    Ok(())
+    if in_warmup {
+        for out in std::iter::once(&mut writer).chain(split_writers.iter_mut().flatten()) {
+            out.write_all(WARMUP_END_MARKER)?;
+            out.write_all(write_opts.line_ending.as_bytes())?;
+        }
+    }
+
+    if let Some(split_writers) = split_writers {
+        for (split_writer, query_type) in split_writers.into_iter().zip(split_query_types) {
+            write_opts.commit(split_writer, &split_path(out_file, query_type), OutputFormat::Query)?;
+        }
+    }
+
+    if let (Some((prepare_writer, prepare_format)), Some((prepare_out_file, _))) = (prepare_writer, prepare_out_file) {
+        write_opts.commit(prepare_writer, prepare_out_file, prepare_format)?;
+    }
+
+    perf::timed(Phase::Io, || write_opts.commit(writer, out_file, OutputFormat::Query))?;
+
+    if checkpointing.is_some() {
+        Checkpoint::remove(out_file)?;
+    }
+
+    Ok((records, cut_off))
 }
 
 fn write_ntriples_file<'a, P, I>(
     out_file: P,
-    append: bool,
-    queries: impl IntoIterator<Item = (QueryType, Option<usize>, I)>,
+    write_opts: WriteOpts,
+    queries: impl IntoIterator<Item = QueryOutput<I>>,
 ) -> io::Result<()>
 where
     P: AsRef<Path>,
-    I: Iterator<Item = RawTriple<'a>>,
+    I: Iterator<Item = io::Result<RawTriple<'a>>>,
 {
-    let f = File::options()
-        .append(append)
-        .truncate(!append)
-        .create(true)
-        .write(true)
-        .open(out_file)?;
+    let out_file = out_file.as_ref();
+    let mut writer = write_opts.writer(out_file, OutputFormat::NTriples)?;
 
-    let mut writer = BufWriter::new(f);
+    let mut triple_buf = Vec::new();
 
-    let write_ntriples = |out: &mut BufWriter<File>, expected_n_triples: Option<usize>, query: I| -> io::Result<()> {
+    let mut write_ntriples = |out: &mut BufWriter<AtomicFile>,
+                              query_ix: usize,
+                              expected_n_triples: Option<usize>,
+                              provenance: Option<&Provenance>,
+                              query: I|
+     -> io::Result<()> {
+        let terminator = write_opts.ntriples_terminator(provenance);
+        let tsv = write_opts.provenance == Some(ProvenanceFormat::Tsv) && provenance.is_some();
         let mut cnt = 0;
 
-        for [s, p, o] in query {
-            out.write_all(s)?;
-            out.write_all(b" ")?;
-            out.write_all(p)?;
-            out.write_all(b" ")?;
-            out.write_all(o)?;
-            out.write_all(b" .\n")?;
+        for triple in query {
+            let triple = triple?;
+
+            if tsv {
+                triple_buf.clear();
+
+                for (ix, term) in triple.into_iter().enumerate() {
+                    if ix > 0 {
+                        triple_buf.push(b'\t');
+                    }
+
+                    if write_opts.ascii_escape {
+                        escape_non_ascii_into(&mut triple_buf, term);
+                    } else {
+                        triple_buf.extend_from_slice(term);
+                    }
+                }
+
+                triple_buf.extend_from_slice(&terminator);
+                out.write_all(&triple_buf)?;
+            } else {
+                out.write_all(serialize_triple(
+                    &mut triple_buf,
+                    triple,
+                    &terminator,
+                    TermEscaping::Verbatim,
+                    write_opts.ascii_escape,
+                ))?;
+            }
 
             cnt += 1;
         }
 
         if let Some(expected_n_triples) = expected_n_triples {
             if cnt != expected_n_triples {
-                println!("Warning: requested query size {expected_n_triples} cannot be fulfilled closest available size is {cnt}");
+                warn(Warning::UnfulfilledQuerySize { query_ix, requested: expected_n_triples, actual: cnt });
             }
         }
 
         Ok(())
     };
 
-    for (_query_type, n_triples, query) in queries {
-        write_ntriples(&mut writer, n_triples, query)?;
+    for (query_ix, query) in queries.into_iter().enumerate() {
+        write_ntriples(
+            &mut writer,
+            query_ix,
+            query.requested_triples,
+            query.provenance.as_ref(),
+            query.triples,
+        )?;
+    }
+
+    write_opts.commit(writer, out_file, OutputFormat::NTriples)
+}
+
+/// Parses every line of the query file at `path` as a SPARQL update, failing on the first invalid one.
+/// Comment lines such as the warm-up markers are skipped.
+pub fn validate_update_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let reader = BufReader::new(File::open(path)?);
+
+    for (line_ix, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{path:?}:{}: {reason}", line_ix + 1),
+            )
+        };
+
+        let query = std::str::from_utf8(&line).map_err(|e| invalid(format!("query is not valid UTF-8: {e}")))?;
+        spargebra::Update::parse(query, None).map_err(|e| invalid(format!("invalid SPARQL update: {e}")))?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Delete queries of one triple each, the triple of the query at `failing_ix` cannot be produced
+    fn delete_queries(
+        query_ixs: Range<usize>,
+        failing_ix: Option<usize>,
+    ) -> impl Iterator<Item = QueryOutput<std::vec::IntoIter<io::Result<RawTriple<'static>>>>> {
+        query_ixs.map(move |query_ix| {
+            let triple: io::Result<RawTriple> = if Some(query_ix) == failing_ix {
+                Err(io::Error::other("triple could not be produced"))
+            } else {
+                Ok([b"<s>", b"<p>", b"<o>"])
+            };
+
+            QueryOutput {
+                query_type: QueryType::DeleteData,
+                graph: None,
+                requested_triples: Some(1),
+                warmup: false,
+                provenance: None,
+                triples: vec![triple].into_iter(),
+            }
+        })
+    }
+
+    /// Dataset index and range of every slice of every planned query
+    fn planned(
+        datasets: &[(QueryType, usize)],
+        min_triples: Option<usize>,
+        max_triples: Option<usize>,
+    ) -> Vec<Vec<(usize, Range<usize>)>> {
+        plan_replicated(datasets, min_triples, max_triples)
+            .into_iter()
+            .map(|query| query.into_iter().map(|slice| (slice.dataset_ix, slice.range)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn replicated_datasets_are_split_and_merged() {
+        use QueryType::{DeleteData as D, InsertData as I};
+
+        assert_eq!(
+            planned(&[(I, 3), (D, 2)], None, None),
+            vec![vec![(0, 0..3)], vec![(1, 0..2)]]
+        );
+
+        assert_eq!(
+            planned(&[(I, 5), (I, 0)], None, Some(2)),
+            vec![vec![(0, 0..2)], vec![(0, 2..4)], vec![(0, 4..5)]]
+        );
+
+        // merged while below the minimum and within the maximum, never across query types
+        assert_eq!(
+            planned(&[(I, 1), (I, 1), (I, 1), (D, 1), (I, 3)], Some(2), Some(3)),
+            vec![
+                vec![(0, 0..1), (1, 0..1)],
+                vec![(2, 0..1)],
+                vec![(3, 0..1)],
+                vec![(4, 0..3)]
+            ]
+        );
+        assert_eq!(
+            planned(&[(I, 2), (I, 2)], Some(3), Some(3)),
+            vec![vec![(0, 0..2)], vec![(1, 0..2)]]
+        );
+    }
+
+    #[test]
+    fn shared_prepare_triples_count_for_their_first_query() {
+        let prepare_triples = vec![
+            ([2, 2, 2], 1),
+            ([1, 1, 1], 0),
+            ([2, 2, 2], 0),
+            ([3, 3, 3], 2),
+            ([1, 1, 1], 2),
+        ];
+        let (triples, n_per_query) = dedup_prepare_triples(prepare_triples, 4);
+
+        assert_eq!(triples, vec![[1, 1, 1], [2, 2, 2], [3, 3, 3]]);
+        assert_eq!(n_per_query, vec![2, 0, 1, 0]);
+    }
+
+    #[test]
+    fn resume_after_error_return() {
+        let dir = std::env::temp_dir().join(format!("sudg-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("queries.sparql");
+
+        let write_opts = WriteOpts { checkpoint_every: Some(1), ..WriteOpts::default() };
+        let checkpointing = Checkpointing { n_planned: 3, resumed: None };
+
+        let failed = write_update_data_queries(
+            &out,
+            None::<(&Path, OutputFormat)>,
+            write_opts.clone(),
+            delete_queries(0..3, Some(2)),
+            Some(checkpointing),
+        );
+        assert!(failed.is_err());
+        assert!(!out.exists());
+
+        let checkpoint = Checkpoint::read(&out).unwrap();
+        assert_eq!(checkpoint.n_triples, vec![1, 1]);
+        assert!(checkpoint.query_file.tmp_path.exists());
+
+        let checkpointing = Checkpointing { n_planned: 3, resumed: Some(checkpoint) };
+        let (records, cut_off) = write_update_data_queries(
+            &out,
+            None::<(&Path, OutputFormat)>,
+            write_opts,
+            delete_queries(2..3, None),
+            Some(checkpointing),
+        )
+        .unwrap();
+
+        assert!(cut_off.is_none());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].query_ix, 2);
+        assert!(!Checkpoint::path(&out).exists());
+
+        let written = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(written.matches("DELETE DATA").count(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+//! Differences between two versions of a dataset, computed by a single merge over the sorted datasets
+
+use crate::rdf::triple_compressor::{CompressedRdfTriples, TripleElementId};
+use std::{cmp::Ordering, collections::BTreeMap, io};
+
+/// Changes from an old to a new dataset, all counts are of triples unless stated otherwise
+pub struct DatasetDiff {
+    /// Triples of the new dataset that are not contained in the old one
+    pub n_added: usize,
+    /// Triples of the old dataset that are not contained in the new one
+    pub n_removed: usize,
+    pub n_common: usize,
+    /// Distinct subjects, predicates and objects of the old and the new dataset
+    pub distinct_counts: [[usize; 3]; 2],
+    /// Triples per predicate in the old and the new dataset
+    pub predicate_counts: BTreeMap<TripleElementId, [usize; 2]>,
+}
+
+impl DatasetDiff {
+    /// Change of the number of distinct subjects, predicates and objects
+    pub fn distinct_deltas(&self) -> [i64; 3] {
+        let [old, new] = self.distinct_counts;
+        [0, 1, 2].map(|ix| new[ix] as i64 - old[ix] as i64)
+    }
+
+    /// Predicates whose number of triples changed with their old and new count
+    pub fn changed_predicates(&self) -> impl Iterator<Item = (TripleElementId, [usize; 2])> + '_ {
+        self.predicate_counts
+            .iter()
+            .filter(|(_, [old, new])| old != new)
+            .map(|(&predicate, &counts)| (predicate, counts))
+    }
+}
+
+/// Compares `old` and `new`, both must be sorted
+pub fn compare_datasets(old: &CompressedRdfTriples, new: &CompressedRdfTriples) -> io::Result<DatasetDiff> {
+    let mut diff = DatasetDiff {
+        n_added: 0,
+        n_removed: 0,
+        n_common: 0,
+        distinct_counts: [old.distinct_counts()?, new.distinct_counts()?],
+        predicate_counts: BTreeMap::new(),
+    };
+
+    let mut old_itr = old.iter().peekable();
+    let mut new_itr = new.iter().peekable();
+
+    loop {
+        let ordering = match (old_itr.peek(), new_itr.peek()) {
+            (Some(o), Some(n)) => o.cmp(n),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+
+        match ordering {
+            Ordering::Less => {
+                let [_, predicate, _] = old_itr.next().unwrap();
+                diff.predicate_counts.entry(*predicate).or_default()[0] += 1;
+                diff.n_removed += 1;
+            },
+            Ordering::Greater => {
+                let [_, predicate, _] = new_itr.next().unwrap();
+                diff.predicate_counts.entry(*predicate).or_default()[1] += 1;
+                diff.n_added += 1;
+            },
+            Ordering::Equal => {
+                let [_, predicate, _] = old_itr.next().unwrap();
+                new_itr.next();
+
+                let counts = diff.predicate_counts.entry(*predicate).or_default();
+                counts[0] += 1;
+                counts[1] += 1;
+                diff.n_common += 1;
+            },
+        }
+    }
+
+    Ok(diff)
+}
@@ -0,0 +1,193 @@
+//! User supplied templates for the written queries, to produce store specific dialects
+//! (e.g. with hints or pragmas) without code changes
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+const PLACEHOLDERS: [(&str, Part); 4] = [
+    ("triples", Part::Triples),
+    ("graph", Part::Graph),
+    ("query_id", Part::QueryId),
+    ("operation", Part::Operation),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Triples,
+    Graph,
+    QueryId,
+    Operation,
+}
+
+/// A template for a single query on a single line, since query files hold one query per line. Placeholders are `{{triples}}`, the serialized triples of the query,
+/// `{{graph}}`, the IRI of the target graph in `<>` or nothing for the default graph, `{{query_id}}`,
+/// the position of the query in the file, and `{{operation}}`, `INSERT DATA` or `DELETE DATA`.
+pub struct QueryTemplate {
+    head: Vec<Segment>,
+    tail: Vec<Segment>,
+}
+
+enum Segment {
+    Literal(String),
+    Placeholder(Part),
+}
+
+impl QueryTemplate {
+    /// Reads the template at `path`, a single trailing line break is replaced by the configured line ending
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let template = std::fs::read_to_string(&path)?;
+        let template = template
+            .strip_suffix('\n')
+            .map(|template| template.strip_suffix('\r').unwrap_or(template))
+            .unwrap_or(&template);
+
+        template.parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid query template {:?}: {e}", path.as_ref()),
+            )
+        })
+    }
+
+    /// Writes the part of the query before the triples
+    pub fn write_head<W: Write>(
+        &self,
+        out: &mut W,
+        operation: &str,
+        graph: Option<&str>,
+        query_ix: usize,
+    ) -> io::Result<()> {
+        write_segments(out, &self.head, operation, graph, query_ix)
+    }
+
+    /// Writes the part of the query after the triples, without line ending
+    pub fn write_tail<W: Write>(
+        &self,
+        out: &mut W,
+        operation: &str,
+        graph: Option<&str>,
+        query_ix: usize,
+    ) -> io::Result<()> {
+        write_segments(out, &self.tail, operation, graph, query_ix)
+    }
+}
+
+fn write_segments<W: Write>(
+    out: &mut W,
+    segments: &[Segment],
+    operation: &str,
+    graph: Option<&str>,
+    query_ix: usize,
+) -> io::Result<()> {
+    for segment in segments {
+        match segment {
+            Segment::Literal(literal) => out.write_all(literal.as_bytes())?,
+            Segment::Placeholder(Part::Graph) => {
+                if let Some(graph) = graph {
+                    write!(out, "<{graph}>")?;
+                }
+            },
+            Segment::Placeholder(Part::QueryId) => write!(out, "{query_ix}")?,
+            Segment::Placeholder(Part::Operation) => out.write_all(operation.as_bytes())?,
+            Segment::Placeholder(Part::Triples) => unreachable!("the triples separate head and tail"),
+        }
+    }
+
+    Ok(())
+}
+
+impl std::str::FromStr for QueryTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['\n', '\r']) {
+            return Err("template must be a single line, query files hold one query per line".to_owned());
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("unterminated placeholder at {:?}", &rest[start..]))?;
+
+            let name = rest[start + 2..start + end].trim();
+            let &(_, part) = PLACEHOLDERS
+                .iter()
+                .find(|(placeholder, _)| *placeholder == name)
+                .ok_or_else(|| {
+                    let known: Vec<_> = PLACEHOLDERS.iter().map(|(placeholder, _)| *placeholder).collect();
+                    format!("unknown placeholder {{{{{name}}}}}, known are {}", known.join(", "))
+                })?;
+
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+
+            segments.push(Segment::Placeholder(part));
+            rest = &rest[start + end + 2..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+
+        let mut triples = segments
+            .iter()
+            .enumerate()
+            .filter(|(_, segment)| matches!(segment, Segment::Placeholder(Part::Triples)))
+            .map(|(ix, _)| ix);
+
+        let (Some(triples_ix), None) = (triples.next(), triples.next()) else {
+            return Err("template must contain {{triples}} exactly once".to_owned());
+        };
+
+        let tail = segments.split_off(triples_ix + 1);
+        segments.pop();
+
+        Ok(QueryTemplate { head: segments, tail })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, operation: &str, graph: Option<&str>, query_ix: usize) -> String {
+        let template: QueryTemplate = template.parse().unwrap();
+        let mut out = Vec::new();
+        template.write_head(&mut out, operation, graph, query_ix).unwrap();
+        out.extend_from_slice(b"<a> <b> <c> . ");
+        template.write_tail(&mut out, operation, graph, query_ix).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        let template = "PREFIX hint: <urn:hint> {{operation}} { GRAPH {{graph}} { {{ triples }}} } # {{query_id}}";
+        assert_eq!(
+            render(template, "INSERT DATA", Some("http://g"), 7),
+            "PREFIX hint: <urn:hint> INSERT DATA { GRAPH <http://g> { <a> <b> <c> . } } # 7"
+        );
+        assert_eq!(
+            render("{{operation}} { {{graph}}{{triples}}}", "DELETE DATA", None, 0),
+            "DELETE DATA { <a> <b> <c> . }"
+        );
+    }
+
+    fn error(template: &str) -> String {
+        template.parse::<QueryTemplate>().err().unwrap()
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert!(error("INSERT DATA { }").contains("exactly once"));
+        assert!(error("{{triples}} {{triples}}").contains("exactly once"));
+        assert!(error("{{triples}} {{graph").contains("unterminated"));
+        assert!(error("{{triples}} {{name}}").contains("unknown placeholder {{name}}"));
+        assert!(error("# {{query_id}}\n{{operation}} { {{triples}} }").contains("single line"));
+    }
+}
@@ -1,45 +1,612 @@
-use std::path::{Path, PathBuf};
+use flate2::{write::GzEncoder, Compression};
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
 
-pub fn dataset_iter(
-    paths: Vec<PathBuf>,
-    recursive: bool,
-    extension: &str,
-) -> impl Iterator<Item = walkdir::Result<PathBuf>> + '_ {
-    paths.into_iter().flat_map(move |path| {
-        if path.is_dir() {
-            if recursive {
-                walkdir::WalkDir::new(path)
-                    .into_iter()
-                    .filter_map(|e| match e {
-                        Ok(e)
-                            if e.file_type().is_file()
-                                && matches!(e.path().extension(), Some(ext) if ext == extension) =>
-                        {
-                            Some(Ok(e.into_path()))
-                        },
-                        Ok(_) => None,
-                        other => Some(other.map(|e| e.into_path())),
-                    })
-                    .collect()
-            } else {
-                vec![]
-            }
-        } else {
-            vec![Ok(path)]
+/// Options controlling how directory trees are traversed
+#[derive(clap::Args, Clone, Default)]
+pub struct TraversalOpts {
+    /// Follow symbolic links when walking directories
+    #[clap(short = 'L', long, action)]
+    pub follow_symlinks: bool,
+
+    /// Descend at most this many levels below the given directories
+    #[clap(long)]
+    pub max_depth: Option<usize>,
+
+    /// Extension of the files picked up when walking directories, instead of the default of the command
+    /// (`compressed_nt`, or `nt` for compress). Matched against the end of the file name, so `nt.gz` works
+    #[clap(long)]
+    pub extension: Option<String>,
+}
+
+impl TraversalOpts {
+    /// Creates a parallel walker, directories are read concurrently but entries are
+    /// still yielded in depth first order
+    fn walker<P: AsRef<Path>>(&self, root: P) -> jwalk::WalkDir {
+        let walker = jwalk::WalkDir::new(root).skip_hidden(false).follow_links(self.follow_symlinks);
+
+        match self.max_depth {
+            Some(max_depth) => walker.max_depth(max_depth),
+            None => walker,
         }
-    })
+    }
+}
+
+/// Options controlling how compressed datasets and compressor states are read
+#[derive(clap::Args, Clone, Copy, Default)]
+pub struct LoadOpts {
+    /// Read the compressed datasets and compressor state fully into memory instead of memory mapping them, if they
+    /// fit. Avoids slow random reads on network filesystems
+    #[clap(long, action)]
+    pub load_into_memory: bool,
+}
+
+/// Options controlling how dataset arguments are expanded into files
+#[derive(clap::Args, Clone, Default)]
+pub struct WalkOpts {
+    /// Operate recursively on directories
+    #[clap(short = 'r', long, action)]
+    pub recursive: bool,
+
+    /// Skip files whose path matches this glob, may be given multiple times
+    #[clap(long, value_parser = parse_glob)]
+    pub exclude: Vec<GlobMatcher>,
+
+    /// Yield files in filesystem order instead of sorted by path, faster but not reproducible across machines
+    #[clap(long, action)]
+    pub unsorted_walk: bool,
+
+    #[clap(flatten)]
+    pub traversal: TraversalOpts,
+}
+
+pub fn parse_glob(glob: &str) -> Result<GlobMatcher, globset::Error> {
+    Ok(Glob::new(glob)?.compile_matcher())
+}
+
+/// Parses a positive duration of the form `<N><s|m|h|d|w>`, e.g. `6h`
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid =
+        || format!("invalid duration '{duration}', expected a positive number with a unit, e.g. 30m, 6h or 1d");
+
+    let unit_start = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (n, unit) = duration.split_at(unit_start);
+    let n: u64 = n.parse().map_err(|_| invalid())?;
+
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    if n == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(n * unit_secs))
+}
+
+impl WalkOpts {
+    fn walker<P: AsRef<Path>>(&self, root: P) -> jwalk::WalkDir {
+        self.traversal.walker(root).sort(!self.unsorted_walk)
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|glob| glob.is_match(path))
+    }
+}
+
+fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// Expands a glob pattern by walking the longest leading directory that contains no glob metacharacters
+fn glob_walk(pattern: &str, opts: &WalkOpts) -> Vec<io::Result<PathBuf>> {
+    let matcher = match GlobBuilder::new(pattern).literal_separator(true).build() {
+        Ok(glob) => glob.compile_matcher(),
+        Err(e) => {
+            return vec![Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid glob pattern {pattern:?}: {e}"),
+            ))]
+        },
+    };
+
+    let pattern_path = Path::new(pattern);
+    let base: PathBuf = pattern_path
+        .components()
+        .take_while(|c| !c.as_os_str().to_str().is_some_and(is_glob_pattern))
+        .collect();
+
+    let relative = base.as_os_str().is_empty();
+    let mut walker = opts.walker(if relative { Path::new(".") } else { &base });
+
+    if !pattern.contains("**") {
+        let pattern_depth = pattern_path.components().count() - base.components().count();
+        walker = walker.max_depth(
+            opts.traversal
+                .max_depth
+                .map_or(pattern_depth, |max_depth| max_depth.min(pattern_depth)),
+        );
+    }
+
+    walker
+        .into_iter()
+        .filter_map(|e| match e {
+            Ok(e) if e.file_type().is_file() => {
+                let path = e.path();
+                let path = if relative {
+                    path.strip_prefix(".").map(Path::to_owned).unwrap_or(path)
+                } else {
+                    path
+                };
+
+                matcher.is_match(&path).then_some(Ok(path))
+            },
+            Ok(_) => None,
+            Err(e) => Some(Err(e.into())),
+        })
+        .collect()
+}
+
+/// Whether the file name of `path` ends in `.<extension>`, so that multi-part extensions like `nt.gz` match as well
+pub fn has_extension(path: &Path, extension: &str) -> bool {
+    let name = path.file_name().unwrap_or_default().as_encoded_bytes();
+
+    name.len() > extension.len() + 1
+        && name.ends_with(extension.as_bytes())
+        && name[name.len() - extension.len() - 1] == b'.'
+}
+
+/// Expands the given paths into the dataset files they denote.
+/// Paths containing glob metacharacters are treated as patterns (e.g. `data/**/*added*.compressed_nt`),
+/// directories are walked if `opts.recursive` is set, keeping only files with the given extension
+/// or the one set in `opts`. Files found while walking are yielded sorted by path unless `opts.unsorted_walk` is set.
+pub fn dataset_iter<'a>(
+    paths: Vec<PathBuf>,
+    opts: &'a WalkOpts,
+    extension: &'a str,
+) -> impl Iterator<Item = io::Result<PathBuf>> + 'a {
+    let extension = opts.traversal.extension.as_deref().unwrap_or(extension);
+
+    paths
+        .into_iter()
+        .flat_map(move |path| match path.to_str() {
+            _ if crate::remote::is_remote(&path) => vec![Ok(path)],
+            Some(pattern) if is_glob_pattern(pattern) => glob_walk(pattern, opts),
+            _ if path.is_dir() => {
+                if opts.recursive {
+                    opts.walker(path)
+                        .into_iter()
+                        .filter_map(|e| match e {
+                            Ok(e) if e.file_type().is_file() && has_extension(&e.path(), extension) => {
+                                Some(Ok(e.path()))
+                            },
+                            Ok(_) => None,
+                            Err(e) => Some(Err(e.into())),
+                        })
+                        .collect()
+                } else {
+                    vec![]
+                }
+            },
+            _ => vec![Ok(path)],
+        })
+        .filter(move |path| !matches!(path, Ok(path) if opts.is_excluded(path)))
 }
 
+/// Yields the changeset files below `path` with the given extension or the one set in `opts` sorted by path
 pub fn changeset_file_iter<P: AsRef<Path>>(
     path: P,
+    opts: &TraversalOpts,
     extension: &str,
-) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + '_ {
-    walkdir::WalkDir::new(path.as_ref())
-        .sort_by_file_name()
-        .into_iter()
-        .filter(move |de| {
-            de.as_ref()
-                .map(|de| de.file_type().is_file() && matches!(de.path().extension(), Some(ext) if ext == extension))
-                .unwrap_or(true)
+) -> impl Iterator<Item = io::Result<PathBuf>> {
+    let extension = opts.extension.clone().unwrap_or_else(|| extension.to_owned());
+
+    opts.walker(path).sort(true).into_iter().filter_map(move |e| match e {
+        Ok(e) if e.file_type().is_file() && has_extension(&e.path(), &extension) => Some(Ok(e.path())),
+        Ok(_) => None,
+        Err(e) => Some(Err(e.into())),
+    })
+}
+
+/// Whether `n_bytes` fit into the currently available memory as reported by `/proc/meminfo`.
+/// Assumes they fit if the available memory cannot be determined.
+pub fn fits_in_memory(n_bytes: u64) -> bool {
+    let available_kib = std::fs::read_to_string("/proc/meminfo").ok().and_then(|meminfo| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix("MemAvailable:"))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse::<u64>()
+            .ok()
+    });
+
+    !matches!(available_kib, Some(available_kib) if n_bytes > available_kib * 1024)
+}
+
+/// Stream of the randomized output order of generated queries, the other streams of a run count up from 0
+pub const OUTPUT_ORDER_STREAM: u64 = u64::MAX;
+
+/// Seed of the random stream `stream` of a run seeded with `seed`, so that the random steps of a run
+/// do not draw the same numbers
+pub fn stream_seed(seed: Option<u64>, stream: u64) -> Option<u64> {
+    seed.map(|seed| seed.wrapping_add(stream))
+}
+
+/// Random number generator seeded with `seed`, or from entropy if there is none
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Limit on the resident memory of the process in bytes set by `--max-memory`, 0 if unlimited
+static MAX_MEMORY: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_max_memory(max_memory: Option<u64>) {
+    MAX_MEMORY.store(max_memory.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Value of the `key` line of `/proc/self/status` in bytes, e.g. `RssAnon:   1024 kB`
+fn status_bytes(status: &str, key: &str) -> Option<u64> {
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Resident anonymous memory of the process in bytes as reported by `/proc/self/status`. Pages of memory mapped
+/// files are left out, the kernel can drop them under memory pressure. Falls back to the whole resident memory
+/// on kernels without `RssAnon`.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status_bytes(&status, "RssAnon").or_else(|| status_bytes(&status, "VmRSS"))
+}
+
+/// Set once SIGINT was received after [`catch_interrupts`]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes the first SIGINT (Ctrl-C) only set [`is_interrupted`] instead of terminating the process, so that long
+/// running steps can finish their current file, finalize their outputs and stop. A second one terminates immediately.
+pub fn catch_interrupts() {
+    static HANDLER: std::sync::Once = std::sync::Once::new();
+
+    HANDLER.call_once(|| {
+        let handler = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+        });
+
+        if let Err(e) = handler {
+            eprintln!("unable to catch interrupts, Ctrl-C terminates immediately: {e}");
+        }
+    });
+}
+
+/// Whether SIGINT was received since [`catch_interrupts`]
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Whether allocating `n_bytes` more keeps the resident memory within `--max-memory`.
+/// Always true without a limit or if the resident memory cannot be determined.
+pub fn fits_memory_limit(n_bytes: u64) -> bool {
+    let max_memory = MAX_MEMORY.load(Ordering::Relaxed);
+    if max_memory == 0 {
+        return true;
+    }
+
+    match resident_memory() {
+        Some(resident) => resident + n_bytes <= max_memory,
+        None => true,
+    }
+}
+
+/// Fails with a message naming `what` if allocating `n_bytes` more would exceed `--max-memory`,
+/// so that memory hungry steps abort early instead of getting killed hours in
+pub fn check_memory(n_bytes: u64, what: &str) -> io::Result<()> {
+    if fits_memory_limit(n_bytes) {
+        return Ok(());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        format!(
+            "{what} needs {n_bytes} more bytes, which would exceed --max-memory {} with {} bytes already resident",
+            MAX_MEMORY.load(Ordering::Relaxed),
+            resident_memory().unwrap_or_default()
+        ),
+    ))
+}
+
+/// A file that is written to a temporary sibling path and only moved to its final
+/// location once [`AtomicFile::commit`] is called. If it is dropped without being
+/// committed the temporary file is removed, so a crash or error never leaves a
+/// truncated file at the destination.
+pub struct AtomicFile {
+    file: File,
+    tmp_path: PathBuf,
+    path: PathBuf,
+    committed: bool,
+    /// Whether the temporary file is left in place when dropped without being committed
+    keep_on_drop: bool,
+    /// Size of the file in bytes, including the copied contents when appending
+    size: u64,
+}
+
+impl AtomicFile {
+    /// Creates a new temporary file for `path`. If `append` is set and `path` already exists
+    /// its current contents are copied into the temporary file first.
+    pub fn create<P: AsRef<Path>>(path: P, append: bool) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{path:?} does not name a file")))?;
+
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(".tmp-{}", std::process::id()));
+
+        let tmp_path = path.with_file_name(tmp_name);
+
+        if append && path.exists() {
+            std::fs::copy(&path, &tmp_path)?;
+        }
+
+        let file = File::options()
+            .append(append)
+            .truncate(!append)
+            .create(true)
+            .write(true)
+            .open(&tmp_path)?;
+
+        let size = file.metadata()?.len();
+
+        Ok(AtomicFile { file, tmp_path, path, committed: false, keep_on_drop: false, size })
+    }
+
+    /// Like [`AtomicFile::create`] but fails if `path` already exists.
+    pub fn create_new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        if path.as_ref().exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", path.as_ref()),
+            ));
+        }
+
+        Self::create(path, false)
+    }
+
+    /// Continues the temporary file `tmp_path` of `path` that an interrupted run left uncommitted,
+    /// discarding everything after its first `len` bytes
+    pub fn resume<P: AsRef<Path>>(path: P, tmp_path: PathBuf, len: u64) -> io::Result<Self> {
+        let file = File::options().append(true).open(&tmp_path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("unable to open {tmp_path:?} of the interrupted run: {e}"),
+            )
+        })?;
+
+        if file.metadata()?.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{tmp_path:?} is shorter than the {len} bytes recorded in the checkpoint"),
+            ));
+        }
+
+        file.set_len(len)?;
+
+        Ok(AtomicFile {
+            file,
+            tmp_path,
+            path: path.as_ref().to_owned(),
+            committed: false,
+            keep_on_drop: false,
+            size: len,
         })
+    }
+
+    /// Size of the file in bytes written so far
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Path of the temporary file the contents are written to before committing
+    pub fn tmp_path(&self) -> &Path {
+        &self.tmp_path
+    }
+
+    /// Leaves the temporary file in place if this is dropped without committing, e.g. because a checkpoint
+    /// refers to it so that an interrupted run can be resumed with [`AtomicFile::resume`]
+    pub fn keep_on_drop(&mut self) {
+        self.keep_on_drop = true;
+    }
+
+    /// Flushes the temporary file to disk and atomically renames it to its final path.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.tmp_path, &self.path)?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n_written = self.file.write(buf)?;
+        self.size += n_written as u64;
+        Ok(n_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed && !self.keep_on_drop {
+            let _ = std::fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// Unwraps the [`BufWriter`] around an [`AtomicFile`] and commits it.
+pub fn commit_buffered(writer: BufWriter<AtomicFile>) -> io::Result<()> {
+    writer.into_inner().map_err(io::IntoInnerError::into_error)?.commit()
+}
+
+/// Gzip compresses everything written to it into an [`AtomicFile`], so that the uncompressed output never
+/// needs to be stored
+pub struct GzipWriter {
+    encoder: GzEncoder<AtomicFile>,
+}
+
+impl GzipWriter {
+    pub fn new(out: AtomicFile) -> Self {
+        GzipWriter { encoder: GzEncoder::new(out, Compression::default()) }
+    }
+
+    /// Writes the end of the compressed stream and commits the output
+    pub fn finish(self) -> io::Result<()> {
+        self.encoder.finish()?.commit()
+    }
+}
+
+impl Write for GzipWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions_match_the_end_of_the_file_name() {
+        assert!(has_extension(Path::new("dumps/dump.nt.gz"), "nt.gz"));
+        assert!(has_extension(Path::new("dumps/dump.nt.gz"), "gz"));
+        assert!(has_extension(Path::new("dump.compressed_nt"), "compressed_nt"));
+        assert!(!has_extension(Path::new("dump.nt.gz"), "nt"));
+        assert!(!has_extension(Path::new("dumpnt.gz"), "nt.gz"));
+        assert!(!has_extension(Path::new(".nt"), "nt"));
+    }
+
+    #[test]
+    fn status_bytes_prefers_exact_key() {
+        let status = "VmRSS:\t   4096 kB\nRssAnon:\t    1024 kB\nRssFile:\t    3072 kB\n";
+
+        assert_eq!(status_bytes(status, "RssAnon"), Some(1024 * 1024));
+        assert_eq!(status_bytes(status, "VmRSS"), Some(4096 * 1024));
+        assert_eq!(status_bytes(status, "Rss"), None);
+    }
+
+    #[test]
+    fn streams_draw_different_numbers() {
+        use rand::Rng;
+
+        let draw = |stream| seeded_rng(stream_seed(Some(42), stream)).gen::<u64>();
+
+        assert_ne!(draw(0), draw(OUTPUT_ORDER_STREAM));
+        assert_ne!(draw(0), draw(1));
+        assert_eq!(draw(1), draw(1));
+        assert_eq!(stream_seed(None, OUTPUT_ORDER_STREAM), None);
+    }
+
+    #[test]
+    fn kept_tmp_file_can_be_resumed() {
+        let path = std::env::temp_dir().join(format!("sudg-keep-{}.txt", std::process::id()));
+
+        let mut file = AtomicFile::create(&path, false).unwrap();
+        file.write_all(b"kept").unwrap();
+        let len = file.size();
+        file.write_all(b" discarded").unwrap();
+        file.keep_on_drop();
+        let tmp_path = file.tmp_path().to_owned();
+        drop(file);
+
+        assert!(tmp_path.exists());
+        assert!(!path.exists());
+
+        let mut file = AtomicFile::resume(&path, tmp_path.clone(), len).unwrap();
+        file.write_all(b" resumed").unwrap();
+        file.commit().unwrap();
+
+        assert!(!tmp_path.exists());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "kept resumed");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn gzip_writer_commits_compressed_output() {
+        use std::io::Read;
+
+        let path = std::env::temp_dir().join(format!("sudg-gzip-{}.nt.gz", std::process::id()));
+
+        let mut writer = GzipWriter::new(AtomicFile::create(&path, false).unwrap());
+        writer.write_all(b"<s> <p> <o> .\n").unwrap();
+        assert!(!path.exists());
+        writer.finish().unwrap();
+
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, "<s> <p> <o> .\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn uncommitted_tmp_file_is_removed() {
+        let path = std::env::temp_dir().join(format!("sudg-drop-{}.txt", std::process::id()));
+
+        let mut file = AtomicFile::create(&path, false).unwrap();
+        file.write_all(b"dropped").unwrap();
+        let tmp_path = file.tmp_path().to_owned();
+        drop(file);
+
+        assert!(!tmp_path.exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn status_bytes_missing_key() {
+        let status = "VmRSS:\t   4096 kB\n";
+
+        assert_eq!(status_bytes(status, "RssAnon"), None);
+        assert_eq!(
+            status_bytes(status, "RssAnon").or_else(|| status_bytes(status, "VmRSS")),
+            Some(4096 * 1024)
+        );
+    }
 }
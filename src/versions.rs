@@ -0,0 +1,213 @@
+//! Materialization of dataset versions by applying changesets to a base dataset in order,
+//! as used by archiving benchmarks (e.g. BEAR)
+
+use crate::{
+    rdf::triple_compressor::{
+        save_compressed_triples, CompressedRdfTriples, CompressedTriple, COMPRESSED_TRIPLE_FILE_EXTENSION,
+    },
+    util::{commit_buffered, AtomicFile},
+    warnings::{warn, Warning},
+};
+use globset::GlobMatcher;
+use rayon::slice::ParallelSliceMut;
+use std::{
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// The added and removed files of one changeset
+pub struct Changeset {
+    /// Common prefix of the file paths, the file name up to its first `.`
+    pub name: PathBuf,
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+/// Effect of a changeset on the dataset
+pub struct VersionDelta {
+    /// Added triples that were not contained before
+    pub n_added: usize,
+    /// Removed triples that were contained before
+    pub n_removed: usize,
+    /// Number of triples of the resulting version
+    pub n_triples: usize,
+}
+
+fn changeset_name(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = file_name.split('.').next().unwrap_or_default();
+    path.with_file_name(stem)
+}
+
+/// Whether the changeset file at `path` contains added (`Some(true)`) or removed (`Some(false)`) triples, by matching
+/// its file name against `insert_pattern` and `delete_pattern`. Files matching neither are skipped with a warning.
+pub fn is_added_changeset_file(
+    path: &Path,
+    insert_pattern: &GlobMatcher,
+    delete_pattern: &GlobMatcher,
+) -> Option<bool> {
+    let file_name = path.file_name().unwrap_or_default();
+
+    if insert_pattern.is_match(file_name) {
+        Some(true)
+    } else if delete_pattern.is_match(file_name) {
+        Some(false)
+    } else {
+        warn(Warning::SkippedFile {
+            path: path.to_owned(),
+            reason: "file name matches neither the insert nor the delete pattern".to_owned(),
+        });
+        None
+    }
+}
+
+/// Groups the (sorted) changeset files into changesets, files are classified by [`is_added_changeset_file`]
+pub fn group_changesets(
+    paths: impl IntoIterator<Item = PathBuf>,
+    insert_pattern: &GlobMatcher,
+    delete_pattern: &GlobMatcher,
+) -> Vec<Changeset> {
+    let mut changesets: Vec<Changeset> = Vec::new();
+
+    for path in paths {
+        let Some(is_insert) = is_added_changeset_file(&path, insert_pattern, delete_pattern) else {
+            continue;
+        };
+
+        let name = changeset_name(&path);
+        let changeset = match changesets.last_mut() {
+            Some(changeset) if changeset.name == name => changeset,
+            _ => {
+                changesets.push(Changeset { name, added: Vec::new(), removed: Vec::new() });
+                changesets.last_mut().unwrap()
+            },
+        };
+
+        if is_insert {
+            changeset.added.push(path);
+        } else {
+            changeset.removed.push(path);
+        }
+    }
+
+    changesets
+}
+
+/// Reads all triples of `paths` into a sorted vector without duplicates
+pub fn read_sorted_triples(paths: &[PathBuf]) -> io::Result<Vec<CompressedTriple>> {
+    let mut triples = Vec::new();
+
+    for path in paths {
+        triples.extend_from_slice(&CompressedRdfTriples::read_into_memory(path)?);
+    }
+
+    triples.par_sort_unstable();
+    triples.dedup();
+    Ok(triples)
+}
+
+/// Removes `removed` from and adds `added` to the sorted triples of `version`, all inputs must be sorted without duplicates
+pub fn apply_changeset(
+    version: &mut Vec<CompressedTriple>,
+    added: &[CompressedTriple],
+    removed: &[CompressedTriple],
+) -> VersionDelta {
+    let n_before = version.len();
+    version.retain(|triple| removed.binary_search(triple).is_err());
+    let n_removed = n_before - version.len();
+
+    let added: Vec<_> = added
+        .iter()
+        .copied()
+        .filter(|triple| version.binary_search(triple).is_err())
+        .collect();
+    let n_added = added.len();
+
+    let mut merged = Vec::with_capacity(version.len() + added.len());
+    let mut existing = std::mem::take(version).into_iter().peekable();
+    let mut added = added.into_iter().peekable();
+
+    while let (Some(e), Some(a)) = (existing.peek(), added.peek()) {
+        if e < a {
+            merged.push(existing.next().unwrap());
+        } else {
+            merged.push(added.next().unwrap());
+        }
+    }
+
+    merged.extend(existing);
+    merged.extend(added);
+    *version = merged;
+
+    VersionDelta { n_added, n_removed, n_triples: version.len() }
+}
+
+/// Path of the compressed dataset of version `ix` in `out_dir`
+pub fn version_path(out_dir: &Path, ix: usize) -> PathBuf {
+    out_dir.join(format!("v{ix}.{COMPRESSED_TRIPLE_FILE_EXTENSION}"))
+}
+
+fn sorted_version(base: &CompressedRdfTriples) -> Vec<CompressedTriple> {
+    let mut version = base.to_vec();
+    version.par_sort_unstable();
+    version.dedup();
+    version
+}
+
+/// Applies `changesets` to `base` in order, writing every version and a `versions.csv` with the delta of each version to `out_dir`
+pub fn materialize_versions(base: &CompressedRdfTriples, changesets: &[Changeset], out_dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut version = sorted_version(base);
+
+    save_compressed_triples(version_path(out_dir, 0), &version)?;
+
+    let mut stats = BufWriter::new(AtomicFile::create(out_dir.join("versions.csv"), false)?);
+    writeln!(stats, "version,changeset,n_added,n_removed,n_triples")?;
+    writeln!(stats, "0,,0,0,{}", version.len())?;
+
+    for (ix, changeset) in changesets.iter().enumerate() {
+        let ix = ix + 1;
+        println!("materializing version {ix} from {:?}...", changeset.name);
+
+        let added = read_sorted_triples(&changeset.added)?;
+        let removed = read_sorted_triples(&changeset.removed)?;
+        let VersionDelta { n_added, n_removed, n_triples } = apply_changeset(&mut version, &added, &removed);
+
+        save_compressed_triples(version_path(out_dir, ix), &version)?;
+
+        writeln!(
+            stats,
+            "{ix},\"{}\",{n_added},{n_removed},{n_triples}",
+            changeset.name.display().to_string().replace('"', "\"\"")
+        )?;
+    }
+
+    commit_buffered(stats)
+}
+
+/// Applies `changesets` in order to `base`, or to an empty dataset, and writes only the resulting dataset to `out`,
+/// sorted and without duplicates so that it can be used as main dataset. Returns the delta of all changesets together.
+pub fn build_dataset(
+    base: Option<&CompressedRdfTriples>,
+    changesets: &[Changeset],
+    out: &Path,
+) -> io::Result<VersionDelta> {
+    let mut version = base.map(sorted_version).unwrap_or_default();
+    let mut total = VersionDelta { n_added: 0, n_removed: 0, n_triples: version.len() };
+
+    for changeset in changesets {
+        println!("applying {:?}...", changeset.name);
+
+        let added = read_sorted_triples(&changeset.added)?;
+        let removed = read_sorted_triples(&changeset.removed)?;
+        let delta = apply_changeset(&mut version, &added, &removed);
+
+        total.n_added += delta.n_added;
+        total.n_removed += delta.n_removed;
+        total.n_triples = delta.n_triples;
+    }
+
+    save_compressed_triples(out, &version)?;
+    Ok(total)
+}
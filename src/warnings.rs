@@ -0,0 +1,127 @@
+//! Collection of the non-fatal problems encountered during a run, so they can be summarized at the end
+//! instead of getting lost in the output
+
+use crate::util::{commit_buffered, AtomicFile};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// A generated query has a different number of triples than requested
+    UnfulfilledQuerySize {
+        query_ix: usize,
+        requested: usize,
+        actual: usize,
+    },
+    /// Triples were left out of the generated queries
+    PrunedTriples { reason: String, n_pruned: usize },
+    /// An input file was not processed
+    SkippedFile { path: PathBuf, reason: String },
+    /// An input line could not be tokenized and was skipped
+    MalformedLine { path: PathBuf, line: usize, reason: String },
+    /// A file did not fit into memory and was memory mapped instead
+    MemoryMappedFallback { path: PathBuf },
+    /// A step would have exceeded `--max-memory` and used a slower way that needs less memory
+    MemoryLimitFallback { step: String, fallback: String },
+    /// Inserts and deletes could not be alternated for the whole output
+    UnevenAlternation { n_inserts: usize, n_deletes: usize },
+    /// Queries of a spec were not generated
+    SkippedQueries {
+        spec: String,
+        n_queries: usize,
+        reason: String,
+    },
+    /// Triples were inserted by more than one generated query
+    RepeatedInserts { n_triples: usize },
+}
+
+impl Warning {
+    fn kind(&self) -> &'static str {
+        match self {
+            Warning::UnfulfilledQuerySize { .. } => "unfulfilled query sizes",
+            Warning::PrunedTriples { .. } => "pruned triples",
+            Warning::SkippedFile { .. } => "skipped files",
+            Warning::MalformedLine { .. } => "malformed lines",
+            Warning::MemoryMappedFallback { .. } => "memory mapped fallbacks",
+            Warning::MemoryLimitFallback { .. } => "memory limit fallbacks",
+            Warning::UnevenAlternation { .. } => "uneven alternations",
+            Warning::SkippedQueries { .. } => "skipped queries",
+            Warning::RepeatedInserts { .. } => "repeated inserts",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnfulfilledQuerySize { query_ix, requested, actual } => write!(
+                f,
+                "query {query_ix}: requested query size {requested} cannot be fulfilled closest available size is {actual}"
+            ),
+            Warning::PrunedTriples { reason, n_pruned } => write!(f, "pruned {n_pruned} triples: {reason}"),
+            Warning::SkippedFile { path, reason } => write!(f, "skipped {path:?}: {reason}"),
+            Warning::MalformedLine { path, line, reason } => write!(f, "{path:?}:{line}: {reason}"),
+            Warning::MemoryMappedFallback { path } => {
+                write!(f, "{path:?} does not fit into available memory, memory mapping it instead")
+            },
+            Warning::MemoryLimitFallback { step, fallback } => {
+                write!(f, "{step} would exceed --max-memory, {fallback} instead")
+            },
+            Warning::UnevenAlternation { n_inserts, n_deletes } => write!(
+                f,
+                "cannot fully alternate {n_inserts} inserts and {n_deletes} deletes, the remaining {} queries are appended",
+                n_inserts.abs_diff(*n_deletes)
+            ),
+            Warning::SkippedQueries { spec, n_queries, reason } => {
+                write!(f, "skipped {n_queries} queries of {spec}: {reason}")
+            },
+            Warning::RepeatedInserts { n_triples } => write!(
+                f,
+                "{n_triples} triples are inserted by more than one query, all but their first insert find them already in the store"
+            ),
+        }
+    }
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Prints the warning and records it for the final summary
+pub fn warn(warning: Warning) {
+    eprintln!("Warning: {warning}");
+    WARNINGS.lock().unwrap().push(warning);
+}
+
+/// Returns all warnings recorded so far
+pub fn collected() -> Vec<Warning> {
+    WARNINGS.lock().unwrap().clone()
+}
+
+/// Prints the number of warnings per kind
+pub fn print_summary(warnings: &[Warning]) {
+    if warnings.is_empty() {
+        return;
+    }
+
+    let mut per_kind: BTreeMap<&str, usize> = BTreeMap::new();
+    for warning in warnings {
+        *per_kind.entry(warning.kind()).or_default() += 1;
+    }
+
+    eprintln!("==== {} warnings ====", warnings.len());
+    for (kind, count) in per_kind {
+        eprintln!("{count} {kind}");
+    }
+}
+
+pub fn write_json<P: AsRef<Path>>(path: P, warnings: &[Warning]) -> io::Result<()> {
+    let mut writer = BufWriter::new(AtomicFile::create(path, false)?);
+    serde_json::to_writer_pretty(&mut writer, warnings)?;
+    commit_buffered(writer)
+}
@@ -0,0 +1,97 @@
+//! Workload files of `generate mix`, combining several generation strategies in one run, e.g. 50 randomized queries
+//! and 50 queries from changesets, so that `--output-order` orders all of their queries together
+//! instead of appending the output of separate runs
+
+use crate::{query_spec::QuerySpecOpt, rdf::triple_generator::ChangesetTruncation};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// How the queries of a changeset part are derived from its changesets, see `generate changeset --type`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangesetGeneration {
+    #[default]
+    AsIs,
+    FixedSize,
+}
+
+fn default_insert_pattern() -> String {
+    "*added.compressed_nt".to_owned()
+}
+
+fn default_delete_pattern() -> String {
+    "*removed.compressed_nt".to_owned()
+}
+
+/// A generation strategy and the query specs generated with it
+#[derive(Deserialize)]
+#[serde(tag = "strategy", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum WorkloadPart {
+    /// Triples selected at random from the main dataset. All randomized parts without duplicates share a generator,
+    /// so that their queries have no triples in common
+    Randomized {
+        #[serde(default)]
+        allow_duplicates: bool,
+        specs: Vec<String>,
+    },
+    /// Triples taken from the compressed changesets in a file or directory tree
+    Changeset {
+        changesets: PathBuf,
+        #[serde(default, rename = "type")]
+        generate_type: ChangesetGeneration,
+        #[serde(default)]
+        allow_overlap: bool,
+        #[serde(default)]
+        truncate: ChangesetTruncation,
+        #[serde(default = "default_insert_pattern")]
+        insert_pattern: String,
+        #[serde(default = "default_delete_pattern")]
+        delete_pattern: String,
+        #[serde(default)]
+        mix_added_removed: bool,
+        specs: Vec<String>,
+    },
+}
+
+impl WorkloadPart {
+    /// Parses the query specs of the part, they have the syntax of the query specs of generate
+    pub fn specs(&self) -> Result<Vec<QuerySpecOpt>, String> {
+        let (WorkloadPart::Randomized { specs, .. } | WorkloadPart::Changeset { specs, .. }) = self;
+        specs.iter().map(|spec| spec.parse()).collect()
+    }
+}
+
+/// The parts of a mixed workload, e.g.
+/// `{"parts": [{"strategy": "randomized", "specs": ["i50x100"]}, {"strategy": "changeset", "changesets": "changesets", "type": "fixed-size", "specs": ["d50x100"]}]}`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workload {
+    pub parts: Vec<WorkloadPart>,
+}
+
+impl Workload {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("unable to open workload file {path:?}: {e}")))?;
+
+        let workload: Workload = serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid workload file {path:?}: {e}"),
+            )
+        })?;
+
+        if workload.parts.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("workload file {path:?} has no parts"),
+            ));
+        }
+
+        Ok(workload)
+    }
+}